@@ -58,6 +58,15 @@ fail_on_blacklist="$(get_config_value "fail_on_blacklist")"
 fail_on_findings="$(get_config_value "fail_on_findings")"
 cancel_on_violation="$(get_config_value "cancel_on_violation")"
 include_local_traffic="$(get_config_value "include_local_traffic")"
+ipfix_collector="$(get_config_value "ipfix_collector")"
+lanscan_webhook="$(get_config_value "lanscan_webhook")"
+history_dir="$(get_config_value "history_dir")"
+history_retention="$(get_config_value "history_retention")"
+history_max_size="$(get_config_value "history_max_size")"
+geoip_db="$(get_config_value "geoip_db")"
+labels_file="$(get_config_value "labels_file")"
+blacklist_feeds="$(get_config_value "blacklist_feeds")"
+blacklist_feeds_interval="$(get_config_value "blacklist_feeds_interval")"
 
 # Agentic configuration
 agentic_mode="$(get_config_value "agentic_mode")"
@@ -248,6 +257,39 @@ if [ "$include_local_traffic" = "true" ]; then
   echo "Include local traffic: enabled"
 fi
 
+if [ -n "$ipfix_collector" ]; then
+  export EDAMAME_IPFIX_COLLECTOR="$ipfix_collector"
+  echo "IPFIX export: $ipfix_collector"
+fi
+
+if [ -n "$lanscan_webhook" ]; then
+  export EDAMAME_LANSCAN_WEBHOOK="$lanscan_webhook"
+  echo "LAN scan change notifications: enabled"
+fi
+
+if [ -n "$history_dir" ]; then
+  export EDAMAME_HISTORY_DIR="$history_dir"
+  [ -n "$history_retention" ] && export EDAMAME_HISTORY_RETENTION="$history_retention"
+  [ -n "$history_max_size" ] && export EDAMAME_HISTORY_MAX_SIZE="$history_max_size"
+  echo "History store: $history_dir"
+fi
+
+if [ -n "$geoip_db" ]; then
+  export EDAMAME_GEOIP_DB="$geoip_db"
+  echo "GeoIP databases: $geoip_db"
+fi
+
+if [ -n "$labels_file" ]; then
+  export EDAMAME_LABELS_FILE="$labels_file"
+  echo "Session labels: $labels_file"
+fi
+
+if [ -n "$blacklist_feeds" ]; then
+  export EDAMAME_BLACKLIST_FEEDS="$blacklist_feeds"
+  [ -n "$blacklist_feeds_interval" ] && export EDAMAME_BLACKLIST_FEEDS_INTERVAL="$blacklist_feeds_interval"
+  echo "Blacklist feeds: $blacklist_feeds"
+fi
+
 echo "Starting edamame_posture service..."
 
 # Execute the main binary in foreground mode (systemd manages daemonization)
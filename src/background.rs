@@ -1,4 +1,19 @@
 use crate::base::*;
+use crate::dismissals::{
+    load_rules, now_secs, record_dismissal, registry_path, remove_rules_at, DismissKind,
+    DismissRule,
+};
+use crate::geoip::{geo_summary, print_geo_summary, GeoGroup, GeoIp};
+use crate::labels::LabelRules;
+use crate::sessions::SessionFilter;
+use crate::steps::{self, Step};
+use crate::suppressions::{
+    keyed_items, load_suppressions, print_suppression_report, publish_file_entries,
+    published_file_entries, resolve_path, suppression_report, ActiveSuppressions, Hit, Matcher,
+    Observations, Suppression, DAEMON_FILE_ENTRIES, DEFAULT_SUPPRESSIONS_FILE, SUPPRESSIONS_ENV,
+};
+use crate::wait;
+use crate::whitelists::wildcard_matches;
 use crate::EDAMAME_CA_PEM;
 use crate::EDAMAME_CLIENT_KEY;
 use crate::EDAMAME_CLIENT_PEM;
@@ -18,7 +33,7 @@ use edamame_core::api::api_score_threats::*;
 use edamame_core::api::api_trust::*;
 use edamame_core::api::api_visibility::*;
 use flodbadd::blacklists::BlacklistsJSON;
-use std::thread::sleep;
+use std::collections::HashSet;
 use std::time::Duration;
 use tracing::{error, info, warn};
 
@@ -28,23 +43,139 @@ pub fn background_get_sessions(
     fail_on_anomalous: bool,
     fail_on_blacklist: bool,
     fail_on_whitelisted: bool,
+    step: Option<&str>,
+    filter: &SessionFilter,
 ) -> i32 {
-    let sessions = match rpc_get_lan_sessions(
+    let step_filter = match step {
+        Some(name) => match steps::steps_for_filter(name) {
+            Ok(all_steps) => Some((name, all_steps)),
+            Err(exit_code) => return exit_code,
+        },
+        None => None,
+    };
+    let geoip = match load_geoip() {
+        Ok(geoip) => geoip,
+        Err(exit_code) => return exit_code,
+    };
+    let labels = match load_labels() {
+        Ok(labels) => labels,
+        Err(exit_code) => return exit_code,
+    };
+    if !filter.labels.is_empty() && labels.is_none() {
+        eprintln!(
+            "--label requires a label file, set {}",
+            crate::labels::LABELS_ENV
+        );
+        return ERROR_CODE_PARAM;
+    }
+    // The core only knows where the AS of a destination is registered
+    let by_country = !filter.countries.is_empty() || filter.summary == Some(GeoGroup::Country);
+    if by_country && !geoip.as_ref().is_some_and(|geoip| geoip.has_countries()) {
+        eprintln!(
+            "--country and --summary country require a Country or City database in {}",
+            crate::geoip::GEOIP_ENV
+        );
+        return ERROR_CODE_PARAM;
+    }
+    let mut sessions = match rpc_get_lan_sessions(
         true,
         &EDAMAME_CA_PEM,
         &EDAMAME_CLIENT_PEM,
         &EDAMAME_CLIENT_KEY,
         &EDAMAME_TARGET,
     ) {
-        Ok(sessions) => sessions,
+        Ok(sessions) => sessions.sessions,
         Err(e) => {
             eprintln!("Error getting LAN sessions: {}", e);
             return ERROR_CODE_SERVER_ERROR;
         }
     };
+    if let Some((name, all_steps)) = &step_filter {
+        sessions.retain(|session| in_step(session, name, all_steps, steps::SESSION_TIME));
+    }
+    let mut geo_sessions = Vec::new();
+    if !filter.is_empty() {
+        if !local_traffic {
+            sessions = filter_global_sessions(sessions);
+        }
+        let mut keep = Vec::new();
+        for session in &sessions {
+            let mut value = serde_json::to_value(session).unwrap_or_default();
+            if let Some(geoip) = &geoip {
+                geoip.enrich(&mut value);
+            }
+            if let Some(labels) = &labels {
+                labels.apply(&mut value);
+            }
+            let matches = filter.matches(&value);
+            keep.push(matches);
+            if matches {
+                geo_sessions.push(value);
+            }
+        }
+        let mut keep = keep.into_iter();
+        sessions.retain(|_| keep.next().unwrap_or(false));
+    }
+
+    let exit_code = if step_filter.is_some() {
+        step_sessions_checks(
+            sessions,
+            zeek_format,
+            local_traffic,
+            fail_on_anomalous,
+            fail_on_blacklist,
+            fail_on_whitelisted,
+        )
+    } else {
+        global_sessions_checks(
+            sessions,
+            zeek_format,
+            local_traffic,
+            fail_on_anomalous,
+            fail_on_blacklist,
+            fail_on_whitelisted,
+        )
+    };
+    if let Some(group) = filter.summary {
+        print_geo_summary(&geo_summary(&geo_sessions, group), group);
+    }
+    exit_code
+}
+
+// GeoIP databases of EDAMAME_GEOIP_DB, an invalid configuration being an error
+fn load_geoip() -> Result<Option<GeoIp>, i32> {
+    GeoIp::from_env().map_err(|e| {
+        eprintln!("{}", e);
+        ERROR_CODE_PARAM
+    })
+}
+
+// Label rules of EDAMAME_LABELS_FILE, an invalid file being an error
+fn load_labels() -> Result<Option<LabelRules>, i32> {
+    LabelRules::from_env().map_err(|e| {
+        eprintln!("{}", e);
+        ERROR_CODE_PARAM
+    })
+}
 
+// Label rules for display only, where an invalid file leaves sessions unlabeled
+fn display_labels() -> Option<LabelRules> {
+    LabelRules::from_env().unwrap_or_else(|e| {
+        warn!("Sessions shown without labels: {}", e);
+        None
+    })
+}
+
+fn global_sessions_checks(
+    sessions: Vec<SessionInfoAPI>,
+    zeek_format: bool,
+    local_traffic: bool,
+    fail_on_anomalous: bool,
+    fail_on_blacklist: bool,
+    fail_on_whitelisted: bool,
+) -> i32 {
     // Filter and display sessions (normal mode)
-    background_display_sessions(sessions.sessions, zeek_format, local_traffic, false);
+    background_display_sessions(sessions, zeek_format, local_traffic, false);
 
     // Determine exit code based on checks
     let mut exit_code = 0;
@@ -115,6 +246,81 @@ pub fn background_get_sessions(
     return exit_code;
 }
 
+/// Whether the serialized form of `item` belongs to the step `name`.
+fn in_step<T: serde::Serialize>(
+    item: &T,
+    name: &str,
+    all_steps: &[Step],
+    pointers: &[&str],
+) -> bool {
+    serde_json::to_value(item)
+        .ok()
+        .and_then(|value| steps::item_step(all_steps, &value, pointers).map(str::to_string))
+        .is_some_and(|step| step == name)
+}
+
+// Sessions of one step: the whitelist and blacklist checks only consider them,
+// the anomaly status of the daemon is global.
+fn step_sessions_checks(
+    sessions: Vec<SessionInfoAPI>,
+    zeek_format: bool,
+    local_traffic: bool,
+    fail_on_anomalous: bool,
+    fail_on_blacklist: bool,
+    fail_on_whitelisted: bool,
+) -> i32 {
+    let sessions = if local_traffic {
+        sessions
+    } else {
+        filter_global_sessions(sessions)
+    };
+    background_display_sessions(sessions.clone(), zeek_format, true, false);
+
+    let mut exit_code = 0;
+    if fail_on_whitelisted
+        && sessions
+            .iter()
+            .any(|session| session.is_whitelisted == WhiteListStateAPI::NonConforming)
+    {
+        eprintln!("Non-conforming sessions detected");
+        exit_code = ERROR_CODE_MISMATCH;
+    }
+    if fail_on_blacklist {
+        match rpc_get_blacklisted_sessions(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ) {
+            Ok(blacklisted) => {
+                let uids: HashSet<String> =
+                    blacklisted.into_iter().map(|session| session.uid).collect();
+                if sessions.iter().any(|session| uids.contains(&session.uid)) {
+                    eprintln!("Blacklisted sessions detected");
+                    exit_code = ERROR_CODE_MISMATCH;
+                }
+            }
+            Err(e) => eprintln!("Error checking blacklisted sessions: {}", e),
+        }
+    }
+    if fail_on_anomalous {
+        match rpc_get_anomalous_status(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ) {
+            Ok(true) => {
+                eprintln!("Anomalous sessions detected");
+                exit_code = ERROR_CODE_MISMATCH;
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("Error checking anomalous session status: {}", e),
+        }
+    }
+    exit_code
+}
+
 pub fn background_display_sessions(
     sessions: Vec<SessionInfoAPI>,
     zeek_format: bool,
@@ -141,12 +347,9 @@ pub fn background_display_sessions(
         filtered_sessions = filter_global_sessions(filtered_sessions);
     }
 
-    // Format the connections and display them
-    let formatted_sessions = if zeek_format {
-        format_sessions_zeek(filtered_sessions)
-    } else {
-        format_sessions_log(filtered_sessions)
-    };
+    // Format the connections and display them, with their labels
+    let formatted_sessions =
+        format_labeled_sessions(filtered_sessions, zeek_format, display_labels().as_ref());
 
     // Display the sessions
     for session in formatted_sessions.iter() {
@@ -154,6 +357,58 @@ pub fn background_display_sessions(
     }
 }
 
+pub fn background_sessions_snapshot_save(file: &str, local_traffic: bool) -> i32 {
+    let sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+
+    let sessions = if local_traffic {
+        sessions
+    } else {
+        filter_global_sessions(sessions)
+    };
+
+    let labels = match load_labels() {
+        Ok(labels) => labels,
+        Err(exit_code) => return exit_code,
+    };
+    let mut values: Vec<serde_json::Value> = match sessions
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+    {
+        Ok(values) => values,
+        Err(e) => {
+            eprintln!("Error serializing sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if let Some(labels) = &labels {
+        values.iter_mut().for_each(|value| labels.apply(value));
+    }
+
+    match crate::sessions::write_snapshot(file, values) {
+        Ok(count) => {
+            println!("Saved {} sessions to {}", count, file);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ERROR_CODE_PARAM
+        }
+    }
+}
+
 pub fn background_get_exceptions(zeek_format: bool, local_traffic: bool) -> i32 {
     let sessions = match rpc_get_lan_sessions(
         true,
@@ -175,6 +430,95 @@ pub fn background_get_exceptions(zeek_format: bool, local_traffic: bool) -> i32
     return 0;
 }
 
+// Explain the whitelist verdict of a single session
+pub fn background_explain_session(uid: &str, limit: usize, json_output: bool) -> i32 {
+    let sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    let geoip = match load_geoip() {
+        Ok(geoip) => geoip,
+        Err(exit_code) => return exit_code,
+    };
+    let mut session = match sessions.iter().find(|session| session.uid == uid) {
+        Some(session) => match serde_json::to_value(session) {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("Error serializing session: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        },
+        None => {
+            eprintln!("Session {} not found", uid);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    if let Some(labels) = display_labels() {
+        labels.apply(&mut session);
+    }
+
+    let whitelist_name = match rpc_get_whitelist_name(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(name) => name,
+        Err(e) => {
+            eprintln!("Error getting whitelist name: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if whitelist_name.is_empty() {
+        eprintln!("No whitelist is active");
+        return ERROR_CODE_PARAM;
+    }
+    let whitelists = match rpc_get_whitelists(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(whitelists) => whitelists,
+        Err(e) => {
+            eprintln!("Error getting whitelists: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+
+    // Explained with the AS information of the core, which decided the state
+    let mut report =
+        crate::whitelists::explain_session(&session, &whitelists, &whitelist_name, limit);
+    if let Some(geoip) = &geoip {
+        geoip.enrich(&mut session);
+        report["geoip"] = serde_json::json!({
+            "dst_country": session.get("dst_country"),
+            "dst_asn": session.get("dst_asn"),
+        });
+    }
+    if json_output {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error formatting explanation: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        }
+    } else {
+        crate::whitelists::print_session_explanation(&report);
+    }
+    0
+}
+
 pub fn background_get_threats_info() -> i32 {
     match rpc_get_score(
         false,
@@ -340,7 +684,9 @@ pub fn background_wait_for_connection(timeout: u64) -> i32 {
     // They are retried within the timeout budget here; every other backend error
     // code stays fast-fail.
     while timeout > 0 {
-        sleep(Duration::from_secs(5));
+        if let Err(interrupted) = wait::pause(Duration::from_secs(5)) {
+            return wait::report("Connection wait", interrupted);
+        }
         timeout = timeout.saturating_sub(5);
 
         connection_status = match rpc_get_connection(
@@ -567,7 +913,34 @@ pub fn background_create_custom_whitelists_with_process() -> i32 {
     }
 }
 
-pub fn background_set_custom_whitelists(whitelist_json: String) -> i32 {
+/// Set custom whitelists on the background process. `signature_file` is the
+/// detached signature of the whitelist file, checked against the list signing
+/// policy.
+pub fn background_set_custom_whitelists(
+    whitelist_json: String,
+    signature_file: Option<&str>,
+) -> i32 {
+    if let Err(msg) = crate::whitelists::check_custom_whitelists(&whitelist_json, "Whitelist") {
+        eprintln!("Error setting custom whitelists: {msg}");
+        return ERROR_CODE_PARAM;
+    }
+    let signer =
+        match crate::signing::check_list_signature(&whitelist_json, signature_file, "Whitelist") {
+            Ok(signer) => signer,
+            Err(msg) => {
+                eprintln!("Error setting custom whitelists: {msg}");
+                return ERROR_CODE_PARAM;
+            }
+        };
+    // The daemon checks the signature again on the list it stores
+    let whitelist_json = match crate::signing::embed_signature(&whitelist_json, signature_file) {
+        Ok(json) => json,
+        Err(msg) => {
+            eprintln!("Error setting custom whitelists: {msg}");
+            return ERROR_CODE_PARAM;
+        }
+    };
+
     match rpc_set_custom_whitelists(
         whitelist_json,
         &EDAMAME_CA_PEM,
@@ -575,7 +948,10 @@ pub fn background_set_custom_whitelists(whitelist_json: String) -> i32 {
         &EDAMAME_CLIENT_KEY,
         &EDAMAME_TARGET,
     ) {
-        Ok(_) => 0,
+        Ok(_) => {
+            crate::signing::log_list_signer("whitelists", &signer);
+            0
+        }
         Err(e) => {
             eprintln!("Error setting custom whitelists: {}", e);
             ERROR_CODE_SERVER_ERROR
@@ -588,7 +964,7 @@ pub fn background_create_and_set_custom_whitelists() -> i32 {
     if exit_code != 0 {
         return exit_code;
     }
-    background_set_custom_whitelists(whitelist_json)
+    background_set_custom_whitelists(whitelist_json, None)
 }
 
 pub fn background_get_score() -> i32 {
@@ -644,11 +1020,8 @@ pub fn background_get_anomalous_sessions(zeek_format: bool) -> i32 {
     }
 
     // Format and display sessions
-    let formatted_sessions = if zeek_format {
-        format_sessions_zeek(anomalous_sessions)
-    } else {
-        format_sessions_log(anomalous_sessions)
-    };
+    let formatted_sessions =
+        format_labeled_sessions(anomalous_sessions, zeek_format, display_labels().as_ref());
     for session in formatted_sessions.iter() {
         println!("{}", session);
     }
@@ -677,11 +1050,8 @@ pub fn background_get_blacklisted_sessions(zeek_format: bool) -> i32 {
     }
 
     // Format and display sessions
-    let formatted_sessions = if zeek_format {
-        format_sessions_zeek(blacklisted_sessions)
-    } else {
-        format_sessions_log(blacklisted_sessions)
-    };
+    let formatted_sessions =
+        format_labeled_sessions(blacklisted_sessions, zeek_format, display_labels().as_ref());
     for session in formatted_sessions.iter() {
         println!("{}", session);
     }
@@ -689,7 +1059,7 @@ pub fn background_get_blacklisted_sessions(zeek_format: bool) -> i32 {
     return 0; // Always return 0 on success, even if sessions are found
 }
 
-fn validate_custom_blacklists_json(blacklist_json: &str) -> Result<(), String> {
+pub fn validate_custom_blacklists_json(blacklist_json: &str) -> Result<(), String> {
     // Empty JSON is a supported "reset to default" signal.
     if blacklist_json.trim().is_empty() {
         return Ok(());
@@ -702,11 +1072,30 @@ fn validate_custom_blacklists_json(blacklist_json: &str) -> Result<(), String> {
         .map_err(|e| format!("Invalid custom blacklist JSON (schema mismatch): {e}"))
 }
 
-pub fn background_set_custom_blacklists(blacklist_json: String) -> i32 {
+pub fn background_set_custom_blacklists(
+    blacklist_json: String,
+    signature_file: Option<&str>,
+) -> i32 {
     if let Err(msg) = validate_custom_blacklists_json(&blacklist_json) {
         eprintln!("Error setting custom blacklists: {msg}");
         return ERROR_CODE_PARAM;
     }
+    let signer =
+        match crate::signing::check_list_signature(&blacklist_json, signature_file, "Blacklist") {
+            Ok(signer) => signer,
+            Err(msg) => {
+                eprintln!("Error setting custom blacklists: {msg}");
+                return ERROR_CODE_PARAM;
+            }
+        };
+    // The daemon checks the signature again on the list it stores
+    let blacklist_json = match crate::signing::embed_signature(&blacklist_json, signature_file) {
+        Ok(json) => json,
+        Err(msg) => {
+            eprintln!("Error setting custom blacklists: {msg}");
+            return ERROR_CODE_PARAM;
+        }
+    };
 
     match rpc_set_custom_blacklists(
         blacklist_json,
@@ -717,6 +1106,7 @@ pub fn background_set_custom_blacklists(blacklist_json: String) -> i32 {
     ) {
         Ok(_) => {
             println!("Custom blacklists set successfully.");
+            crate::signing::log_list_signer("blacklists", &signer);
             0
         }
         Err(e) => {
@@ -726,6 +1116,79 @@ pub fn background_set_custom_blacklists(blacklist_json: String) -> i32 {
     }
 }
 
+/// Convert a threat-intel feed into a custom blacklist. The result is written
+/// to `output` (stdout by default), or merged into the blacklists currently
+/// set on the background process.
+pub fn background_import_blacklist(
+    options: &crate::blacklists::BlacklistImport,
+    output: Option<&str>,
+    merge: bool,
+) -> i32 {
+    let entry = match crate::blacklists::import_blacklist_entry(options) {
+        Ok(entry) => entry,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+
+    let document = if merge {
+        let current = match rpc_get_blacklists(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ) {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("Error getting blacklists: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        };
+        match crate::blacklists::merge_blacklist_entry(&current, entry) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("Error merging imported blacklist: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        }
+    } else {
+        crate::blacklists::blacklist_document(vec![entry])
+    };
+
+    let blacklist_json = match serde_json::to_string_pretty(&document) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Error formatting blacklist JSON: {}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    if let Err(msg) = validate_custom_blacklists_json(&blacklist_json) {
+        eprintln!("Error importing blacklist: {msg}");
+        return ERROR_CODE_PARAM;
+    }
+
+    if merge {
+        return background_set_custom_blacklists(blacklist_json, None);
+    }
+    match output {
+        Some(path) => match std::fs::write(path, blacklist_json + "\n") {
+            Ok(_) => {
+                eprintln!("Blacklist written to {}", path);
+                0
+            }
+            Err(e) => {
+                eprintln!("Error writing blacklist file '{}': {}", path, e);
+                ERROR_CODE_PARAM
+            }
+        },
+        None => {
+            println!("{}", blacklist_json);
+            0
+        }
+    }
+}
+
 // Function to retrieve blacklists
 pub fn background_get_blacklists() -> i32 {
     match rpc_get_blacklists(
@@ -853,6 +1316,168 @@ pub fn background_augment_custom_whitelists() -> i32 {
     }
 }
 
+/// Learn a custom whitelist by augmenting it periodically until it converges.
+/// Each round merges the augmented whitelist into the learned one; the round's
+/// change is the percentage difference between consecutive learned whitelists.
+/// Convergence is reached once the change stays below `converge_below` for
+/// `stable_rounds` consecutive rounds.
+pub fn background_learn_whitelist(
+    out_file: &str,
+    report_file: &str,
+    converge_below: f64,
+    stable_rounds: u32,
+    interval_secs: u64,
+    max_duration_secs: u64,
+    apply: bool,
+) -> i32 {
+    let start = std::time::Instant::now();
+    let mut learned: Option<String> = None;
+    let mut rounds: Vec<serde_json::Value> = Vec::new();
+    let mut stable = 0u32;
+
+    eprintln!(
+        "Learning whitelist into {} (converge below {:.2}% for {} rounds, interval {}s, max {}s)",
+        out_file, converge_below, stable_rounds, interval_secs, max_duration_secs
+    );
+
+    // Ctrl-C and the global --timeout stop the wait between two rounds
+    let outcome: Result<bool, wait::Interrupted> = loop {
+        let (augmented, augment_percent) = match rpc_augment_custom_whitelists_info(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Error augmenting custom whitelists: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        };
+        if augmented.is_empty() {
+            eprintln!("Failed to augment custom whitelists");
+            return ERROR_CODE_SERVER_ERROR;
+        }
+
+        // Sessions age out of the daemon, so accumulate instead of replacing
+        let (next, percent_changed) = match &learned {
+            None => (augmented, augment_percent as f64),
+            Some(previous) => {
+                let merged = merge_custom_whitelists(previous.clone(), augmented);
+                if merged.is_empty() {
+                    eprintln!("Failed to merge augmented whitelist");
+                    return ERROR_CODE_SERVER_ERROR;
+                }
+                let change = compare_custom_whitelists(previous.clone(), merged.clone()) as f64;
+                (merged, change)
+            }
+        };
+
+        stable = if percent_changed < converge_below {
+            stable + 1
+        } else {
+            0
+        };
+        let (whitelists, _) = crate::whitelists::parse_whitelists(&next);
+        let endpoints: usize = whitelists.iter().map(|w| w.endpoints.len()).sum();
+        let elapsed = start.elapsed().as_secs();
+        eprintln!(
+            "Round {}: {:.2}% changed, {} endpoints, {}/{} stable rounds ({}s elapsed)",
+            rounds.len() + 1,
+            percent_changed,
+            endpoints,
+            stable,
+            stable_rounds,
+            elapsed
+        );
+        rounds.push(serde_json::json!({
+            "round": rounds.len() + 1,
+            "elapsed_secs": elapsed,
+            "percent_changed": percent_changed,
+            "endpoints": endpoints,
+            "stable_rounds": stable,
+        }));
+
+        // Persist progress every round so an interrupted run keeps its result
+        let pretty = match serde_json::from_str::<serde_json::Value>(&next)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+        {
+            Ok(pretty) => pretty,
+            Err(e) => {
+                eprintln!("Error parsing learned whitelist JSON: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        };
+        if let Err(e) = std::fs::write(out_file, pretty) {
+            eprintln!("Error writing whitelist file '{}': {}", out_file, e);
+            return ERROR_CODE_PARAM;
+        }
+        learned = Some(next);
+
+        if stable >= stable_rounds {
+            break Ok(true);
+        }
+        if start.elapsed().as_secs() + interval_secs > max_duration_secs {
+            break Ok(false);
+        }
+        if let Err(interrupted) = wait::pause(Duration::from_secs(interval_secs)) {
+            break Err(interrupted);
+        }
+    };
+
+    let report = serde_json::json!({
+        "whitelist_file": out_file,
+        "converged": outcome == Ok(true),
+        "interrupted": outcome.is_err(),
+        "converge_below_percent": converge_below,
+        "required_stable_rounds": stable_rounds,
+        "interval_secs": interval_secs,
+        "duration_secs": start.elapsed().as_secs(),
+        "rounds": rounds,
+    });
+    match serde_json::to_string_pretty(&report) {
+        Ok(report) => {
+            if let Err(e) = std::fs::write(report_file, report) {
+                eprintln!("Error writing report file '{}': {}", report_file, e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+        Err(e) => {
+            eprintln!("Error formatting convergence report: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    }
+
+    let converged = match outcome {
+        Ok(converged) => converged,
+        Err(interrupted) => {
+            eprintln!("Partial result written to {}", out_file);
+            return wait::report("Whitelist learning", interrupted);
+        }
+    };
+    if !converged {
+        eprintln!(
+            "Whitelist did not converge within {}s, partial result written to {}",
+            max_duration_secs, out_file
+        );
+        if apply {
+            eprintln!("Not applying a whitelist that did not converge");
+        }
+        return ERROR_CODE_TIMEOUT;
+    }
+
+    println!(
+        "Whitelist converged after {} rounds, written to {} (report: {})",
+        rounds.len(),
+        out_file,
+        report_file
+    );
+    match learned {
+        Some(learned) if apply => background_set_custom_whitelists(learned, None),
+        _ => 0,
+    }
+}
+
 /// Configure notification channels for the EDAMAME Portal provider path.
 /// The EDAMAME API key is already set; this merges Slack/Telegram one-way
 /// notification fields into the existing config by calling
@@ -1721,7 +2346,11 @@ pub fn background_divergence_get_history(limit: usize) -> i32 {
     }
 }
 
-pub fn background_divergence_dismiss(finding_key: String) -> i32 {
+pub fn background_divergence_dismiss(
+    finding_key: String,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if finding_key.trim().is_empty() {
         eprintln!("Finding key cannot be empty");
         return ERROR_CODE_PARAM;
@@ -1743,7 +2372,10 @@ pub fn background_divergence_dismiss(finding_key: String) -> i32 {
             };
             if json["success"].as_bool().unwrap_or(false) {
                 println!("Divergence evidence dismissed.");
-                0
+                record_dismissal(
+                    DismissRule::new(DismissKind::Divergence, finding_key.trim(), None, "cli")
+                        .with_terms(expires_at, reason),
+                )
             } else {
                 eprintln!(
                     "Failed to dismiss divergence evidence: {}",
@@ -1920,7 +2552,12 @@ pub fn background_vulnerability_stop() -> i32 {
 /// Exit codes:
 ///   0 -- printed report (zero or more findings)
 ///   ERROR_CODE_SERVER_ERROR -- RPC failed or response was unparseable
-pub fn background_vulnerability_findings(active_only: bool) -> i32 {
+pub fn background_vulnerability_findings(active_only: bool, step: Option<&str>) -> i32 {
+    let all_steps = match step.map(steps::steps_for_filter) {
+        Some(Ok(all_steps)) => all_steps,
+        Some(Err(exit_code)) => return exit_code,
+        None => Vec::new(),
+    };
     match rpc_get_vulnerability_findings(
         &EDAMAME_CA_PEM,
         &EDAMAME_CLIENT_PEM,
@@ -1953,6 +2590,13 @@ pub fn background_vulnerability_findings(active_only: bool) -> i32 {
                     });
                 }
             }
+            if let Some(name) = step {
+                if let Some(findings) = report.get_mut("findings").and_then(|f| f.as_array_mut()) {
+                    findings.retain(|finding| {
+                        steps::item_step(&all_steps, finding, steps::FINDING_TIME) == Some(name)
+                    });
+                }
+            }
 
             match serde_json::to_string_pretty(&report) {
                 Ok(pretty) => println!("{}", pretty),
@@ -2130,7 +2774,11 @@ pub fn background_vulnerability_debug_trace(report_id: Option<String>) -> i32 {
     }
 }
 
-pub fn background_vulnerability_dismiss(finding_key: String) -> i32 {
+pub fn background_vulnerability_dismiss(
+    finding_key: String,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if finding_key.trim().is_empty() {
         eprintln!("Finding key cannot be empty");
         return ERROR_CODE_PARAM;
@@ -2156,7 +2804,10 @@ pub fn background_vulnerability_dismiss(finding_key: String) -> i32 {
                 } else {
                     println!("No matching finding found or already dismissed.");
                 }
-                0
+                record_dismissal(
+                    DismissRule::new(DismissKind::Vulnerability, finding_key.trim(), None, "cli")
+                        .with_terms(expires_at, reason),
+                )
             } else {
                 eprintln!(
                     "Failed to dismiss vulnerability finding: {}",
@@ -2269,7 +2920,11 @@ pub fn background_clear_vulnerability_history() -> i32 {
     }
 }
 
-pub fn background_agentic_dismiss_with_scope(request_json: String) -> i32 {
+pub fn background_agentic_dismiss_with_scope(
+    request_json: String,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if request_json.trim().is_empty() {
         eprintln!("Dismiss-with-scope request JSON cannot be empty");
         return ERROR_CODE_PARAM;
@@ -2290,11 +2945,19 @@ pub fn background_agentic_dismiss_with_scope(request_json: String) -> i32 {
                 }
             };
             if json["success"].as_bool().unwrap_or(false) {
-                println!(
-                    "Dismissal rule created: {}",
-                    json["rule_id"].as_str().unwrap_or("unknown")
-                );
-                0
+                let rule_id = json["rule_id"].as_str();
+                println!("Dismissal rule created: {}", rule_id.unwrap_or("unknown"));
+                match rule_id {
+                    Some(rule_id) => record_dismissal(
+                        DismissRule::new(DismissKind::ScopeRule, rule_id, None, "cli")
+                            .with_terms(expires_at, reason),
+                    ),
+                    None if expires_at.is_some() => {
+                        eprintln!("No rule id returned, the expiry will not be enforced");
+                        ERROR_CODE_SERVER_ERROR
+                    }
+                    None => 0,
+                }
             } else {
                 eprintln!(
                     "Failed to create dismissal rule: {}",
@@ -2345,12 +3008,305 @@ pub fn background_agentic_list_dismissal_rules(domain: Option<String>) -> i32 {
                     return ERROR_CODE_SERVER_ERROR;
                 }
             }
-            0
+            0
+        }
+        Err(e) => {
+            eprintln!("Error listing dismissal rules: {}", e);
+            ERROR_CODE_SERVER_ERROR
+        }
+    }
+}
+
+// Revoke the recorded dismissals whose expiry has passed, so the devices,
+// ports, sessions and findings are reported again. Called periodically by the
+// daemon, which hosts the core the network dismissals were applied to.
+pub fn background_expire_dismissals() {
+    let path = registry_path();
+    let rules = match load_rules(&path) {
+        Ok(rules) => rules,
+        Err(e) => {
+            warn!("Cannot check dismissal expiry: {}", e);
+            return;
+        }
+    };
+    let now = now_secs();
+    let mut revoked = Vec::new();
+    for rule in rules.iter().filter(|r| r.is_expired(now)) {
+        let target = rule.target.clone();
+        let result = match rule.kind {
+            DismissKind::Vulnerability => rpc_undismiss_vulnerability_finding(
+                target,
+                &EDAMAME_CA_PEM,
+                &EDAMAME_CLIENT_PEM,
+                &EDAMAME_CLIENT_KEY,
+                &EDAMAME_TARGET,
+            ),
+            DismissKind::Divergence => rpc_undismiss_divergence_evidence(
+                target,
+                &EDAMAME_CA_PEM,
+                &EDAMAME_CLIENT_PEM,
+                &EDAMAME_CLIENT_KEY,
+                &EDAMAME_TARGET,
+            ),
+            DismissKind::ScopeRule => rpc_agentic_remove_dismissal_rule(
+                target,
+                &EDAMAME_CA_PEM,
+                &EDAMAME_CLIENT_PEM,
+                &EDAMAME_CLIENT_KEY,
+                &EDAMAME_TARGET,
+            ),
+            _ => base_undismiss(rule).map(|_| serde_json::json!({ "success": true }).to_string()),
+        };
+        match result.map(|r| serde_json::from_str::<serde_json::Value>(&r)) {
+            Ok(Ok(json)) if json["success"].as_bool().unwrap_or(false) => {
+                info!(
+                    "Dismissal {} ({}) expired and was revoked",
+                    rule.id,
+                    rule.describe()
+                );
+                revoked.push(rule.id.clone());
+            }
+            Ok(Ok(json)) => warn!(
+                "Failed to revoke expired dismissal {}: {}",
+                rule.id,
+                json["error"].as_str().unwrap_or("Unknown")
+            ),
+            Ok(Err(e)) => warn!("Failed to revoke expired dismissal {}: {}", rule.id, e),
+            Err(e) => warn!("Failed to revoke expired dismissal {}: {}", rule.id, e),
+        }
+    }
+    if !revoked.is_empty() {
+        if let Err(e) = remove_rules_at(&path, &revoked) {
+            warn!(
+                "Expired dismissals revoked but not removed from the registry: {}",
+                e
+            );
+        }
+    }
+}
+
+// Current findings, evidence, sessions and file events, fetching only what
+// the suppression entries can match
+fn collect_suppression_observations(entries: &[Suppression]) -> Result<Observations, String> {
+    let needs = |kind: &str| entries.iter().any(|e| e.matcher.kind() == kind);
+    let mut observations = Observations::default();
+    if needs("vulnerability") {
+        let report = rpc_get_vulnerability_findings(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting vulnerability findings: {}", e))?;
+        let report: serde_json::Value = serde_json::from_str(&report)
+            .map_err(|e| format!("Error parsing vulnerability findings: {}", e))?;
+        observations.findings = keyed_items(&report["findings"]);
+    }
+    if needs("divergence") {
+        let verdict = rpc_get_divergence_verdict(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting divergence verdict: {}", e))?;
+        let verdict: serde_json::Value = serde_json::from_str(&verdict)
+            .map_err(|e| format!("Error parsing divergence verdict: {}", e))?;
+        observations.divergence = keyed_items(&verdict);
+    }
+    if needs("session") {
+        let sessions = rpc_get_lan_sessions(
+            true,
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting LAN sessions: {}", e))?;
+        observations.sessions = sessions
+            .sessions
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Error serializing sessions: {}", e))?;
+        if entries
+            .iter()
+            .any(|entry| matches!(&entry.matcher, Matcher::Session { label: Some(_), .. }))
+        {
+            // Entries matching labels need the label file the daemon was started with
+            let labels = LabelRules::from_env()?.ok_or_else(|| {
+                format!(
+                    "session suppressions match labels but {} is not set",
+                    crate::labels::LABELS_ENV
+                )
+            })?;
+            observations
+                .sessions
+                .iter_mut()
+                .for_each(|session| labels.apply(session));
+        }
+    }
+    if needs("file") {
+        let snapshot = rpc_get_file_events(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting file events: {}", e))?;
+        observations.file_paths = snapshot.events.iter().map(|e| e.path.clone()).collect();
+    }
+    Ok(observations)
+}
+
+pub fn background_load_suppressions() -> Option<ActiveSuppressions> {
+    let active =
+        std::env::var(SUPPRESSIONS_ENV)
+            .ok()
+            .and_then(|path| match load_suppressions(&path) {
+                Ok(entries) => {
+                    info!("Applying {} suppressions from {}", entries.len(), path);
+                    Some(ActiveSuppressions::new(&path, entries))
+                }
+                Err(e) => {
+                    warn!("Suppressions not applied: {}", e);
+                    None
+                }
+            });
+    // get-file-events honors the file entries of the daemon only
+    if let Err(e) = publish_file_entries(DAEMON_FILE_ENTRIES, active.as_ref()) {
+        warn!("File suppressions not published: {}", e);
+    }
+    active
+}
+
+fn dismiss_suppressed(matcher: &Matcher, id: &str) -> Result<(), String> {
+    let result = match matcher {
+        Matcher::Vulnerability { .. } => rpc_dismiss_vulnerability_finding(
+            id.to_string(),
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ),
+        Matcher::Divergence { .. } => rpc_dismiss_divergence_evidence(
+            id.to_string(),
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ),
+        Matcher::Session { .. } => {
+            add_dismiss_rule_from_session(id.to_string());
+            return Ok(());
+        }
+        // File events have no dismiss API, get-file-events honors the entries
+        Matcher::File { .. } => return Ok(()),
+    };
+    let json: serde_json::Value =
+        serde_json::from_str(&result.map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    if json["success"].as_bool().unwrap_or(false) {
+        Ok(())
+    } else {
+        Err(json["error"].as_str().unwrap_or("Unknown").to_string())
+    }
+}
+
+/// Dismiss what the suppression entries match and log the entries that stopped
+/// matching anything. Called periodically by the daemon.
+pub fn background_apply_suppressions(active: &mut ActiveSuppressions) {
+    let observations = match collect_suppression_observations(&active.entries) {
+        Ok(observations) => observations,
+        Err(e) => {
+            warn!("Cannot apply suppressions: {}", e);
+            return;
+        }
+    };
+    let hits: Vec<Vec<Hit>> = active
+        .entries
+        .iter()
+        .map(|entry| entry.hits(&observations))
+        .collect();
+    for (i, entry) in active.entries.iter().enumerate() {
+        for hit in hits[i].iter().filter(|hit| !hit.dismissed) {
+            let applied_key = format!("{}:{}", entry.matcher.kind(), hit.id);
+            if active.applied.contains(&applied_key) {
+                continue;
+            }
+            match dismiss_suppressed(&entry.matcher, &hit.id) {
+                Ok(()) => {
+                    info!(
+                        "Suppressed {} {} (entry {}, owner {}): {}",
+                        entry.matcher.kind(),
+                        hit.id,
+                        i,
+                        entry.owner,
+                        entry.justification
+                    );
+                    active.applied.insert(applied_key);
+                }
+                Err(e) => warn!(
+                    "Failed to suppress {} {}: {}",
+                    entry.matcher.kind(),
+                    hit.id,
+                    e
+                ),
+            }
+        }
+    }
+    for i in active.update_matches(&hits) {
+        let entry = &active.entries[i];
+        warn!(
+            "Suppression entry {} ({}, owner {}) in {} no longer matches anything",
+            i,
+            entry.matcher.describe(),
+            entry.owner,
+            active.path
+        );
+    }
+}
+
+/// Report which entries of a suppression file match what the daemon currently
+/// sees. Exits with ERROR_CODE_MISMATCH when some entries match nothing.
+pub fn background_check_suppressions(file: Option<String>, json_output: bool) -> i32 {
+    let Some(path) = resolve_path(file.as_deref()) else {
+        eprintln!(
+            "No suppression file given and no {} in the working directory",
+            DEFAULT_SUPPRESSIONS_FILE
+        );
+        return ERROR_CODE_PARAM;
+    };
+    let entries = match load_suppressions(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
         }
+    };
+    let observations = match collect_suppression_observations(&entries) {
+        Ok(observations) => observations,
         Err(e) => {
-            eprintln!("Error listing dismissal rules: {}", e);
-            ERROR_CODE_SERVER_ERROR
+            eprintln!("{}", e);
+            return ERROR_CODE_SERVER_ERROR;
         }
+    };
+    let hits: Vec<Vec<Hit>> = entries.iter().map(|e| e.hits(&observations)).collect();
+    let report = suppression_report(&path, &entries, &hits);
+    if json_output {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error formatting suppression report: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        }
+    } else {
+        print_suppression_report(&report);
+    }
+    if report["stale"].as_array().is_some_and(|s| !s.is_empty()) {
+        ERROR_CODE_MISMATCH
+    } else {
+        0
     }
 }
 
@@ -3308,8 +4264,13 @@ pub fn background_file_monitor_status() -> i32 {
     }
 }
 
-pub fn background_get_file_events(fail_on_suspicious: bool) -> i32 {
-    let snapshot = match rpc_get_file_events(
+pub fn background_get_file_events(fail_on_suspicious: bool, step: Option<&str>) -> i32 {
+    let all_steps = match step.map(steps::steps_for_filter) {
+        Some(Ok(all_steps)) => all_steps,
+        Some(Err(exit_code)) => return exit_code,
+        None => Vec::new(),
+    };
+    let mut snapshot = match rpc_get_file_events(
         &EDAMAME_CA_PEM,
         &EDAMAME_CLIENT_PEM,
         &EDAMAME_CLIENT_KEY,
@@ -3321,6 +4282,35 @@ pub fn background_get_file_events(fail_on_suspicious: bool) -> i32 {
             return ERROR_CODE_SERVER_ERROR;
         }
     };
+    // Counts and the suspicious status then describe the events of the step
+    let (mut event_count, mut sensitive_count) = (
+        snapshot.event_count.to_string(),
+        snapshot.sensitive_events.len(),
+    );
+    if let Some(name) = step {
+        snapshot
+            .events
+            .retain(|event| in_step(event, name, &all_steps, steps::FILE_EVENT_TIME));
+        event_count = snapshot.events.len().to_string();
+        sensitive_count = snapshot.events.iter().filter(|e| e.is_sensitive).count();
+        snapshot.has_suspicious_events &= sensitive_count > 0;
+    }
+
+    // File entries of the daemon mark the events they cover. A suppression file
+    // of the caller (working directory or environment) is not consulted, so
+    // that it cannot change the verdict of --fail-on-suspicious.
+    let suppressed_paths = match published_file_entries(DAEMON_FILE_ENTRIES) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    let is_suppressed = |path: &str| {
+        suppressed_paths
+            .iter()
+            .any(|pattern| wildcard_matches(pattern, path))
+    };
 
     for event in &snapshot.events {
         let sensitivity = if event.is_sensitive { "sensitive" } else { "" };
@@ -3336,7 +4326,7 @@ pub fn background_get_file_events(fail_on_suspicious: bool) -> i32 {
             .unwrap_or_default();
 
         println!(
-            "[{}] {} {}{}{}{}",
+            "[{}] {} {}{}{}{}{}",
             event.timestamp,
             event.event_type,
             event.path,
@@ -3346,18 +4336,34 @@ pub fn background_get_file_events(fail_on_suspicious: bool) -> i32 {
             } else {
                 String::new()
             },
-            process
+            process,
+            if is_suppressed(&event.path) {
+                " [suppressed]"
+            } else {
+                ""
+            }
         );
     }
 
     println!(
         "\nTotal events: {}, Sensitive: {}, Monitoring: {}",
-        snapshot.event_count,
-        snapshot.sensitive_events.len(),
-        snapshot.is_monitoring
+        event_count, sensitive_count, snapshot.is_monitoring
     );
 
+    // Suspicious activity is only excused when every sensitive event is suppressed
+    let sensitive_paths: Vec<&str> = snapshot
+        .events
+        .iter()
+        .filter(|event| event.is_sensitive)
+        .map(|event| event.path.as_str())
+        .collect();
+    let all_suppressed =
+        !sensitive_paths.is_empty() && sensitive_paths.iter().all(|path| is_suppressed(path));
     if fail_on_suspicious && snapshot.has_suspicious_events {
+        if all_suppressed {
+            println!("Suspicious file events are covered by the suppressions of the daemon");
+            return 0;
+        }
         eprintln!("Suspicious file events detected");
         return ERROR_CODE_MISMATCH;
     }
@@ -3365,6 +4371,155 @@ pub fn background_get_file_events(fail_on_suspicious: bool) -> i32 {
     0
 }
 
+/// Sessions and findings of the background process that fail the enabled
+/// checks, evaluated as `policy_violations` does for an in-process capture.
+pub fn collect_policy_violations(
+    checks: &PolicyChecks,
+    labels: Option<&LabelRules>,
+) -> Result<PolicyViolations, String> {
+    let mut sessions = Vec::new();
+    if checks.whitelist {
+        let conforms = rpc_get_whitelist_conformance(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting whitelist conformance: {}", e))?;
+        if !conforms {
+            sessions = rpc_get_lan_sessions(
+                true,
+                &EDAMAME_CA_PEM,
+                &EDAMAME_CLIENT_PEM,
+                &EDAMAME_CLIENT_KEY,
+                &EDAMAME_TARGET,
+            )
+            .map_err(|e| format!("Error retrieving LAN sessions: {}", e))?
+            .sessions;
+        }
+    }
+    let blacklisted = if checks.blacklist {
+        rpc_get_blacklisted_sessions(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error retrieving blacklisted sessions: {}", e))?
+    } else {
+        Vec::new()
+    };
+    let anomalous = if checks.anomalous {
+        rpc_get_anomalous_sessions(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error retrieving anomalous sessions: {}", e))?
+    } else {
+        Vec::new()
+    };
+
+    let mut violations = policy_violations(checks, sessions, blacklisted, anomalous, labels);
+    if checks.findings {
+        let status = rpc_get_vulnerability_detector_status(
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        )
+        .map_err(|e| format!("Error getting vulnerability detector status: {}", e))?;
+        (
+            violations.vulnerability_findings,
+            violations.vulnerability_label,
+        ) = alertable_findings(&status)?;
+    }
+    Ok(violations)
+}
+
+/// Run a command while the background process captures, and report the
+/// sessions and file events of its process tree.
+pub fn background_run(options: &crate::run::RunOptions) -> i32 {
+    let labels = match load_labels() {
+        Ok(labels) => labels,
+        Err(exit_code) => return exit_code,
+    };
+    if !options.whitelist_name.is_empty() {
+        if let Err(e) = rpc_set_whitelist(
+            options.whitelist_name.clone(),
+            &EDAMAME_CA_PEM,
+            &EDAMAME_CLIENT_PEM,
+            &EDAMAME_CLIENT_KEY,
+            &EDAMAME_TARGET,
+        ) {
+            eprintln!("Error setting whitelist: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+        println!(
+            "Whitelist of the background process set to {}",
+            options.whitelist_name
+        );
+    }
+    let tracked = match crate::run::run_tracked(&options.command) {
+        Ok(tracked) => tracked,
+        Err(exit_code) => return exit_code,
+    };
+    // Leave the capture time to account for the last sessions of the command
+    let _ = wait::pause(RUN_SETTLE_TIME);
+
+    let mut sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if !options.local_traffic {
+        sessions = filter_global_sessions(sessions);
+    }
+    let violations = match collect_policy_violations(&run_checks(options), labels.as_ref()) {
+        Ok(violations) => violations,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    // File monitoring is optional, the report goes without it when unavailable
+    let file_events = match rpc_get_file_events(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(snapshot) => Some(
+            snapshot
+                .events
+                .iter()
+                .filter_map(|event| serde_json::to_value(event).ok())
+                .collect(),
+        ),
+        Err(e) => {
+            warn!("File events unavailable for the run report: {}", e);
+            None
+        }
+    };
+
+    crate::run::conclude(
+        options,
+        &tracked,
+        labeled_session_values(&sessions, labels.as_ref()),
+        &violations.by_uid(),
+        file_events,
+    )
+}
+
 pub fn background_clear_file_events() -> i32 {
     match rpc_clear_file_events(
         &EDAMAME_CA_PEM,
@@ -3382,3 +4537,296 @@ pub fn background_clear_file_events() -> i32 {
         }
     }
 }
+
+/// Egress and violations broken down by the steps recorded with
+/// `background-mark-step`.
+pub fn background_step_report(local_traffic: bool, json_output: bool) -> i32 {
+    let all_steps = match steps::load_steps(&steps::registry_path()) {
+        Ok(all_steps) => all_steps,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let mut sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if !local_traffic {
+        sessions = filter_global_sessions(sessions);
+    }
+    let blacklisted: HashSet<String> = match rpc_get_blacklisted_sessions(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(blacklisted) => blacklisted.into_iter().map(|session| session.uid).collect(),
+        Err(e) => {
+            eprintln!("Error getting blacklisted sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    // File monitoring and the attack pattern detector are optional
+    let file_events: Vec<serde_json::Value> = match rpc_get_file_events(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(snapshot) => snapshot
+            .events
+            .iter()
+            .filter_map(|event| serde_json::to_value(event).ok())
+            .collect(),
+        Err(e) => {
+            warn!("File events unavailable for the step report: {}", e);
+            Vec::new()
+        }
+    };
+    let findings: Vec<serde_json::Value> = match rpc_get_vulnerability_findings(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(result) => serde_json::from_str::<serde_json::Value>(&result)
+            .ok()
+            .and_then(|report| report.get("findings").and_then(|f| f.as_array()).cloned())
+            .unwrap_or_default(),
+        Err(e) => {
+            warn!(
+                "Vulnerability findings unavailable for the step report: {}",
+                e
+            );
+            Vec::new()
+        }
+    };
+
+    let sessions: Vec<serde_json::Value> = sessions
+        .iter()
+        .filter_map(|session| serde_json::to_value(session).ok())
+        .collect();
+    let report = steps::step_report(&all_steps, &sessions, &blacklisted, &file_events, &findings);
+    if json_output {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error serializing step report: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        }
+    } else {
+        steps::print_step_report(&all_steps, &report);
+    }
+    0
+}
+
+/// Egress BOM of the current sessions, optionally restricted to a pipeline step.
+pub fn background_egress_bom(
+    format: crate::bom::BomFormat,
+    output: Option<&str>,
+    local_traffic: bool,
+    step: Option<&str>,
+) -> i32 {
+    let all_steps = match step.map(steps::steps_for_filter) {
+        Some(Ok(all_steps)) => all_steps,
+        Some(Err(exit_code)) => return exit_code,
+        None => Vec::new(),
+    };
+    let mut sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if !local_traffic {
+        sessions = filter_global_sessions(sessions);
+    }
+    if let Some(name) = step {
+        sessions.retain(|session| in_step(session, name, &all_steps, steps::SESSION_TIME));
+    }
+    let blacklisted: HashSet<String> = match rpc_get_blacklisted_sessions(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(blacklisted) => blacklisted.into_iter().map(|session| session.uid).collect(),
+        Err(e) => {
+            eprintln!("Error getting blacklisted sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+
+    let geoip = match load_geoip() {
+        Ok(geoip) => geoip,
+        Err(exit_code) => return exit_code,
+    };
+    let labels = match load_labels() {
+        Ok(labels) => labels,
+        Err(exit_code) => return exit_code,
+    };
+    let sessions: Vec<serde_json::Value> = sessions
+        .iter()
+        .filter_map(|session| serde_json::to_value(session).ok())
+        .map(|mut session| {
+            if let Some(geoip) = &geoip {
+                geoip.enrich(&mut session);
+            }
+            if let Some(labels) = &labels {
+                labels.apply(&mut session);
+            }
+            session
+        })
+        .collect();
+    crate::bom::write_bom(&sessions, &blacklisted, format, output)
+}
+
+pub fn background_ioc_sweep(file: &str, history_dir: Option<&str>, json_output: bool) -> i32 {
+    let indicators = match crate::ioc::load_iocs(file) {
+        Ok(indicators) => indicators,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    // An explicit history directory must exist, the default one is optional
+    let dir = history_dir
+        .map(str::to_string)
+        .unwrap_or_else(crate::history::history_dir);
+    let use_history = std::path::Path::new(&dir).is_dir();
+    if history_dir.is_some() && !use_history {
+        eprintln!("History directory '{}' does not exist", dir);
+        return ERROR_CODE_PARAM;
+    }
+
+    let sessions = match rpc_get_lan_sessions(
+        true,
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(sessions) => sessions.sessions,
+        Err(e) => {
+            eprintln!("Error getting LAN sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    let blacklisted = match rpc_get_blacklisted_sessions(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(blacklisted) => blacklisted,
+        Err(e) => {
+            eprintln!("Error getting blacklisted sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    let anomalous = match rpc_get_anomalous_sessions(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(anomalous) => anomalous,
+        Err(e) => {
+            eprintln!("Error getting anomalous sessions: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    // File monitoring is optional
+    let file_events: Vec<serde_json::Value> = match rpc_get_file_events(
+        &EDAMAME_CA_PEM,
+        &EDAMAME_CLIENT_PEM,
+        &EDAMAME_CLIENT_KEY,
+        &EDAMAME_TARGET,
+    ) {
+        Ok(snapshot) => snapshot
+            .events
+            .iter()
+            .filter_map(|event| serde_json::to_value(event).ok())
+            .collect(),
+        Err(e) => {
+            warn!("File events unavailable for the IOC sweep: {}", e);
+            Vec::new()
+        }
+    };
+
+    // Each live session once, tagged with the lists it appears in
+    let now = steps::now_millis();
+    let blacklisted_uids: HashSet<String> = blacklisted.iter().map(|s| s.uid.clone()).collect();
+    let anomalous_uids: HashSet<String> = anomalous.iter().map(|s| s.uid.clone()).collect();
+    let mut seen = HashSet::new();
+    let mut records = Vec::new();
+    for session in sessions.iter().chain(&blacklisted).chain(&anomalous) {
+        if !seen.insert(session.uid.clone()) {
+            continue;
+        }
+        let Ok(data) = serde_json::to_value(session) else {
+            continue;
+        };
+        let mut record =
+            crate::history::history_record(crate::history::RecordKind::Session, data, now);
+        let tags: Vec<&str> = [
+            ("blacklisted", blacklisted_uids.contains(&session.uid)),
+            ("anomalous", anomalous_uids.contains(&session.uid)),
+        ]
+        .into_iter()
+        .filter_map(|(tag, set)| set.then_some(tag))
+        .collect();
+        record["source"] = serde_json::json!("live");
+        record["tags"] = serde_json::json!(tags);
+        records.push(record);
+    }
+    for event in file_events {
+        let mut record =
+            crate::history::history_record(crate::history::RecordKind::FileEvent, event, now);
+        record["source"] = serde_json::json!("live");
+        records.push(record);
+    }
+    if use_history {
+        let query = crate::history::HistoryQuery {
+            kinds: vec![
+                crate::history::RecordKind::Session,
+                crate::history::RecordKind::FileEvent,
+            ],
+            ..Default::default()
+        };
+        match crate::history::query_history(&dir, &query) {
+            Ok(history) => records.extend(history.into_iter().map(|mut record| {
+                record["source"] = serde_json::json!("history");
+                record
+            })),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+    } else if !json_output {
+        println!("No history store in '{}', sweeping live data only", dir);
+    }
+
+    let hashes = crate::ioc::hash_executables(&indicators, &records);
+    let hits = crate::ioc::sweep(&indicators, &records, &hashes);
+    crate::ioc::print_hits(&hits, indicators.len(), records.len(), json_output)
+}
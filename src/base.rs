@@ -1,3 +1,17 @@
+use crate::capture::{CaptureOptions, FinishedSessions, SessionFile, StopReason};
+use crate::dismissals::{
+    now_secs, parse_rules_document, record_dismissal, record_rules_at, registry_path, DismissKind,
+    DismissRule,
+};
+use crate::labels::{labeled_line, LabelRules};
+use crate::lanscan::{
+    change_lines, devices_csv, devices_from_core, devices_text, diff_scans, has_changes,
+    history_dir, history_files, load_scan, notify_changes, restrict_devices, save_to_history,
+    scan_document, LanscanOptions,
+};
+use crate::run::{self, RunOptions};
+use crate::sessions::session_str;
+use crate::wait::{self, Interrupted, Poll};
 use crate::ERROR_CODE_MISMATCH;
 use crate::ERROR_CODE_PARAM;
 use crate::ERROR_CODE_SERVER_ERROR;
@@ -6,40 +20,43 @@ use edamame_core::api::api_flodbadd::*;
 use edamame_core::api::api_score::*;
 use edamame_core::api::api_score_threats::*;
 use edamame_core::api::api_trust::*;
-use indicatif::{ProgressBar, ProgressStyle};
 use serde_json;
 use std::net::IpAddr;
 use std::thread::sleep;
-use std::time::Duration;
-use sysinfo::{Disks, Networks, System};
+use std::time::{Duration, Instant};
+use sysinfo::{Disks, Networks, Pid, ProcessesToUpdate, System};
+use tracing::warn;
 
-pub fn base_get_score(progress_bar: bool) {
+pub fn base_get_score(progress_bar: bool) -> i32 {
     // Request a score computation
     compute_score();
 
-    let total_steps = 100;
-    let pb = ProgressBar::new(total_steps);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} ({eta})")
-        .expect("failed to set progress style")
-        .progress_chars("#>-"));
-
-    let mut score = get_score(false, false);
-    while score.compute_in_progress {
-        if progress_bar {
-            pb.set_position(score.compute_progress_percent as u64);
-        }
-        sleep(Duration::from_millis(100));
-        score = get_score(false, false);
+    let waited = wait::wait_for(
+        "Score computation",
+        None,
+        Duration::from_millis(100),
+        progress_bar,
+        || {
+            let score = get_score(false, false);
+            if score.compute_in_progress {
+                Poll::Pending(Some(score.compute_progress_percent as u64))
+            } else {
+                Poll::Ready
+            }
+        },
+    );
+    if let Err(interrupted) = waited {
+        return wait::report("Score computation", interrupted);
     }
 
     // Make sure we have the final score
-    score = get_score(true, false);
+    let score = get_score(true, false);
     let url = get_threats_url().to_string();
     // Pretty print the final score with important details
     println!("Security Score summary:");
     println!("{}", score);
     println!("Model URL: {}", url);
+    0
 }
 
 pub fn base_get_system_info() {
@@ -138,7 +155,11 @@ pub fn base_remediate_threat(threat_id: String) -> i32 {
     }
 }
 
-pub fn base_dismiss_device(ip_address: String) -> i32 {
+pub fn base_dismiss_device(
+    ip_address: String,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if ip_address.parse::<IpAddr>().is_err() {
         eprintln!("Invalid IP address: {}", ip_address);
         return ERROR_CODE_PARAM;
@@ -146,10 +167,18 @@ pub fn base_dismiss_device(ip_address: String) -> i32 {
 
     dismiss_all_device_ports(ip_address.clone());
     println!("Dismissed device {} (all ports)", ip_address);
-    0
+    record_dismissal(
+        DismissRule::new(DismissKind::Device, &ip_address, None, "cli")
+            .with_terms(expires_at, reason),
+    )
 }
 
-pub fn base_dismiss_device_port(ip_address: String, port: u16) -> i32 {
+pub fn base_dismiss_device_port(
+    ip_address: String,
+    port: u16,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if ip_address.parse::<IpAddr>().is_err() {
         eprintln!("Invalid IP address: {}", ip_address);
         return ERROR_CODE_PARAM;
@@ -157,10 +186,13 @@ pub fn base_dismiss_device_port(ip_address: String, port: u16) -> i32 {
 
     dismiss_device_port(ip_address.clone(), port);
     println!("Dismissed port {} on {}", port, ip_address);
-    0
+    record_dismissal(
+        DismissRule::new(DismissKind::DevicePort, &ip_address, Some(port), "cli")
+            .with_terms(expires_at, reason),
+    )
 }
 
-pub fn base_dismiss_session(uid: String) -> i32 {
+pub fn base_dismiss_session(uid: String, expires_at: Option<u64>, reason: Option<String>) -> i32 {
     if uid.trim().is_empty() {
         eprintln!("Session UID cannot be empty");
         return ERROR_CODE_PARAM;
@@ -169,10 +201,16 @@ pub fn base_dismiss_session(uid: String) -> i32 {
     let uid = uid.trim().to_string();
     add_dismiss_rule_from_session(uid.clone());
     println!("Dismissed session {}", uid);
-    0
+    record_dismissal(
+        DismissRule::new(DismissKind::Session, &uid, None, "cli").with_terms(expires_at, reason),
+    )
 }
 
-pub fn base_dismiss_session_process(uid: String) -> i32 {
+pub fn base_dismiss_session_process(
+    uid: String,
+    expires_at: Option<u64>,
+    reason: Option<String>,
+) -> i32 {
     if uid.trim().is_empty() {
         eprintln!("Session UID cannot be empty");
         return ERROR_CODE_PARAM;
@@ -181,7 +219,91 @@ pub fn base_dismiss_session_process(uid: String) -> i32 {
     let uid = uid.trim().to_string();
     add_dismiss_rule_from_process(uid.clone());
     println!("Dismissed future sessions for process {}", uid);
-    0
+    record_dismissal(
+        DismissRule::new(DismissKind::Process, &uid, None, "cli").with_terms(expires_at, reason),
+    )
+}
+
+// Revoke a network dismissal in the core, the counterpart of the dismiss commands
+pub fn base_undismiss(rule: &DismissRule) -> Result<(), String> {
+    match rule.kind {
+        DismissKind::Device => undismiss_all_device_ports(rule.target.clone()),
+        DismissKind::DevicePort => match rule.port {
+            Some(port) => undismiss_device_port(rule.target.clone(), port),
+            None => return Err("port rule without a port".to_string()),
+        },
+        DismissKind::Session => remove_dismiss_rule_from_session(rule.target.clone()),
+        DismissKind::Process => remove_dismiss_rule_from_process(rule.target.clone()),
+        _ => return Err("not a network dismissal".to_string()),
+    }
+    Ok(())
+}
+
+// Apply the dismissal rules of an exported registry file and record them
+pub fn base_import_dismiss_rules(file: &str) -> i32 {
+    let content = match std::fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error reading dismissal rules file '{}': {}", file, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let rules = match parse_rules_document(&content) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Invalid dismissal rules file '{}': {}", file, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+
+    let origin = format!("import:{}", file);
+    let mut applied = Vec::new();
+    let mut skipped = 0;
+    let now = now_secs();
+    for rule in rules {
+        if rule.is_expired(now) {
+            eprintln!("Skipping {} ({}): expired", rule.id, rule.describe());
+            skipped += 1;
+            continue;
+        }
+        match rule.kind {
+            DismissKind::Device => dismiss_all_device_ports(rule.target.clone()),
+            DismissKind::DevicePort => {
+                dismiss_device_port(rule.target.clone(), rule.port.unwrap_or(0))
+            }
+            // Session UIDs and finding keys do not carry over to other hosts
+            _ => {
+                eprintln!(
+                    "Skipping {} ({}): not portable to other hosts",
+                    rule.id,
+                    rule.describe()
+                );
+                skipped += 1;
+                continue;
+            }
+        }
+        applied.push(
+            DismissRule::new(rule.kind, &rule.target, rule.port, &origin)
+                .with_terms(rule.expires_at, rule.reason),
+        );
+    }
+
+    match record_rules_at(&registry_path(), &applied) {
+        Ok(added) => {
+            println!(
+                "Applied {} dismissal rules from {} ({} new, {} skipped)",
+                applied.len(),
+                file,
+                added,
+                skipped
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Dismissals applied but not recorded: {}", e);
+            ERROR_CODE_PARAM
+        }
+    }
 }
 
 pub fn base_rollback_threat(threat_id: String) -> i32 {
@@ -226,13 +348,16 @@ pub fn base_get_threat_info(threat_id: String) {
     println!("Threat information: {}", threat_info);
 }
 
-pub fn base_remediate(remediations_to_skip: &str) {
+pub fn base_remediate(remediations_to_skip: &str) -> i32 {
     println!("Score before remediation:");
     println!("-------------------------");
     println!();
 
     // Show the score before remediation
-    base_get_score(false);
+    let exit_code = base_get_score(false);
+    if exit_code != 0 {
+        return exit_code;
+    }
 
     // Get the score
     let score = get_score(true, false);
@@ -260,60 +385,657 @@ pub fn base_remediate(remediations_to_skip: &str) {
     println!("------------------------");
 
     // Show the score after remediation
-    base_get_score(false);
+    base_get_score(false)
 }
 
-pub fn base_capture(seconds: u64, whitelist_name: &str, zeek_format: bool, local_traffic: bool) {
+pub fn base_capture(options: &CaptureOptions) -> i32 {
+    if options.stop_on_nonconforming && options.whitelist_name.is_empty() {
+        eprintln!("--stop-on-nonconforming requires a whitelist name");
+        return ERROR_CODE_PARAM;
+    }
+    if let Some(pid) = options.until_pid {
+        if !base_process_alive(pid) {
+            eprintln!("No running process with PID {}", pid);
+            return ERROR_CODE_PARAM;
+        }
+    }
+    let labels = match LabelRules::from_env() {
+        Ok(labels) => labels,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let mut output = match &options.output {
+        Some(path) => {
+            match SessionFile::create(path, options.rotate_size, options.rotate_interval) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ERROR_CODE_PARAM;
+                }
+            }
+        }
+        None => None,
+    };
+
     // Start capturing packets
-    set_whitelist(whitelist_name.to_string());
+    set_whitelist(options.whitelist_name.clone());
     // Filter sessions based on local_traffic
-    set_filter(if local_traffic {
+    set_filter(if options.local_traffic {
         SessionFilterAPI::All
     } else {
         SessionFilterAPI::GlobalOnly
     });
     start_capture();
+    wait::on_interrupt(|| {
+        stop_capture();
+    });
 
-    // Wait for the specified number of seconds
-    sleep(Duration::from_secs(seconds));
+    // Write sessions as they finish until a stop condition is met
+    let started = Instant::now();
+    let mut tracker = FinishedSessions::new();
+    let stopped: Result<StopReason, String> = loop {
+        let sessions = get_sessions();
+        let finished = tracker.update(sessions.iter().map(capture_entry).collect());
+        if let Some(file) = output.as_mut() {
+            if let Err(e) = write_capture(file, finished, options.zeek_format, labels.as_ref()) {
+                break Err(e);
+            }
+        }
+
+        if options.stop_on_blacklisted {
+            if let Some(session) = get_blacklisted_sessions().into_iter().next() {
+                break Ok(StopReason::Blacklisted(capture_line(session)));
+            }
+        }
+        if options.stop_on_nonconforming {
+            if let Some(session) = sessions
+                .iter()
+                .find(|s| s.is_whitelisted == WhiteListStateAPI::NonConforming)
+            {
+                break Ok(StopReason::NonConforming(capture_line(session.clone())));
+            }
+        }
+        if let Some(count) = options.max_sessions {
+            if tracker.seen() >= count {
+                break Ok(StopReason::SessionCount(tracker.seen()));
+            }
+        }
+        if let Some(pid) = options.until_pid {
+            if !base_process_alive(pid) {
+                break Ok(StopReason::ProcessExited(pid));
+            }
+        }
+        if options
+            .seconds
+            .is_some_and(|seconds| started.elapsed() >= Duration::from_secs(seconds))
+        {
+            break Ok(StopReason::Duration);
+        }
+        if let Err(interrupted) = wait::pause(Duration::from_secs(1)) {
+            break Ok(StopReason::Interrupted(interrupted));
+        }
+    };
 
     // Stop capturing packets
     stop_capture();
 
-    // Display the captured connections
+    // Write what is left, or display the captured connections
     let sessions = get_sessions();
+    let mut stopped = stopped;
+    if let Some(file) = output.as_mut() {
+        let mut remaining = tracker.update(sessions.iter().map(capture_entry).collect());
+        remaining.extend(tracker.drain());
+        if let Err(e) = write_capture(file, remaining, options.zeek_format, labels.as_ref()) {
+            stopped = Err(e);
+        }
+    } else {
+        for line in format_labeled_sessions(sessions, options.zeek_format, labels.as_ref()) {
+            println!("{}", line);
+        }
+    }
+
+    let reason = match stopped {
+        Ok(reason) => reason,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    if let Some(path) = &options.generate_whitelist {
+        let exit_code =
+            base_generate_whitelist(path, options.with_process, options.merge_into.as_deref());
+        if exit_code != 0 {
+            return exit_code;
+        }
+    }
+    if let StopReason::Interrupted(interrupted) = &reason {
+        wait::report("Capture", *interrupted);
+    }
+    eprintln!("Capture summary:");
+    eprintln!("  Stopped: {}", reason.describe());
+    eprintln!("  Duration: {}s", started.elapsed().as_secs());
+    eprintln!("  Sessions: {}", tracker.seen());
+    if let (Some(file), Some(path)) = (&output, &options.output) {
+        eprintln!("  Written: {} to {}", file.lines, path);
+        if !file.rotated.is_empty() {
+            eprintln!("  Rotated files: {}", file.rotated.join(", "));
+        }
+    }
+    eprintln!("  Exit code: {}", reason.exit_code());
+    reason.exit_code()
+}
+
+fn capture_entry(session: &SessionInfoAPI) -> (String, bool, SessionInfoAPI) {
+    let finished = serde_json::to_value(session)
+        .ok()
+        .is_some_and(|value| session_str(&value, "/stats/end_time").is_some());
+    (session.uid.clone(), finished, session.clone())
+}
+
+fn capture_line(session: SessionInfoAPI) -> String {
+    format_sessions_log(vec![session])
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+}
+
+fn write_capture(
+    file: &mut SessionFile,
+    sessions: Vec<SessionInfoAPI>,
+    zeek_format: bool,
+    labels: Option<&LabelRules>,
+) -> Result<(), String> {
+    if zeek_format {
+        for line in format_labeled_sessions(sessions, true, labels) {
+            file.write_line(&line)?;
+        }
+    } else {
+        for session in sessions {
+            let mut value = serde_json::to_value(&session)
+                .map_err(|e| format!("Error serializing session: {}", e))?;
+            if let Some(labels) = labels {
+                labels.apply(&mut value);
+            }
+            file.write_line(&value.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Log or Zeek lines of the sessions, with their labels when label rules are
+/// configured.
+pub fn format_labeled_sessions(
+    sessions: Vec<SessionInfoAPI>,
+    zeek_format: bool,
+    labels: Option<&LabelRules>,
+) -> Vec<String> {
+    let format = |sessions: Vec<SessionInfoAPI>| {
+        if zeek_format {
+            format_sessions_zeek(sessions)
+        } else {
+            format_sessions_log(sessions)
+        }
+    };
+    let Some(labels) = labels else {
+        return format(sessions);
+    };
+    sessions
+        .into_iter()
+        .flat_map(|session| {
+            let session_labels = serde_json::to_value(&session)
+                .map(|value| labels.labels(&value))
+                .unwrap_or_default();
+            format(vec![session])
+                .into_iter()
+                .map(move |line| labeled_line(&line, &session_labels, zeek_format))
+        })
+        .collect()
+}
+
+/// Write a whitelist of the captured sessions to `path`, optionally merged
+/// into an existing whitelist file.
+pub fn base_generate_whitelist(path: &str, with_process: bool, merge_into: Option<&str>) -> i32 {
+    let generated = if with_process {
+        create_custom_whitelists_with_process()
+    } else {
+        create_custom_whitelists()
+    };
+    if generated.is_empty() {
+        eprintln!("Failed to create custom whitelists");
+        return ERROR_CODE_SERVER_ERROR;
+    }
+
+    let whitelist_json = match merge_into {
+        Some(existing_path) => {
+            let existing = match std::fs::read_to_string(existing_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Error reading whitelist file '{}': {}", existing_path, e);
+                    return ERROR_CODE_PARAM;
+                }
+            };
+            if let Err(msg) = crate::whitelists::check_custom_whitelists(&existing, existing_path) {
+                eprintln!("Error merging custom whitelists: {msg}");
+                return ERROR_CODE_PARAM;
+            }
+            let merged = merge_custom_whitelists(existing, generated);
+            if merged.is_empty() {
+                eprintln!("Failed to merge custom whitelists");
+                return ERROR_CODE_SERVER_ERROR;
+            }
+            merged
+        }
+        None => generated,
+    };
+
+    let pretty_json = match serde_json::from_str::<serde_json::Value>(&whitelist_json)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+    {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Error parsing whitelist JSON: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    match std::fs::write(path, pretty_json) {
+        Ok(()) => {
+            match merge_into {
+                Some(existing_path) => {
+                    eprintln!(
+                        "Whitelist merged into {} and saved to {}",
+                        existing_path, path
+                    )
+                }
+                None => eprintln!("Whitelist saved to {}", path),
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Error writing whitelist file '{}': {}", path, e);
+            ERROR_CODE_PARAM
+        }
+    }
+}
+
+/// The `--fail-on-*` checks of the daemon and of `run`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PolicyChecks {
+    pub whitelist: bool,
+    pub blacklist: bool,
+    pub anomalous: bool,
+    pub findings: bool,
+    pub local_traffic: bool,
+}
+
+/// Sessions and findings that fail the enabled checks.
+#[derive(Debug, Default)]
+pub struct PolicyViolations {
+    pub non_conforming: Vec<SessionInfoAPI>,
+    pub blacklisted: Vec<SessionInfoAPI>,
+    pub anomalous: Vec<SessionInfoAPI>,
+    pub vulnerability_findings: u64,
+    pub vulnerability_label: &'static str,
+    // Labels of the violating sessions, by uid, see crate::labels
+    pub labels: std::collections::HashMap<String, Vec<String>>,
+}
+
+impl PolicyViolations {
+    pub fn is_empty(&self) -> bool {
+        self.non_conforming.is_empty()
+            && self.blacklisted.is_empty()
+            && self.anomalous.is_empty()
+            && self.vulnerability_findings == 0
+    }
+
+    /// Every violating session, once.
+    pub fn sessions(&self) -> Vec<SessionInfoAPI> {
+        let mut seen = std::collections::HashSet::new();
+        self.non_conforming
+            .iter()
+            .chain(&self.blacklisted)
+            .chain(&self.anomalous)
+            .filter(|session| seen.insert(session.uid.clone()))
+            .cloned()
+            .collect()
+    }
+
+    /// Labels of the violating sessions, each once and sorted.
+    pub fn session_labels(&self) -> Vec<String> {
+        let labels: std::collections::BTreeSet<&String> = self.labels.values().flatten().collect();
+        labels.into_iter().cloned().collect()
+    }
+
+    pub fn by_uid(&self) -> run::Violations {
+        let uids = |sessions: &[SessionInfoAPI]| sessions.iter().map(|s| s.uid.clone()).collect();
+        run::Violations {
+            non_conforming: uids(&self.non_conforming),
+            blacklisted: uids(&self.blacklisted),
+            anomalous: uids(&self.anomalous),
+            vulnerability_findings: self.vulnerability_findings,
+        }
+    }
+}
+
+/// Evaluate the enabled session checks. Only sessions in the Conforming state
+/// conform to the whitelist, Unknown ones are violations too. The violating
+/// sessions are labeled with the label rules, when there are some.
+pub fn policy_violations(
+    checks: &PolicyChecks,
+    sessions: Vec<SessionInfoAPI>,
+    blacklisted: Vec<SessionInfoAPI>,
+    anomalous: Vec<SessionInfoAPI>,
+    labels: Option<&LabelRules>,
+) -> PolicyViolations {
+    let checked = |enabled: bool, sessions: Vec<SessionInfoAPI>| match enabled {
+        false => Vec::new(),
+        true if checks.local_traffic => sessions,
+        true => filter_global_sessions(sessions),
+    };
+    let mut violations = PolicyViolations {
+        non_conforming: checked(checks.whitelist, sessions)
+            .into_iter()
+            .filter(|session| session.is_whitelisted != WhiteListStateAPI::Conforming)
+            .collect(),
+        blacklisted: checked(checks.blacklist, blacklisted),
+        anomalous: checked(checks.anomalous, anomalous),
+        ..Default::default()
+    };
+    if let Some(rules) = labels {
+        for session in violations.sessions() {
+            let session_labels = serde_json::to_value(&session)
+                .map(|value| rules.labels(&value))
+                .unwrap_or_default();
+            if !session_labels.is_empty() {
+                violations.labels.insert(session.uid, session_labels);
+            }
+        }
+    }
+    violations
+}
+
+/// Serialized sessions, labeled with the label rules when there are some.
+pub fn labeled_session_values(
+    sessions: &[SessionInfoAPI],
+    labels: Option<&LabelRules>,
+) -> Vec<serde_json::Value> {
+    sessions
+        .iter()
+        .filter_map(|session| serde_json::to_value(session).ok())
+        .map(|mut value| {
+            if let Some(labels) = labels {
+                labels.apply(&mut value);
+            }
+            value
+        })
+        .collect()
+}
+
+/// Active findings counted by `--fail-on-findings`, from the status of the
+/// vulnerability detector: the HIGH/CRITICAL ones, or every finding for older
+/// daemons that do not report them apart.
+pub fn alertable_findings(status: &str) -> Result<(u64, &'static str), String> {
+    let status: serde_json::Value = serde_json::from_str(status)
+        .map_err(|e| format!("Error parsing vulnerability detector status: {}", e))?;
+    if let Some(error) = status.get("error").and_then(|value| value.as_str()) {
+        return Err(format!(
+            "Error getting vulnerability detector status: {}",
+            error
+        ));
+    }
+    match status
+        .get("active_alertable_findings")
+        .and_then(|value| value.as_u64())
+    {
+        Some(alertable) => Ok((alertable, "HIGH/CRITICAL severity")),
+        None => Ok((
+            status
+                .get("active_findings")
+                .and_then(|value| value.as_u64())
+                .unwrap_or(0),
+            "all severities (legacy daemon)",
+        )),
+    }
+}
+
+/// The checks enabled by the options of `run`.
+pub fn run_checks(options: &RunOptions) -> PolicyChecks {
+    PolicyChecks {
+        whitelist: options.fail_on_whitelist,
+        blacklist: options.fail_on_blacklist,
+        anomalous: options.fail_on_anomalous,
+        findings: options.fail_on_findings,
+        local_traffic: options.local_traffic,
+    }
+}
 
-    let sessions = if zeek_format {
-        format_sessions_zeek(sessions)
+/// Run a command under an in-process capture and report its egress. The
+/// vulnerability detector only runs in the daemon, see `--daemon`.
+pub fn base_run(options: &RunOptions) -> i32 {
+    if options.fail_on_whitelist && options.whitelist_name.is_empty() {
+        eprintln!("--fail-on-whitelist requires a whitelist name, provide --whitelist <NAME>");
+        return ERROR_CODE_PARAM;
+    }
+    let labels = match LabelRules::from_env() {
+        Ok(labels) => labels,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    set_whitelist(options.whitelist_name.clone());
+    set_filter(if options.local_traffic {
+        SessionFilterAPI::All
     } else {
-        format_sessions_log(sessions)
+        SessionFilterAPI::GlobalOnly
+    });
+    start_capture();
+    edamame_core::api::api_fim::start_file_monitor(Vec::new());
+    wait::on_interrupt(|| {
+        edamame_core::api::api_fim::stop_file_monitor();
+        stop_capture();
+    });
+
+    let tracked = match run::run_tracked(&options.command) {
+        Ok(tracked) => tracked,
+        Err(exit_code) => {
+            edamame_core::api::api_fim::stop_file_monitor();
+            stop_capture();
+            return exit_code;
+        }
     };
-    for session in sessions.iter() {
-        println!("{}", session);
+    // Leave the capture time to account for the last sessions of the command
+    let _ = wait::pause(RUN_SETTLE_TIME);
+
+    let sessions = get_sessions();
+    let violations = policy_violations(
+        &run_checks(options),
+        sessions.clone(),
+        get_blacklisted_sessions(),
+        get_anomalous_sessions(),
+        labels.as_ref(),
+    );
+    let file_events = edamame_core::api::api_fim::get_file_events()
+        .events
+        .iter()
+        .filter_map(|event| serde_json::to_value(event).ok())
+        .collect();
+    edamame_core::api::api_fim::stop_file_monitor();
+    stop_capture();
+    run::conclude(
+        options,
+        &tracked,
+        labeled_session_values(&sessions, labels.as_ref()),
+        &violations.by_uid(),
+        Some(file_events),
+    )
+}
+
+pub fn base_process_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    sys.process(pid).is_some()
+}
+
+/// (interface, address/prefix) for every address of the local interfaces.
+pub fn base_local_networks() -> Vec<(String, String)> {
+    let networks = Networks::new_with_refreshed_list();
+    let mut local = Vec::new();
+    for (interface_name, data) in &networks {
+        for network in data.ip_networks() {
+            local.push((
+                interface_name.to_string(),
+                format!("{}/{}", network.addr, network.prefix),
+            ));
+        }
     }
+    local
 }
 
-pub fn base_lanscan() {
+/// Time given to the capture to see the last sessions of a command run with `run`.
+pub const RUN_SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Seconds to wait for the gateway detection before a LAN scan gives up.
+pub const GATEWAY_TIMEOUT: u64 = 60;
+
+/// Wait for the core to detect the gateway, which LAN scans depend on.
+pub fn base_wait_gateway(timeout: Duration) -> Result<(), Interrupted> {
+    wait::wait_for(
+        "Gateway detection",
+        Some(timeout),
+        Duration::from_secs(2),
+        false,
+        || {
+            if get_last_gateway_scan().is_empty() {
+                Poll::Pending(None)
+            } else {
+                Poll::Ready
+            }
+        },
+    )
+}
+
+pub fn base_lanscan(options: &LanscanOptions) -> i32 {
     // The network, has been set, consent has been granted and a scan has been requested if needed
-    let total_steps = 100;
-    let pb = ProgressBar::new(total_steps);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} ({eta})")
-        .expect("failed to set progress style")
-        .progress_chars("#>-"));
-
-    // Wait completion of the scan
+    // Wait completion of the scan, keeping stdout clean for JSON and CSV output
+    let machine_output = matches!(options.format.as_str(), "json" | "csv");
     let mut devices = get_lanscan(false, false, false);
-    println!("Waiting for LAN scan to complete...");
-    while devices.scan_in_progress {
-        pb.set_position(devices.scan_progress_percent as u64);
-        sleep(Duration::from_secs(5));
+    if machine_output {
+        eprintln!("Waiting for LAN scan to complete...");
+    } else {
+        println!("Waiting for LAN scan to complete...");
+    }
+    let waited = wait::wait_for("LAN scan", None, Duration::from_secs(5), true, || {
         devices = get_lanscan(false, false, false);
+        if devices.scan_in_progress {
+            Poll::Pending(Some(devices.scan_progress_percent as u64))
+        } else {
+            Poll::Ready
+        }
+    });
+    if let Err(interrupted) = waited {
+        return wait::report("LAN scan", interrupted);
     }
 
-    // Display the devices
-    println!("LAN scan completed at: {}", devices.last_scan);
-    println!("{}", devices);
+    let inventory = match serde_json::to_value(&devices.devices) {
+        Ok(value) => devices_from_core(&value),
+        Err(e) => {
+            eprintln!("Error serializing LAN devices: {}", e);
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    };
+    let scanned_at = devices.last_scan.to_string();
+    // The history keeps full scans so that later diffs are not skewed by a scope
+    if options.persist {
+        if let Err(e) = save_to_history(
+            &history_dir(),
+            &scan_document(&scanned_at, &inventory),
+            now_secs(),
+        ) {
+            eprintln!("Warning: LAN scan not saved to the scan history: {}", e);
+        }
+    }
+    let scoped = !options.cidrs.is_empty() || !options.ports.is_empty();
+    let inventory = restrict_devices(inventory, &options.cidrs, &options.ports);
+
+    let rendered = match options.format.as_str() {
+        "json" => match serde_json::to_string_pretty(&scan_document(&scanned_at, &inventory)) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Error serializing LAN devices: {}", e);
+                return ERROR_CODE_SERVER_ERROR;
+            }
+        },
+        "csv" => devices_csv(&inventory),
+        _ if scoped => format!(
+            "LAN scan completed at: {}\n{}",
+            scanned_at,
+            devices_text(&inventory)
+        ),
+        _ => format!("LAN scan completed at: {}\n{}", scanned_at, devices),
+    };
+    match &options.output {
+        Some(path) => match std::fs::write(path, rendered) {
+            Ok(()) => {
+                println!("Saved {} devices to {}", inventory.len(), path);
+                0
+            }
+            Err(e) => {
+                eprintln!("Error writing '{}': {}", path, e);
+                ERROR_CODE_PARAM
+            }
+        },
+        None => {
+            println!("{}", rendered);
+            0
+        }
+    }
+}
+
+/// Persist each LAN scan completed by the core and report the changes since
+/// the previous scan in the history. `last_scan` holds the time of the last
+/// scan handled, so a scan is only processed once.
+pub fn base_check_lanscan_changes(last_scan: &mut String, webhook: Option<&str>) {
+    let devices = get_lanscan(false, false, false);
+    let scanned_at = devices.last_scan.to_string();
+    if devices.scan_in_progress || scanned_at.is_empty() || scanned_at == *last_scan {
+        return;
+    }
+    *last_scan = scanned_at.clone();
+
+    let inventory = match serde_json::to_value(&devices.devices) {
+        Ok(value) => devices_from_core(&value),
+        Err(e) => {
+            warn!("Cannot record LAN scan: {}", e);
+            return;
+        }
+    };
+    let dir = history_dir();
+    let previous = history_files(&dir)
+        .ok()
+        .and_then(|files| files.last().cloned())
+        .and_then(|path| load_scan(&path).ok());
+    if let Err(e) = save_to_history(&dir, &scan_document(&scanned_at, &inventory), now_secs()) {
+        warn!("LAN scan not saved to the scan history: {}", e);
+    }
+
+    let Some(previous) = previous else {
+        return;
+    };
+    let report = diff_scans(&previous, &inventory);
+    if !has_changes(&report) {
+        return;
+    }
+    for line in change_lines(&report) {
+        warn!("LAN scan change: {}", line);
+    }
+    if let Some(webhook) = webhook {
+        if let Err(e) = notify_changes(webhook, &report) {
+            warn!("Cannot notify LAN scan changes: {}", e);
+        }
+    }
 }
 
 pub fn base_request_pin(user: String, domain: String) -> i32 {
@@ -540,7 +1262,10 @@ pub fn base_check_policy(minimum_score: f32, threat_ids: String, tag_prefixes: S
     );
 
     // Compute the score
-    base_get_score(true);
+    let exit_code = base_get_score(true);
+    if exit_code != 0 {
+        return exit_code;
+    }
 
     // Make sure we have the final score
     let score = get_score(true, false);
@@ -596,27 +1321,125 @@ pub fn base_get_tag_prefixes() {
     println!("Tag prefixes: {:?}", tag_prefixes);
 }
 
-pub fn base_merge_custom_whitelists(whitelist1_json: String, whitelist2_json: String) -> i32 {
-    let merged_json = merge_custom_whitelists(whitelist1_json, whitelist2_json);
+/// Merge any number of whitelist JSON strings into one whitelist printed to
+/// stdout, like `base_merge_custom_whitelists_files` does for files.
+pub fn base_merge_custom_whitelists(
+    whitelist_jsons: Vec<String>,
+    annotate_sources: bool,
+    fail_on_conflict: bool,
+) -> i32 {
+    let inputs = whitelist_jsons
+        .into_iter()
+        .enumerate()
+        .map(|(i, json)| (format!("whitelist {}", i + 1), json))
+        .collect();
+    merge_whitelist_inputs(inputs, annotate_sources, fail_on_conflict)
+}
 
-    if merged_json.is_empty() {
-        eprintln!("Failed to merge custom whitelists");
-        return ERROR_CODE_SERVER_ERROR;
+/// Merge any number of whitelist files (or file name globs) into one
+/// whitelist printed to stdout, sorted so that it diffs cleanly.
+pub fn base_merge_custom_whitelists_files(
+    patterns: Vec<String>,
+    annotate_sources: bool,
+    fail_on_conflict: bool,
+) -> i32 {
+    let files = match crate::whitelists::expand_whitelist_paths(&patterns) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    if files.len() < 2 {
+        eprintln!(
+            "At least two whitelist files are required to merge, got {}",
+            files.len()
+        );
+        return ERROR_CODE_PARAM;
     }
 
-    match serde_json::from_str::<serde_json::Value>(&merged_json) {
-        Ok(json_value) => {
-            match serde_json::to_string_pretty(&json_value) {
-                Ok(pretty_json) => println!("{}", pretty_json),
-                Err(_) => println!("{}", merged_json),
+    let mut sources = Vec::new();
+    for file in files {
+        match std::fs::read_to_string(&file) {
+            Ok(json) => sources.push((file, json)),
+            Err(e) => {
+                eprintln!("Error reading whitelist file '{}': {}", file, e);
+                return ERROR_CODE_PARAM;
             }
-            0
         }
+    }
+    merge_whitelist_inputs(sources, annotate_sources, fail_on_conflict)
+}
+
+// Check, merge and print (source, JSON) whitelists in order, reporting the
+// same-name whitelists whose extends differ between sources
+fn merge_whitelist_inputs(
+    sources: Vec<(String, String)>,
+    annotate_sources: bool,
+    fail_on_conflict: bool,
+) -> i32 {
+    let mut jsons = Vec::new();
+    let mut inputs = Vec::new();
+    for (source, json) in sources {
+        if let Err(msg) = crate::whitelists::check_custom_whitelists(&json, &source) {
+            eprintln!("Error merging custom whitelists: {msg}");
+            return ERROR_CODE_PARAM;
+        }
+        inputs.push((source, crate::whitelists::parse_whitelists(&json).0));
+        jsons.push(json);
+    }
+    let conflicts = crate::whitelists::extends_conflicts(&inputs);
+    for conflict in &conflicts {
+        let variants: Vec<String> = conflict["extends"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|variant| {
+                format!(
+                    "{} in {}",
+                    variant["extends"],
+                    variant["source"].as_str().unwrap_or("")
+                )
+            })
+            .collect();
+        eprintln!(
+            "Conflict: whitelist '{}' extends {}",
+            conflict["whitelist"].as_str().unwrap_or(""),
+            variants.join(", ")
+        );
+    }
+
+    let mut jsons = jsons.into_iter();
+    let mut merged_json = jsons.next().unwrap_or_default();
+    for json in jsons {
+        merged_json = merge_custom_whitelists(merged_json, json);
+        if merged_json.is_empty() {
+            eprintln!("Failed to merge custom whitelists");
+            return ERROR_CODE_SERVER_ERROR;
+        }
+    }
+
+    let mut merged = match serde_json::from_str::<serde_json::Value>(&merged_json) {
+        Ok(merged) => merged,
         Err(e) => {
             eprintln!("Error parsing merged whitelist JSON: {}", e);
-            ERROR_CODE_PARAM
+            return ERROR_CODE_PARAM;
         }
+    };
+    crate::whitelists::normalize_merged_whitelists(&mut merged, &inputs, annotate_sources);
+    match serde_json::to_string_pretty(&merged) {
+        Ok(pretty_json) => println!("{}", pretty_json),
+        Err(_) => println!("{}", merged_json),
+    }
+
+    if fail_on_conflict && !conflicts.is_empty() {
+        eprintln!(
+            "{} whitelist(s) have conflicting extends across inputs",
+            conflicts.len()
+        );
+        return ERROR_CODE_MISMATCH;
     }
+    0
 }
 
 pub fn base_compare_custom_whitelists(whitelist1_json: String, whitelist2_json: String) -> i32 {
@@ -0,0 +1,708 @@
+// Conversion of threat-intel feeds into the flodbadd custom blacklist schema.
+//
+// Blacklists only match IP addresses and ranges, so domain indicators (hosts
+// files, adblock lists, STIX and MISP domain attributes) are either resolved
+// to their current addresses or reported as skipped.
+
+use crate::whitelists::{cidr_contains, parse_cidr};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntelFormat {
+    Ips,
+    Hosts,
+    Adblock,
+    Csv,
+    Stix,
+    Misp,
+}
+
+impl IntelFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ips" | "ip" | "cidr" | "text" => Some(IntelFormat::Ips),
+            "hosts" => Some(IntelFormat::Hosts),
+            "adblock" => Some(IntelFormat::Adblock),
+            "csv" => Some(IntelFormat::Csv),
+            "stix" | "stix2" => Some(IntelFormat::Stix),
+            "misp" => Some(IntelFormat::Misp),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the file name and content.
+    pub fn detect(file: &str, content: &str) -> Self {
+        if let Ok(json) = serde_json::from_str::<Value>(content) {
+            if json.get("type").and_then(|t| t.as_str()) == Some("bundle") {
+                return IntelFormat::Stix;
+            }
+            return IntelFormat::Misp;
+        }
+        if file.to_lowercase().ends_with(".csv") {
+            return IntelFormat::Csv;
+        }
+        let lines: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('!'))
+            .take(50)
+            .collect();
+        if lines.iter().any(|l| l.starts_with("||")) {
+            return IntelFormat::Adblock;
+        }
+        let hosts_line = |l: &&str| {
+            let mut fields = l.split_whitespace();
+            matches!(
+                (fields.next().map(|f| f.parse::<IpAddr>()), fields.next()),
+                (Some(Ok(_)), Some(_))
+            )
+        };
+        if !lines.is_empty() && lines.iter().all(hosts_line) {
+            return IntelFormat::Hosts;
+        }
+        IntelFormat::Ips
+    }
+}
+
+/// Indicators extracted from a feed, before conversion to IP ranges.
+#[derive(Debug, Default)]
+pub struct Indicators {
+    pub ips: Vec<String>,
+    pub domains: BTreeSet<String>,
+    pub invalid: Vec<String>,
+}
+
+impl Indicators {
+    fn add_ip(&mut self, value: &str) {
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        if !value.is_empty() {
+            self.ips.push(value.to_string());
+        }
+    }
+
+    fn add_domain(&mut self, value: &str) {
+        let domain = value
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .trim_end_matches('.')
+            .to_lowercase();
+        if domain.is_empty() {
+            return;
+        }
+        // Addresses show up in domain lists too
+        if parse_cidr(&domain).is_some() {
+            self.ips.push(domain);
+        } else if is_domain(&domain) {
+            self.domains.insert(domain);
+        } else {
+            self.invalid.push(domain);
+        }
+    }
+}
+
+fn is_domain(value: &str) -> bool {
+    value.contains('.')
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split('#').next().unwrap_or("").trim()
+}
+
+fn parse_ip_list(content: &str, indicators: &mut Indicators) {
+    for line in content.lines() {
+        // Several lists use ";" for trailing comments, e.g. Spamhaus DROP
+        let line = strip_comment(line).split(';').next().unwrap_or("");
+        for token in line.split([',', ' ', '\t']).filter(|t| !t.is_empty()) {
+            indicators.add_ip(token);
+        }
+    }
+}
+
+fn parse_hosts(content: &str, indicators: &mut Indicators) {
+    for line in content.lines() {
+        let mut fields = strip_comment(line).split_whitespace();
+        // The leading address is the sinkhole, the names are the indicators
+        if fields.next().is_none() {
+            continue;
+        }
+        for name in fields {
+            if !matches!(
+                name,
+                "localhost" | "localhost.localdomain" | "broadcasthost"
+            ) {
+                indicators.add_domain(name);
+            }
+        }
+    }
+}
+
+fn parse_adblock(content: &str, indicators: &mut Indicators) {
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            continue;
+        }
+        // Exceptions (@@) and cosmetic filters (##) are not block rules
+        if line.starts_with("@@") || line.contains("##") {
+            continue;
+        }
+        let rule = line.trim_start_matches("||");
+        let rule = rule.split(['^', '$', '/']).next().unwrap_or("");
+        if !rule.contains('*') {
+            indicators.add_domain(rule);
+        }
+    }
+}
+
+// Split a CSV line, honouring double quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+const CSV_IP_COLUMNS: [&str; 8] = [
+    "ip",
+    "ip_address",
+    "ipaddress",
+    "dst_ip",
+    "cidr",
+    "network",
+    "indicator",
+    "value",
+];
+const CSV_DOMAIN_COLUMNS: [&str; 3] = ["domain", "hostname", "host"];
+
+fn parse_csv(
+    content: &str,
+    column: Option<&str>,
+    indicators: &mut Indicators,
+) -> Result<(), String> {
+    let rows: Vec<Vec<String>> = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(csv_fields)
+        .collect();
+    let Some(first) = rows.first() else {
+        return Ok(());
+    };
+    let header: Vec<String> = first.iter().map(|h| h.to_lowercase()).collect();
+    let has_header = !first.iter().any(|f| parse_cidr(f).is_some());
+
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| header.iter().position(|h| h == n))
+    };
+    let (index, domains) = match column {
+        Some(column) => match column.parse::<usize>() {
+            Ok(index) => (index, false),
+            Err(_) => match header.iter().position(|h| *h == column.to_lowercase()) {
+                Some(index) => (
+                    index,
+                    CSV_DOMAIN_COLUMNS.contains(&column.to_lowercase().as_str()),
+                ),
+                None => return Err(format!("CSV has no column named '{}'", column)),
+            },
+        },
+        None if has_header => match (find(&CSV_IP_COLUMNS), find(&CSV_DOMAIN_COLUMNS)) {
+            (Some(index), _) => (index, false),
+            (None, Some(index)) => (index, true),
+            (None, None) => {
+                return Err(format!(
+                    "cannot find an IP or domain column in CSV header {:?}, use --column",
+                    first
+                ))
+            }
+        },
+        // No header: use the first column holding addresses
+        None => match first.iter().position(|f| parse_cidr(f).is_some()) {
+            Some(index) => (index, false),
+            None => return Err("cannot find an IP column in CSV, use --column".to_string()),
+        },
+    };
+
+    for row in rows.iter().skip(usize::from(has_header)) {
+        match row.get(index) {
+            Some(value) if domains => indicators.add_domain(value),
+            Some(value) => indicators.add_ip(value),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+// Comparison expressions of a STIX pattern, e.g. [ipv4-addr:value = '198.51.100.1']
+fn parse_stix_pattern(pattern: &str, indicators: &mut Indicators) {
+    let mut rest = pattern;
+    while let Some(start) = rest.find(":value") {
+        let object_type = rest[..start]
+            .rsplit(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .next()
+            .unwrap_or("");
+        let after = &rest[start + ":value".len()..];
+        let value = after.split_once('\'').and_then(|(operator, quoted)| {
+            // Only equality and set membership name concrete indicators
+            let operator = operator.trim();
+            if operator == "=" || operator == "IN (" || operator == "IN" {
+                quoted.split_once('\'').map(|(value, _)| value)
+            } else {
+                None
+            }
+        });
+        if let Some(value) = value {
+            match object_type {
+                "ipv4-addr" | "ipv6-addr" => indicators.add_ip(value),
+                "domain-name" => indicators.add_domain(value),
+                _ => {}
+            }
+        }
+        rest = after;
+    }
+}
+
+fn parse_stix(bundle: &Value, indicators: &mut Indicators) -> Result<(), String> {
+    let objects = bundle
+        .get("objects")
+        .and_then(|o| o.as_array())
+        .ok_or("STIX bundle has no 'objects' array")?;
+    for object in objects {
+        if object.get("revoked").and_then(|r| r.as_bool()) == Some(true) {
+            continue;
+        }
+        let value = object.get("value").and_then(|v| v.as_str());
+        match object.get("type").and_then(|t| t.as_str()) {
+            Some("indicator") => {
+                let pattern_type = object.get("pattern_type").and_then(|t| t.as_str());
+                if pattern_type.is_none_or(|t| t == "stix") {
+                    if let Some(pattern) = object.get("pattern").and_then(|p| p.as_str()) {
+                        parse_stix_pattern(pattern, indicators);
+                    }
+                }
+            }
+            Some("ipv4-addr") | Some("ipv6-addr") => indicators.add_ip(value.unwrap_or("")),
+            Some("domain-name") => indicators.add_domain(value.unwrap_or("")),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn misp_attribute(attribute: &Value, indicators: &mut Indicators) {
+    let (Some(kind), Some(value)) = (
+        attribute.get("type").and_then(|t| t.as_str()),
+        attribute.get("value").and_then(|v| v.as_str()),
+    ) else {
+        return;
+    };
+    // Composite attributes hold "first|second"
+    let mut parts = value.split('|');
+    let first = parts.next().unwrap_or("");
+    let second = parts.next().unwrap_or("");
+    match kind {
+        "ip-src" | "ip-dst" | "ip-src|port" | "ip-dst|port" => indicators.add_ip(first),
+        "domain" | "hostname" | "hostname|port" => indicators.add_domain(first),
+        "domain|ip" => {
+            indicators.add_domain(first);
+            indicators.add_ip(second);
+        }
+        _ => {}
+    }
+}
+
+fn parse_misp(json: &Value, indicators: &mut Indicators) -> Result<(), String> {
+    // A single event, a list of events or a REST search response
+    let events: Vec<&Value> = match json {
+        Value::Array(items) => items.iter().collect(),
+        _ => match json.get("response") {
+            Some(Value::Array(items)) => items.iter().collect(),
+            _ => vec![json],
+        },
+    };
+    let mut found = false;
+    for event in events {
+        let event = event.get("Event").unwrap_or(event);
+        let attributes = event.get("Attribute").and_then(|a| a.as_array());
+        let objects = event.get("Object").and_then(|o| o.as_array());
+        found |= attributes.is_some() || objects.is_some();
+        for attribute in attributes.into_iter().flatten() {
+            misp_attribute(attribute, indicators);
+        }
+        for object in objects.into_iter().flatten() {
+            let attributes = object.get("Attribute").and_then(|a| a.as_array());
+            for attribute in attributes.into_iter().flatten() {
+                misp_attribute(attribute, indicators);
+            }
+        }
+    }
+    if !found {
+        return Err("JSON is neither a STIX 2.1 bundle nor a MISP event".to_string());
+    }
+    Ok(())
+}
+
+pub fn extract_indicators(
+    content: &str,
+    format: IntelFormat,
+    csv_column: Option<&str>,
+) -> Result<Indicators, String> {
+    let mut indicators = Indicators::default();
+    match format {
+        IntelFormat::Ips => parse_ip_list(content, &mut indicators),
+        IntelFormat::Hosts => parse_hosts(content, &mut indicators),
+        IntelFormat::Adblock => parse_adblock(content, &mut indicators),
+        IntelFormat::Csv => parse_csv(content, csv_column, &mut indicators)?,
+        IntelFormat::Stix | IntelFormat::Misp => {
+            let json: Value =
+                serde_json::from_str(content).map_err(|e| format!("invalid JSON: {}", e))?;
+            if format == IntelFormat::Stix {
+                parse_stix(&json, &mut indicators)?;
+            } else {
+                parse_misp(&json, &mut indicators)?;
+            }
+        }
+    }
+    Ok(indicators)
+}
+
+/// Resolve domains to their current addresses. Unresolvable domains are returned.
+pub fn resolve_domains(indicators: &mut Indicators) -> Vec<String> {
+    let mut unresolved = Vec::new();
+    for domain in &indicators.domains {
+        match (domain.as_str(), 0).to_socket_addrs() {
+            Ok(addrs) => {
+                let before = indicators.ips.len();
+                indicators
+                    .ips
+                    .extend(addrs.map(|addr| addr.ip().to_string()));
+                if indicators.ips.len() == before {
+                    unresolved.push(domain.clone());
+                }
+            }
+            Err(_) => unresolved.push(domain.clone()),
+        }
+    }
+    unresolved
+}
+
+fn masked(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+/// Validate and normalize addresses and ranges: host bits are cleared,
+/// duplicates and ranges contained in other ranges are dropped. Returns the
+/// sorted ranges and the rejected values.
+pub fn normalize_ip_ranges(values: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut invalid = Vec::new();
+    let mut ranges: BTreeSet<(IpAddr, u8)> = BTreeSet::new();
+    for value in values {
+        match parse_cidr(value) {
+            Some((addr, prefix)) => {
+                ranges.insert((masked(addr, prefix), prefix));
+            }
+            None => invalid.push(value.clone()),
+        }
+    }
+    let formatted: Vec<(String, u8)> = ranges
+        .iter()
+        .map(|(addr, prefix)| {
+            let full = if addr.is_ipv4() { 32 } else { 128 };
+            if *prefix == full {
+                (addr.to_string(), *prefix)
+            } else {
+                (format!("{}/{}", addr, prefix), *prefix)
+            }
+        })
+        .collect();
+    let kept = formatted
+        .iter()
+        .filter(|(range, prefix)| {
+            !formatted
+                .iter()
+                .any(|(other, other_prefix)| other_prefix < prefix && cidr_contains(other, range))
+        })
+        .map(|(range, _)| range.clone())
+        .collect();
+    (kept, invalid)
+}
+
+// Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+pub fn today_utc() -> (i64, u32, u32) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    civil_from_days(secs.div_euclid(86_400))
+}
+
+/// One blacklist entry in the flodbadd schema.
+pub fn blacklist_entry(
+    name: &str,
+    description: &str,
+    source_url: &str,
+    ip_ranges: &[String],
+) -> Value {
+    let (year, month, day) = today_utc();
+    json!({
+        "name": name,
+        "description": description,
+        "last_updated": format!("{:04}-{:02}-{:02}", year, month, day),
+        "source_url": source_url,
+        "ip_ranges": ip_ranges,
+    })
+}
+
+pub fn blacklist_document(entries: Vec<Value>) -> Value {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    let (year, month, day) = today_utc();
+    json!({
+        "date": format!("{} {} {}", MONTHS[month as usize - 1], day, year),
+        "signature": "",
+        "blacklists": entries,
+    })
+}
+
+/// Add `entry` to the blacklist document `current`, replacing any blacklist
+/// with the same name. An empty document starts a new one.
+pub fn merge_blacklist_entry(current: &str, entry: Value) -> Result<Value, String> {
+    if current.trim().is_empty() {
+        return Ok(blacklist_document(vec![entry]));
+    }
+    let mut document: Value = serde_json::from_str(current)
+        .map_err(|e| format!("invalid current blacklist JSON: {}", e))?;
+    let blacklists = document
+        .get_mut("blacklists")
+        .and_then(|b| b.as_array_mut())
+        .ok_or("current blacklist JSON has no 'blacklists' array")?;
+    blacklists.retain(|b| b.get("name") != entry.get("name"));
+    blacklists.push(entry);
+    // The merged list is not the one that was signed
+    document["signature"] = json!("");
+    Ok(document)
+}
+
+/// Options of the `import-blacklist` command.
+#[derive(Debug, Default)]
+pub struct BlacklistImport {
+    pub file: String,
+    pub name: Option<String>,
+    pub format: Option<IntelFormat>,
+    pub csv_column: Option<String>,
+    pub description: Option<String>,
+    pub source_url: Option<String>,
+    pub resolve_domains: bool,
+}
+
+/// Read a feed and convert it into one blacklist entry, reporting what was
+/// dropped on stderr.
+pub fn import_blacklist_entry(options: &BlacklistImport) -> Result<Value, String> {
+    let content = std::fs::read_to_string(&options.file)
+        .map_err(|e| format!("Error reading feed file '{}': {}", options.file, e))?;
+    let format = options
+        .format
+        .unwrap_or_else(|| IntelFormat::detect(&options.file, &content));
+    let mut indicators = extract_indicators(&content, format, options.csv_column.as_deref())
+        .map_err(|e| format!("Error parsing '{}' as {:?}: {}", options.file, format, e))?;
+
+    if !indicators.domains.is_empty() {
+        if options.resolve_domains {
+            let unresolved = resolve_domains(&mut indicators);
+            if !unresolved.is_empty() {
+                eprintln!("{} domain(s) could not be resolved", unresolved.len());
+            }
+        } else {
+            eprintln!(
+                "Skipped {} domain indicator(s), blacklists only match IP ranges (use --resolve-domains)",
+                indicators.domains.len()
+            );
+        }
+    }
+    let (ranges, invalid_ips) = normalize_ip_ranges(&indicators.ips);
+    let removed = indicators.ips.len() - invalid_ips.len() - ranges.len();
+    let invalid: Vec<&String> = indicators.invalid.iter().chain(&invalid_ips).collect();
+    if let Some(example) = invalid.first() {
+        eprintln!(
+            "Skipped {} invalid indicator(s), e.g. '{}'",
+            invalid.len(),
+            example
+        );
+    }
+    if ranges.is_empty() {
+        return Err(format!("No IP indicators found in '{}'", options.file));
+    }
+    eprintln!(
+        "Imported {} IP range(s) from {} ({:?} format, {} duplicate or contained entries removed)",
+        ranges.len(),
+        options.file,
+        format,
+        removed
+    );
+
+    let stem = std::path::Path::new(&options.file)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("imported");
+    let name = options.name.clone().unwrap_or_else(|| stem.to_string());
+    let description = options
+        .description
+        .clone()
+        .unwrap_or_else(|| format!("Imported from {} ({:?})", options.file, format));
+    let source_url = options
+        .source_url
+        .clone()
+        .unwrap_or_else(|| options.file.clone());
+    Ok(blacklist_entry(&name, &description, &source_url, &ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_lists_are_validated_and_deduplicated() {
+        let content = "# feed\n198.51.100.7\n198.51.100.7 ; dup\n10.1.2.3/8\n10.9.9.9\n2001:db8::1/32\nnot-an-ip\n";
+        let indicators = extract_indicators(content, IntelFormat::Ips, None).unwrap();
+        let (ranges, invalid) = normalize_ip_ranges(&indicators.ips);
+        // Host bits are cleared and 10.9.9.9 falls inside 10.0.0.0/8
+        assert_eq!(ranges, ["10.0.0.0/8", "198.51.100.7", "2001:db8::/32"]);
+        assert_eq!(invalid, ["not-an-ip"]);
+    }
+
+    #[test]
+    fn domain_lists_are_detected() {
+        let hosts = "127.0.0.1 localhost\n0.0.0.0 ads.example.com tracker.example.net # ads\n";
+        assert_eq!(IntelFormat::detect("hosts.txt", hosts), IntelFormat::Hosts);
+        let indicators = extract_indicators(hosts, IntelFormat::Hosts, None).unwrap();
+        assert_eq!(
+            indicators.domains.iter().collect::<Vec<_>>(),
+            ["ads.example.com", "tracker.example.net"]
+        );
+
+        let adblock = "! Title: list\n||bad.example.com^\n||cdn.example.org^$third-party\n@@||good.example.com^\nexample.com##.ad\n";
+        assert_eq!(
+            IntelFormat::detect("list.txt", adblock),
+            IntelFormat::Adblock
+        );
+        let indicators = extract_indicators(adblock, IntelFormat::Adblock, None).unwrap();
+        assert_eq!(
+            indicators.domains.iter().collect::<Vec<_>>(),
+            ["bad.example.com", "cdn.example.org"]
+        );
+    }
+
+    #[test]
+    fn csv_columns_are_found_by_header() {
+        let csv = "first_seen,dst_ip,port,\"malware, family\"\n2025-01-01,203.0.113.5,443,\"Emotet, variant\"\n2025-01-02,203.0.113.6,8080,x\n";
+        let indicators = extract_indicators(csv, IntelFormat::Csv, None).unwrap();
+        assert_eq!(indicators.ips, ["203.0.113.5", "203.0.113.6"]);
+
+        let indicators = extract_indicators(csv, IntelFormat::Csv, Some("port")).unwrap();
+        assert_eq!(indicators.ips, ["443", "8080"]);
+        assert!(extract_indicators(csv, IntelFormat::Csv, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn stix_and_misp_indicators() {
+        let bundle = r#"{"type": "bundle", "objects": [
+            {"type": "indicator", "pattern_type": "stix",
+             "pattern": "[ipv4-addr:value = '198.51.100.1'] OR [domain-name:value = 'evil.example.com']"},
+            {"type": "indicator", "revoked": true, "pattern": "[ipv4-addr:value = '198.51.100.2']"},
+            {"type": "indicator", "pattern_type": "snort", "pattern": "alert ip any any"},
+            {"type": "ipv6-addr", "value": "2001:db8::5"}
+        ]}"#;
+        assert_eq!(IntelFormat::detect("feed.json", bundle), IntelFormat::Stix);
+        let indicators = extract_indicators(bundle, IntelFormat::Stix, None).unwrap();
+        assert_eq!(indicators.ips, ["198.51.100.1", "2001:db8::5"]);
+        assert!(indicators.domains.contains("evil.example.com"));
+
+        let event = r#"{"Event": {"info": "campaign", "Attribute": [
+            {"type": "ip-dst", "value": "203.0.113.9"},
+            {"type": "ip-dst|port", "value": "203.0.113.10|443"},
+            {"type": "domain|ip", "value": "c2.example.com|203.0.113.11"},
+            {"type": "md5", "value": "d41d8cd98f00b204e9800998ecf8427e"}
+        ], "Object": [{"Attribute": [{"type": "ip-src", "value": "192.0.2.1"}]}]}}"#;
+        let indicators = extract_indicators(event, IntelFormat::Misp, None).unwrap();
+        assert_eq!(
+            indicators.ips,
+            ["203.0.113.9", "203.0.113.10", "203.0.113.11", "192.0.2.1"]
+        );
+        assert!(indicators.domains.contains("c2.example.com"));
+    }
+
+    #[test]
+    fn merge_replaces_same_name_blacklist() {
+        let current = r#"{"date": "d", "signature": "ed25519:a2V5:c2ln", "blacklists": [
+            {"name": "feed", "description": "old", "ip_ranges": ["192.0.2.1"]},
+            {"name": "other", "description": "", "ip_ranges": []}
+        ]}"#;
+        let entry = blacklist_entry("feed", "new", "", &["198.51.100.0/24".to_string()]);
+        let merged = merge_blacklist_entry(current, entry).unwrap();
+        let blacklists = merged["blacklists"].as_array().unwrap();
+        assert_eq!(blacklists.len(), 2);
+        assert_eq!(blacklists[1]["name"], "feed");
+        assert_eq!(blacklists[1]["ip_ranges"], json!(["198.51.100.0/24"]));
+        assert_eq!(merged["signature"], "");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20_000), (2024, 10, 4));
+    }
+}
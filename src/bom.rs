@@ -0,0 +1,393 @@
+// Network bill of materials (egress BOM) of a build.
+//
+// The BOM lists every external endpoint the sessions reached, one entry per
+// (process executable, destination, port, protocol), in an EDAMAME JSON form
+// or as CycloneDX services. Its hash covers the endpoints only, not the bytes
+// or the times, so two builds with the same network dependencies share it.
+
+use crate::ipfix::parse_rfc3339_millis;
+use crate::labels::session_labels;
+use crate::sessions::{session_destination, session_str, session_u64};
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+
+const BOM_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BomFormat {
+    Json,
+    CycloneDx,
+}
+
+impl BomFormat {
+    pub fn from_name(name: &str) -> Option<BomFormat> {
+        match name {
+            "json" => Some(BomFormat::Json),
+            "cyclonedx" => Some(BomFormat::CycloneDx),
+            _ => None,
+        }
+    }
+}
+
+fn session_executable(session: &Value) -> String {
+    session_str(session, "/l7/process_path")
+        .or_else(|| session_str(session, "/l7/process_name"))
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// Whitelist states from the most to the least severe
+fn whitelist_rank(state: &str) -> u8 {
+    match state {
+        "NonConforming" => 0,
+        "Unknown" => 1,
+        _ => 2,
+    }
+}
+
+/// One entry per endpoint, sorted by executable, destination, port and protocol.
+pub fn bom_entries(sessions: &[Value], blacklisted: &HashSet<String>) -> Vec<Value> {
+    let mut entries: BTreeMap<(String, String, u64, String), Value> = BTreeMap::new();
+    for session in sessions {
+        let key = (
+            session_executable(session),
+            session_destination(session),
+            session_u64(session, "/session/dst_port"),
+            session_str(session, "/session/protocol")
+                .unwrap_or("")
+                .to_string(),
+        );
+        let entry = entries.entry(key.clone()).or_insert_with(|| {
+            json!({
+                "process": key.0,
+                "destination": key.1,
+                "ip": session_str(session, "/session/dst_ip"),
+                "port": key.2,
+                "protocol": key.3,
+                "asn": session.get("dst_asn").filter(|asn| !asn.is_null()).map(|_| json!({
+                    "number": session.pointer("/dst_asn/as_number").and_then(|v| v.as_u64()),
+                    "owner": session_str(session, "/dst_asn/owner"),
+                    "country": session_str(session, "/dst_asn/country"),
+                })),
+                "sessions": 0,
+                "outbound_bytes": 0,
+                "inbound_bytes": 0,
+                "first_seen": null,
+                "last_seen": null,
+                "whitelist": "Conforming",
+                "blacklisted": false,
+                "labels": [],
+            })
+        });
+        entry["sessions"] = json!(entry["sessions"].as_u64().unwrap_or(0) + 1);
+        entry["outbound_bytes"] = json!(
+            entry["outbound_bytes"].as_u64().unwrap_or(0)
+                + session_u64(session, "/stats/outbound_bytes")
+        );
+        entry["inbound_bytes"] = json!(
+            entry["inbound_bytes"].as_u64().unwrap_or(0)
+                + session_u64(session, "/stats/inbound_bytes")
+        );
+
+        let first = session_str(session, "/stats/start_time")
+            .or_else(|| session_str(session, "/stats/first_activity"));
+        let last = session_str(session, "/stats/end_time")
+            .or_else(|| session_str(session, "/stats/last_activity"))
+            .or(first);
+        let time = |value: &Value| value.as_str().and_then(parse_rfc3339_millis);
+        if let Some(first) = first {
+            if time(&entry["first_seen"])
+                .is_none_or(|seen| parse_rfc3339_millis(first).is_some_and(|first| first < seen))
+            {
+                entry["first_seen"] = json!(first);
+            }
+        }
+        if let Some(last) = last {
+            if time(&entry["last_seen"])
+                .is_none_or(|seen| parse_rfc3339_millis(last).is_some_and(|last| last > seen))
+            {
+                entry["last_seen"] = json!(last);
+            }
+        }
+
+        let state = session_str(session, "/is_whitelisted").unwrap_or("Unknown");
+        if whitelist_rank(state) < whitelist_rank(entry["whitelist"].as_str().unwrap_or("")) {
+            entry["whitelist"] = json!(state);
+        }
+        if session_str(session, "/uid").is_some_and(|uid| blacklisted.contains(uid)) {
+            entry["blacklisted"] = json!(true);
+        }
+        if let Some(labels) = entry["labels"].as_array_mut() {
+            for label in session_labels(session) {
+                if !labels.iter().any(|known| known == label) {
+                    labels.push(json!(label));
+                }
+            }
+        }
+    }
+    entries.into_values().collect()
+}
+
+/// SHA-256 over the sorted endpoints, as lowercase hex.
+pub fn bom_hash(entries: &[Value]) -> String {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(format!(
+            "{}|{}|{}|{}|{}\n",
+            entry["process"].as_str().unwrap_or(""),
+            entry["destination"].as_str().unwrap_or(""),
+            entry["port"],
+            entry["protocol"].as_str().unwrap_or(""),
+            entry["asn"]["number"]
+        ));
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn bom_json(entries: &[Value], generated_at: &str) -> Value {
+    json!({
+        "bom_format": "edamame-egress-bom",
+        "version": BOM_VERSION,
+        "generated_at": generated_at,
+        "tool": { "name": "edamame_posture", "version": env!("CARGO_PKG_VERSION") },
+        "hash": { "alg": "SHA-256", "content": bom_hash(entries) },
+        "entries": entries,
+    })
+}
+
+/// CycloneDX 1.5 BOM with one service per endpoint.
+pub fn bom_cyclonedx(entries: &[Value], generated_at: &str) -> Value {
+    let services: Vec<Value> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let protocol = entry["protocol"].as_str().unwrap_or("").to_lowercase();
+            let labels = match session_labels(entry).join(",") {
+                labels if labels.is_empty() => Value::Null,
+                labels => Value::from(labels),
+            };
+            let destination = entry["destination"].as_str().unwrap_or("");
+            let property = |name: &str, value: &Value| -> Option<Value> {
+                let value = match value {
+                    Value::Null => return None,
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                Some(json!({ "name": format!("edamame:{}", name), "value": value }))
+            };
+            let properties: Vec<Value> = [
+                ("process", &entry["process"]),
+                ("ip", &entry["ip"]),
+                ("port", &entry["port"]),
+                ("protocol", &entry["protocol"]),
+                ("asn", &entry["asn"]["number"]),
+                ("as_owner", &entry["asn"]["owner"]),
+                ("as_country", &entry["asn"]["country"]),
+                ("sessions", &entry["sessions"]),
+                ("outbound_bytes", &entry["outbound_bytes"]),
+                ("inbound_bytes", &entry["inbound_bytes"]),
+                ("first_seen", &entry["first_seen"]),
+                ("last_seen", &entry["last_seen"]),
+                ("whitelist", &entry["whitelist"]),
+                ("blacklisted", &entry["blacklisted"]),
+                ("labels", &labels),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| property(name, value))
+            .collect();
+            json!({
+                "bom-ref": format!("egress-{}", index + 1),
+                "name": destination,
+                "group": entry["process"],
+                "endpoints": [format!("{}://{}:{}", protocol, destination, entry["port"])],
+                "x-trust-boundary": true,
+                "properties": properties,
+            })
+        })
+        .collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": generated_at,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "edamame_posture",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "properties": [
+                { "name": "edamame:egress-hash", "value": format!("sha256:{}", bom_hash(entries)) },
+            ],
+        },
+        "services": services,
+    })
+}
+
+fn rfc3339(secs: u64) -> String {
+    let (year, month, day) = crate::blacklists::civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Build the BOM of `sessions` and print it, or write it to `output`.
+pub fn write_bom(
+    sessions: &[Value],
+    blacklisted: &HashSet<String>,
+    format: BomFormat,
+    output: Option<&str>,
+) -> i32 {
+    let entries = bom_entries(sessions, blacklisted);
+    let generated_at = rfc3339(crate::dismissals::now_secs());
+    let bom = match format {
+        BomFormat::Json => bom_json(&entries, &generated_at),
+        BomFormat::CycloneDx => bom_cyclonedx(&entries, &generated_at),
+    };
+    let content = match serde_json::to_string_pretty(&bom) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error serializing egress BOM: {}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    match output {
+        Some(path) => match std::fs::write(path, content) {
+            Ok(()) => {
+                println!(
+                    "Egress BOM with {} endpoint(s) saved to {} (sha256:{})",
+                    entries.len(),
+                    path,
+                    bom_hash(&entries)
+                );
+                0
+            }
+            Err(e) => {
+                eprintln!("Error writing egress BOM '{}': {}", path, e);
+                ERROR_CODE_PARAM
+            }
+        },
+        None => {
+            println!("{}", content);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(uid: &str, port: u64, start: &str, bytes: u64, state: &str) -> Value {
+        json!({
+            "uid": uid,
+            "dst_domain": "registry.npmjs.org",
+            "session": { "dst_ip": "104.16.0.35", "dst_port": port, "protocol": "TCP" },
+            "dst_asn": { "as_number": 13335, "owner": "CLOUDFLARENET", "country": "US" },
+            "l7": { "process_name": "node", "process_path": "/usr/bin/node" },
+            "stats": { "start_time": start, "last_activity": start, "outbound_bytes": bytes },
+            "is_whitelisted": state,
+        })
+    }
+
+    #[test]
+    fn entries_group_sessions_by_endpoint() {
+        let sessions = vec![
+            session("b", 443, "2026-05-18T10:05:00Z", 200, "NonConforming"),
+            session("a", 443, "2026-05-18T10:00:00Z", 100, "Conforming"),
+            session("c", 80, "2026-05-18T10:01:00Z", 10, "Conforming"),
+        ];
+        let entries = bom_entries(&sessions, &HashSet::from(["c".to_string()]));
+        assert_eq!(entries.len(), 2);
+        let https = &entries[1];
+        assert_eq!(https["process"], "/usr/bin/node");
+        assert_eq!(https["port"], 443);
+        assert_eq!(https["asn"]["number"], 13335);
+        assert_eq!(https["sessions"], 2);
+        assert_eq!(https["outbound_bytes"], 300);
+        assert_eq!(https["first_seen"], "2026-05-18T10:00:00Z");
+        assert_eq!(https["last_seen"], "2026-05-18T10:05:00Z");
+        assert_eq!(https["whitelist"], "NonConforming");
+        assert_eq!(entries[0]["blacklisted"], true);
+
+        let services = bom_cyclonedx(&entries, "2026-05-18T10:10:00Z")["services"].clone();
+        assert_eq!(services[0]["endpoints"][0], "tcp://registry.npmjs.org:80");
+        assert!(services[1]["properties"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "name": "edamame:asn", "value": "13335" })));
+    }
+
+    #[test]
+    fn incomplete_sessions_do_not_corrupt_their_entry() {
+        let sparse = json!({
+            "uid": "s",
+            "session": { "dst_ip": "10.0.0.1", "dst_port": 22, "protocol": "TCP" },
+        });
+        let mut garbled = sparse.clone();
+        garbled["uid"] = json!("g");
+        garbled["stats"] = json!({ "start_time": "yesterday" });
+        garbled["is_whitelisted"] = json!("Conforming");
+        let mut dated = garbled.clone();
+        dated["uid"] = json!("d");
+        dated["stats"] = json!({ "start_time": "2026-05-18T10:00:00Z" });
+
+        let entries = bom_entries(&[garbled, dated, sparse], &HashSet::new());
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry["process"], "unknown");
+        assert_eq!(entry["destination"], "10.0.0.1");
+        assert!(entry["asn"].is_null());
+        assert_eq!(entry["sessions"], 3);
+        // An unreadable time gives way to a real one, never the reverse
+        assert_eq!(entry["first_seen"], "2026-05-18T10:00:00Z");
+        assert_eq!(entry["last_seen"], "2026-05-18T10:00:00Z");
+        // A session without a whitelist state was not evaluated
+        assert_eq!(entry["whitelist"], "Unknown");
+    }
+
+    #[test]
+    fn an_unwritable_output_is_an_error() {
+        let sessions = vec![session("a", 443, "2026-05-18T10:00:00Z", 1, "Conforming")];
+        assert_eq!(
+            write_bom(
+                &sessions,
+                &HashSet::new(),
+                BomFormat::CycloneDx,
+                Some("/nonexistent-dir/bom.json")
+            ),
+            ERROR_CODE_PARAM
+        );
+    }
+
+    #[test]
+    fn hash_only_depends_on_endpoints() {
+        let first = vec![
+            session("a", 443, "2026-05-18T10:00:00Z", 100, "Conforming"),
+            session("c", 80, "2026-05-18T10:01:00Z", 10, "Conforming"),
+        ];
+        let second = vec![
+            session("x", 80, "2026-06-01T08:00:00Z", 99, "Conforming"),
+            session("y", 443, "2026-06-01T08:00:03Z", 5000, "Conforming"),
+        ];
+        let hash = bom_hash(&bom_entries(&first, &HashSet::new()));
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, bom_hash(&bom_entries(&second, &HashSet::new())));
+        assert_ne!(hash, bom_hash(&bom_entries(&first[..1], &HashSet::new())));
+    }
+}
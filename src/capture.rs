@@ -0,0 +1,346 @@
+// Incremental output and stop conditions of the `capture` command.
+//
+// Sessions are written as soon as they finish, so a capture cut short by a
+// crash or Ctrl-C keeps everything that was already written. Rotated files are
+// renamed `<file>.1`, `<file>.2`, ... in the order they were closed, skipping
+// the names taken by an earlier capture.
+//
+// Each stop reason has its own exit code, so that a script can tell why the
+// capture ended:
+//
+//   0   duration elapsed
+//   4   Ctrl-C or the global --timeout, like other commands
+//   10  --max-sessions reached
+//   11  --until-pid process exited
+//   12  blacklisted session (--stop-on-blacklisted)
+//   13  non-conforming session (--stop-on-nonconforming)
+
+use crate::wait::Interrupted;
+use crate::ERROR_CODE_TIMEOUT;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const EXIT_SESSION_COUNT: i32 = 10;
+pub const EXIT_PROCESS_EXITED: i32 = 11;
+pub const EXIT_BLACKLISTED: i32 = 12;
+pub const EXIT_NON_CONFORMING: i32 = 13;
+
+#[derive(Debug, Default)]
+pub struct CaptureOptions {
+    // Capture duration, unbounded when a stop condition is given instead
+    pub seconds: Option<u64>,
+    pub whitelist_name: String,
+    pub zeek_format: bool,
+    pub local_traffic: bool,
+    // Sessions are written to this file as they finish, printed at the end otherwise
+    pub output: Option<String>,
+    pub rotate_size: Option<u64>,
+    pub rotate_interval: Option<u64>,
+    pub stop_on_blacklisted: bool,
+    pub stop_on_nonconforming: bool,
+    pub max_sessions: Option<usize>,
+    pub until_pid: Option<u32>,
+    // Whitelist generated from the captured sessions
+    pub generate_whitelist: Option<String>,
+    pub with_process: bool,
+    // Existing whitelist file the generated one is merged into
+    pub merge_into: Option<String>,
+}
+
+/// Why a capture ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Duration,
+    SessionCount(usize),
+    ProcessExited(u32),
+    Blacklisted(String),
+    NonConforming(String),
+    Interrupted(Interrupted),
+}
+
+impl StopReason {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StopReason::Duration => 0,
+            StopReason::SessionCount(_) => EXIT_SESSION_COUNT,
+            StopReason::ProcessExited(_) => EXIT_PROCESS_EXITED,
+            StopReason::Blacklisted(_) => EXIT_BLACKLISTED,
+            StopReason::NonConforming(_) => EXIT_NON_CONFORMING,
+            StopReason::Interrupted(_) => ERROR_CODE_TIMEOUT,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            StopReason::Duration => "capture duration elapsed".to_string(),
+            StopReason::SessionCount(count) => format!("{} sessions captured", count),
+            StopReason::ProcessExited(pid) => format!("process {} exited", pid),
+            StopReason::Blacklisted(session) => format!("blacklisted session: {}", session),
+            StopReason::NonConforming(session) => format!("non-conforming session: {}", session),
+            StopReason::Interrupted(Interrupted::Cancelled) => "cancelled".to_string(),
+            StopReason::Interrupted(Interrupted::TimedOut) => "timeout reached".to_string(),
+        }
+    }
+}
+
+/// Tracks sessions across polls and hands out each one once it is over:
+/// when the core reports an end time, or when it stops reporting it.
+pub struct FinishedSessions<T> {
+    active: HashMap<String, T>,
+    done: HashSet<String>,
+}
+
+impl<T> FinishedSessions<T> {
+    pub fn new() -> Self {
+        FinishedSessions {
+            active: HashMap::new(),
+            done: HashSet::new(),
+        }
+    }
+
+    /// Number of distinct sessions seen so far.
+    pub fn seen(&self) -> usize {
+        self.active.len() + self.done.len()
+    }
+
+    /// Take in the (uid, finished, session) of a poll and return the sessions
+    /// that finished since the previous one.
+    pub fn update(&mut self, sessions: Vec<(String, bool, T)>) -> Vec<T> {
+        let mut finished = Vec::new();
+        let mut current = HashSet::new();
+        for (uid, is_finished, session) in sessions {
+            current.insert(uid.clone());
+            if self.done.contains(&uid) {
+                continue;
+            }
+            if is_finished {
+                self.active.remove(&uid);
+                self.done.insert(uid);
+                finished.push(session);
+            } else {
+                self.active.insert(uid, session);
+            }
+        }
+        let vanished: Vec<String> = self
+            .active
+            .keys()
+            .filter(|uid| !current.contains(*uid))
+            .cloned()
+            .collect();
+        for uid in vanished {
+            if let Some(session) = self.active.remove(&uid) {
+                self.done.insert(uid);
+                finished.push(session);
+            }
+        }
+        finished
+    }
+
+    /// Sessions still ongoing, once the capture has stopped.
+    pub fn drain(&mut self) -> Vec<T> {
+        let active = std::mem::take(&mut self.active);
+        let mut sessions = Vec::new();
+        for (uid, session) in active {
+            self.done.insert(uid);
+            sessions.push(session);
+        }
+        sessions
+    }
+}
+
+impl<T> Default for FinishedSessions<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Line-oriented output file with size- and time-based rotation.
+pub struct SessionFile {
+    path: String,
+    file: File,
+    rotate_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+    written: u64,
+    opened_at: Instant,
+    pub lines: usize,
+    pub rotated: Vec<String>,
+}
+
+impl SessionFile {
+    pub fn create(
+        path: &str,
+        rotate_size: Option<u64>,
+        rotate_interval: Option<u64>,
+    ) -> Result<SessionFile, String> {
+        Ok(SessionFile {
+            path: path.to_string(),
+            file: create_file(path)?,
+            rotate_size,
+            rotate_interval: rotate_interval.map(Duration::from_secs),
+            written: 0,
+            opened_at: Instant::now(),
+            lines: 0,
+            rotated: Vec::new(),
+        })
+    }
+
+    /// Append one line and flush it, rotating first when the file is due.
+    pub fn write_line(&mut self, line: &str) -> Result<(), String> {
+        let line_len = line.len() as u64 + 1;
+        let size_due = self
+            .rotate_size
+            .is_some_and(|size| self.written > 0 && self.written + line_len > size);
+        let time_due = self
+            .rotate_interval
+            .is_some_and(|interval| self.written > 0 && self.opened_at.elapsed() >= interval);
+        if size_due || time_due {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Error writing '{}': {}", self.path, e))?;
+        self.written += line_len;
+        self.lines += 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        // Files of an earlier capture to the same path are never overwritten
+        let mut index = self.rotated.len() + 1;
+        while Path::new(&format!("{}.{}", self.path, index)).exists() {
+            index += 1;
+        }
+        let rotated = format!("{}.{}", self.path, index);
+        std::fs::rename(&self.path, &rotated)
+            .map_err(|e| format!("Error rotating '{}': {}", self.path, e))?;
+        self.file = create_file(&self.path)?;
+        self.rotated.push(rotated);
+        self.written = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+}
+
+fn create_file(path: &str) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|e| format!("Error creating '{}': {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_handed_out_once_finished() {
+        let mut tracker = FinishedSessions::new();
+        let finished = tracker.update(vec![
+            ("a".to_string(), false, "a"),
+            ("b".to_string(), true, "b"),
+        ]);
+        assert_eq!(finished, vec!["b"]);
+        // b is still reported by the core, a vanished and c is new
+        let finished = tracker.update(vec![
+            ("b".to_string(), true, "b"),
+            ("c".to_string(), false, "c"),
+        ]);
+        assert_eq!(finished, vec!["a"]);
+        assert_eq!(tracker.seen(), 3);
+        assert_eq!(tracker.drain(), vec!["c"]);
+        assert!(tracker
+            .update(vec![("c".to_string(), true, "c")])
+            .is_empty());
+    }
+
+    #[test]
+    fn output_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("edamame-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.ndjson").to_string_lossy().to_string();
+        let mut file = SessionFile::create(&path, Some(20), None).unwrap();
+        for line in [
+            "{\"uid\":\"one\"}",
+            "{\"uid\":\"two\"}",
+            "{\"uid\":\"three\"}",
+        ] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(file.lines, 3);
+        assert_eq!(
+            file.rotated,
+            vec![format!("{}.1", path), format!("{}.2", path)]
+        );
+        assert_eq!(
+            std::fs::read_to_string(format!("{}.1", path)).unwrap(),
+            "{\"uid\":\"one\"}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"uid\":\"three\"}\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_keeps_the_files_of_an_earlier_capture() {
+        let dir = std::env::temp_dir().join(format!("edamame-rotation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.ndjson").to_string_lossy().to_string();
+        // An earlier capture left .1 and .3 behind
+        std::fs::write(format!("{}.1", path), "earlier 1\n").unwrap();
+        std::fs::write(format!("{}.3", path), "earlier 3\n").unwrap();
+
+        let mut file = SessionFile::create(&path, Some(10), None).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(
+            file.rotated,
+            vec![
+                format!("{}.2", path),
+                format!("{}.4", path),
+                format!("{}.5", path)
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(format!("{}.1", path)).unwrap(),
+            "earlier 1\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(format!("{}.3", path)).unwrap(),
+            "earlier 3\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(format!("{}.4", path)).unwrap(),
+            "second\n"
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stop_reasons_have_distinct_exit_codes() {
+        let reasons = [
+            StopReason::Duration,
+            StopReason::SessionCount(5),
+            StopReason::ProcessExited(42),
+            StopReason::Blacklisted(String::new()),
+            StopReason::NonConforming(String::new()),
+            StopReason::Interrupted(Interrupted::TimedOut),
+        ];
+        let codes: HashSet<i32> = reasons.iter().map(StopReason::exit_code).collect();
+        assert_eq!(codes.len(), reasons.len());
+        assert_eq!(StopReason::Duration.exit_code(), 0);
+        // Ctrl-C and the timeout share the code of the other commands
+        assert_eq!(
+            StopReason::Interrupted(Interrupted::Cancelled).exit_code(),
+            ERROR_CODE_TIMEOUT
+        );
+    }
+}
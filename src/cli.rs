@@ -1,7 +1,15 @@
+use crate::geoip::parse_country;
+use crate::history::parse_field_filter;
+use crate::parse_cidr_arg;
 use crate::parse_digits_only;
+use crate::parse_duration;
 use crate::parse_email;
+use crate::parse_expiry;
 use crate::parse_fqdn;
+use crate::parse_port_set;
 use crate::parse_signature;
+use crate::parse_size;
+use crate::parse_time;
 use crate::parse_username;
 use crate::CORE_VERSION;
 use clap::{arg, Arg, ArgAction, Command};
@@ -30,36 +38,30 @@ pub fn build_cli() -> Command {
         .action(ArgAction::Count)
         .global(true),
     )
+    .arg(
+        Arg::new("timeout")
+            .long("timeout")
+            .value_name("DURATION")
+            .help("Give up waiting (score, LAN scan, capture, connection) after this duration, e.g. 90s or 10m, with exit code 4")
+            .env("EDAMAME_TIMEOUT")
+            .required(false)
+            .global(true)
+            .value_parser(parse_duration),
+    )
+    .arg(
+        arg!(--"progress-json" "Report the progress of waits as JSON lines on stderr")
+            .required(false)
+            .global(true),
+    )
     ////////////////
     // Base commands
     ////////////////
     .subcommand(Command::new("get-score").alias("score").about("Get score information"))
-    .subcommand(Command::new("lanscan").about("Performs a LAN scan"))
-    .subcommand(
-        Command::new("capture")
-            .about("Capture packets")
-            .arg(
-                arg!([SECONDS] "Number of seconds to capture")
-                    .required(false)
-                    .value_parser(clap::value_parser!(u64)),
-            )
-            .arg(
-                arg!([WHITELIST_NAME] "Whitelist name")
-                    .required(false)
-                    .value_parser(clap::value_parser!(String)),
-            )
-            .arg(
-                arg!([ZEEK_FORMAT] "Zeek format")
-                    .required(false)
-                    .value_parser(clap::value_parser!(bool)),
-            )
-            .arg(
-                arg!([LOCAL_TRAFFIC] "Include local traffic")
-                    .required(false)
-                    .default_value("false")
-                    .value_parser(clap::value_parser!(bool)),
-            ),
-    )
+    .subcommand(lanscan_command())
+    .subcommand(lanscan_diff_command())
+    .subcommand(capture_command())
+    .subcommand(run_command())
+    .subcommands(step_commands())
     .subcommand(Command::new("get-core-info").about("Get core information"))
     .subcommand(Command::new("get-device-info").about("Get device information"))
     .subcommand(Command::new("get-system-info").about("Get system information"))
@@ -98,7 +100,8 @@ pub fn build_cli() -> Command {
                 arg!(<IP_ADDRESS> "Device IP address")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
+            )
+            .args(dismiss_args()),
     )
     .subcommand(
         Command::new("dismiss-device-port")
@@ -112,7 +115,8 @@ pub fn build_cli() -> Command {
                 arg!(<PORT> "Port number")
                     .required(true)
                     .value_parser(clap::value_parser!(u16)),
-            ),
+            )
+            .args(dismiss_args()),
     )
     .subcommand(
         Command::new("dismiss-session")
@@ -121,7 +125,8 @@ pub fn build_cli() -> Command {
                 arg!(<SESSION_UID> "Session UID")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
+            )
+            .args(dismiss_args()),
     )
     .subcommand(
         Command::new("dismiss-session-process")
@@ -130,8 +135,12 @@ pub fn build_cli() -> Command {
                 arg!(<SESSION_UID> "Session or process UID")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
+            )
+            .args(dismiss_args()),
     )
+    .subcommand(network_dismissals_command())
+    .subcommand(expiring_suppressions_command())
+    .subcommand(check_suppressions_command())
     .subcommand(Command::new("rollback-threat").about("Rollback a threat").arg(
         arg!(<THREAT_ID> "Threat ID")
             .required(true)
@@ -255,6 +264,30 @@ pub fn build_cli() -> Command {
                 arg!(--"fail-on-anomalous" "Exit with code 1 if anomalous sessions are detected")
                     .required(false)
                     .action(ArgAction::SetTrue),
+            )
+            .arg(step_arg("Only show the sessions of this pipeline step; the whitelist and blacklist checks then only consider them"))
+            .arg(
+                arg!(--"country" <CODE> "Only show sessions to addresses located in this country, by ISO code (repeatable, needs a Country database)")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .value_parser(parse_country),
+            )
+            .arg(
+                arg!(--"asn" <NUMBER> "Only show sessions to this autonomous system (repeatable)")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(u32)),
+            )
+            .arg(
+                arg!(--"label" <PATTERN> "Only show sessions with a label matching this pattern, from the EDAMAME_LABELS_FILE rules (repeatable)")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(String)),
+            )
+            .arg(
+                arg!(--"summary" <BY> "Print the sessions and bytes per country or per AS after the sessions")
+                    .required(false)
+                    .value_parser(["country", "asn"]),
             ),
     )
     .subcommand(
@@ -273,166 +306,90 @@ pub fn build_cli() -> Command {
                     .value_parser(clap::value_parser!(bool)),
             ),
     )
-    .subcommand(Command::new("background-threats-info").alias("get-threats-info").about("Get threats information of the background process"))
-    .subcommand(
-        Command::new("foreground-start")
-            .about("Start reporting in the foreground (used by the systemd service)")
-            .args(start_common_args()),
-    )
-    .subcommand(
-        Command::new("background-start")
-            .alias("start")
-            .about("Start reporting background process")
-            .args(start_common_args()),
-    )
-    .subcommand(Command::new("background-stop").alias("stop").about("Stop reporting background process"))
-    ////////////////
-    // MCP Server commands
-    ////////////////
     .subcommand(
-        Command::new("background-mcp-start").alias("mcp-start").about("Start MCP server for external AI clients (e.g., Claude Desktop)")
-            .arg(
-                arg!([PORT] "Port to listen on")
-                    .required(false)
-                    .default_value("3000")
-                    .value_parser(clap::value_parser!(u16)),
-            )
+        Command::new("explain-session")
+            .about("Explain why a session does or does not conform to the active whitelists")
             .arg(
-                arg!([PSK] "Pre-shared key for authentication (min 32 chars)")
-                    .required(false)
+                arg!(<SESSION_UID> "Session UID")
+                    .required(true)
                     .value_parser(clap::value_parser!(String)),
             )
             .arg(
-                Arg::new("all-interfaces")
-                    .long("all-interfaces")
-                    .help("Listen on all interfaces")
-                    .required(false)
-                    .action(ArgAction::SetTrue),
-            )
-    )
-    .subcommand(Command::new("background-mcp-stop").alias("mcp-stop").about("Stop MCP server"))
-    .subcommand(Command::new("background-mcp-status").alias("mcp-status").about("Get MCP server status"))
-    .subcommand(Command::new("background-mcp-generate-psk").alias("mcp-generate-psk").about("Generate a secure PSK for MCP server"))
-    .subcommand(Command::new("background-agentic-summary").alias("agentic-summary").about("Get comprehensive agentic status summary (provider, mode, todos, actions, Slack)"))
-    .subcommand(
-        Command::new("background-agentic-start")
-            .alias("agentic-start")
-            .about("Start AI assistant todo-processing loop in background process")
-            .arg(
-                arg!([MODE] "Loop mode: auto (execute) or analyze (review)")
+                arg!(--"limit" <COUNT> "Number of candidate rules to show")
                     .required(false)
-                    .default_value("analyze")
-                    .value_parser(["auto", "analyze"]),
+                    .default_value("5")
+                    .value_parser(clap::value_parser!(usize)),
             )
             .arg(
-                arg!([INTERVAL_SECS] "Tick interval in seconds")
+                arg!(--"json" "Output the explanation as JSON")
                     .required(false)
-                    .default_value("3600")
-                    .value_parser(clap::value_parser!(u64)),
+                    .action(ArgAction::SetTrue),
             ),
     )
     .subcommand(
-        Command::new("background-agentic-stop")
-            .alias("agentic-stop")
-            .about("Stop AI assistant todo-processing loop in background process"),
-    )
-    .subcommand(
-        Command::new("background-agentic-status")
-            .alias("agentic-status")
-            .about("Get AI assistant loop status"),
+        Command::new("background-sessions-snapshot")
+            .alias("sessions-snapshot")
+            .about("Persist the current sessions of the background process")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("save")
+                    .about("Save the current sessions to a JSON snapshot file")
+                    .arg(
+                        arg!(<FILE> "Snapshot file path")
+                            .required(true)
+                            .value_parser(clap::value_parser!(String)),
+                    )
+                    .arg(
+                        arg!(--"include-local-traffic" "Include local traffic in the snapshot")
+                            .required(false)
+                            .action(ArgAction::SetTrue),
+                    ),
+            ),
     )
+    .subcommand(egress_bom_command())
+    .subcommand(history_query_command())
+    .subcommand(ioc_sweep_command())
     .subcommand(
-        Command::new("background-divergence-upsert-model")
-            .alias("divergence-upsert-model")
-            .about("Upsert a behavioral model JSON payload for divergence detection")
+        Command::new("sessions-diff")
+            .about("Compare two session snapshots (new/vanished destinations, new processes, byte changes)")
             .arg(
-                arg!(<MODEL_JSON> "Behavioral model JSON")
+                arg!(<OLD_FILE> "Baseline snapshot file path")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
-    )
-    .subcommand(
-        Command::new("background-divergence-upsert-model-from-file")
-            .alias("divergence-upsert-model-from-file")
-            .about("Upsert a behavioral model JSON payload from file")
+            )
             .arg(
-                arg!(<MODEL_FILE> "Path to behavioral model JSON file")
+                arg!(<NEW_FILE> "Current snapshot file path")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
-    )
-    .subcommand(
-        Command::new("background-divergence-get-model")
-            .alias("divergence-get-model")
-            .about("Get current behavioral model used by divergence engine"),
-    )
-    .subcommand(
-        Command::new("background-divergence-clear-model")
-            .alias("divergence-clear-model")
-            .about("Clear current behavioral model"),
-    )
-    .subcommand(
-        Command::new("background-divergence-start")
-            .alias("divergence-start")
-            .about("Start divergence engine in background process")
+            )
             .arg(
-                arg!([INTERVAL_SECS] "Tick interval in seconds")
+                arg!(--"json" "Output the diff as JSON")
                     .required(false)
-                    .default_value("120")
-                    .value_parser(clap::value_parser!(u64)),
-            ),
-    )
-    .subcommand(
-        Command::new("background-divergence-stop")
-            .alias("divergence-stop")
-            .about("Stop divergence engine in background process"),
-    )
-    .subcommand(
-        Command::new("background-divergence-status")
-            .alias("divergence-status")
-            .about("Get divergence engine status"),
-    )
-    .subcommand(
-        Command::new("background-divergence-get-verdict")
-            .alias("divergence-get-verdict")
-            .about("Get latest divergence verdict"),
-    )
-    .subcommand(
-        Command::new("background-divergence-get-history")
-            .alias("divergence-get-history")
-            .about("Get divergence verdict history")
+                    .action(ArgAction::SetTrue),
+            )
             .arg(
-                arg!([LIMIT] "Maximum number of history entries")
+                arg!(--"fail-on-new-egress" "Exit with code 1 if new destinations appear since the baseline")
                     .required(false)
-                    .default_value("20")
-                    .value_parser(clap::value_parser!(usize)),
-            ),
-    )
-    .subcommand(
-        Command::new("background-divergence-dismiss")
-            .alias("divergence-dismiss")
-            .about("Dismiss divergence evidence by finding key")
-            .arg(
-                arg!(<FINDING_KEY> "Finding key to dismiss")
-                    .required(true)
-                    .value_parser(clap::value_parser!(String)),
+                    .action(ArgAction::SetTrue),
             ),
     )
+    .subcommand(Command::new("background-threats-info").alias("get-threats-info").about("Get threats information of the background process"))
     .subcommand(
-        Command::new("background-divergence-undismiss")
-            .alias("divergence-undismiss")
-            .about("Restore previously dismissed divergence evidence")
-            .arg(
-                arg!(<FINDING_KEY> "Finding key to restore")
-                    .required(true)
-                    .value_parser(clap::value_parser!(String)),
-            ),
+        Command::new("foreground-start")
+            .about("Start reporting in the foreground (used by the systemd service)")
+            .args(start_common_args()),
     )
     .subcommand(
-        Command::new("background-divergence-reset-suppressions")
-            .alias("divergence-reset-suppressions")
-            .about("Reset all divergence suppressions"),
+        Command::new("background-start")
+            .alias("start")
+            .about("Start reporting background process")
+            .args(start_common_args()),
     )
+    .subcommand(Command::new("background-stop").alias("stop").about("Stop reporting background process"))
+    ////////////////
+    // MCP Server commands
+    ////////////////
+    .subcommands(mcp_agentic_commands())
     ////////////////
     // Attack Pattern Detector commands (model-independent)
     //
@@ -482,7 +439,8 @@ pub fn build_cli() -> Command {
                 arg!(--"active-only" "Filter out dismissed findings before printing")
                     .required(false)
                     .action(ArgAction::SetTrue),
-            ),
+            )
+            .arg(step_arg("Only print the findings of this pipeline step")),
     )
     .subcommand(
         Command::new("background-vulnerability-dismiss")
@@ -494,7 +452,8 @@ pub fn build_cli() -> Command {
                 arg!(<FINDING_KEY> "Finding key to dismiss")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
+            )
+            .args(dismiss_args()),
     )
     .subcommand(
         Command::new("background-vulnerability-undismiss")
@@ -552,7 +511,8 @@ pub fn build_cli() -> Command {
                 arg!(<REQUEST_JSON> "JSON request accepted by agentic_dismiss_with_scope")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
-            ),
+            )
+            .args(dismiss_args()),
     )
     .subcommand(
         Command::new("background-agentic-list-dismissal-rules")
@@ -956,6 +916,11 @@ pub fn build_cli() -> Command {
                 arg!(<WHITELIST_FILE> "The path to the whitelist file")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
+            )
+            .arg(
+                arg!(--"signature" <SIGNATURE_FILE> "Detached signature of the whitelist file (default: <WHITELIST_FILE>.sig)")
+                    .required(false)
+                    .value_parser(clap::value_parser!(String)),
             ),
     )
     .subcommand(
@@ -991,6 +956,11 @@ pub fn build_cli() -> Command {
                 arg!(<BLACKLIST_FILE> "The path to the blacklist file")
                     .required(true)
                     .value_parser(clap::value_parser!(String)),
+            )
+            .arg(
+                arg!(--"signature" <SIGNATURE_FILE> "Detached signature of the blacklist file (default: <BLACKLIST_FILE>.sig)")
+                    .required(false)
+                    .value_parser(clap::value_parser!(String)),
             ),
     )
     .subcommand(Command::new("background-score").alias("get-background-score").about("Get security score from the background process"))
@@ -1015,6 +985,38 @@ pub fn build_cli() -> Command {
             ),
     )
     .subcommand(Command::new("background-get-blacklists").alias("get-blacklists").about("Get blacklists from the background process"))
+    .subcommand(Command::new("import-blacklist")
+        .about("Convert a threat-intel feed (IP/CIDR list, hosts, adblock, CSV, STIX 2.1, MISP) into a custom blacklist")
+        .arg(arg!(<FEED_FILE> "Feed file to import")
+            .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"format" <FORMAT> "Feed format")
+            .required(false)
+            .default_value("auto")
+            .value_parser(["auto", "ips", "hosts", "adblock", "csv", "stix", "misp"]))
+        .arg(arg!(--"name" <NAME> "Blacklist name (default: feed file name)")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"description" <TEXT> "Blacklist description")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"source-url" <URL> "Source of the feed recorded in the blacklist (default: feed file path)")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"column" <COLUMN> "CSV column name or zero-based index holding the indicators")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"resolve-domains" "Resolve domain indicators to their current IP addresses instead of skipping them")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"output" <FILE> "Write the blacklist JSON to a file instead of stdout")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"merge" "Merge into the blacklists currently set on the background process, replacing a blacklist with the same name")
+            .required(false)
+            .conflicts_with("output")
+            .action(ArgAction::SetTrue)))
+    .subcommand(blacklist_feeds_command())
     .subcommand(Command::new("background-get-whitelists").alias("get-whitelists").about("Get whitelists from the background process"))
     .subcommand(Command::new("background-get-whitelist-name").alias("get-whitelist-name").about("Get the current whitelist name from the background process"))
     ////////////////////
@@ -1022,37 +1024,127 @@ pub fn build_cli() -> Command {
     ////////////////////
     .subcommand(Command::new("augment-custom-whitelists")
         .about("Augment the current custom whitelist locally using current whitelist exceptions"))
-    .subcommand(Command::new("merge-custom-whitelists")
-        .about("Merge two custom whitelist JSON strings into one consolidated whitelist")
-        .arg(arg!(<WHITELIST_JSON_1> "First whitelist JSON string")
+    .subcommand(Command::new("learn-whitelist")
+        .about("Learn a custom whitelist by augmenting it periodically until the rate of change converges")
+        .arg(arg!(--"out" <FILE> "Output file for the learned whitelist JSON")
             .required(true)
             .value_parser(clap::value_parser!(String)))
-        .arg(arg!(<WHITELIST_JSON_2> "Second whitelist JSON string")
+        .arg(arg!(--"converge-below" <PERCENT> "Percent change per round below which a round is considered stable")
+            .required(false)
+            .default_value("1.0")
+            .value_parser(clap::value_parser!(f64)))
+        .arg(arg!(--"stable-rounds" <COUNT> "Number of consecutive stable rounds required to converge")
+            .required(false)
+            .default_value("3")
+            .value_parser(clap::value_parser!(u32).range(1..)))
+        .arg(arg!(--"interval" <DURATION> "Time between rounds (e.g. 90s, 5m)")
+            .required(false)
+            .default_value("60s")
+            .value_parser(parse_duration))
+        .arg(arg!(--"max-duration" <DURATION> "Give up if not converged after this long (e.g. 30m, 2h)")
+            .required(false)
+            .default_value("2h")
+            .value_parser(parse_duration))
+        .arg(arg!(--"report" <FILE> "Convergence report file (default: <out>.report.json)")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"apply" "Apply the learned whitelist to the background process once converged")
+            .action(ArgAction::SetTrue)))
+    .subcommand(Command::new("merge-custom-whitelists")
+        .about("Merge two or more custom whitelist JSON strings into one consolidated whitelist")
+        .arg(arg!(<WHITELIST_JSONS> ... "Whitelist JSON strings, merged in order")
             .required(true)
-            .value_parser(clap::value_parser!(String))))
+            .num_args(2..)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"annotate-sources" "Record in each endpoint's description which inputs (whitelist 1, 2, ...) contributed it")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"fail-on-conflict" "Exit with an error code when same-name whitelists extend different parents")
+            .required(false)
+            .action(ArgAction::SetTrue)))
     .subcommand(Command::new("merge-custom-whitelists-from-files")
-        .about("Merge two custom whitelist JSON files into one consolidated whitelist")
-        .arg(arg!(<WHITELIST_FILE_1> "First whitelist JSON file path")
+        .about("Merge two or more custom whitelist JSON files into one consolidated whitelist")
+        .arg(arg!(<WHITELIST_FILES> ... "Whitelist JSON file paths or file name globs (e.g. 'learned/*.json')")
             .required(true)
             .value_parser(clap::value_parser!(String)))
-        .arg(arg!(<WHITELIST_FILE_2> "Second whitelist JSON file path")
-            .required(true)
-            .value_parser(clap::value_parser!(String))))
+        .arg(arg!(--"annotate-sources" "Record in each endpoint's description which input files contributed it")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"fail-on-conflict" "Exit with an error code when same-name whitelists extend different parents")
+            .required(false)
+            .action(ArgAction::SetTrue)))
     .subcommand(Command::new("compare-custom-whitelists")
-        .about("Compare two custom whitelist JSON strings and return percentage difference")
+        .about("Compare two custom whitelist JSON strings and return percentage difference or a structured diff")
         .arg(arg!(<WHITELIST_JSON_1> "First whitelist JSON string")
             .required(true)
             .value_parser(clap::value_parser!(String)))
         .arg(arg!(<WHITELIST_JSON_2> "Second whitelist JSON string")
             .required(true)
-            .value_parser(clap::value_parser!(String))))
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"diff" "Show added, removed and modified endpoints per whitelist instead of a percentage")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"json" "Output the structured diff as JSON (implies --diff)")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"fail-on-diff" "Exit with an error code when the whitelists differ (implies --diff)")
+            .required(false)
+            .action(ArgAction::SetTrue)))
     .subcommand(Command::new("compare-custom-whitelists-from-files")
-        .about("Compare two custom whitelist JSON files and return percentage difference")
+        .about("Compare two custom whitelist JSON files and return percentage difference or a structured diff")
         .arg(arg!(<WHITELIST_FILE_1> "First whitelist JSON file path")
             .required(true)
             .value_parser(clap::value_parser!(String)))
         .arg(arg!(<WHITELIST_FILE_2> "Second whitelist JSON file path")
             .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"diff" "Show added, removed and modified endpoints per whitelist instead of a percentage")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"json" "Output the structured diff as JSON (implies --diff)")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"fail-on-diff" "Exit with an error code when the whitelists differ (implies --diff)")
+            .required(false)
+            .action(ArgAction::SetTrue)))
+    .subcommand(Command::new("lint-custom-whitelists")
+        .about("Validate a custom whitelist JSON file and report problems with their JSON path")
+        .arg(arg!(<WHITELIST_FILE> "Whitelist JSON file path")
+            .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"strict" "Also exit with an error code when only warnings are found")
+            .required(false)
+            .action(ArgAction::SetTrue)))
+    ////////////////////
+    // List signing commands
+    ////////////////////
+    .subcommand(Command::new("generate-list-key")
+        .about("Generate an Ed25519 key pair for signing whitelist and blacklist files, printing the public key")
+        .arg(arg!(<PRIVATE_KEY_FILE> "Path where the new private key is written")
+            .required(true)
+            .value_parser(clap::value_parser!(String))))
+    .subcommand(Command::new("sign-list")
+        .about("Create a detached Ed25519 signature for a whitelist or blacklist file")
+        .arg(arg!(<LIST_FILE> "Whitelist or blacklist JSON file")
+            .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"key" <PRIVATE_KEY_FILE> "Private key file created by generate-list-key")
+            .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"output" <SIGNATURE_FILE> "Signature file (default: <LIST_FILE>.sig)")
+            .required(false)
+            .value_parser(clap::value_parser!(String))))
+    .subcommand(Command::new("verify-list")
+        .about("Verify the detached signature of a whitelist or blacklist file against trusted keys")
+        .arg(arg!(<LIST_FILE> "Whitelist or blacklist JSON file")
+            .required(true)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"signature" <SIGNATURE_FILE> "Signature file (default: <LIST_FILE>.sig)")
+            .required(false)
+            .value_parser(clap::value_parser!(String)))
+        .arg(arg!(--"trusted-key" <NAME_KEY> "Trusted public key as name:base64 (repeatable, default: configured trusted keys)")
+            .required(false)
+            .action(ArgAction::Append)
             .value_parser(clap::value_parser!(String))))
     ////////////////////
     // File Integrity Monitoring commands
@@ -1085,7 +1177,8 @@ pub fn build_cli() -> Command {
                 arg!(--"fail-on-suspicious" "Exit with code 1 if suspicious file events are detected")
                     .required(false)
                     .action(ArgAction::SetTrue),
-            ),
+            )
+            .arg(step_arg("Only show, and check, the file events of this pipeline step")),
     )
     .subcommand(
         Command::new("background-clear-file-events")
@@ -1094,19 +1187,567 @@ pub fn build_cli() -> Command {
     )
 }
 
-fn start_common_args() -> Vec<Arg> {
+// MCP server, AI assistant and divergence commands
+fn mcp_agentic_commands() -> Vec<Command> {
     vec![
-        Arg::new("user")
-            .long("user")
-            .short('u')
-            .value_name("USER")
-            .help("User name")
-            .value_parser(parse_username)
-            .default_value(""),
-        Arg::new("domain")
-            .long("domain")
-            .short('d')
-            .value_name("DOMAIN")
+        Command::new("background-mcp-start")
+            .alias("mcp-start")
+            .about("Start MCP server for external AI clients (e.g., Claude Desktop)")
+            .arg(
+                arg!([PORT] "Port to listen on")
+                    .required(false)
+                    .default_value("3000")
+                    .value_parser(clap::value_parser!(u16)),
+            )
+            .arg(
+                arg!([PSK] "Pre-shared key for authentication (min 32 chars)")
+                    .required(false)
+                    .value_parser(clap::value_parser!(String)),
+            )
+            .arg(
+                Arg::new("all-interfaces")
+                    .long("all-interfaces")
+                    .help("Listen on all interfaces")
+                    .required(false)
+                    .action(ArgAction::SetTrue),
+            ),
+        Command::new("background-mcp-stop")
+            .alias("mcp-stop")
+            .about("Stop MCP server"),
+        Command::new("background-mcp-status")
+            .alias("mcp-status")
+            .about("Get MCP server status"),
+        Command::new("background-mcp-generate-psk")
+            .alias("mcp-generate-psk")
+            .about("Generate a secure PSK for MCP server"),
+        Command::new("background-agentic-summary")
+            .alias("agentic-summary")
+            .about(
+                "Get comprehensive agentic status summary (provider, mode, todos, actions, Slack)",
+            ),
+        Command::new("background-agentic-start")
+            .alias("agentic-start")
+            .about("Start AI assistant todo-processing loop in background process")
+            .arg(
+                arg!([MODE] "Loop mode: auto (execute) or analyze (review)")
+                    .required(false)
+                    .default_value("analyze")
+                    .value_parser(["auto", "analyze"]),
+            )
+            .arg(
+                arg!([INTERVAL_SECS] "Tick interval in seconds")
+                    .required(false)
+                    .default_value("3600")
+                    .value_parser(clap::value_parser!(u64)),
+            ),
+        Command::new("background-agentic-stop")
+            .alias("agentic-stop")
+            .about("Stop AI assistant todo-processing loop in background process"),
+        Command::new("background-agentic-status")
+            .alias("agentic-status")
+            .about("Get AI assistant loop status"),
+        Command::new("background-divergence-upsert-model")
+            .alias("divergence-upsert-model")
+            .about("Upsert a behavioral model JSON payload for divergence detection")
+            .arg(
+                arg!(<MODEL_JSON> "Behavioral model JSON")
+                    .required(true)
+                    .value_parser(clap::value_parser!(String)),
+            ),
+        Command::new("background-divergence-upsert-model-from-file")
+            .alias("divergence-upsert-model-from-file")
+            .about("Upsert a behavioral model JSON payload from file")
+            .arg(
+                arg!(<MODEL_FILE> "Path to behavioral model JSON file")
+                    .required(true)
+                    .value_parser(clap::value_parser!(String)),
+            ),
+        Command::new("background-divergence-get-model")
+            .alias("divergence-get-model")
+            .about("Get current behavioral model used by divergence engine"),
+        Command::new("background-divergence-clear-model")
+            .alias("divergence-clear-model")
+            .about("Clear current behavioral model"),
+        Command::new("background-divergence-start")
+            .alias("divergence-start")
+            .about("Start divergence engine in background process")
+            .arg(
+                arg!([INTERVAL_SECS] "Tick interval in seconds")
+                    .required(false)
+                    .default_value("120")
+                    .value_parser(clap::value_parser!(u64)),
+            ),
+        Command::new("background-divergence-stop")
+            .alias("divergence-stop")
+            .about("Stop divergence engine in background process"),
+        Command::new("background-divergence-status")
+            .alias("divergence-status")
+            .about("Get divergence engine status"),
+        Command::new("background-divergence-get-verdict")
+            .alias("divergence-get-verdict")
+            .about("Get latest divergence verdict"),
+        Command::new("background-divergence-get-history")
+            .alias("divergence-get-history")
+            .about("Get divergence verdict history")
+            .arg(
+                arg!([LIMIT] "Maximum number of history entries")
+                    .required(false)
+                    .default_value("20")
+                    .value_parser(clap::value_parser!(usize)),
+            ),
+        Command::new("background-divergence-dismiss")
+            .alias("divergence-dismiss")
+            .about("Dismiss divergence evidence by finding key")
+            .arg(
+                arg!(<FINDING_KEY> "Finding key to dismiss")
+                    .required(true)
+                    .value_parser(clap::value_parser!(String)),
+            )
+            .args(dismiss_args()),
+        Command::new("background-divergence-undismiss")
+            .alias("divergence-undismiss")
+            .about("Restore previously dismissed divergence evidence")
+            .arg(
+                arg!(<FINDING_KEY> "Finding key to restore")
+                    .required(true)
+                    .value_parser(clap::value_parser!(String)),
+            ),
+        Command::new("background-divergence-reset-suppressions")
+            .alias("divergence-reset-suppressions")
+            .about("Reset all divergence suppressions"),
+    ]
+}
+
+fn capture_command() -> Command {
+    Command::new("capture")
+        .about("Capture packets")
+        .arg(
+            arg!([SECONDS] "Number of seconds to capture (default: 900, unbounded with a stop condition)")
+                .required(false)
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!([WHITELIST_NAME] "Whitelist name")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!([ZEEK_FORMAT] "Zeek format")
+                .required(false)
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!([LOCAL_TRAFFIC] "Include local traffic")
+                .required(false)
+                .default_value("false")
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!(--"output" <FILE> "Write sessions to this file as they finish")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"format" <FORMAT> "Format of the output file: ndjson (default) or zeek")
+                .required(false)
+                .requires("output")
+                .value_parser(["ndjson", "zeek"]),
+        )
+        .arg(
+            arg!(--"rotate-size" <SIZE> "Rotate the output file when it would exceed this size (e.g. 100M)")
+                .required(false)
+                .requires("output")
+                .value_parser(parse_size),
+        )
+        .arg(
+            arg!(--"rotate-interval" <DURATION> "Rotate the output file after this duration (e.g. 1h)")
+                .required(false)
+                .requires("output")
+                .value_parser(parse_duration),
+        )
+        .arg(
+            arg!(--"stop-on-blacklisted" "Stop at the first blacklisted session (exit code 12)")
+                .required(false),
+        )
+        .arg(
+            arg!(--"stop-on-nonconforming" "Stop at the first session not conforming to the whitelist (exit code 13)")
+                .required(false),
+        )
+        .arg(
+            arg!(--"max-sessions" <N> "Stop once this many sessions have been captured (exit code 10)")
+                .required(false)
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"until-pid" <PID> "Stop when this process exits (exit code 11)")
+                .required(false)
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"generate-whitelist" <FILE> "Write a whitelist of the captured sessions to this file")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"with-process" "Include process information in the generated whitelist (stricter matching)")
+                .required(false)
+                .requires("generate-whitelist"),
+        )
+        .arg(
+            arg!(--"merge-into" <FILE> "Merge the generated whitelist into this existing whitelist file")
+                .required(false)
+                .requires("generate-whitelist")
+                .value_parser(clap::value_parser!(String)),
+        )
+}
+
+fn run_command() -> Command {
+    Command::new("run")
+        .about("Run a command under capture and report the network egress of its process tree")
+        .arg(
+            arg!(--"whitelist" <NAME> "Whitelist the sessions of the command are checked against, set on the background process with --daemon")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"fail-on-whitelist" "Exit with code 1 when a session of the command does not conform to the whitelist")
+                .required(false),
+        )
+        .arg(
+            arg!(--"fail-on-blacklist" "Exit with code 1 when a session of the command is blacklisted")
+                .required(false),
+        )
+        .arg(
+            arg!(--"fail-on-anomalous" "Exit with code 1 when a session of the command is anomalous")
+                .required(false),
+        )
+        .arg(
+            arg!(--"fail-on-findings" "Exit with code 1 when the vulnerability detector of the background process has active HIGH/CRITICAL findings")
+                .required(false)
+                .requires("daemon"),
+        )
+        .arg(
+            arg!(--"include-local-traffic" "Include local traffic in the report")
+                .required(false),
+        )
+        .arg(
+            arg!(--"daemon" "Use the capture of the running background process")
+                .required(false),
+        )
+        .arg(
+            arg!(--"report" <FILE> "Write the egress report as JSON to this file")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(<COMMAND> ... "Command to run, after --")
+                .required(true)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(String)),
+        )
+}
+
+fn egress_bom_command() -> Command {
+    Command::new("background-egress-bom")
+        .alias("egress-bom")
+        .about("Network bill of materials: the endpoints reached by the current sessions, with a reproducibility hash")
+        .arg(
+            arg!(--"format" <FORMAT> "Output format: json or cyclonedx")
+                .required(false)
+                .default_value("json")
+                .value_parser(["json", "cyclonedx"]),
+        )
+        .arg(
+            arg!(--"output" <FILE> "Write the BOM to a file instead of stdout")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"include-local-traffic" "Include local traffic in the BOM")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(step_arg("Only include the sessions of this pipeline step"))
+}
+
+fn history_query_command() -> Command {
+    Command::new("history-query")
+        .about("Query the sessions, findings and file events recorded by the daemon's history store")
+        .arg(
+            arg!(--"kind" <KIND> "Only return records of this kind (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(["sessions", "findings", "file-events"]),
+        )
+        .arg(
+            arg!(--"since" <TIME> "Start of the time range: a duration ago (e.g. 3d), a date or an RFC 3339 timestamp")
+                .required(false)
+                .value_parser(parse_time),
+        )
+        .arg(
+            arg!(--"until" <TIME> "End of the time range, in the same forms as --since")
+                .required(false)
+                .value_parser(parse_time),
+        )
+        .arg(
+            arg!(--"where" <FILTER> "FIELD=PATTERN filter on a record field, e.g. dst_domain=*.npmjs.org or l7.process_name=curl (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_field_filter),
+        )
+        .arg(
+            arg!(--"limit" <COUNT> "Return at most this many records, oldest first")
+                .required(false)
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"dir" <DIR> "History directory (default: EDAMAME_HISTORY_DIR or /var/lib/edamame_posture/history)")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"json" "Output the records as JSON")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn ioc_sweep_command() -> Command {
+    Command::new("background-ioc-sweep")
+        .alias("ioc-sweep")
+        .about("Sweep live sessions, file events and the local history for indicators of compromise")
+        .arg(
+            arg!(<FILE> "IOC file: one IP, CIDR, domain, absolute path or executable hash per line")
+                .required(true)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"dir" <DIR> "History directory (default: EDAMAME_HISTORY_DIR or /var/lib/edamame_posture/history, if present)")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"json" "Output the hits as JSON")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn step_commands() -> Vec<Command> {
+    vec![
+        Command::new("background-mark-step")
+            .alias("mark-step")
+            .about("Mark the start of a pipeline step, or its end with --end")
+            .arg(
+                arg!([NAME] "Step name, e.g. npm-install")
+                    .required_unless_present("end")
+                    .value_parser(clap::value_parser!(String)),
+            )
+            .arg(
+                arg!(--"end" "End the step in progress (which must be NAME when given)")
+                    .required(false)
+                    .action(ArgAction::SetTrue),
+            ),
+        Command::new("background-step-report")
+            .alias("step-report")
+            .about("Break down network egress and violations by pipeline step")
+            .arg(
+                arg!(--"include-local-traffic" "Include local traffic in the report")
+                    .required(false)
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                arg!(--"json" "Output the report as JSON")
+                    .required(false)
+                    .action(ArgAction::SetTrue),
+            ),
+    ]
+}
+
+fn step_arg(help: &'static str) -> Arg {
+    Arg::new("step")
+        .long("step")
+        .value_name("NAME")
+        .help(help)
+        .required(false)
+        .value_parser(clap::value_parser!(String))
+}
+
+fn lanscan_command() -> Command {
+    Command::new("lanscan")
+        .about("Performs a LAN scan")
+        .arg(
+            arg!(--"format" <FORMAT> "Output format: text, json or csv")
+                .required(false)
+                .default_value("text")
+                .value_parser(["text", "json", "csv"]),
+        )
+        .arg(
+            arg!(--"output" <FILE> "Write the devices to a file instead of stdout")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"cidr" <CIDR> "Only scan the interfaces covering this range and report devices in it, e.g. 192.168.10.0/24 (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_cidr_arg),
+        )
+        .arg(
+            arg!(--"interface" <NAME> "Only scan and report the networks of this interface (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"ports" <PORTS> "Only report these open ports, e.g. 22,80,8000-8100 (the core still probes its own port list)")
+                .required(false)
+                .value_parser(parse_port_set),
+        )
+}
+
+fn lanscan_diff_command() -> Command {
+    Command::new("lanscan-diff")
+        .about("Compare two LAN scans: new and disappeared devices, newly opened ports and MAC changes")
+        .arg(
+            arg!([OLD] "Older scan file (default: previous scan in the scan history)")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!([NEW] "Newer scan file (default: latest scan in the scan history)")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"json" "Output the diff as JSON")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"fail-on-change" "Exit with code 1 when the LAN inventory changed")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn blacklist_feeds_command() -> Command {
+    Command::new("blacklist-feeds")
+        .about("Inspect the blacklist feeds refreshed by the background process (EDAMAME_BLACKLIST_FEEDS)")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("status")
+                .about("Show the state of every feed at the last refresh; exit code 1 when a feed failed")
+                .arg(
+                    arg!(--"json" "Output the status as JSON")
+                        .required(false)
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+}
+
+fn network_dismissals_command() -> Command {
+    Command::new("network-dismissals")
+        .about("Manage the network dismissal rules added by the dismiss commands")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("List dismissal rules with their creation time and origin")
+                .arg(
+                    arg!(--"json" "Output the rules as JSON")
+                        .required(false)
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Revoke a dismissal in the core and remove its rule by id")
+                .arg(
+                    arg!(<RULE_ID> "Dismissal rule id")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the dismissal rules to a JSON file")
+                .arg(
+                    arg!(<FILE> "Output file path")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Apply and record the dismissal rules of an exported JSON file")
+                .arg(
+                    arg!(<FILE> "Exported dismissal rules file")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+}
+
+fn expiring_suppressions_command() -> Command {
+    Command::new("expiring-suppressions")
+        .about("Report dismissals that expire soon or have expired")
+        .arg(
+            arg!(--"within" <DURATION> "Report dismissals expiring within this duration (e.g. 7d)")
+                .required(false)
+                .default_value("7d")
+                .value_parser(parse_duration),
+        )
+        .arg(
+            arg!(--"json" "Output the report as JSON")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn check_suppressions_command() -> Command {
+    Command::new("check-suppressions")
+        .about("Report which suppression file entries match current findings, sessions and file events")
+        .arg(
+            arg!([FILE] "Suppression file (default: .edamame-suppressions.yaml in the working directory)")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"json" "Output the report as JSON")
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn dismiss_args() -> Vec<Arg> {
+    vec![
+        Arg::new("expires")
+            .long("expires")
+            .value_name("DURATION|DATE")
+            .help("Expire the dismissal after a duration (e.g. 7d) or at a date (e.g. 2026-12-31), enforced by the daemon")
+            .value_parser(parse_expiry),
+        Arg::new("reason")
+            .long("reason")
+            .value_name("TEXT")
+            .help("Justification recorded with the dismissal")
+            .value_parser(clap::value_parser!(String)),
+    ]
+}
+
+fn start_common_args() -> Vec<Arg> {
+    vec![
+        Arg::new("user")
+            .long("user")
+            .short('u')
+            .value_name("USER")
+            .help("User name")
+            .value_parser(parse_username)
+            .default_value(""),
+        Arg::new("domain")
+            .long("domain")
+            .short('d')
+            .value_name("DOMAIN")
             .help("Domain name")
             .value_parser(parse_fqdn)
             .default_value(""),
@@ -1195,6 +1836,18 @@ fn start_common_args() -> Vec<Arg> {
             .help("Force export of structured AI check failure details to Hub (Intune/managed fleets)")
             .env("EDAMAME_EXPORT_AI_FAILURE_DETAILS")
             .action(ArgAction::SetTrue),
+        Arg::new("lanscan_webhook")
+            .long("lanscan-webhook")
+            .value_name("URL")
+            .help("Webhook notified of LAN inventory changes found by the daemon's scans")
+            .env("EDAMAME_LANSCAN_WEBHOOK")
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("suppressions")
+            .long("suppressions")
+            .value_name("FILE")
+            .help("Suppression file applied by the daemon (default: .edamame-suppressions.yaml in the working directory)")
+            .env("EDAMAME_SUPPRESSIONS_FILE")
+            .value_parser(clap::value_parser!(String)),
     ]
 }
 
@@ -1273,6 +1926,18 @@ fn disconnected_start_args() -> Vec<Arg> {
             .help("Force export of structured AI check failure details to Hub (Intune/managed fleets)")
             .env("EDAMAME_EXPORT_AI_FAILURE_DETAILS")
             .action(ArgAction::SetTrue),
+        Arg::new("lanscan_webhook")
+            .long("lanscan-webhook")
+            .value_name("URL")
+            .help("Webhook notified of LAN inventory changes found by the daemon's scans")
+            .env("EDAMAME_LANSCAN_WEBHOOK")
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("suppressions")
+            .long("suppressions")
+            .value_name("FILE")
+            .help("Suppression file applied by the daemon (default: .edamame-suppressions.yaml in the working directory)")
+            .env("EDAMAME_SUPPRESSIONS_FILE")
+            .value_parser(clap::value_parser!(String)),
     ]
 }
 
@@ -1656,4 +2321,672 @@ mod tests {
         let (sub, _) = matches.subcommand().expect("expected subcommand");
         assert_eq!(sub, "background-start-file-monitor");
     }
+
+    #[test]
+    fn sessions_snapshot_save_parses() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "sessions-snapshot",
+                "save",
+                "baseline.json",
+            ])
+            .expect("sessions-snapshot save should parse");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "background-sessions-snapshot");
+        let (action, action_matches) = sub_matches.subcommand().expect("expected action");
+        assert_eq!(action, "save");
+        assert_eq!(
+            action_matches.get_one::<String>("FILE").map(String::as_str),
+            Some("baseline.json")
+        );
+        assert!(!action_matches.get_flag("include-local-traffic"));
+    }
+
+    #[test]
+    fn sessions_snapshot_requires_action() {
+        let result = build_cli().try_get_matches_from(["edamame_posture", "sessions-snapshot"]);
+        assert!(
+            result.is_err(),
+            "sessions-snapshot without an action should fail"
+        );
+    }
+
+    #[test]
+    fn sessions_diff_parses_with_fail_flag() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "sessions-diff",
+                "old.json",
+                "new.json",
+                "--fail-on-new-egress",
+            ])
+            .expect("sessions-diff should accept --fail-on-new-egress");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "sessions-diff");
+        assert_eq!(
+            sub_matches
+                .get_one::<String>("OLD_FILE")
+                .map(String::as_str),
+            Some("old.json")
+        );
+        assert!(sub_matches.get_flag("fail-on-new-egress"));
+        assert!(!sub_matches.get_flag("json"));
+    }
+
+    #[test]
+    fn lint_custom_whitelists_parses() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "lint-custom-whitelists",
+                "whitelist.json",
+                "--strict",
+            ])
+            .expect("lint-custom-whitelists should parse");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "lint-custom-whitelists");
+        assert_eq!(
+            sub_matches
+                .get_one::<String>("WHITELIST_FILE")
+                .map(String::as_str),
+            Some("whitelist.json")
+        );
+        assert!(sub_matches.get_flag("strict"));
+    }
+
+    #[test]
+    fn learn_whitelist_parses_durations() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "learn-whitelist",
+                "--out",
+                "learned.json",
+                "--converge-below",
+                "0.5",
+                "--interval",
+                "5m",
+                "--apply",
+            ])
+            .expect("learn-whitelist should parse");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "learn-whitelist");
+        assert_eq!(sub_matches.get_one::<f64>("converge-below"), Some(&0.5));
+        assert_eq!(sub_matches.get_one::<u64>("interval"), Some(&300));
+        assert_eq!(sub_matches.get_one::<u64>("max-duration"), Some(&7200));
+        assert_eq!(sub_matches.get_one::<u32>("stable-rounds"), Some(&3));
+        assert!(sub_matches.get_flag("apply"));
+
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "learn-whitelist",
+                "--out",
+                "learned.json",
+                "--interval",
+                "soon",
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn compare_custom_whitelists_from_files_parses_diff_flags() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "compare-custom-whitelists-from-files",
+                "old.json",
+                "new.json",
+                "--json",
+                "--fail-on-diff",
+            ])
+            .expect("compare-custom-whitelists-from-files should parse");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "compare-custom-whitelists-from-files");
+        assert!(sub_matches.get_flag("json"));
+        assert!(sub_matches.get_flag("fail-on-diff"));
+        assert!(!sub_matches.get_flag("diff"));
+    }
+
+    #[test]
+    fn merge_custom_whitelists_from_files_accepts_many_inputs() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "merge-custom-whitelists-from-files",
+                "linux.json",
+                "macos.json",
+                "windows/*.json",
+                "--annotate-sources",
+            ])
+            .expect("merge-custom-whitelists-from-files should parse");
+
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "merge-custom-whitelists-from-files");
+        let files: Vec<&String> = sub_matches
+            .get_many::<String>("WHITELIST_FILES")
+            .expect("files")
+            .collect();
+        assert_eq!(files, ["linux.json", "macos.json", "windows/*.json"]);
+        assert!(sub_matches.get_flag("annotate-sources"));
+        assert!(!sub_matches.get_flag("fail-on-conflict"));
+    }
+
+    #[test]
+    fn merge_custom_whitelists_takes_two_or_more_jsons() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "merge-custom-whitelists",
+                r#"{"date":"a","whitelists":[]}"#,
+                r#"{"date":"b","whitelists":[]}"#,
+                r#"{"date":"c","whitelists":[]}"#,
+                "--fail-on-conflict",
+            ])
+            .expect("merge-custom-whitelists should accept three whitelists");
+        let (_, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(
+            sub_matches
+                .get_many::<String>("WHITELIST_JSONS")
+                .expect("whitelists")
+                .count(),
+            3
+        );
+        assert!(sub_matches.get_flag("fail-on-conflict"));
+
+        let single = build_cli().try_get_matches_from([
+            "edamame_posture",
+            "merge-custom-whitelists",
+            r#"{"date":"a","whitelists":[]}"#,
+        ]);
+        assert!(single.is_err(), "one whitelist is nothing to merge");
+    }
+
+    #[test]
+    fn list_signing_commands_parse() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "sign-list",
+                "whitelist.json",
+                "--key",
+                "signing.key",
+            ])
+            .expect("sign-list should parse");
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "sign-list");
+        assert_eq!(
+            sub_matches.get_one::<String>("key").map(String::as_str),
+            Some("signing.key")
+        );
+
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "verify-list",
+                "whitelist.json",
+                "--trusted-key",
+                "ci:AAAA",
+                "--trusted-key",
+                "alice:BBBB",
+            ])
+            .expect("verify-list should parse");
+        let (_, sub_matches) = matches.subcommand().expect("expected subcommand");
+        let keys: Vec<&String> = sub_matches
+            .get_many::<String>("trusted-key")
+            .expect("keys")
+            .collect();
+        assert_eq!(keys, ["ci:AAAA", "alice:BBBB"]);
+
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "sign-list", "whitelist.json"])
+            .is_err());
+    }
+
+    #[test]
+    fn import_blacklist_parses() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "import-blacklist",
+                "feed.csv",
+                "--format",
+                "csv",
+                "--column",
+                "dst_ip",
+                "--merge",
+            ])
+            .expect("import-blacklist should parse");
+        let (sub, sub_matches) = matches.subcommand().expect("expected subcommand");
+        assert_eq!(sub, "import-blacklist");
+        assert_eq!(
+            sub_matches.get_one::<String>("format").map(String::as_str),
+            Some("csv")
+        );
+        assert!(sub_matches.get_flag("merge"));
+
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "import-blacklist",
+                "feed.txt",
+                "--format",
+                "yaml",
+            ])
+            .is_err());
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "import-blacklist",
+                "feed.txt",
+                "--merge",
+                "--output",
+                "out.json",
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn explain_session_args() {
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "explain-session", "uid-1", "--json"])
+            .expect("explain-session should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(sub.get_one::<String>("SESSION_UID").unwrap(), "uid-1");
+        assert_eq!(*sub.get_one::<usize>("limit").unwrap(), 5);
+        assert!(sub.get_flag("json"));
+    }
+
+    #[test]
+    fn network_dismissals_subcommands() {
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "network-dismissals", "list", "--json"])
+            .expect("network-dismissals list should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        let (name, list) = sub.subcommand().expect("nested subcommand");
+        assert_eq!(name, "list");
+        assert!(list.get_flag("json"));
+
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "network-dismissals",
+                "remove",
+                "3f1c0a9b52de",
+            ])
+            .expect("network-dismissals remove should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        let (_, remove) = sub.subcommand().expect("nested subcommand");
+        assert_eq!(remove.get_one::<String>("RULE_ID").unwrap(), "3f1c0a9b52de");
+
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "network-dismissals"])
+            .is_err());
+    }
+
+    #[test]
+    fn dismiss_commands_accept_expiry_and_reason() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "dismiss-device-port",
+                "192.168.1.20",
+                "22",
+                "--expires",
+                "7d",
+                "--reason",
+                "CHG-1042",
+            ])
+            .expect("dismiss-device-port should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert!(*sub.get_one::<u64>("expires").unwrap() > 7 * 86_400);
+        assert_eq!(sub.get_one::<String>("reason").unwrap(), "CHG-1042");
+
+        for command in [
+            "background-vulnerability-dismiss",
+            "background-divergence-dismiss",
+        ] {
+            assert!(build_cli()
+                .try_get_matches_from([
+                    "edamame_posture",
+                    command,
+                    "key",
+                    "--expires",
+                    "2099-01-01"
+                ])
+                .is_ok());
+        }
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "dismiss-device",
+                "10.0.0.5",
+                "--expires",
+                "2000-01-01"
+            ])
+            .is_err());
+
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "expiring-suppressions"])
+            .expect("expiring-suppressions should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(*sub.get_one::<u64>("within").unwrap(), 7 * 86_400);
+    }
+
+    #[test]
+    fn start_commands_accept_suppression_file() {
+        for command in ["background-start-disconnected", "foreground-start"] {
+            let matches = build_cli()
+                .try_get_matches_from([
+                    "edamame_posture",
+                    command,
+                    "--suppressions",
+                    "ci/.edamame-suppressions.yaml",
+                ])
+                .expect("start command should parse");
+            let (_, sub) = matches.subcommand().expect("subcommand");
+            assert_eq!(
+                sub.get_one::<String>("suppressions").unwrap(),
+                "ci/.edamame-suppressions.yaml"
+            );
+        }
+
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "check-suppressions", "--json"])
+            .expect("check-suppressions should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert!(sub.get_one::<String>("FILE").is_none());
+        assert!(sub.get_flag("json"));
+    }
+
+    #[test]
+    fn lanscan_exports_and_diff_parse() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "lanscan",
+                "--format",
+                "csv",
+                "--output",
+                "devices.csv",
+            ])
+            .expect("lanscan should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(sub.get_one::<String>("format").unwrap(), "csv");
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "lanscan", "--format", "xml"])
+            .is_err());
+
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "lanscan-diff", "--fail-on-change"])
+            .expect("lanscan-diff should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert!(sub.get_one::<String>("OLD").is_none());
+        assert!(sub.get_flag("fail-on-change"));
+    }
+
+    #[test]
+    fn lanscan_scope_parses() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "lanscan",
+                "--cidr",
+                "10.20.0.0/24",
+                "--cidr",
+                "10.30.0.0/24",
+                "--ports",
+                "22,8000-8002",
+                "--timeout",
+                "90s",
+            ])
+            .expect("lanscan scope should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(sub.get_many::<String>("cidr").unwrap().count(), 2);
+        assert_eq!(
+            sub.get_one::<Vec<u16>>("ports").unwrap(),
+            &vec![22, 8000, 8001, 8002]
+        );
+        assert_eq!(*sub.get_one::<u64>("timeout").unwrap(), 90);
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "lanscan", "--cidr", "10.20.0.1"])
+            .is_err());
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "lanscan", "--ports", "0-10"])
+            .is_err());
+    }
+
+    #[test]
+    fn capture_stop_conditions_parse() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "capture",
+                "--output",
+                "capture.ndjson",
+                "--rotate-size",
+                "100M",
+                "--stop-on-blacklisted",
+                "--until-pid",
+                "4242",
+            ])
+            .expect("capture should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert!(sub.get_one::<u64>("SECONDS").is_none());
+        assert_eq!(*sub.get_one::<u64>("rotate-size").unwrap(), 100 << 20);
+        assert!(sub.get_flag("stop-on-blacklisted"));
+        assert_eq!(*sub.get_one::<u32>("until-pid").unwrap(), 4242);
+        // Rotation only applies to an output file
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "capture",
+                "60",
+                "--rotate-interval",
+                "1h"
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn capture_generates_whitelists() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "capture",
+                "120",
+                "--generate-whitelist",
+                "baseline.json",
+                "--with-process",
+                "--merge-into",
+                "whitelist.json",
+            ])
+            .expect("capture should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(
+            sub.get_one::<String>("generate-whitelist").unwrap(),
+            "baseline.json"
+        );
+        assert!(sub.get_flag("with-process"));
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "capture", "--with-process"])
+            .is_err());
+    }
+
+    #[test]
+    fn run_takes_a_command() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "run",
+                "--whitelist",
+                "github_ubuntu",
+                "--fail-on-whitelist",
+                "--",
+                "npm",
+                "ci",
+                "--no-audit",
+            ])
+            .expect("run should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        let command: Vec<&String> = sub.get_many::<String>("COMMAND").unwrap().collect();
+        assert_eq!(command, ["npm", "ci", "--no-audit"]);
+        assert!(sub.get_flag("fail-on-whitelist"));
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "run"])
+            .is_err());
+
+        // Findings come from the detector of the background process
+        let findings = ["edamame_posture", "run", "--fail-on-findings", "--", "make"];
+        assert!(build_cli().try_get_matches_from(findings).is_err());
+        assert!(build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "run",
+                "--daemon",
+                "--whitelist",
+                "github_ubuntu",
+                "--fail-on-findings",
+                "--fail-on-anomalous",
+                "--",
+                "make",
+                "--fail-on-findings",
+            ])
+            .is_ok());
+    }
+
+    #[test]
+    fn steps_are_marked_and_filtered() {
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "mark-step", "npm-install"])
+            .expect("mark-step should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(sub.get_one::<String>("NAME").unwrap(), "npm-install");
+        assert!(!sub.get_flag("end"));
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "mark-step", "--end"])
+            .is_ok());
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "mark-step"])
+            .is_err());
+
+        for command in ["get-sessions", "get-file-events", "vulnerability-findings"] {
+            let matches = build_cli()
+                .try_get_matches_from(["edamame_posture", command, "--step", "tests"])
+                .unwrap_or_else(|e| panic!("{} should accept --step: {}", command, e));
+            let (_, sub) = matches.subcommand().expect("subcommand");
+            assert_eq!(sub.get_one::<String>("step").unwrap(), "tests");
+        }
+    }
+
+    #[test]
+    fn egress_bom_formats() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "egress-bom",
+                "--format",
+                "cyclonedx",
+                "--output",
+                "egress.cdx.json",
+            ])
+            .expect("egress-bom should parse");
+        let (name, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(name, "background-egress-bom");
+        assert_eq!(sub.get_one::<String>("format").unwrap(), "cyclonedx");
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "egress-bom", "--format", "spdx"])
+            .is_err());
+    }
+
+    #[test]
+    fn history_query_filters_parse() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "history-query",
+                "--kind",
+                "sessions",
+                "--since",
+                "2026-05-11",
+                "--until",
+                "2026-05-18T12:00:00Z",
+                "--where",
+                "dst_domain=*.example.com",
+            ])
+            .expect("history-query should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(*sub.get_one::<u64>("since").unwrap(), 1_778_457_600_000);
+        assert_eq!(*sub.get_one::<u64>("until").unwrap(), 1_779_105_600_000);
+        assert_eq!(
+            sub.get_one::<(String, String)>("where").unwrap(),
+            &("dst_domain".to_string(), "*.example.com".to_string())
+        );
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "history-query", "--where", "no-equals"])
+            .is_err());
+    }
+
+    #[test]
+    fn sessions_geo_options() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "edamame_posture",
+                "get-sessions",
+                "--country",
+                "us",
+                "--country",
+                "DE",
+                "--asn",
+                "13335",
+                "--summary",
+                "country",
+                "--label",
+                "corp-*",
+            ])
+            .expect("get-sessions should parse");
+        let (_, sub) = matches.subcommand().expect("subcommand");
+        let countries: Vec<&String> = sub.get_many::<String>("country").unwrap().collect();
+        assert_eq!(countries, ["US", "DE"]);
+        assert_eq!(*sub.get_one::<u32>("asn").unwrap(), 13335);
+        assert_eq!(sub.get_one::<String>("label").unwrap(), "corp-*");
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "get-sessions", "--country", "USA"])
+            .is_err());
+    }
+
+    #[test]
+    fn ioc_sweep_takes_a_file() {
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "ioc-sweep", "iocs.txt", "--json"])
+            .expect("ioc-sweep should parse");
+        let (name, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(name, "background-ioc-sweep");
+        assert_eq!(sub.get_one::<String>("FILE").unwrap(), "iocs.txt");
+        assert!(sub.get_flag("json"));
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "ioc-sweep"])
+            .is_err());
+    }
+
+    #[test]
+    fn blacklist_feeds_status() {
+        let matches = build_cli()
+            .try_get_matches_from(["edamame_posture", "blacklist-feeds", "status", "--json"])
+            .expect("blacklist-feeds status should parse");
+        let (name, sub) = matches.subcommand().expect("subcommand");
+        assert_eq!(name, "blacklist-feeds");
+        let (_, status) = sub.subcommand().expect("status");
+        assert!(status.get_flag("json"));
+        assert!(build_cli()
+            .try_get_matches_from(["edamame_posture", "blacklist-feeds"])
+            .is_err());
+    }
 }
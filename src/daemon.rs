@@ -1,17 +1,30 @@
-use crate::background::background_display_sessions;
+use crate::background::{
+    background_apply_suppressions, background_display_sessions, background_expire_dismissals,
+    background_load_suppressions, collect_policy_violations, validate_custom_blacklists_json,
+};
+use crate::dismissals::now_secs;
+use crate::feeds::BlacklistFeeds;
+use crate::geoip::GeoIp;
+use crate::history::HistoryStore;
+use crate::ipfix::IpfixExporter;
+use crate::labels::LabelRules;
+use crate::lanscan::{LanscanOptions, WEBHOOK_ENV};
+use crate::signing::{list_policy, GuardedList, ListPolicy, ListReview};
 use crate::EDAMAME_CA_PEM;
 use crate::EDAMAME_CLIENT_KEY;
 use crate::EDAMAME_CLIENT_PEM;
 use crate::EDAMAME_TARGET;
 use crate::{
-    base_get_core_info, base_get_core_version, base_lanscan, connect_domain, ERROR_CODE_MISMATCH,
-    ERROR_CODE_PARAM,
+    base_check_lanscan_changes, base_get_core_info, base_get_core_version, base_lanscan,
+    base_wait_gateway, connect_domain, PolicyChecks, ERROR_CODE_MISMATCH, ERROR_CODE_PARAM,
+    GATEWAY_TIMEOUT,
 };
 use edamame_core::api::api_core::*;
 use edamame_core::api::api_flodbadd::*;
 use edamame_core::api::api_score::*;
 use edamame_core::api::api_trust::*;
 use std::env;
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::Duration;
 use tracing::{error, info, warn};
@@ -116,20 +129,97 @@ pub fn background_process(
         info!("Packet capture disabled. Skipping capture initialization.");
     }
 
+    // Optional IPFIX export of finished sessions (EDAMAME_IPFIX_COLLECTOR=host:port)
+    let mut ipfix_exporter = if packet_capture {
+        match IpfixExporter::from_env() {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                error!("IPFIX export disabled: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Optional local history of sessions, findings and file events (EDAMAME_HISTORY_DIR=path)
+    let mut history = match HistoryStore::from_env() {
+        Ok(history) => history,
+        Err(e) => {
+            error!("History store disabled: {}", e);
+            None
+        }
+    };
+
+    // Optional offline AS and country enrichment of the recorded sessions (EDAMAME_GEOIP_DB=paths)
+    let geoip = match GeoIp::from_env() {
+        Ok(Some(geoip)) => {
+            info!("GeoIP databases: {}", geoip.describe());
+            Some(geoip)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("GeoIP enrichment disabled: {}", e);
+            None
+        }
+    };
+
+    // Optional user-defined session labels (EDAMAME_LABELS_FILE=path)
+    let labels = match LabelRules::from_env() {
+        Ok(Some(labels)) => {
+            info!(
+                "Session labels: {} rule(s) from {}",
+                labels.rules.len(),
+                labels.path
+            );
+            Some(labels)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Session labels disabled: {}", e);
+            None
+        }
+    };
+
+    // Lists set through RPC are checked here, the CLI checks are not enough
+    let list_guard = ListGuard::start().map(|guard| {
+        let guard = Arc::new(Mutex::new(guard));
+        let checked = guard.clone();
+        std::thread::spawn(move || loop {
+            sleep(LIST_GUARD_INTERVAL);
+            checked.lock().unwrap_or_else(|e| e.into_inner()).check();
+        });
+        guard
+    });
+
+    // Optional scheduled refresh of the custom blacklists (EDAMAME_BLACKLIST_FEEDS=sources)
+    let mut blacklist_feeds = match BlacklistFeeds::from_env() {
+        Ok(Some(feeds)) => {
+            info!("Blacklist feeds: {}", feeds.describe());
+            Some(feeds)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Blacklist feeds disabled: {}", e);
+            None
+        }
+    };
+
     // Scan the network interfaces
     if lan_scanning {
         info!("Scanning network interfaces...");
 
         if packet_capture {
             // Wait for the gateway detection to complete
-            let mut last_gateway_scan = get_last_gateway_scan();
-            while last_gateway_scan.is_empty() {
-                info!("Waiting for gateway detection to complete...");
-                sleep(Duration::from_secs(5));
-                last_gateway_scan = get_last_gateway_scan();
+            info!("Waiting for gateway detection to complete...");
+            if base_wait_gateway(Duration::from_secs(GATEWAY_TIMEOUT)).is_ok() {
+                info!("Gateway detection complete, requesting a LAN scan...");
+            } else {
+                warn!(
+                    "No gateway detected after {} seconds, requesting a LAN scan anyway",
+                    GATEWAY_TIMEOUT
+                );
             }
-
-            info!("Gateway detection complete, requesting a LAN scan...");
         } else {
             info!("Packet capture disabled; requesting LAN scan without waiting for gateway detection.");
         }
@@ -138,7 +228,7 @@ pub fn background_process(
         _ = get_lanscan(true, false, false);
 
         // Wait for the scan to complete
-        base_lanscan();
+        base_lanscan(&LanscanOptions::default());
     }
 
     info!("LAN scan complete, starting connection status loop");
@@ -190,28 +280,103 @@ pub fn background_process(
     // Loop forever as background process is running
     let mut violation_check_counter = 0u64;
     const VIOLATION_CHECK_INTERVAL: u64 = 10; // seconds (reduced from 30 for faster response)
+    let mut expiry_check_counter = 0u64;
+    const EXPIRY_CHECK_INTERVAL: u64 = 60; // seconds
+    let mut suppressions = background_load_suppressions();
+    let mut history_check_counter = 0u64;
+    const HISTORY_CHECK_INTERVAL: u64 = 30; // seconds
+    let mut lanscan_check_counter = 0u64;
+    const LANSCAN_CHECK_INTERVAL: u64 = 60; // seconds
+    let mut last_lanscan = String::new();
+    // Optional notification of LAN inventory changes (EDAMAME_LANSCAN_WEBHOOK=url)
+    let lanscan_webhook = env::var(WEBHOOK_ENV)
+        .ok()
+        .filter(|webhook| !webhook.trim().is_empty());
+    if lan_scanning && lanscan_webhook.is_some() {
+        info!("LAN scan changes will be sent to the configured webhook");
+    }
     loop {
         // Sleep for 5 seconds
         sleep(Duration::from_secs(5));
         violation_check_counter += 5;
+        expiry_check_counter += 5;
+        lanscan_check_counter += 5;
+        history_check_counter += 5;
+
+        if expiry_check_counter >= EXPIRY_CHECK_INTERVAL {
+            expiry_check_counter = 0;
+            background_expire_dismissals();
+            if let Some(active) = suppressions.as_mut() {
+                background_apply_suppressions(active);
+            }
+        }
+
+        if let Some(feeds) = blacklist_feeds.as_mut() {
+            let now = now_secs();
+            if feeds.is_due(now) {
+                refresh_blacklist_feeds(feeds, now, list_guard.as_deref());
+            }
+        }
+
+        if lan_scanning && lanscan_check_counter >= LANSCAN_CHECK_INTERVAL {
+            lanscan_check_counter = 0;
+            base_check_lanscan_changes(&mut last_lanscan, lanscan_webhook.as_deref());
+        }
+
+        let record_sessions = packet_capture && history.is_some();
+        if ipfix_exporter.is_some() || record_sessions {
+            let sessions = get_sessions();
+            if let Some(exporter) = ipfix_exporter.as_mut() {
+                exporter.export_finished_sessions(&sessions, labels.as_ref());
+            }
+            if let Some(store) = history.as_mut().filter(|_| record_sessions) {
+                store.record_sessions(
+                    sessions
+                        .iter()
+                        .filter_map(|session| serde_json::to_value(session).ok())
+                        .map(|mut session| {
+                            if let Some(geoip) = &geoip {
+                                geoip.enrich(&mut session);
+                            }
+                            if let Some(labels) = &labels {
+                                labels.apply(&mut session);
+                            }
+                            session
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        if history_check_counter >= HISTORY_CHECK_INTERVAL {
+            history_check_counter = 0;
+            if let Some(store) = history.as_mut() {
+                record_findings_and_file_events(store);
+            }
+        }
 
         if cancel_on_violation && violation_check_counter >= VIOLATION_CHECK_INTERVAL {
             violation_check_counter = 0;
-            match collect_policy_violations(
-                fail_on_whitelist,
-                fail_on_blacklist,
-                fail_on_findings,
+            let checks = PolicyChecks {
+                whitelist: fail_on_whitelist,
+                blacklist: fail_on_blacklist,
+                findings: fail_on_findings,
                 local_traffic,
-            ) {
+                ..Default::default()
+            };
+            match collect_policy_violations(&checks, labels.as_ref()) {
                 Ok(violations) => {
                     if !violations.is_empty() {
-                        if !violations.sessions.is_empty() {
+                        let sessions = violations.sessions();
+                        if !sessions.is_empty() {
                             println!("\n=== Violating Sessions Detected ===");
-                            background_display_sessions(
-                                violations.sessions,
-                                false,
-                                local_traffic,
-                                false,
+                            background_display_sessions(sessions, false, local_traffic, false);
+                        }
+                        let session_labels = violations.session_labels();
+                        if !session_labels.is_empty() {
+                            println!(
+                                "Labels of the violating sessions: {}",
+                                session_labels.join(", ")
                             );
                         }
                         if violations.vulnerability_findings > 0 {
@@ -240,110 +405,116 @@ pub fn background_process(
     }
 }
 
-struct PolicyViolations {
-    sessions: Vec<SessionInfoAPI>,
-    vulnerability_findings: u64,
-    vulnerability_label: &'static str,
-}
-
-impl PolicyViolations {
-    fn is_empty(&self) -> bool {
-        self.sessions.is_empty() && self.vulnerability_findings == 0
+// Feeds merge into the blacklists active on the core, so that the ones set
+// through set-custom-blacklists stay in place
+fn refresh_blacklist_feeds(feeds: &mut BlacklistFeeds, now: u64, guard: Option<&Mutex<ListGuard>>) {
+    // Held while the lists change, so the guard does not see them unaccepted
+    let mut guard = guard.map(|guard| guard.lock().unwrap_or_else(|e| e.into_inner()));
+    let current = get_blacklists();
+    if let Some(blacklist_json) = feeds.refresh(now, &current, &validate_custom_blacklists_json) {
+        set_custom_blacklists(blacklist_json);
+        feeds.applied();
+        // Each feed was checked against the signing policy
+        if let Some(guard) = guard.as_mut() {
+            guard.blacklists = GuardedList::new(get_blacklists());
+        }
+        info!(
+            "Custom blacklists updated from {} feed(s)",
+            feeds.feeds.len()
+        );
+    }
+    if let Err(e) = feeds.save_status(now) {
+        warn!("{}", e);
     }
 }
 
-fn collect_policy_violations(
-    fail_on_whitelist: bool,
-    fail_on_blacklist: bool,
-    fail_on_findings: bool,
-    include_local_traffic: bool,
-) -> Result<PolicyViolations, String> {
-    let mut violating_sessions: Vec<SessionInfoAPI> = Vec::new();
-    let mut vulnerability_findings = 0u64;
-    let mut vulnerability_label = "HIGH/CRITICAL severity";
-
-    if fail_on_whitelist {
-        let conforms = rpc_get_whitelist_conformance(
-            &EDAMAME_CA_PEM,
-            &EDAMAME_CLIENT_PEM,
-            &EDAMAME_CLIENT_KEY,
-            &EDAMAME_TARGET,
-        )
-        .map_err(|e| format!("Error getting whitelist conformance: {}", e))?;
-
-        if !conforms {
-            let mut sessions = rpc_get_lan_sessions(
-                true,
-                &EDAMAME_CA_PEM,
-                &EDAMAME_CLIENT_PEM,
-                &EDAMAME_CLIENT_KEY,
-                &EDAMAME_TARGET,
-            )
-            .map_err(|e| format!("Error retrieving LAN sessions: {}", e))?
-            .sessions;
+// While signed lists are required, a whitelist or blacklist set on the core
+// by any client must carry a trusted signature (see signing::embed_signature).
+// The core applies lists set through RPC without asking the daemon, so other
+// changes are undone by setting the last accepted list again, within
+// LIST_GUARD_INTERVAL.
+const LIST_GUARD_INTERVAL: Duration = Duration::from_millis(500);
+
+struct ListGuard {
+    policy: ListPolicy,
+    whitelists: GuardedList,
+    blacklists: GuardedList,
+}
 
-            if !include_local_traffic {
-                sessions = filter_global_sessions(sessions);
+impl ListGuard {
+    fn start() -> Option<ListGuard> {
+        let policy = match list_policy() {
+            Ok(policy) if policy.require_signed => policy,
+            Ok(_) => return None,
+            Err(e) => {
+                // Fail closed, as the CLI does
+                error!(
+                    "List signing policy error, refusing every list change: {}",
+                    e
+                );
+                ListPolicy {
+                    require_signed: true,
+                    trusted: Vec::new(),
+                }
             }
-
-            let non_conforming: Vec<SessionInfoAPI> = sessions
-                .into_iter()
-                .filter(|session| session.is_whitelisted != WhiteListStateAPI::Conforming)
-                .collect();
-
-            violating_sessions.extend(non_conforming);
-        }
+        };
+        info!(
+            "Signed lists required, trusted keys: {}",
+            policy
+                .trusted
+                .iter()
+                .map(|key| key.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Some(ListGuard {
+            policy,
+            whitelists: GuardedList::new(get_whitelists()),
+            blacklists: GuardedList::new(get_blacklists()),
+        })
     }
 
-    if fail_on_blacklist {
-        let mut blacklisted = rpc_get_blacklisted_sessions(
-            &EDAMAME_CA_PEM,
-            &EDAMAME_CLIENT_PEM,
-            &EDAMAME_CLIENT_KEY,
-            &EDAMAME_TARGET,
-        )
-        .map_err(|e| format!("Error retrieving blacklisted sessions: {}", e))?;
-
-        if !blacklisted.is_empty() {
-            if !include_local_traffic {
-                blacklisted = filter_global_sessions(blacklisted);
+    fn check(&mut self) {
+        match self.whitelists.review(get_whitelists(), &self.policy) {
+            ListReview::Unchanged => {}
+            ListReview::Accepted(signer) => {
+                info!("Accepted custom whitelists signed by {}", signer)
+            }
+            ListReview::Rejected(e) => {
+                error!("Custom whitelists rolled back: {}", e);
+                set_custom_whitelists(self.whitelists.accepted.clone());
             }
-            violating_sessions.extend(blacklisted);
-        }
-    }
-
-    if fail_on_findings {
-        let status = edamame_core::api::api_agentic::get_vulnerability_detector_status();
-        let status_json: serde_json::Value = serde_json::from_str(&status)
-            .map_err(|e| format!("Error parsing vulnerability detector status: {}", e))?;
-
-        if let Some(error) = status_json.get("error").and_then(|value| value.as_str()) {
-            return Err(format!(
-                "Error getting vulnerability detector status: {}",
-                error
-            ));
         }
-
-        if let Some(alertable) = status_json
-            .get("active_alertable_findings")
-            .and_then(|value| value.as_u64())
-        {
-            vulnerability_findings = alertable;
-            vulnerability_label = "HIGH/CRITICAL severity";
-        } else {
-            vulnerability_findings = status_json
-                .get("active_findings")
-                .and_then(|value| value.as_u64())
-                .unwrap_or(0);
-            vulnerability_label = "all severities (legacy daemon)";
+        match self.blacklists.review(get_blacklists(), &self.policy) {
+            ListReview::Unchanged => {}
+            ListReview::Accepted(signer) => {
+                info!("Accepted custom blacklists signed by {}", signer)
+            }
+            ListReview::Rejected(e) => {
+                error!("Custom blacklists rolled back: {}", e);
+                set_custom_blacklists(self.blacklists.accepted.clone());
+            }
         }
     }
+}
 
-    Ok(PolicyViolations {
-        sessions: violating_sessions,
-        vulnerability_findings,
-        vulnerability_label,
-    })
+// Findings and file events are empty while the detector or file monitoring is off
+fn record_findings_and_file_events(store: &mut HistoryStore) {
+    let findings = edamame_core::api::api_agentic::get_vulnerability_findings();
+    store.record_findings(
+        serde_json::from_str::<serde_json::Value>(&findings)
+            .ok()
+            .and_then(|report| report.get("findings").and_then(|f| f.as_array()).cloned())
+            .unwrap_or_default(),
+    );
+    let snapshot = edamame_core::api::api_fim::get_file_events();
+    store.record_file_events(
+        snapshot
+            .events
+            .iter()
+            .filter_map(|event| serde_json::to_value(event).ok())
+            .collect(),
+    );
 }
 
 fn halt_ci_pipeline(reason: &str) -> Result<(), String> {
@@ -0,0 +1,684 @@
+// Registry of the dismissals applied through the CLI.
+//
+// The core applies device, port, session and process dismissals but does not
+// report them back, so every rule added by the dismiss commands or by an import
+// is also recorded in a JSON registry. The registry is what `network-dismissals`
+// lists, removes and exports, and an exported file can be imported on other
+// hosts. Dismissals applied by other clients of the core are not in it:
+//
+//   { "version": 1, "rules": [ { "id": "3f1c0a9b52de", "kind": "device_port",
+//     "target": "192.168.1.20", "port": 22, "created_at": 1760745600,
+//     "origin": "cli", "expires_at": 1761350400, "reason": "CHG-1042" } ] }
+//
+// Rule ids are derived from the kind and target, so the same dismissal has the
+// same id on every host. Vulnerability, divergence and scoped dismissals are
+// recorded as well so that the daemon can revoke them once they expire.
+
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+pub const REGISTRY_ENV: &str = "EDAMAME_DISMISSALS_FILE";
+#[cfg(not(windows))]
+const DEFAULT_REGISTRY: &str = "/var/lib/edamame_posture/dismissals.json";
+#[cfg(windows)]
+const DEFAULT_REGISTRY: &str = r"C:\ProgramData\edamame_posture\dismissals.json";
+const REGISTRY_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DismissKind {
+    Device,
+    DevicePort,
+    Session,
+    Process,
+    Vulnerability,
+    Divergence,
+    // Recurrence-aware rule created by agentic-dismiss-with-scope, target is the rule id
+    ScopeRule,
+}
+
+impl DismissKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DismissKind::Device => "device",
+            DismissKind::DevicePort => "device_port",
+            DismissKind::Session => "session",
+            DismissKind::Process => "process",
+            DismissKind::Vulnerability => "vulnerability",
+            DismissKind::Divergence => "divergence",
+            DismissKind::ScopeRule => "scope_rule",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DismissKind> {
+        match name {
+            "device" => Some(DismissKind::Device),
+            "device_port" => Some(DismissKind::DevicePort),
+            "session" => Some(DismissKind::Session),
+            "process" => Some(DismissKind::Process),
+            "vulnerability" => Some(DismissKind::Vulnerability),
+            "divergence" => Some(DismissKind::Divergence),
+            "scope_rule" => Some(DismissKind::ScopeRule),
+            _ => None,
+        }
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self,
+            DismissKind::Device
+                | DismissKind::DevicePort
+                | DismissKind::Session
+                | DismissKind::Process
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DismissRule {
+    pub id: String,
+    pub kind: DismissKind,
+    // Device IP address, or session UID for session and process rules
+    pub target: String,
+    pub port: Option<u16>,
+    pub created_at: u64,
+    pub origin: String,
+    pub expires_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+impl DismissRule {
+    pub fn new(kind: DismissKind, target: &str, port: Option<u16>, origin: &str) -> DismissRule {
+        DismissRule {
+            id: rule_id(kind, target, port),
+            kind,
+            target: target.to_string(),
+            port,
+            created_at: now_secs(),
+            origin: origin.to_string(),
+            expires_at: None,
+            reason: None,
+        }
+    }
+
+    pub fn with_terms(mut self, expires_at: Option<u64>, reason: Option<String>) -> DismissRule {
+        self.expires_at = expires_at;
+        self.reason = reason.filter(|r| !r.trim().is_empty());
+        self
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut rule = json!({
+            "id": self.id,
+            "kind": self.kind.name(),
+            "target": self.target,
+            "created_at": self.created_at,
+            "origin": self.origin,
+        });
+        if let Some(port) = self.port {
+            rule["port"] = Value::from(port);
+        }
+        if let Some(expires_at) = self.expires_at {
+            rule["expires_at"] = Value::from(expires_at);
+        }
+        if let Some(reason) = &self.reason {
+            rule["reason"] = Value::from(reason.as_str());
+        }
+        rule
+    }
+
+    pub fn from_json(value: &Value) -> Result<DismissRule, String> {
+        let kind_name = value["kind"].as_str().unwrap_or("");
+        let kind = DismissKind::from_name(kind_name)
+            .ok_or_else(|| format!("unknown dismissal kind '{}'", kind_name))?;
+        let target = value["target"]
+            .as_str()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| "missing 'target'".to_string())?;
+        let port = match &value["port"] {
+            Value::Null => None,
+            port => Some(
+                port.as_u64()
+                    .and_then(|p| u16::try_from(p).ok())
+                    .ok_or_else(|| format!("invalid port {}", port))?,
+            ),
+        };
+        match kind {
+            DismissKind::Device | DismissKind::DevicePort if target.parse::<IpAddr>().is_err() => {
+                return Err(format!("invalid IP address '{}'", target));
+            }
+            DismissKind::DevicePort if port.is_none() => {
+                return Err("missing 'port' for a device_port rule".to_string());
+            }
+            _ => {}
+        }
+        let port = if kind == DismissKind::DevicePort {
+            port
+        } else {
+            None
+        };
+        Ok(DismissRule {
+            // Ids are recomputed so that edited files cannot collide with other rules
+            id: rule_id(kind, target, port),
+            kind,
+            target: target.to_string(),
+            port,
+            created_at: value["created_at"].as_u64().unwrap_or_else(now_secs),
+            origin: value["origin"].as_str().unwrap_or("").to_string(),
+            expires_at: value["expires_at"].as_u64(),
+            reason: value["reason"].as_str().map(|r| r.to_string()),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match self.kind {
+            DismissKind::Device => format!("device {} (all ports)", self.target),
+            DismissKind::DevicePort => {
+                format!("port {} on {}", self.port.unwrap_or(0), self.target)
+            }
+            DismissKind::Session => format!("session {}", self.target),
+            DismissKind::Process => format!("process of session {}", self.target),
+            DismissKind::Vulnerability => format!("vulnerability finding {}", self.target),
+            DismissKind::Divergence => format!("divergence evidence {}", self.target),
+            DismissKind::ScopeRule => format!("scoped dismissal rule {}", self.target),
+        }
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Stable id of a dismissal: FNV-1a over its kind and target, as 12 hex digits.
+pub fn rule_id(kind: DismissKind, target: &str, port: Option<u16>) -> String {
+    let key = format!(
+        "{}|{}|{}",
+        kind.name(),
+        target.trim().to_lowercase(),
+        port.map(|p| p.to_string()).unwrap_or_default()
+    );
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:012x}", hash >> 16)
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC` form of a Unix timestamp.
+pub fn format_timestamp(secs: u64) -> String {
+    let (year, month, day) = crate::blacklists::civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+pub fn registry_path() -> String {
+    match std::env::var(REGISTRY_ENV) {
+        Ok(path) if !path.trim().is_empty() => path.trim().to_string(),
+        _ => DEFAULT_REGISTRY.to_string(),
+    }
+}
+
+/// Parse a registry or export document.
+pub fn parse_rules_document(content: &str) -> Result<Vec<DismissRule>, String> {
+    let document: Value =
+        serde_json::from_str(content).map_err(|e| format!("invalid JSON: {}", e))?;
+    let rules = document["rules"]
+        .as_array()
+        .ok_or_else(|| "missing 'rules' array".to_string())?;
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| DismissRule::from_json(rule).map_err(|e| format!("rules[{}]: {}", i, e)))
+        .collect()
+}
+
+pub fn rules_document(rules: &[DismissRule]) -> Value {
+    json!({
+        "version": REGISTRY_VERSION,
+        "rules": rules.iter().map(DismissRule::to_json).collect::<Vec<_>>(),
+    })
+}
+
+/// Rules recorded in the registry file, empty when the file does not exist yet.
+pub fn load_rules(path: &str) -> Result<Vec<DismissRule>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(format!(
+                "Error reading dismissal registry '{}': {}",
+                path, e
+            ))
+        }
+    };
+    parse_rules_document(&content)
+        .map_err(|e| format!("Invalid dismissal registry '{}': {}", path, e))
+}
+
+pub fn save_rules(path: &str, rules: &[DismissRule]) -> Result<(), String> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+    }
+    let content = serde_json::to_string_pretty(&rules_document(rules))
+        .map_err(|e| format!("Error serializing dismissal registry: {}", e))?;
+    std::fs::write(path, content)
+        .map_err(|e| format!("Error writing dismissal registry '{}': {}", path, e))
+}
+
+/// Add rules to the registry at `path`. Rules already recorded keep their
+/// creation time and origin but take the expiry and reason of the latest
+/// dismissal. Returns the number of new rules.
+pub fn record_rules_at(path: &str, new_rules: &[DismissRule]) -> Result<usize, String> {
+    let mut rules = load_rules(path)?;
+    let mut added = 0;
+    for rule in new_rules {
+        match rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => {
+                existing.expires_at = rule.expires_at;
+                existing.reason = rule.reason.clone();
+            }
+            None => {
+                rules.push(rule.clone());
+                added += 1;
+            }
+        }
+    }
+    save_rules(path, &rules)?;
+    Ok(added)
+}
+
+/// Drop the rules with the given ids from the registry at `path`.
+pub fn remove_rules_at(path: &str, ids: &[String]) -> Result<Vec<DismissRule>, String> {
+    let (removed, kept): (Vec<DismissRule>, Vec<DismissRule>) = load_rules(path)?
+        .into_iter()
+        .partition(|r| ids.contains(&r.id));
+    if !removed.is_empty() {
+        save_rules(path, &kept)?;
+    }
+    Ok(removed)
+}
+
+/// Record a dismissal that was just applied and return the exit code. A rule
+/// without expiry that cannot be recorded is only a warning, since it stays in
+/// effect as requested. With an expiry it is an error: nothing would revoke it.
+pub fn record_dismissal(rule: DismissRule) -> i32 {
+    let expires_at = rule.expires_at;
+    match record_rules_at(&registry_path(), &[rule]) {
+        Ok(_) => {
+            if let Some(expires_at) = expires_at {
+                println!("Dismissal expires {}", format_timestamp(expires_at));
+            }
+            0
+        }
+        Err(e) if expires_at.is_some() => {
+            eprintln!(
+                "Dismissal applied but its expiry will not be enforced: {}",
+                e
+            );
+            ERROR_CODE_PARAM
+        }
+        Err(e) => {
+            warn!("Dismissal applied but not recorded: {}", e);
+            eprintln!("Warning: dismissal applied but not recorded: {}", e);
+            0
+        }
+    }
+}
+
+fn load_network_rules() -> Result<Vec<DismissRule>, String> {
+    Ok(load_rules(&registry_path())?
+        .into_iter()
+        .filter(|r| r.kind.is_network())
+        .collect())
+}
+
+pub fn list_rules(json_output: bool) -> i32 {
+    let rules = match load_network_rules() {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    if json_output {
+        match serde_json::to_string_pretty(&rules_document(&rules)) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error formatting dismissal rules: {}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+        return 0;
+    }
+    if rules.is_empty() {
+        println!("No network dismissal rules recorded");
+        return 0;
+    }
+    for rule in &rules {
+        println!(
+            "{}  {}  created {}  origin {}{}",
+            rule.id,
+            rule.describe(),
+            format_timestamp(rule.created_at),
+            if rule.origin.is_empty() {
+                "unknown"
+            } else {
+                &rule.origin
+            },
+            terms_summary(rule)
+        );
+    }
+    0
+}
+
+fn terms_summary(rule: &DismissRule) -> String {
+    let mut summary = String::new();
+    if let Some(expires_at) = rule.expires_at {
+        summary.push_str(&format!("  expires {}", format_timestamp(expires_at)));
+    }
+    if let Some(reason) = &rule.reason {
+        summary.push_str(&format!("  reason {:?}", reason));
+    }
+    summary
+}
+
+// Coarse "3d 4h" / "5h 10m" / "42m" form of a positive duration
+fn format_remaining(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes.max(1))
+    }
+}
+
+/// Rules expiring within `window` seconds of `now`, including expired rules
+/// that have not been revoked yet, soonest first.
+pub fn expiring_rules(rules: &[DismissRule], now: u64, window: u64) -> Vec<DismissRule> {
+    let mut expiring: Vec<DismissRule> = rules
+        .iter()
+        .filter(|r| {
+            r.expires_at
+                .is_some_and(|expires_at| expires_at <= now.saturating_add(window))
+        })
+        .cloned()
+        .collect();
+    expiring.sort_by_key(|r| r.expires_at);
+    expiring
+}
+
+pub fn expiring_suppressions(within: u64, json_output: bool) -> i32 {
+    let rules = match load_rules(&registry_path()) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let now = now_secs();
+    let expiring = expiring_rules(&rules, now, within);
+
+    if json_output {
+        let report = json!({
+            "generated_at": now,
+            "within_secs": within,
+            "rules": expiring
+                .iter()
+                .map(|rule| {
+                    let mut value = rule.to_json();
+                    value["expired"] = Value::from(rule.is_expired(now));
+                    value
+                })
+                .collect::<Vec<_>>(),
+        });
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error formatting expiring suppressions: {}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+        return 0;
+    }
+
+    if expiring.is_empty() {
+        println!("No suppressions expire within {}", format_remaining(within));
+        return 0;
+    }
+    for rule in &expiring {
+        let expires_at = rule.expires_at.unwrap_or(0);
+        let when = if rule.is_expired(now) {
+            "expired".to_string()
+        } else {
+            format!("in {}", format_remaining(expires_at - now))
+        };
+        println!(
+            "{}  {}  expires {} ({})  reason {}",
+            rule.id,
+            rule.describe(),
+            format_timestamp(expires_at),
+            when,
+            rule.reason.as_deref().unwrap_or("none given")
+        );
+    }
+    0
+}
+
+pub fn export_rules(file: &str) -> i32 {
+    let rules = match load_network_rules() {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    match save_rules(file, &rules) {
+        Ok(()) => {
+            println!("Exported {} dismissal rules to {}", rules.len(), file);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ERROR_CODE_PARAM
+        }
+    }
+}
+
+/// Revoke the network rule `id` with `revoke`, then delete it from the
+/// registry. The rule stays recorded when it cannot be revoked.
+pub fn remove_rule_at(
+    path: &str,
+    id: &str,
+    revoke: &dyn Fn(&DismissRule) -> Result<(), String>,
+) -> Result<DismissRule, String> {
+    let id = id.trim();
+    let rule = load_rules(path)?
+        .into_iter()
+        .find(|r| r.id == id && r.kind.is_network())
+        .ok_or_else(|| format!("No network dismissal rule with id {}", id))?;
+    revoke(&rule)
+        .map_err(|e| format!("Error revoking {} ({}): {}", rule.id, rule.describe(), e))?;
+    remove_rules_at(path, std::slice::from_ref(&rule.id))
+        .map_err(|e| format!("Dismissal revoked but its rule was not removed: {}", e))?;
+    Ok(rule)
+}
+
+pub fn remove_rule(id: &str, revoke: &dyn Fn(&DismissRule) -> Result<(), String>) -> i32 {
+    match remove_rule_at(&registry_path(), id, revoke) {
+        Ok(rule) => {
+            println!("Removed dismissal rule {} ({})", rule.id, rule.describe());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ERROR_CODE_PARAM
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_ids_are_stable_and_distinct() {
+        let id = rule_id(DismissKind::DevicePort, "192.168.1.20", Some(22));
+        assert_eq!(id.len(), 12);
+        assert_eq!(
+            id,
+            rule_id(DismissKind::DevicePort, " 192.168.1.20 ", Some(22))
+        );
+        assert_ne!(
+            id,
+            rule_id(DismissKind::DevicePort, "192.168.1.20", Some(23))
+        );
+        assert_ne!(
+            rule_id(DismissKind::Session, "uid-1", None),
+            rule_id(DismissKind::Process, "uid-1", None)
+        );
+    }
+
+    #[test]
+    fn rules_document_round_trip_and_validation() {
+        let rules = vec![
+            DismissRule::new(DismissKind::Device, "10.0.0.5", None, "cli"),
+            DismissRule::new(
+                DismissKind::DevicePort,
+                "10.0.0.6",
+                Some(8080),
+                "import:fleet.json",
+            ),
+        ];
+        let content = rules_document(&rules).to_string();
+        assert_eq!(parse_rules_document(&content).unwrap(), rules);
+
+        let invalid = r#"{ "rules": [ { "kind": "device_port", "target": "10.0.0.6" } ] }"#;
+        assert_eq!(
+            parse_rules_document(invalid).unwrap_err(),
+            "rules[0]: missing 'port' for a device_port rule"
+        );
+        let invalid = r#"{ "rules": [ { "kind": "device", "target": "not-an-ip" } ] }"#;
+        assert!(parse_rules_document(invalid).is_err());
+    }
+
+    #[test]
+    fn recording_keeps_existing_rules() {
+        let path = std::env::temp_dir().join(format!("dismissals-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut first = DismissRule::new(DismissKind::Device, "10.0.0.5", None, "cli");
+        first.created_at = 100;
+        assert_eq!(record_rules_at(path, &[first.clone()]).unwrap(), 1);
+        let again = DismissRule::new(DismissKind::Device, "10.0.0.5", None, "import:x.json");
+        let other = DismissRule::new(DismissKind::Session, "uid-1", None, "cli");
+        assert_eq!(record_rules_at(path, &[again, other]).unwrap(), 1);
+
+        let rules = load_rules(path).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0], first);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn expiring_rules_are_sorted_and_terms_are_updated() {
+        let path = std::env::temp_dir().join(format!("expiring-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let now = 1_000_000;
+
+        let expired = DismissRule::new(DismissKind::Vulnerability, "finding-a", None, "cli")
+            .with_terms(Some(now - 10), Some("accepted until patch".to_string()));
+        let soon = DismissRule::new(DismissKind::Device, "10.0.0.5", None, "cli")
+            .with_terms(Some(now + 3 * 86_400), None);
+        let later = DismissRule::new(DismissKind::Divergence, "finding-b", None, "cli")
+            .with_terms(Some(now + 30 * 86_400), None);
+        let permanent = DismissRule::new(DismissKind::Session, "uid-1", None, "cli");
+        record_rules_at(path, &[soon.clone(), later, permanent, expired.clone()]).unwrap();
+
+        let rules = load_rules(path).unwrap();
+        let expiring = expiring_rules(&rules, now, 7 * 86_400);
+        assert_eq!(expiring, vec![expired.clone(), soon.clone()]);
+        assert!(expiring[0].is_expired(now));
+        assert!(!expiring[1].is_expired(now));
+
+        // Dismissing again renews the terms but keeps the creation time
+        let renewed = DismissRule::new(DismissKind::Device, "10.0.0.5", None, "cli")
+            .with_terms(Some(now + 60 * 86_400), Some("lab printer".to_string()));
+        assert_eq!(record_rules_at(path, &[renewed]).unwrap(), 0);
+        let rules = load_rules(path).unwrap();
+        let device = rules.iter().find(|r| r.id == soon.id).unwrap();
+        assert_eq!(device.created_at, soon.created_at);
+        assert_eq!(device.expires_at, Some(now + 60 * 86_400));
+        assert_eq!(device.reason.as_deref(), Some("lab printer"));
+
+        let removed = remove_rules_at(path, std::slice::from_ref(&expired.id)).unwrap();
+        assert_eq!(removed, vec![expired]);
+        assert_eq!(load_rules(path).unwrap().len(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn timestamps_are_formatted_in_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(
+            format_timestamp(1_760_745_600 + 3_723),
+            "2025-10-18 01:02:03 UTC"
+        );
+    }
+
+    #[test]
+    fn removal_keeps_the_rule_when_the_core_cannot_revoke_it() {
+        let path = std::env::temp_dir().join(format!("removal-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let port = DismissRule::new(DismissKind::DevicePort, "10.0.0.6", Some(22), "cli");
+        let finding = DismissRule::new(DismissKind::Vulnerability, "finding-a", None, "cli");
+        record_rules_at(path, &[port.clone(), finding.clone()]).unwrap();
+
+        let refuse = |_: &DismissRule| Err("core unavailable".to_string());
+        let err = remove_rule_at(path, &port.id, &refuse).unwrap_err();
+        assert!(err.contains("core unavailable"), "{}", err);
+        assert_eq!(load_rules(path).unwrap().len(), 2);
+
+        // Only network rules can be removed, the others expire through the daemon
+        let accept = |_: &DismissRule| Ok(());
+        assert!(remove_rule_at(path, &finding.id, &accept).is_err());
+        assert!(remove_rule_at(path, "000000000000", &accept).is_err());
+
+        let revoked = std::cell::RefCell::new(Vec::new());
+        let record = |r: &DismissRule| {
+            revoked.borrow_mut().push(r.id.clone());
+            Ok(())
+        };
+        let id = format!(" {} ", port.id);
+        assert_eq!(remove_rule_at(path, &id, &record).unwrap(), port);
+        assert_eq!(revoked.into_inner(), vec![port.id.clone()]);
+        assert_eq!(load_rules(path).unwrap(), vec![finding]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,682 @@
+// Scheduled refresh of the custom blacklists from feeds.
+//
+// EDAMAME_BLACKLIST_FEEDS lists the feeds of the daemon, separated by commas:
+// local files or http(s) URLs. A feed holds either a custom blacklist document
+// (the JSON of set-custom-blacklists) or a threat-intel feed in one of the
+// formats of import-blacklist, which becomes one blacklist named after the
+// feed. Feeds are polled every EDAMAME_BLACKLIST_FEEDS_INTERVAL (1h by
+// default): files are read again when their modification time changes, URLs
+// are requested with the ETag of the previous download and an unchanged feed
+// answers 304. A feed that cannot be fetched, parsed, validated or merged
+// keeps the blacklists of its last good version. The daemon writes the state
+// of every feed to a status file read by `blacklist-feeds status`.
+
+use crate::blacklists::{
+    blacklist_document, blacklist_entry, extract_indicators, normalize_ip_ranges, IntelFormat,
+};
+use crate::dismissals::format_timestamp;
+use crate::{ERROR_CODE_MISMATCH, ERROR_CODE_PARAM};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::warn;
+
+pub const FEEDS_ENV: &str = "EDAMAME_BLACKLIST_FEEDS";
+pub const INTERVAL_ENV: &str = "EDAMAME_BLACKLIST_FEEDS_INTERVAL";
+pub const STATUS_ENV: &str = "EDAMAME_BLACKLIST_FEEDS_STATUS";
+const DEFAULT_INTERVAL: u64 = 3600;
+#[cfg(not(windows))]
+const DEFAULT_STATUS: &str = "/var/lib/edamame_posture/blacklist_feeds.json";
+#[cfg(windows)]
+const DEFAULT_STATUS: &str = r"C:\ProgramData\edamame_posture\blacklist_feeds.json";
+const STATUS_VERSION: u64 = 1;
+// Seconds allowed for one download
+const FETCH_TIMEOUT: &str = "60";
+
+/// Check of a blacklist document against the schema of the core.
+pub type Validator = dyn Fn(&str) -> Result<(), String>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Feed {
+    pub source: String,
+    // Blacklist name of a threat-intel feed
+    pub name: String,
+    etag: Option<String>,
+    mtime: Option<u64>,
+    sha256: Option<String>,
+    // Blacklists of the last good version
+    entries: Vec<Value>,
+    last_checked: Option<u64>,
+    last_changed: Option<u64>,
+    error: Option<String>,
+}
+
+enum Fetched {
+    Unchanged,
+    Content(String),
+}
+
+fn is_url(source: &str) -> bool {
+    let lower = source.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Blacklist name of a threat-intel feed: its file name without extension.
+fn feed_name(source: &str) -> String {
+    let path = source.split(['?', '#']).next().unwrap_or(source);
+    let file = path
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(path);
+    Path::new(file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("feed")
+        .to_string()
+}
+
+/// Status code and ETag of the last response of `curl -D -` headers, the
+/// earlier ones being redirects.
+fn parse_response_headers(headers: &str) -> (u16, Option<String>) {
+    let mut status = 0;
+    let mut etag = None;
+    for line in headers.lines().map(str::trim) {
+        if line.starts_with("HTTP/") {
+            status = line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse().ok())
+                .unwrap_or(0);
+            etag = None;
+        } else if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("etag") && !value.trim().is_empty() {
+                etag = Some(value.trim().to_string());
+            }
+        }
+    }
+    (status, etag)
+}
+
+/// Split the output of `curl --include` into its header blocks, one per
+/// redirect, and the body that follows the last one.
+fn split_response(output: &[u8]) -> (String, &[u8]) {
+    let mut headers = String::new();
+    let mut rest = output;
+    while rest.starts_with(b"HTTP/") {
+        let end = rest
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| i + 4)
+            .or_else(|| rest.windows(2).position(|w| w == b"\n\n").map(|i| i + 2))
+            .unwrap_or(rest.len());
+        headers.push_str(&String::from_utf8_lossy(&rest[..end]));
+        rest = &rest[end..];
+    }
+    (headers, rest)
+}
+
+/// Blacklists of the content of a feed, checked against the signing policy
+/// and the schema of the core. Signatures of local feeds are read from
+/// `<file>.sig`; URL feeds are refused when signed lists are required.
+pub fn feed_entries(
+    source: &str,
+    name: &str,
+    content: &str,
+    validate: &Validator,
+) -> Result<Vec<Value>, String> {
+    let signature_file = (!is_url(source)).then(|| format!("{}.sig", source));
+    crate::signing::check_list_signature(content, signature_file.as_deref(), "Blacklist feed")?;
+
+    if let Some(document) = serde_json::from_str::<Value>(content)
+        .ok()
+        .filter(|document| document.get("blacklists").is_some())
+    {
+        validate(content)?;
+        return match document["blacklists"].as_array() {
+            Some(entries) if !entries.is_empty() => Ok(entries.clone()),
+            _ => Err("blacklist document without blacklists".to_string()),
+        };
+    }
+
+    let format = IntelFormat::detect(source, content);
+    let indicators = extract_indicators(content, format, None)
+        .map_err(|e| format!("Error parsing feed as {:?}: {}", format, e))?;
+    let (ranges, _) = normalize_ip_ranges(&indicators.ips);
+    if ranges.is_empty() {
+        return Err(format!("No IP indicators found ({:?} format)", format));
+    }
+    let entry = blacklist_entry(
+        name,
+        &format!("Blacklist feed {} ({:?})", source, format),
+        source,
+        &ranges,
+    );
+    validate(&blacklist_document(vec![entry.clone()]).to_string())?;
+    Ok(vec![entry])
+}
+
+// Headers and body are both read from curl's stdout, the daemon runs as root
+// and must not write the download to a shared directory
+fn fetch_url(url: &str, etag: Option<&str>) -> Result<(u16, Option<String>, String), String> {
+    let mut command = std::process::Command::new("curl");
+    command.args(["-s", "-S", "-L", "-i", "--max-time", FETCH_TIMEOUT]);
+    if let Some(etag) = etag {
+        command.args(["-H", &format!("If-None-Match: {}", etag)]);
+    }
+    let output = command
+        .arg(url)
+        .output()
+        .map_err(|e| format!("Failed to execute 'curl' command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Download failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let (headers, body) = split_response(&output.stdout);
+    let (status, etag) = parse_response_headers(&headers);
+    Ok((status, etag, String::from_utf8_lossy(body).to_string()))
+}
+
+impl Feed {
+    pub fn new(source: &str) -> Feed {
+        Feed {
+            source: source.to_string(),
+            name: feed_name(source),
+            ..Default::default()
+        }
+    }
+
+    /// The new version of the feed, if any. Change markers are kept even when
+    /// the version is rejected, so that it is not downloaded again.
+    fn fetch(&mut self) -> Result<Fetched, String> {
+        if is_url(&self.source) {
+            let (status, etag, content) = fetch_url(&self.source, self.etag.as_deref())?;
+            match status {
+                304 => Ok(Fetched::Unchanged),
+                200 => {
+                    self.etag = etag;
+                    Ok(Fetched::Content(content))
+                }
+                status => Err(format!("Download failed with HTTP status {}", status)),
+            }
+        } else {
+            let metadata = std::fs::metadata(&self.source)
+                .map_err(|e| format!("Error reading feed file '{}': {}", self.source, e))?;
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs());
+            if mtime.is_some() && mtime == self.mtime {
+                return Ok(Fetched::Unchanged);
+            }
+            let content = std::fs::read_to_string(&self.source)
+                .map_err(|e| format!("Error reading feed file '{}': {}", self.source, e))?;
+            self.mtime = mtime;
+            Ok(Fetched::Content(content))
+        }
+    }
+
+    /// Take a new version of the feed. Returns whether its blacklists changed.
+    fn update(&mut self, content: &str, now: u64, validate: &Validator) -> bool {
+        let sha256 = format!("{:x}", Sha256::digest(content.as_bytes()));
+        if self.sha256.as_deref() == Some(sha256.as_str()) {
+            // Back to the last good version
+            self.error = None;
+            return false;
+        }
+        match feed_entries(&self.source, &self.name, content, validate) {
+            Ok(entries) => {
+                self.entries = entries;
+                self.sha256 = Some(sha256);
+                self.last_changed = Some(now);
+                self.error = None;
+                true
+            }
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    fn poll(&mut self, now: u64, validate: &Validator) -> bool {
+        self.last_checked = Some(now);
+        let changed = match self.fetch() {
+            Ok(Fetched::Unchanged) => false,
+            Ok(Fetched::Content(content)) => self.update(&content, now, validate),
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        };
+        if let Some(error) = &self.error {
+            warn!("Blacklist feed {}: {}", self.source, error);
+        }
+        changed
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry["name"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn status(&self) -> Value {
+        let state = match (&self.error, self.entries.is_empty()) {
+            (None, false) => "ok",
+            (None, true) => "pending",
+            // Still serving the last good version
+            (Some(_), false) => "stale",
+            (Some(_), true) => "error",
+        };
+        let ip_ranges: usize = self
+            .entries
+            .iter()
+            .filter_map(|entry| entry["ip_ranges"].as_array())
+            .map(|ranges| ranges.len())
+            .sum();
+        json!({
+            "source": self.source,
+            "type": if is_url(&self.source) { "url" } else { "file" },
+            "state": state,
+            "blacklists": self.names(),
+            "ip_ranges": ip_ranges,
+            "sha256": self.sha256,
+            "etag": self.etag,
+            "mtime": self.mtime,
+            "last_checked": self.last_checked,
+            "last_changed": self.last_changed,
+            "error": self.error,
+        })
+    }
+}
+
+pub fn status_path() -> String {
+    match std::env::var(STATUS_ENV) {
+        Ok(path) if !path.trim().is_empty() => path.trim().to_string(),
+        _ => DEFAULT_STATUS.to_string(),
+    }
+}
+
+pub struct BlacklistFeeds {
+    pub feeds: Vec<Feed>,
+    pub interval: u64,
+    status_path: String,
+    // Names of the blacklists the feeds set on the core
+    applied: Vec<String>,
+    next_refresh: u64,
+}
+
+impl BlacklistFeeds {
+    pub fn new(sources: &str, interval: u64, status_path: &str) -> Result<BlacklistFeeds, String> {
+        let mut feeds: Vec<Feed> = Vec::new();
+        for source in sources.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if feeds.iter().any(|feed| feed.source == source) {
+                continue;
+            }
+            let mut feed = Feed::new(source);
+            // Two feeds of the same file name must not replace each other
+            if feeds.iter().any(|other| other.name == feed.name) {
+                feed.name = format!("{}-{}", feed.name, feeds.len() + 1);
+            }
+            feeds.push(feed);
+        }
+        if feeds.is_empty() {
+            return Err(format!("No blacklist feed in '{}'", sources));
+        }
+        if interval == 0 {
+            return Err("The refresh interval must not be zero".to_string());
+        }
+        Ok(BlacklistFeeds {
+            feeds,
+            interval,
+            status_path: status_path.to_string(),
+            applied: Vec::new(),
+            next_refresh: 0,
+        })
+    }
+
+    /// Feeds configured by EDAMAME_BLACKLIST_FEEDS, if set. The interval comes
+    /// from EDAMAME_BLACKLIST_FEEDS_INTERVAL (e.g. 30m) and the status file
+    /// from EDAMAME_BLACKLIST_FEEDS_STATUS.
+    pub fn from_env() -> Result<Option<BlacklistFeeds>, String> {
+        let sources = match std::env::var(FEEDS_ENV) {
+            Ok(sources) if !sources.trim().is_empty() => sources,
+            _ => return Ok(None),
+        };
+        let interval = match std::env::var(INTERVAL_ENV) {
+            Ok(value) if !value.trim().is_empty() => crate::parse_duration(&value)
+                .map_err(|e| format!("Invalid {} '{}': {}", INTERVAL_ENV, value, e))?,
+            _ => DEFAULT_INTERVAL,
+        };
+        BlacklistFeeds::new(&sources, interval, &status_path()).map(Some)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} (every {}s, status in {})",
+            self.feeds
+                .iter()
+                .map(|feed| feed.source.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.interval,
+            self.status_path
+        )
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        now >= self.next_refresh
+    }
+
+    /// True when blacklists set by the feeds are no longer active, e.g. after
+    /// set-custom-blacklists replaced them.
+    fn replaced(&self, current: &str) -> bool {
+        let active: Vec<String> = serde_json::from_str::<Value>(current)
+            .ok()
+            .and_then(|document| document["blacklists"].as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|b| b["name"].as_str().map(str::to_string))
+            .collect();
+        self.applied.iter().any(|name| !active.contains(name))
+    }
+
+    /// The blacklist document `current` with the blacklists of the feeds,
+    /// which replace the ones they set before and any of the same name.
+    fn merged_document(&self, current: &str) -> Result<Value, String> {
+        let mut document = if current.trim().is_empty() {
+            blacklist_document(Vec::new())
+        } else {
+            serde_json::from_str(current)
+                .map_err(|e| format!("invalid active blacklist JSON: {}", e))?
+        };
+        let blacklists = document
+            .get_mut("blacklists")
+            .and_then(|b| b.as_array_mut())
+            .ok_or("active blacklist JSON has no 'blacklists' array")?;
+        blacklists.retain(|b| {
+            !b["name"]
+                .as_str()
+                .is_some_and(|name| self.applied.iter().any(|applied| applied == name))
+        });
+        for entry in self.feeds.iter().flat_map(|feed| &feed.entries) {
+            blacklists.retain(|b| b.get("name") != entry.get("name"));
+            blacklists.push(entry.clone());
+        }
+        // The merged list is not the one that was signed
+        document["signature"] = json!("");
+        Ok(document)
+    }
+
+    /// Poll the feeds. When one of them changed, returns the blacklist JSON to
+    /// set in place of `current`, the document active on the core. If the
+    /// merged document is rejected, the changed feeds go back to their
+    /// previous version and are downloaded again at the next refresh.
+    pub fn refresh(&mut self, now: u64, current: &str, validate: &Validator) -> Option<String> {
+        self.next_refresh = now + self.interval;
+        let previous = self.feeds.clone();
+        let changed: Vec<bool> = self
+            .feeds
+            .iter_mut()
+            .map(|feed| feed.poll(now, validate))
+            .collect();
+        if !changed.contains(&true) && !self.replaced(current) {
+            return None;
+        }
+        let merged = self.merged_document(current).and_then(|document| {
+            let blacklist_json = serde_json::to_string_pretty(&document)
+                .map_err(|e| format!("Error formatting blacklist JSON: {}", e))?;
+            validate(&blacklist_json)?;
+            Ok(blacklist_json)
+        });
+        match merged {
+            Ok(blacklist_json) => Some(blacklist_json),
+            Err(e) => {
+                let error = format!("Merged blacklist rejected, keeping the previous one: {}", e);
+                warn!("{}", error);
+                for ((feed, previous), changed) in self.feeds.iter_mut().zip(previous).zip(changed)
+                {
+                    if changed {
+                        *feed = Feed {
+                            last_checked: Some(now),
+                            error: Some(error.clone()),
+                            ..previous
+                        };
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Record that the JSON returned by `refresh` is now active.
+    pub fn applied(&mut self) {
+        self.applied = self.feeds.iter().flat_map(Feed::names).collect();
+    }
+
+    pub fn status(&self, now: u64) -> Value {
+        json!({
+            "version": STATUS_VERSION,
+            "updated_at": now,
+            "interval": self.interval,
+            "next_refresh": self.next_refresh,
+            "feeds": self.feeds.iter().map(Feed::status).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Replace the status file, through a temporary file so that readers never
+    /// see a partial one.
+    pub fn save_status(&self, now: u64) -> Result<(), String> {
+        let path = Path::new(&self.status_path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+        let content = serde_json::to_string_pretty(&self.status(now))
+            .map_err(|e| format!("Error serializing blacklist feed status: {}", e))?;
+        let temp = format!("{}.tmp", self.status_path);
+        std::fs::write(&temp, content)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| {
+                format!(
+                    "Error writing blacklist feed status '{}': {}",
+                    self.status_path, e
+                )
+            })
+    }
+}
+
+fn format_time(value: &Value) -> String {
+    value
+        .as_u64()
+        .map(format_timestamp)
+        .unwrap_or_else(|| "never".to_string())
+}
+
+/// Print the feed status written by the daemon. Returns ERROR_CODE_MISMATCH
+/// when a feed failed at its last refresh.
+pub fn print_status(json_output: bool) -> i32 {
+    let path = status_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!(
+                "No blacklist feed status in '{}', start the daemon with {} set",
+                path, FEEDS_ENV
+            );
+            return ERROR_CODE_PARAM;
+        }
+        Err(e) => {
+            eprintln!("Error reading blacklist feed status '{}': {}", path, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let status: Value = match serde_json::from_str(&content) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Invalid blacklist feed status '{}': {}", path, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let feeds = status["feeds"].as_array().cloned().unwrap_or_default();
+
+    if json_output {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).unwrap_or_default()
+        );
+    } else {
+        println!(
+            "Blacklist feeds, refreshed every {}s (last refresh {}, next {})",
+            status["interval"],
+            format_time(&status["updated_at"]),
+            format_time(&status["next_refresh"])
+        );
+        for feed in &feeds {
+            println!(
+                "\n{:<8} {}",
+                feed["state"].as_str().unwrap_or(""),
+                feed["source"].as_str().unwrap_or("")
+            );
+            let names: Vec<&str> = feed["blacklists"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str())
+                .collect();
+            if !names.is_empty() {
+                println!(
+                    "         {} ({} IP range(s))",
+                    names.join(", "),
+                    feed["ip_ranges"]
+                );
+            }
+            println!(
+                "         changed {}, checked {}",
+                format_time(&feed["last_changed"]),
+                format_time(&feed["last_checked"])
+            );
+            if let Some(error) = feed["error"].as_str() {
+                println!("         error: {}", error);
+            }
+        }
+    }
+    if feeds.iter().any(|feed| !feed["error"].is_null()) {
+        ERROR_CODE_MISMATCH
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(json: &str) -> Result<(), String> {
+        let document: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        match document["blacklists"].as_array() {
+            Some(entries) if entries.iter().all(|e| e["ip_ranges"].is_array()) => Ok(()),
+            _ => Err("schema mismatch".to_string()),
+        }
+    }
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("edamame-feeds-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn feed_names_and_headers() {
+        assert_eq!(
+            feed_name("https://iplists.example/firehol_level1.netset?x=1"),
+            "firehol_level1"
+        );
+        assert_eq!(feed_name("/etc/edamame/blocked.json"), "blocked");
+        assert_eq!(feed_name("https://example.com/"), "example");
+        let headers = "HTTP/1.1 302 Found\r\nETag: \"a\"\r\nLocation: /x\r\n\r\n\
+                       HTTP/2 200\r\netag: W/\"b\"\r\n\r\n";
+        assert_eq!(
+            parse_response_headers(headers),
+            (200, Some("W/\"b\"".to_string()))
+        );
+        assert_eq!(
+            parse_response_headers("HTTP/1.1 304 Not Modified\r\n"),
+            (304, None)
+        );
+        let output = format!("{}203.0.113.7\n\n198.51.100.0/24\n", headers);
+        let (split_headers, body) = split_response(output.as_bytes());
+        assert_eq!(split_headers, headers);
+        assert_eq!(body, b"203.0.113.7\n\n198.51.100.0/24\n");
+        assert_eq!(split_response(b"HTTP/1.1 304 Not Modified\r\n\r\n").1, b"");
+    }
+
+    #[test]
+    fn bad_versions_keep_the_last_good_one() {
+        let ips = temp_file("ips.txt", "203.0.113.7\n198.51.100.0/24\n");
+        let document = temp_file(
+            "doc.json",
+            r#"{"date":"","signature":"","blacklists":[{"name":"corp","ip_ranges":["192.0.2.0/24"]}]}"#,
+        );
+        let status = temp_file("status.json", "");
+        let mut feeds =
+            BlacklistFeeds::new(&format!("{}, {}", ips, document), 60, &status).unwrap();
+        let current = r#"{"date":"","signature":"","blacklists":[{"name":"vendor","ip_ranges":["10.0.0.0/8"]},{"name":"corp","ip_ranges":[]}]}"#;
+
+        let merged = feeds
+            .refresh(1000, current, &schema)
+            .expect("feeds changed");
+        let merged: Value = serde_json::from_str(&merged).unwrap();
+        let names: Vec<&str> = merged["blacklists"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["vendor", feeds.feeds[0].name.as_str(), "corp"]);
+        assert_eq!(
+            merged["blacklists"][1]["ip_ranges"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        feeds.applied();
+        assert!(!feeds.is_due(1059) && feeds.is_due(1060));
+
+        // Unchanged files are not read again
+        let active = serde_json::to_string(&merged).unwrap();
+        assert!(feeds.refresh(1060, &active, &schema).is_none());
+        // but their blacklists are set again when another list replaced them
+        assert!(feeds.refresh(1120, current, &schema).is_some());
+
+        // An invalid version is reported and the previous blacklists stay
+        feeds.feeds[1].mtime = None;
+        std::fs::write(&document, r#"{"blacklists":[{"name":"corp"}]}"#).unwrap();
+        assert!(feeds.refresh(1180, &active, &schema).is_none());
+        let state = feeds.status(1180);
+        assert_eq!(state["feeds"][0]["state"], "ok");
+        assert_eq!(state["feeds"][1]["state"], "stale");
+        assert_eq!(state["feeds"][1]["blacklists"][0], "corp");
+        assert!(state["feeds"][1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("schema"));
+
+        feeds.save_status(1180).unwrap();
+        let saved: Value =
+            serde_json::from_str(&std::fs::read_to_string(&status).unwrap()).unwrap();
+        assert_eq!(saved, state);
+        for path in [ips, document, status] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
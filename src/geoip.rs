@@ -0,0 +1,349 @@
+// Offline GeoIP and ASN enrichment from MaxMind DB (.mmdb) files.
+//
+// EDAMAME_GEOIP_DB lists .mmdb files, or directories holding them, separated
+// by commas. Country and City databases (GeoLite2, GeoIP2 or compatible such
+// as DB-IP) give the country of an address, ASN databases its AS number and
+// owner. Lookups never leave the machine. The country is added to sessions as
+// `dst_country`, apart from `dst_asn.country`, the country the AS is
+// registered in. The AS of the core is kept: the ASN databases only fill
+// `dst_asn` when the core has no AS number.
+
+use crate::sessions::{session_str, session_u64};
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+pub const GEOIP_ENV: &str = "EDAMAME_GEOIP_DB";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DatabaseKind {
+    Country,
+    Asn,
+}
+
+struct Database {
+    path: String,
+    kind: DatabaseKind,
+    reader: Reader<Vec<u8>>,
+}
+
+pub struct GeoIp {
+    databases: Vec<Database>,
+}
+
+/// What the databases know about an address.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub as_number: Option<u32>,
+    pub as_owner: Option<String>,
+}
+
+fn database_kind(database_type: &str) -> Option<DatabaseKind> {
+    if database_type.contains("ASN") {
+        Some(DatabaseKind::Asn)
+    } else if ["Country", "City", "Enterprise"]
+        .iter()
+        .any(|kind| database_type.contains(kind))
+    {
+        Some(DatabaseKind::Country)
+    } else {
+        None
+    }
+}
+
+/// The .mmdb files of a comma-separated list of files and directories.
+fn database_files(paths: &str) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for path in paths.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if Path::new(path).is_dir() {
+            let entries = std::fs::read_dir(path)
+                .map_err(|e| format!("Error reading GeoIP directory '{}': {}", path, e))?;
+            let mut found: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "mmdb"))
+                .map(|path| path.display().to_string())
+                .collect();
+            if found.is_empty() {
+                return Err(format!("No .mmdb file in GeoIP directory '{}'", path));
+            }
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(path.to_string());
+        }
+    }
+    Ok(files)
+}
+
+impl GeoIp {
+    /// Open the databases of a comma-separated list of files and directories.
+    pub fn open(paths: &str) -> Result<GeoIp, String> {
+        let mut databases = Vec::new();
+        for path in database_files(paths)? {
+            let reader = Reader::open_readfile(&path)
+                .map_err(|e| format!("Error opening GeoIP database '{}': {}", path, e))?;
+            let kind = database_kind(&reader.metadata.database_type).ok_or_else(|| {
+                format!(
+                    "Unsupported GeoIP database '{}' of type '{}', expected a Country, City or ASN database",
+                    path, reader.metadata.database_type
+                )
+            })?;
+            databases.push(Database { path, kind, reader });
+        }
+        if databases.is_empty() {
+            return Err(format!("No GeoIP database in '{}'", paths));
+        }
+        Ok(GeoIp { databases })
+    }
+
+    /// Databases configured by EDAMAME_GEOIP_DB, if set.
+    pub fn from_env() -> Result<Option<GeoIp>, String> {
+        match std::env::var(GEOIP_ENV) {
+            Ok(paths) if !paths.trim().is_empty() => GeoIp::open(&paths).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether a Country or City database gives the country of addresses.
+    pub fn has_countries(&self) -> bool {
+        self.databases
+            .iter()
+            .any(|db| db.kind == DatabaseKind::Country)
+    }
+
+    pub fn describe(&self) -> String {
+        self.databases
+            .iter()
+            .map(|db| format!("{} ({})", db.reader.metadata.database_type, db.path))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Look an address up in every database, the first answer winning.
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        for db in &self.databases {
+            match db.kind {
+                DatabaseKind::Country if info.country.is_none() => {
+                    let country: Result<geoip2::Country, MaxMindDBError> = db.reader.lookup(ip);
+                    if let Ok(country) = country {
+                        info.country = country
+                            .country
+                            .and_then(|c| c.iso_code)
+                            .or_else(|| country.registered_country.and_then(|c| c.iso_code))
+                            .map(str::to_string);
+                    }
+                }
+                DatabaseKind::Asn if info.as_number.is_none() => {
+                    let asn: Result<geoip2::Asn, MaxMindDBError> = db.reader.lookup(ip);
+                    if let Ok(asn) = asn {
+                        info.as_number = asn.autonomous_system_number;
+                        info.as_owner = asn.autonomous_system_organization.map(str::to_string);
+                    }
+                }
+                _ => {}
+            }
+        }
+        info
+    }
+
+    /// Enrich a serialized session, see `apply_geo`.
+    pub fn enrich(&self, session: &mut Value) -> bool {
+        let Some(ip) = session_str(session, "/session/dst_ip").and_then(|ip| ip.parse().ok())
+        else {
+            return false;
+        };
+        apply_geo(session, &self.lookup(ip))
+    }
+}
+
+/// Set the `dst_country` of a session from `info`, and its `dst_asn` when
+/// the core has no AS number. The AS fields are replaced together so that
+/// they describe a single AS; ASN databases do not know where an AS is
+/// registered, so its country is left empty. Only output fields change, the
+/// whitelist state of the session is the core's. Returns whether AS
+/// information was added.
+pub fn apply_geo(session: &mut Value, info: &GeoInfo) -> bool {
+    if !session.is_object() {
+        return false;
+    }
+    if let Some(country) = &info.country {
+        session["dst_country"] = json!(country);
+    }
+    match info.as_number {
+        Some(as_number) if session_u64(session, "/dst_asn/as_number") == 0 => {
+            session["dst_asn"] = json!({
+                "as_number": as_number,
+                "owner": info.as_owner.clone().unwrap_or_default(),
+                "country": "",
+            });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parse a `--country` ISO 3166 alpha-2 code.
+pub fn parse_country(s: &str) -> Result<String, String> {
+    let code = s.trim();
+    if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_uppercase())
+    } else {
+        Err(format!(
+            "Invalid country '{}', expected a two-letter ISO code such as US or DE",
+            s
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoGroup {
+    Country,
+    Asn,
+}
+
+impl GeoGroup {
+    pub fn from_name(name: &str) -> Option<GeoGroup> {
+        match name {
+            "country" => Some(GeoGroup::Country),
+            "asn" => Some(GeoGroup::Asn),
+            _ => None,
+        }
+    }
+}
+
+/// Sessions and bytes per country or AS, the largest senders first.
+pub fn geo_summary(sessions: &[Value], group: GeoGroup) -> Vec<Value> {
+    let mut rows: HashMap<String, Value> = HashMap::new();
+    for session in sessions {
+        let key = match group {
+            GeoGroup::Country => session_str(session, "/dst_country")
+                .unwrap_or("unknown")
+                .to_string(),
+            GeoGroup::Asn => match session_u64(session, "/dst_asn/as_number") {
+                0 => "unknown".to_string(),
+                number => format!("AS{}", number),
+            },
+        };
+        let row = rows.entry(key.clone()).or_insert_with(|| {
+            json!({
+                "key": key,
+                "owner": if group == GeoGroup::Asn { session_str(session, "/dst_asn/owner") } else { None },
+                "sessions": 0,
+                "outbound_bytes": 0,
+                "inbound_bytes": 0,
+            })
+        });
+        row["sessions"] = json!(row["sessions"].as_u64().unwrap_or(0) + 1);
+        for field in ["outbound_bytes", "inbound_bytes"] {
+            row[field] = json!(
+                row[field].as_u64().unwrap_or(0)
+                    + session_u64(session, &format!("/stats/{}", field))
+            );
+        }
+    }
+    let mut rows: Vec<Value> = rows.into_values().collect();
+    rows.sort_by(|a, b| {
+        b["outbound_bytes"]
+            .as_u64()
+            .cmp(&a["outbound_bytes"].as_u64())
+            .then(a["key"].as_str().cmp(&b["key"].as_str()))
+    });
+    rows
+}
+
+pub fn print_geo_summary(rows: &[Value], group: GeoGroup) {
+    let title = match group {
+        GeoGroup::Country => "Country",
+        GeoGroup::Asn => "AS",
+    };
+    println!(
+        "\n{:<12} {:>8} {:>14} {:>14}  Owner",
+        title, "Sessions", "Bytes out", "Bytes in"
+    );
+    for row in rows {
+        println!(
+            "{:<12} {:>8} {:>14} {:>14}  {}",
+            row["key"].as_str().unwrap_or(""),
+            row["sessions"],
+            row["outbound_bytes"],
+            row["inbound_bytes"],
+            row["owner"].as_str().unwrap_or("")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::SessionFilter;
+
+    #[test]
+    fn enrichment_only_fills_missing_fields() {
+        let info = GeoInfo {
+            country: Some("DE".to_string()),
+            as_number: Some(24940),
+            as_owner: Some("Hetzner Online GmbH".to_string()),
+        };
+        let mut unknown = json!({ "session": { "dst_ip": "203.0.113.7" }, "dst_asn": null });
+        assert!(apply_geo(&mut unknown, &info));
+        assert_eq!(unknown["dst_asn"]["as_number"], 24940);
+        assert_eq!(unknown["dst_asn"]["owner"], "Hetzner Online GmbH");
+        assert_eq!(unknown["dst_asn"]["country"], "");
+        assert_eq!(unknown["dst_country"], "DE");
+
+        // The core's AS is kept whole, its registration country included
+        let mut known =
+            json!({ "dst_asn": { "as_number": 13335, "owner": "CLOUDFLARENET", "country": "US" } });
+        assert!(!apply_geo(&mut known, &info));
+        assert_eq!(known["dst_asn"]["as_number"], 13335);
+        assert_eq!(known["dst_asn"]["owner"], "CLOUDFLARENET");
+        assert_eq!(known["dst_asn"]["country"], "US");
+        assert_eq!(known["dst_country"], "DE");
+
+        let mut nothing = json!({ "dst_asn": null });
+        assert!(!apply_geo(&mut nothing, &GeoInfo::default()));
+        assert!(nothing["dst_asn"].is_null());
+        assert!(nothing.get("dst_country").is_none());
+    }
+
+    #[test]
+    fn filters_and_summary_by_country() {
+        // AS registration countries differ from where the addresses are
+        let session = |country: &str, asn: u64, out: u64| {
+            json!({
+                "dst_country": country,
+                "dst_asn": { "as_number": asn, "owner": "owner", "country": "NL" },
+                "stats": { "outbound_bytes": out, "inbound_bytes": 1 },
+            })
+        };
+        let sessions = vec![
+            session("US", 13335, 100),
+            session("DE", 24940, 500),
+            session("US", 16509, 50),
+            json!({ "dst_asn": null, "stats": { "outbound_bytes": 7 } }),
+        ];
+        let filter = SessionFilter {
+            countries: vec!["us".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(sessions.iter().filter(|s| filter.matches(s)).count(), 2);
+        let filter = SessionFilter {
+            asns: vec![24940],
+            ..Default::default()
+        };
+        assert_eq!(sessions.iter().filter(|s| filter.matches(s)).count(), 1);
+
+        let rows = geo_summary(&sessions, GeoGroup::Country);
+        let keys: Vec<&str> = rows.iter().map(|r| r["key"].as_str().unwrap()).collect();
+        assert_eq!(keys, vec!["DE", "US", "unknown"]);
+        assert_eq!(rows[1]["sessions"], 2);
+        assert_eq!(rows[1]["outbound_bytes"], 150);
+        assert!(rows[1]["owner"].is_null());
+        assert_eq!(geo_summary(&sessions, GeoGroup::Asn)[0]["key"], "AS24940");
+    }
+}
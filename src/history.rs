@@ -0,0 +1,657 @@
+// Local history of finished sessions, findings and file events.
+//
+// The daemon appends records to segment files in the history directory, one
+// JSON record per line, and starts a new segment every hour or when the
+// current one is full:
+//
+//   {"kind":"session","time":1760745600000,"recorded_at":1760745605000,"data":{...}}
+//
+// `time` is the start time of the session, or the time of the finding or
+// file event, and `data` the item as reported by the core. Items that fall
+// within a pipeline step marked with `background-mark-step` also carry the
+// name of the step in `step`. Segments are named
+// after the time they were opened, so they sort chronologically, and whole
+// segments are deleted once older than the retention or beyond the size limit.
+
+use crate::capture::FinishedSessions;
+use crate::sessions::{session_destination, session_str, session_u64};
+use crate::steps::{
+    item_time_ms, load_steps, now_millis, step_at, FILE_EVENT_TIME, FINDING_TIME, SESSION_TIME,
+};
+use crate::whitelists::wildcard_matches;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+pub const DIR_ENV: &str = "EDAMAME_HISTORY_DIR";
+pub const RETENTION_ENV: &str = "EDAMAME_HISTORY_RETENTION";
+pub const MAX_SIZE_ENV: &str = "EDAMAME_HISTORY_MAX_SIZE";
+#[cfg(not(windows))]
+pub const DEFAULT_DIR: &str = "/var/lib/edamame_posture/history";
+#[cfg(windows)]
+pub const DEFAULT_DIR: &str = r"C:\ProgramData\edamame_posture\history";
+const DEFAULT_RETENTION: u64 = 30 * 86_400;
+const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+const SEGMENT_INTERVAL: Duration = Duration::from_secs(3600);
+// Segments are kept small relative to the size limit so that deleting the
+// oldest one frees a fraction of the store only
+const MAX_SEGMENT_SIZE: u64 = 16 << 20;
+const RETENTION_CHECK: Duration = Duration::from_secs(60);
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".ndjson";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Session,
+    Finding,
+    FileEvent,
+}
+
+impl RecordKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordKind::Session => "session",
+            RecordKind::Finding => "finding",
+            RecordKind::FileEvent => "file_event",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RecordKind> {
+        match name {
+            "session" | "sessions" => Some(RecordKind::Session),
+            "finding" | "findings" => Some(RecordKind::Finding),
+            "file_event" | "file-event" | "file-events" => Some(RecordKind::FileEvent),
+            _ => None,
+        }
+    }
+
+    fn time_pointers(&self) -> &'static [&'static str] {
+        match self {
+            RecordKind::Session => SESSION_TIME,
+            RecordKind::Finding => FINDING_TIME,
+            RecordKind::FileEvent => FILE_EVENT_TIME,
+        }
+    }
+}
+
+/// A history record of `data`, timed by the item or else `recorded_at`.
+pub fn history_record(kind: RecordKind, data: Value, recorded_at: u64) -> Value {
+    let time = item_time_ms(&data, kind.time_pointers()).unwrap_or(recorded_at);
+    json!({ "kind": kind.name(), "time": time, "recorded_at": recorded_at, "data": data })
+}
+
+pub fn history_dir() -> String {
+    match std::env::var(DIR_ENV) {
+        Ok(dir) if !dir.trim().is_empty() => dir.trim().to_string(),
+        _ => DEFAULT_DIR.to_string(),
+    }
+}
+
+struct Segment {
+    file: File,
+    size: u64,
+    opened_at: Instant,
+}
+
+pub struct HistoryStore {
+    dir: PathBuf,
+    retention: Duration,
+    max_size: u64,
+    segment: Option<Segment>,
+    last_retention_check: Option<Instant>,
+    sessions: FinishedSessions<Value>,
+    // Findings and file events already recorded and still reported by the core
+    findings: HashSet<String>,
+    file_events: HashSet<String>,
+    // Step registry written by `background-mark-step`
+    steps_path: String,
+}
+
+impl HistoryStore {
+    pub fn new(dir: &str, retention: u64, max_size: u64) -> Result<HistoryStore, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Error creating history directory '{}': {}", dir, e))?;
+        Ok(HistoryStore {
+            dir: PathBuf::from(dir),
+            retention: Duration::from_secs(retention),
+            max_size,
+            segment: None,
+            last_retention_check: None,
+            sessions: FinishedSessions::new(),
+            findings: HashSet::new(),
+            file_events: HashSet::new(),
+            steps_path: crate::steps::registry_path(),
+        })
+    }
+
+    /// Build the store from EDAMAME_HISTORY_DIR, or None when unset.
+    /// EDAMAME_HISTORY_RETENTION (e.g. 7d) and EDAMAME_HISTORY_MAX_SIZE (e.g. 500M)
+    /// are optional.
+    pub fn from_env() -> Result<Option<HistoryStore>, String> {
+        let dir = match std::env::var(DIR_ENV) {
+            Ok(dir) if !dir.trim().is_empty() => dir.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let retention = match std::env::var(RETENTION_ENV) {
+            Ok(value) if !value.trim().is_empty() => crate::parse_duration(&value)
+                .map_err(|e| format!("Invalid {} '{}': {}", RETENTION_ENV, value, e))?,
+            _ => DEFAULT_RETENTION,
+        };
+        let max_size = match std::env::var(MAX_SIZE_ENV) {
+            Ok(value) if !value.trim().is_empty() => crate::parse_size(&value)
+                .map_err(|e| format!("Invalid {} '{}': {}", MAX_SIZE_ENV, value, e))?,
+            _ => DEFAULT_MAX_SIZE,
+        };
+        let store = HistoryStore::new(&dir, retention, max_size)?;
+        info!(
+            "History store enabled: dir={}, retention={}s, max_size={} bytes",
+            dir, retention, max_size
+        );
+        Ok(Some(store))
+    }
+
+    /// Record the sessions that finished since the previous call.
+    pub fn record_sessions(&mut self, sessions: Vec<Value>) {
+        let polled = sessions
+            .into_iter()
+            .map(|session| {
+                let uid = session_str(&session, "/uid")
+                    .unwrap_or_default()
+                    .to_string();
+                let finished = session_str(&session, "/stats/end_time").is_some();
+                (uid, finished, session)
+            })
+            .collect();
+        let finished = self.sessions.update(polled);
+        self.record(RecordKind::Session, finished);
+    }
+
+    /// Record the findings not recorded yet, by finding key.
+    pub fn record_findings(&mut self, findings: Vec<Value>) {
+        let fresh = fresh_items(&mut self.findings, findings, |finding| {
+            session_str(finding, "/finding_key")
+                .map(str::to_string)
+                .unwrap_or_else(|| finding.to_string())
+        });
+        self.record(RecordKind::Finding, fresh);
+    }
+
+    /// Record the file events not recorded yet.
+    pub fn record_file_events(&mut self, events: Vec<Value>) {
+        let fresh = fresh_items(&mut self.file_events, events, |event| {
+            format!(
+                "{}|{}|{}",
+                event["timestamp"], event["event_type"], event["path"]
+            )
+        });
+        self.record(RecordKind::FileEvent, fresh);
+    }
+
+    fn record(&mut self, kind: RecordKind, items: Vec<Value>) {
+        if items.is_empty() {
+            return;
+        }
+        let now = now_millis();
+        // Read for each batch, steps are marked while the daemon runs
+        let steps = load_steps(&self.steps_path).unwrap_or_else(|e| {
+            warn!("History: records not tagged with steps: {}", e);
+            Vec::new()
+        });
+        let lines: Vec<String> = items
+            .into_iter()
+            .map(|data| {
+                let mut record = history_record(kind, data, now);
+                let time = record["time"].as_u64().unwrap_or(now);
+                if let Some(step) = step_at(&steps, time) {
+                    record["step"] = json!(step.name);
+                }
+                record.to_string()
+            })
+            .collect();
+        if let Err(e) = self.write_lines(&lines) {
+            warn!(
+                "History: failed to record {} {}(s): {}",
+                lines.len(),
+                kind.name(),
+                e
+            );
+        }
+        let due = self
+            .last_retention_check
+            .is_none_or(|checked| checked.elapsed() >= RETENTION_CHECK);
+        if due {
+            self.last_retention_check = Some(Instant::now());
+            match self.enforce_retention() {
+                Ok(0) => {}
+                Ok(removed) => info!("History: removed {} expired segment(s)", removed),
+                Err(e) => warn!("History: retention failed: {}", e),
+            }
+        }
+    }
+
+    fn segment_size(&self) -> u64 {
+        (self.max_size / 8).clamp(1, MAX_SEGMENT_SIZE)
+    }
+
+    fn write_lines(&mut self, lines: &[String]) -> Result<(), String> {
+        let segment_size = self.segment_size();
+        for line in lines {
+            let due = self.segment.as_ref().is_none_or(|segment| {
+                segment.size >= segment_size || segment.opened_at.elapsed() >= SEGMENT_INTERVAL
+            });
+            if due {
+                self.segment = Some(self.open_segment()?);
+            }
+            let segment = self.segment.as_mut().expect("segment opened above");
+            writeln!(segment.file, "{}", line).map_err(|e| e.to_string())?;
+            segment.size += line.len() as u64 + 1;
+        }
+        if let Some(segment) = self.segment.as_mut() {
+            segment.file.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn open_segment(&self) -> Result<Segment, String> {
+        // A second segment within the same millisecond gets the next name
+        let mut opened = now_millis();
+        let mut path = segment_path(&self.dir, opened);
+        while path.exists() {
+            opened += 1;
+            path = segment_path(&self.dir, opened);
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Error creating '{}': {}", path.display(), e))?;
+        Ok(Segment {
+            file,
+            size: 0,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// Delete the segments last written before the retention, then the oldest
+    /// ones while the store exceeds its size limit. The newest segment is kept.
+    /// Returns the number of segments deleted.
+    pub fn enforce_retention(&self) -> Result<usize, String> {
+        let mut segments = list_segments(&self.dir)?;
+        let newest = segments.pop();
+        let now = SystemTime::now();
+        let mut total: u64 = newest
+            .as_ref()
+            .and_then(|path| path.metadata().ok())
+            .map_or(0, |metadata| metadata.len());
+        let mut removed = 0;
+        let mut kept = Vec::new();
+        for path in segments {
+            let metadata = path
+                .metadata()
+                .map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+            let expired = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > self.retention);
+            if expired {
+                remove_segment(&path)?;
+                removed += 1;
+            } else {
+                total += metadata.len();
+                kept.push((path, metadata.len()));
+            }
+        }
+        for (path, size) in kept {
+            if total <= self.max_size {
+                break;
+            }
+            remove_segment(&path)?;
+            total -= size;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+fn fresh_items(
+    seen: &mut HashSet<String>,
+    items: Vec<Value>,
+    key: impl Fn(&Value) -> String,
+) -> Vec<Value> {
+    let mut current = HashSet::new();
+    let mut fresh = Vec::new();
+    for item in items {
+        let key = key(&item);
+        if seen.insert(key.clone()) {
+            fresh.push(item);
+        }
+        current.insert(key);
+    }
+    // Forget items the core no longer reports, so the set stays bounded
+    seen.retain(|key| current.contains(key));
+    fresh
+}
+
+fn segment_path(dir: &Path, opened_ms: u64) -> PathBuf {
+    dir.join(format!(
+        "{}{:013}{}",
+        SEGMENT_PREFIX, opened_ms, SEGMENT_SUFFIX
+    ))
+}
+
+fn remove_segment(path: &Path) -> Result<(), String> {
+    std::fs::remove_file(path).map_err(|e| format!("Error removing '{}': {}", path.display(), e))
+}
+
+/// Segment files of the store, oldest first.
+fn list_segments(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(format!(
+                "Error reading history directory '{}': {}",
+                dir.display(),
+                e
+            ))
+        }
+    };
+    let mut segments: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(SEGMENT_PREFIX) && name.ends_with(SEGMENT_SUFFIX)
+                })
+        })
+        .collect();
+    segments.sort();
+    Ok(segments)
+}
+
+/// Filters of `history-query`. Times are Unix milliseconds.
+#[derive(Debug, Default)]
+pub struct HistoryQuery {
+    pub kinds: Vec<RecordKind>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // (dotted field path, pattern) pairs, all of which must match
+    pub fields: Vec<(String, String)>,
+    pub limit: Option<usize>,
+}
+
+/// Parse a `--where FIELD=PATTERN` filter.
+pub fn parse_field_filter(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((field, pattern)) if !field.trim().is_empty() => {
+            Ok((field.trim().to_string(), pattern.trim().to_string()))
+        }
+        _ => Err(format!(
+            "Invalid filter '{}', expected FIELD=PATTERN, e.g. dst_domain=*.npmjs.org",
+            s
+        )),
+    }
+}
+
+/// Value of a dotted field path (e.g. `l7.process_name`) of a record's data,
+/// as a string.
+fn field_value(data: &Value, field: &str) -> Option<String> {
+    let pointer = format!("/{}", field.replace('.', "/"));
+    match data.pointer(&pointer)? {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, record: &Value) -> bool {
+        let kind = record["kind"].as_str().and_then(RecordKind::from_name);
+        if !self.kinds.is_empty() && !kind.is_some_and(|kind| self.kinds.contains(&kind)) {
+            return false;
+        }
+        let time = record["time"].as_u64().unwrap_or(0);
+        if self.since.is_some_and(|since| time < since)
+            || self.until.is_some_and(|until| time > until)
+        {
+            return false;
+        }
+        self.fields.iter().all(|(field, pattern)| {
+            field_value(&record["data"], field).is_some_and(|value| {
+                wildcard_matches(&pattern.to_lowercase(), &value.to_lowercase())
+            })
+        })
+    }
+}
+
+/// Records of the store in `dir` matching `query`, oldest first.
+pub fn query_history(dir: &str, query: &HistoryQuery) -> Result<Vec<Value>, String> {
+    let mut records = Vec::new();
+    for path in list_segments(Path::new(dir))? {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            // Removed by the retention since it was listed
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Error reading '{}': {}", path.display(), e)),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+            // A line cut short by a crash is skipped
+            let Ok(record) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if query.matches(&record) {
+                records.push(record);
+            }
+        }
+    }
+    records.sort_by_key(|record| record["time"].as_u64().unwrap_or(0));
+    if let Some(limit) = query.limit {
+        records.truncate(limit);
+    }
+    Ok(records)
+}
+
+/// One-line summary of a record.
+pub fn describe_record(record: &Value) -> String {
+    let data = &record["data"];
+    let time = record["time"].as_u64().unwrap_or(0);
+    let summary = match record["kind"].as_str().and_then(RecordKind::from_name) {
+        Some(RecordKind::Session) => format!(
+            "{} -> {}:{}/{} ({} bytes out, {} bytes in, {})",
+            session_str(data, "/l7/process_name").unwrap_or("unknown"),
+            session_destination(data),
+            session_u64(data, "/session/dst_port"),
+            session_str(data, "/session/protocol").unwrap_or(""),
+            session_u64(data, "/stats/outbound_bytes"),
+            session_u64(data, "/stats/inbound_bytes"),
+            session_str(data, "/is_whitelisted").unwrap_or("Unknown")
+        ),
+        Some(RecordKind::Finding) => format!(
+            "{} {}",
+            session_str(data, "/severity").unwrap_or(""),
+            session_str(data, "/finding_key").unwrap_or("")
+        ),
+        Some(RecordKind::FileEvent) => format!(
+            "{} {}{}",
+            session_str(data, "/event_type").unwrap_or(""),
+            session_str(data, "/path").unwrap_or(""),
+            session_str(data, "/process_name")
+                .map(|p| format!(" ({})", p))
+                .unwrap_or_default()
+        ),
+        None => data.to_string(),
+    };
+    format!(
+        "[{}] {} {}",
+        crate::dismissals::format_timestamp(time / 1000),
+        record["kind"].as_str().unwrap_or(""),
+        summary.trim()
+    )
+}
+
+pub fn history_query(dir: &str, query: &HistoryQuery, json_output: bool) -> i32 {
+    if !Path::new(dir).is_dir() {
+        eprintln!(
+            "No history store in '{}', start the daemon with {} set",
+            dir, DIR_ENV
+        );
+        return crate::ERROR_CODE_PARAM;
+    }
+    let records = match query_history(dir, query) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("{}", e);
+            return crate::ERROR_CODE_PARAM;
+        }
+    };
+    if json_output {
+        match serde_json::to_string_pretty(&records) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error serializing history records: {}", e);
+                return crate::ERROR_CODE_PARAM;
+            }
+        }
+    } else {
+        for record in &records {
+            println!("{}", describe_record(record));
+        }
+        println!("\n{} record(s)", records.len());
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("edamame-history-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_string_lossy().to_string()
+    }
+
+    fn session(uid: &str, start: &str, domain: &str, finished: bool) -> Value {
+        json!({
+            "uid": uid,
+            "dst_domain": domain,
+            "session": { "dst_port": 443, "protocol": "TCP" },
+            "l7": { "process_name": "curl" },
+            "stats": { "start_time": start, "end_time": if finished { json!(start) } else { Value::Null } },
+        })
+    }
+
+    #[test]
+    fn records_are_queried_by_kind_time_and_field() {
+        let dir = temp_dir("query");
+        let mut store = HistoryStore::new(&dir, 86_400, 1 << 20).unwrap();
+        store.steps_path = format!("{}.steps.json", dir);
+        std::fs::write(
+            &store.steps_path,
+            r#"{"version":1,"steps":[{"name":"install","started_at_ms":1779098400000,"ended_at_ms":1779102000000}]}"#,
+        )
+        .unwrap();
+        store.record_sessions(vec![
+            session("a", "2026-05-18T10:00:00Z", "registry.npmjs.org", true),
+            session("b", "2026-05-18T11:00:00Z", "evil.example.com", false),
+        ]);
+        // b vanished, so it is recorded as well; a is not recorded twice
+        store.record_sessions(vec![session(
+            "a",
+            "2026-05-18T10:00:00Z",
+            "registry.npmjs.org",
+            true,
+        )]);
+        let finding = json!({ "finding_key": "credential_access:python", "first_seen": "2026-05-18T10:30:00Z" });
+        store.record_findings(vec![finding.clone()]);
+        store.record_findings(vec![finding]);
+
+        let all = query_history(&dir, &HistoryQuery::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[1]["kind"], "finding");
+        assert_eq!(all[0]["step"], "install");
+        assert_eq!(all[1]["step"], "install");
+        assert!(all[2].get("step").is_none());
+
+        let query = HistoryQuery {
+            kinds: vec![RecordKind::Session],
+            since: Some(1_779_098_400_000 + 1),
+            fields: vec![parse_field_filter("l7.process_name=CURL").unwrap()],
+            ..Default::default()
+        };
+        let sessions = query_history(&dir, &query).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["data"]["uid"], "b");
+
+        let query = HistoryQuery {
+            fields: vec![parse_field_filter("dst_domain=*.npmjs.org").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(query_history(&dir, &query).unwrap().len(), 1);
+        assert!(parse_field_filter("=x").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&store.steps_path).unwrap();
+    }
+
+    #[test]
+    fn oldest_segments_go_beyond_the_size_limit() {
+        let dir = temp_dir("retention");
+        // 8 KiB store with 1 KiB segments
+        let mut store = HistoryStore::new(&dir, 86_400, 8 << 10).unwrap();
+        let padding = "x".repeat(400);
+        for i in 0..60 {
+            store.record_findings(vec![json!({ "finding_key": format!("{}-{}", i, padding) })]);
+        }
+        store.enforce_retention().unwrap();
+        let segments = list_segments(Path::new(&dir)).unwrap();
+        let total: u64 = segments
+            .iter()
+            .map(|path| path.metadata().unwrap().len())
+            .sum();
+        assert!(total <= 8 << 10);
+        // The newest findings are kept
+        let records = query_history(&dir, &HistoryQuery::default()).unwrap();
+        assert!(records.last().unwrap()["data"]["finding_key"]
+            .as_str()
+            .unwrap()
+            .starts_with("59-"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_lines_and_foreign_files_are_skipped() {
+        let dir = temp_dir("damaged");
+        std::fs::create_dir_all(&dir).unwrap();
+        let record = history_record(
+            RecordKind::Finding,
+            json!({ "finding_key": "credential_access:python" }),
+            1_779_098_400_000,
+        );
+        // A line cut short by a crash, then a later complete record
+        std::fs::write(
+            segment_path(Path::new(&dir), 1),
+            format!("{}\n{{\"kind\":\"fin\n\n{}\n", record, record),
+        )
+        .unwrap();
+        std::fs::write(Path::new(&dir).join("notes.jsonl"), format!("{}\n", record)).unwrap();
+        let records = query_history(&dir, &HistoryQuery::default()).unwrap();
+        assert_eq!(records.len(), 2);
+        // A missing store is empty, a store path that is a file is an error
+        assert!(
+            query_history(&format!("{}-missing", dir), &HistoryQuery::default())
+                .unwrap()
+                .is_empty()
+        );
+        let file = Path::new(&dir).join("notes.jsonl");
+        assert!(query_history(file.to_str().unwrap(), &HistoryQuery::default()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,435 @@
+// Indicator of compromise (IOC) sweep.
+//
+// An IOC file lists one indicator per line: an IP address or CIDR range, a
+// domain (with the wildcards of whitelist domains), an absolute file path
+// (with `*` and `?` wildcards) or the MD5, SHA-1 or SHA-256 hash of an
+// executable. Blank lines and `#` comments are skipped, and defanged forms
+// such as `evil[.]example` are accepted. Indicators are matched against
+// records in the history format, `{kind, time, data}`, so live sessions and
+// file events are swept the same way as the persisted ones. The core does not
+// hash executables, so hash indicators are matched against the hashes of the
+// process executables of the records, computed at sweep time.
+
+use crate::history::{describe_record, RecordKind};
+use crate::sessions::session_str;
+use crate::whitelists::{cidr_contains, domain_matches, parse_cidr, wildcard_matches};
+use crate::ERROR_CODE_MISMATCH;
+use md5::Md5;
+use serde_json::{json, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+const SESSION_IP_FIELDS: [&str; 2] = ["/session/dst_ip", "/session/src_ip"];
+const SESSION_PATH_FIELDS: [&str; 1] = ["/l7/process_path"];
+const FILE_EVENT_PATH_FIELDS: [&str; 2] = ["/path", "/process_path"];
+
+/// Lowercase hex SHA-256, SHA-1 and MD5 of executables, by path.
+pub type ExecutableHashes = BTreeMap<String, [(&'static str, String); 3]>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indicator {
+    // IP address or CIDR range
+    Network(String),
+    Domain(String),
+    Path(String),
+    // Lowercase hex
+    Hash(String),
+}
+
+fn is_hash(value: &str) -> bool {
+    matches!(value.len(), 32 | 40 | 64) && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_path(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    value.starts_with('/')
+        || value.starts_with("\\\\")
+        || (bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":\\")
+}
+
+/// Parse one indicator, already trimmed and without comment.
+pub fn parse_indicator(value: &str) -> Result<Indicator, String> {
+    let value = value
+        .replace("[.]", ".")
+        .replace("(.)", ".")
+        .replace("[:]", ":");
+    if parse_cidr(&value).is_some() {
+        return Ok(Indicator::Network(value));
+    }
+    if is_hash(&value) {
+        return Ok(Indicator::Hash(value.to_lowercase()));
+    }
+    if is_path(&value) {
+        return Ok(Indicator::Path(value));
+    }
+    let domain = value.trim_end_matches('.');
+    if !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '*'))
+    {
+        return Ok(Indicator::Domain(domain.to_lowercase()));
+    }
+    Err(format!(
+        "'{}' is not an IP address, CIDR range, domain, absolute path or MD5/SHA-1/SHA-256 hash",
+        value
+    ))
+}
+
+/// Parse the content of an IOC file. Errors name the offending line.
+pub fn parse_iocs(content: &str) -> Result<Vec<Indicator>, String> {
+    let mut indicators = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let indicator = parse_indicator(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        if !indicators.contains(&indicator) {
+            indicators.push(indicator);
+        }
+    }
+    Ok(indicators)
+}
+
+pub fn load_iocs(path: &str) -> Result<Vec<Indicator>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading IOC file '{}': {}", path, e))?;
+    parse_iocs(&content).map_err(|e| format!("Invalid IOC file '{}', {}", path, e))
+}
+
+// The process executable of a session or file event
+fn executable_pointer(kind: RecordKind) -> Option<&'static str> {
+    match kind {
+        RecordKind::Session => Some("/l7/process_path"),
+        RecordKind::FileEvent => Some("/process_path"),
+        _ => None,
+    }
+}
+
+fn hash_file(path: &str) -> Result<[(&'static str, String); 3], String> {
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let (mut sha256, mut sha1, mut md5) = (Sha256::new(), Sha1::new(), Md5::new());
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+        sha1.update(&buffer[..read]);
+        md5.update(&buffer[..read]);
+    }
+    Ok([
+        ("sha256", format!("{:x}", sha256.finalize())),
+        ("sha1", format!("{:x}", sha1.finalize())),
+        ("md5", format!("{:x}", md5.finalize())),
+    ])
+}
+
+/// Hash each distinct executable of `records`, when there are hash
+/// indicators to match. Executables that cannot be read are reported and
+/// skipped: they may have been removed since a history record was written.
+pub fn hash_executables(indicators: &[Indicator], records: &[Value]) -> ExecutableHashes {
+    let mut hashes = ExecutableHashes::new();
+    if !indicators.iter().any(|i| matches!(i, Indicator::Hash(_))) {
+        return hashes;
+    }
+    let mut unreadable = Vec::new();
+    for record in records {
+        let Some(pointer) = record["kind"]
+            .as_str()
+            .and_then(RecordKind::from_name)
+            .and_then(executable_pointer)
+        else {
+            continue;
+        };
+        let Some(path) = session_str(&record["data"], pointer) else {
+            continue;
+        };
+        if hashes.contains_key(path) || unreadable.contains(&path) {
+            continue;
+        }
+        match hash_file(path) {
+            Ok(digests) => {
+                hashes.insert(path.to_string(), digests);
+            }
+            Err(e) => {
+                eprintln!("Warning: cannot hash executable {}: {}", path, e);
+                unreadable.push(path);
+            }
+        }
+    }
+    hashes
+}
+
+fn pointer_fields<'a>(data: &'a Value, pointers: &[&str]) -> Vec<(String, &'a str)> {
+    pointers
+        .iter()
+        .filter_map(|pointer| {
+            session_str(data, pointer).map(|value| (pointer[1..].replace('/', "."), value))
+        })
+        .collect()
+}
+
+impl Indicator {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Indicator::Network(_) => "ip",
+            Indicator::Domain(_) => "domain",
+            Indicator::Path(_) => "path",
+            Indicator::Hash(_) => "hash",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Indicator::Network(value)
+            | Indicator::Domain(value)
+            | Indicator::Path(value)
+            | Indicator::Hash(value) => value,
+        }
+    }
+
+    /// The field of `data` matching the indicator and its value. Hashes are
+    /// looked up in `hashes` by executable, the field names the algorithm.
+    pub fn matches(
+        &self,
+        kind: RecordKind,
+        data: &Value,
+        hashes: &ExecutableHashes,
+    ) -> Option<(String, String)> {
+        let found = match (self, kind) {
+            (Indicator::Network(cidr), RecordKind::Session) => {
+                pointer_fields(data, &SESSION_IP_FIELDS)
+                    .into_iter()
+                    .find(|(_, ip)| cidr_contains(cidr, ip))
+            }
+            (Indicator::Domain(pattern), RecordKind::Session) => {
+                pointer_fields(data, &["/dst_domain"])
+                    .into_iter()
+                    .find(|(_, domain)| domain_matches(pattern, domain))
+            }
+            (Indicator::Path(pattern), RecordKind::Session | RecordKind::FileEvent) => {
+                let pointers: &[&str] = if kind == RecordKind::Session {
+                    &SESSION_PATH_FIELDS
+                } else {
+                    &FILE_EVENT_PATH_FIELDS
+                };
+                pointer_fields(data, pointers)
+                    .into_iter()
+                    .find(|(_, path)| wildcard_matches(pattern, path))
+            }
+            (Indicator::Hash(hash), RecordKind::Session | RecordKind::FileEvent) => {
+                let pointer = executable_pointer(kind)?;
+                let path = session_str(data, pointer)?;
+                let (algorithm, _) = hashes
+                    .get(path)?
+                    .iter()
+                    .find(|(_, digest)| digest == hash)?;
+                return Some((
+                    format!("{}({})", algorithm, pointer[1..].replace('/', ".")),
+                    path.to_string(),
+                ));
+            }
+            _ => None,
+        };
+        found.map(|(field, value)| (field, value.to_string()))
+    }
+}
+
+/// Every (record, indicator) match, oldest first. Records carry a `source`
+/// (`live` or `history`) and optional `tags` such as `blacklisted`.
+pub fn sweep(indicators: &[Indicator], records: &[Value], hashes: &ExecutableHashes) -> Vec<Value> {
+    let mut hits = Vec::new();
+    for record in records {
+        let Some(kind) = record["kind"].as_str().and_then(RecordKind::from_name) else {
+            continue;
+        };
+        for indicator in indicators {
+            if let Some((field, value)) = indicator.matches(kind, &record["data"], hashes) {
+                hits.push(json!({
+                    "indicator": { "type": indicator.type_name(), "value": indicator.value() },
+                    "field": field,
+                    "value": value,
+                    "source": record["source"],
+                    "tags": record.get("tags").cloned().unwrap_or_else(|| json!([])),
+                    "kind": kind.name(),
+                    "time": record["time"],
+                    "summary": describe_record(record),
+                    "data": record["data"],
+                }));
+            }
+        }
+    }
+    hits.sort_by_key(|hit| hit["time"].as_u64().unwrap_or(0));
+    hits
+}
+
+/// Print the hits and return ERROR_CODE_MISMATCH when there are any.
+pub fn print_hits(hits: &[Value], indicators: usize, records: usize, json_output: bool) -> i32 {
+    if json_output {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "indicators": indicators,
+                "records": records,
+                "hits": hits,
+            }))
+            .unwrap_or_default()
+        );
+    } else {
+        for hit in hits {
+            let tags: Vec<&str> = std::iter::once(hit["source"].as_str().unwrap_or(""))
+                .chain(
+                    hit["tags"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|tag| tag.as_str()),
+                )
+                .collect();
+            println!(
+                "HIT {} {}: {}={} [{}]",
+                hit["indicator"]["type"].as_str().unwrap_or(""),
+                hit["indicator"]["value"].as_str().unwrap_or(""),
+                hit["field"].as_str().unwrap_or(""),
+                hit["value"].as_str().unwrap_or(""),
+                tags.join(", ")
+            );
+            println!("    {}", hit["summary"].as_str().unwrap_or(""));
+        }
+        println!(
+            "{} hit(s) for {} indicator(s) across {} record(s)",
+            hits.len(),
+            indicators,
+            records
+        );
+    }
+    if hits.is_empty() {
+        0
+    } else {
+        ERROR_CODE_MISMATCH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indicators_are_detected_by_form() {
+        let iocs = parse_iocs(
+            "# campaign X\n\
+             203.0.113.7\n\
+             198.51.100.0/24  # C2 range\n\
+             *.evil[.]example\n\
+             /tmp/.x/loader*\n\
+             D41D8CD98F00B204E9800998ECF8427E\n\
+             \n\
+             203.0.113.7\n",
+        )
+        .unwrap();
+        assert_eq!(
+            iocs,
+            vec![
+                Indicator::Network("203.0.113.7".to_string()),
+                Indicator::Network("198.51.100.0/24".to_string()),
+                Indicator::Domain("*.evil.example".to_string()),
+                Indicator::Path("/tmp/.x/loader*".to_string()),
+                Indicator::Hash("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+            ]
+        );
+        assert!(parse_iocs("ok.example\nnot an indicator\n")
+            .unwrap_err()
+            .starts_with("line 2:"));
+    }
+
+    #[test]
+    fn sweep_matches_sessions_and_file_events() {
+        let iocs = parse_iocs(
+            "198.51.100.0/24\ncdn.evil.example\n/tmp/.x/*\nd41d8cd98f00b204e9800998ecf8427e\n",
+        )
+        .unwrap();
+        // An empty file stands for an executable whose MD5 is the indicator
+        let loader = std::env::temp_dir().join(format!("edamame-ioc-{}", std::process::id()));
+        std::fs::write(&loader, "").unwrap();
+        let loader = loader.to_str().unwrap();
+        let records = vec![
+            json!({
+                "kind": "session", "time": 2000, "source": "live", "tags": ["blacklisted"],
+                "data": {
+                    "dst_domain": "cdn.evil.example",
+                    "session": { "dst_ip": "198.51.100.9", "src_ip": "10.0.0.2" },
+                    "l7": { "process_path": "/nonexistent/curl" },
+                },
+            }),
+            json!({
+                "kind": "file_event", "time": 1000, "source": "history",
+                "data": { "path": "/tmp/.x/payload", "process_path": loader },
+            }),
+            json!({
+                "kind": "session", "time": 3000, "source": "live",
+                "data": { "dst_domain": "evil.example", "session": { "dst_ip": "192.0.2.1" } },
+            }),
+        ];
+        let hashes = hash_executables(&iocs, &records);
+        assert_eq!(hashes.keys().collect::<Vec<_>>(), vec![loader]);
+        assert_eq!(
+            hashes[loader][0],
+            (
+                "sha256",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+            )
+        );
+        assert_eq!(
+            hashes[loader][1].1,
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert!(hash_executables(&iocs[..3], &records).is_empty());
+        let hits = sweep(&iocs, &records, &hashes);
+        let found: Vec<(&str, &str)> = hits
+            .iter()
+            .map(|hit| {
+                (
+                    hit["field"].as_str().unwrap(),
+                    hit["source"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("path", "history"),
+                ("md5(process_path)", "history"),
+                ("session.dst_ip", "live"),
+                ("dst_domain", "live"),
+            ]
+        );
+        assert_eq!(
+            print_hits(&hits, iocs.len(), records.len(), true),
+            ERROR_CODE_MISMATCH
+        );
+        assert_eq!(print_hits(&[], iocs.len(), records.len(), true), 0);
+        std::fs::remove_file(loader).unwrap();
+    }
+
+    #[test]
+    fn unreadable_or_invalid_ioc_files_name_the_file() {
+        let path = std::env::temp_dir().join(format!("edamame-iocs-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(load_iocs(path)
+            .unwrap_err()
+            .starts_with(&format!("Error reading IOC file '{}'", path)));
+        std::fs::write(path, "203.0.113.7\nnot an indicator\n").unwrap();
+        assert!(load_iocs(path)
+            .unwrap_err()
+            .starts_with(&format!("Invalid IOC file '{}', line 2:", path)));
+        // Comments and blank lines only make an empty, valid file
+        std::fs::write(path, "# nothing yet\n\n").unwrap();
+        assert!(load_iocs(path).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,654 @@
+// IPFIX (RFC 7011) exporter for the packet-capture path of the background process.
+//
+// Finished sessions are sent to a UDP collector as flow records. The 5-tuple,
+// timestamps and byte/packet counters use IANA information elements, while the
+// EDAMAME-specific attributes (process, PID, user, whitelist state, criticality,
+// labels) are sent as enterprise-specific information elements.
+//
+// NetFlow v9 has no notion of enterprise-specific elements, so only IPFIX is
+// emitted. Most NetFlow v9 collectors also accept IPFIX on the same port.
+
+use crate::labels::{session_labels, LabelRules};
+use edamame_core::api::api_flodbadd::SessionInfoAPI;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const IPFIX_VERSION: u16 = 10;
+const IPFIX_HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+const TEMPLATE_SET_ID: u16 = 2;
+const TEMPLATE_ID_V4: u16 = 256;
+const TEMPLATE_ID_V6: u16 = 257;
+// Keep messages below a typical path MTU to avoid IP fragmentation
+const MAX_MESSAGE_LEN: usize = 1400;
+// Templates are resent periodically as UDP gives no delivery guarantee (RFC 7011, section 8.4)
+const TEMPLATE_REFRESH: Duration = Duration::from_secs(60);
+const VARLEN: u16 = 65535;
+// Cap variable-length strings so a single record always fits in one message
+const MAX_STRING_LEN: usize = 256;
+
+// Private Enterprise Number reserved for documentation (RFC 5612), override with EDAMAME_IPFIX_ENTERPRISE_ID
+const DEFAULT_ENTERPRISE_ID: u32 = 32473;
+
+// IANA information elements
+const IE_OCTET_DELTA_COUNT: u16 = 1;
+const IE_PACKET_DELTA_COUNT: u16 = 2;
+const IE_PROTOCOL_IDENTIFIER: u16 = 4;
+const IE_SOURCE_TRANSPORT_PORT: u16 = 7;
+const IE_SOURCE_IPV4_ADDRESS: u16 = 8;
+const IE_DESTINATION_TRANSPORT_PORT: u16 = 11;
+const IE_DESTINATION_IPV4_ADDRESS: u16 = 12;
+const IE_SOURCE_IPV6_ADDRESS: u16 = 27;
+const IE_DESTINATION_IPV6_ADDRESS: u16 = 28;
+const IE_FLOW_START_MILLISECONDS: u16 = 152;
+const IE_FLOW_END_MILLISECONDS: u16 = 153;
+const IE_INITIATOR_OCTETS: u16 = 231;
+const IE_RESPONDER_OCTETS: u16 = 232;
+const IE_INITIATOR_PACKETS: u16 = 298;
+const IE_RESPONDER_PACKETS: u16 = 299;
+
+// Enterprise-specific information elements
+const EIE_PROCESS_NAME: u16 = 1;
+const EIE_PROCESS_ID: u16 = 2;
+const EIE_USER_NAME: u16 = 3;
+const EIE_WHITELIST_STATE: u16 = 4;
+const EIE_CRITICALITY: u16 = 5;
+// Comma-separated labels of the label file, see crate::labels
+const EIE_LABELS: u16 = 6;
+
+/// A finished session flattened into the fields carried by the IPFIX templates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowRecord {
+    pub uid: String,
+    pub protocol: u8,
+    pub src_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_ip: IpAddr,
+    pub dst_port: u16,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub initiator_octets: u64,
+    pub responder_octets: u64,
+    pub initiator_packets: u64,
+    pub responder_packets: u64,
+    pub process_name: String,
+    pub process_id: u32,
+    pub user_name: String,
+    pub whitelist_state: String,
+    pub criticality: String,
+    pub labels: String,
+    pub finished: bool,
+}
+
+impl FlowRecord {
+    /// Build a record from the serialized form of a `SessionInfoAPI`.
+    /// Returns None when the session has no usable addresses.
+    pub fn from_session_value(value: &Value) -> Option<FlowRecord> {
+        let session = value.get("session")?;
+        let stats = value.get("stats");
+        let l7 = value.get("l7").filter(|l7| !l7.is_null());
+
+        let src_ip: IpAddr = session.get("src_ip")?.as_str()?.parse().ok()?;
+        let dst_ip: IpAddr = session.get("dst_ip")?.as_str()?.parse().ok()?;
+        // Both addresses must share a family to fit in one template
+        if src_ip.is_ipv4() != dst_ip.is_ipv4() {
+            return None;
+        }
+
+        let stat_u64 = |key: &str| -> u64 {
+            stats
+                .and_then(|s| s.get(key))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0)
+        };
+        let stat_time = |keys: &[&str]| -> Option<u64> {
+            keys.iter().find_map(|key| {
+                stats
+                    .and_then(|s| s.get(*key))
+                    .and_then(|v| v.as_str())
+                    .and_then(parse_rfc3339_millis)
+            })
+        };
+        let l7_str = |key: &str| -> String {
+            l7.and_then(|l7| l7.get(key))
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string()
+        };
+
+        let end_time = stat_time(&["end_time"]);
+        let last_activity = stat_time(&["last_activity"]);
+        let start_ms = stat_time(&["start_time", "first_activity"])
+            .or(last_activity)
+            .unwrap_or(0);
+        let end_ms = end_time.or(last_activity).unwrap_or(start_ms);
+
+        Some(FlowRecord {
+            uid: value
+                .get("uid")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            protocol: protocol_number(session.get("protocol").and_then(|v| v.as_str())),
+            src_ip,
+            src_port: session
+                .get("src_port")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0) as u16,
+            dst_ip,
+            dst_port: session
+                .get("dst_port")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0) as u16,
+            start_ms,
+            end_ms,
+            initiator_octets: stat_u64("outbound_bytes"),
+            responder_octets: stat_u64("inbound_bytes"),
+            initiator_packets: stat_u64("orig_pkts"),
+            responder_packets: stat_u64("resp_pkts"),
+            process_name: l7_str("process_name"),
+            process_id: l7
+                .and_then(|l7| l7.get("pid"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0) as u32,
+            user_name: l7_str("username"),
+            whitelist_state: value
+                .get("is_whitelisted")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            criticality: value
+                .get("criticality")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            labels: session_labels(value).join(","),
+            finished: end_time.is_some(),
+        })
+    }
+}
+
+pub struct IpfixExporter {
+    socket: UdpSocket,
+    collector: SocketAddr,
+    observation_domain_id: u32,
+    enterprise_id: u32,
+    sequence: u32,
+    last_template_sent: Option<Instant>,
+    // Ongoing sessions seen in the previous poll, exported when they vanish
+    active: HashMap<String, FlowRecord>,
+    // Finished sessions already exported but still reported by the core
+    exported: HashSet<String>,
+}
+
+impl IpfixExporter {
+    pub fn new(
+        collector: &str,
+        observation_domain_id: u32,
+        enterprise_id: u32,
+    ) -> Result<IpfixExporter, String> {
+        let collector = collector
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid IPFIX collector address '{}': {}", collector, e))?
+            .next()
+            .ok_or_else(|| format!("IPFIX collector '{}' did not resolve", collector))?;
+        let bind_addr = if collector.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)
+            .map_err(|e| format!("Failed to bind IPFIX exporter socket: {}", e))?;
+
+        Ok(IpfixExporter {
+            socket,
+            collector,
+            observation_domain_id,
+            enterprise_id,
+            sequence: 0,
+            last_template_sent: None,
+            active: HashMap::new(),
+            exported: HashSet::new(),
+        })
+    }
+
+    /// Build an exporter from EDAMAME_IPFIX_COLLECTOR (host:port), or None when unset.
+    /// EDAMAME_IPFIX_DOMAIN_ID and EDAMAME_IPFIX_ENTERPRISE_ID are optional.
+    pub fn from_env() -> Result<Option<IpfixExporter>, String> {
+        let collector = match std::env::var("EDAMAME_IPFIX_COLLECTOR") {
+            Ok(collector) if !collector.trim().is_empty() => collector.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let observation_domain_id = env_u32("EDAMAME_IPFIX_DOMAIN_ID", 1)?;
+        let enterprise_id = env_u32("EDAMAME_IPFIX_ENTERPRISE_ID", DEFAULT_ENTERPRISE_ID)?;
+
+        let exporter = IpfixExporter::new(&collector, observation_domain_id, enterprise_id)?;
+        info!(
+            "IPFIX export enabled: collector={}, observation_domain={}, enterprise_id={}",
+            exporter.collector, observation_domain_id, enterprise_id
+        );
+        Ok(Some(exporter))
+    }
+
+    /// Export the sessions that finished since the previous call: sessions with an end
+    /// time, plus ongoing sessions that are no longer reported by the core.
+    pub fn export_finished_sessions(
+        &mut self,
+        sessions: &[SessionInfoAPI],
+        labels: Option<&LabelRules>,
+    ) {
+        let mut current: HashSet<String> = HashSet::new();
+        let mut finished: Vec<FlowRecord> = Vec::new();
+
+        for session in sessions {
+            let value = serde_json::to_value(session).ok().map(|mut value| {
+                if let Some(labels) = labels {
+                    labels.apply(&mut value);
+                }
+                value
+            });
+            let record = match value.as_ref().and_then(FlowRecord::from_session_value) {
+                Some(record) => record,
+                None => continue,
+            };
+            current.insert(record.uid.clone());
+
+            if record.finished {
+                self.active.remove(&record.uid);
+                if self.exported.insert(record.uid.clone()) {
+                    finished.push(record);
+                }
+            } else {
+                self.active.insert(record.uid.clone(), record);
+            }
+        }
+
+        let vanished: Vec<String> = self
+            .active
+            .keys()
+            .filter(|uid| !current.contains(*uid))
+            .cloned()
+            .collect();
+        for uid in vanished {
+            if let Some(record) = self.active.remove(&uid) {
+                finished.push(record);
+            }
+        }
+        self.exported.retain(|uid| current.contains(uid));
+
+        if finished.is_empty() {
+            return;
+        }
+        match self.send_records(&finished) {
+            Ok(sent) => info!("IPFIX: exported {} finished session(s)", sent),
+            Err(e) => warn!("IPFIX: failed to export sessions: {}", e),
+        }
+    }
+
+    /// Send records to the collector, returning the number of records sent.
+    pub fn send_records(&mut self, records: &[FlowRecord]) -> Result<usize, String> {
+        let refresh_templates = match self.last_template_sent {
+            Some(sent) => sent.elapsed() >= TEMPLATE_REFRESH,
+            None => true,
+        };
+        if refresh_templates {
+            self.send_templates()?;
+        }
+
+        let mut sent = 0;
+        for template_id in [TEMPLATE_ID_V4, TEMPLATE_ID_V6] {
+            let encoded: Vec<Vec<u8>> = records
+                .iter()
+                .filter(|r| template_for(r) == template_id)
+                .map(encode_record)
+                .collect();
+
+            let mut batch: Vec<u8> = Vec::new();
+            let mut batch_count = 0u32;
+            for record in encoded {
+                if !batch.is_empty()
+                    && IPFIX_HEADER_LEN + SET_HEADER_LEN + batch.len() + record.len()
+                        > MAX_MESSAGE_LEN
+                {
+                    self.send_data_set(template_id, &batch, batch_count)?;
+                    sent += batch_count as usize;
+                    batch.clear();
+                    batch_count = 0;
+                }
+                batch.extend_from_slice(&record);
+                batch_count += 1;
+            }
+            if batch_count > 0 {
+                self.send_data_set(template_id, &batch, batch_count)?;
+                sent += batch_count as usize;
+            }
+        }
+        Ok(sent)
+    }
+
+    fn send_templates(&mut self) -> Result<(), String> {
+        let mut set = Vec::new();
+        encode_template(&mut set, TEMPLATE_ID_V4, false, self.enterprise_id);
+        encode_template(&mut set, TEMPLATE_ID_V6, true, self.enterprise_id);
+        self.send_set(TEMPLATE_SET_ID, &set)?;
+        self.last_template_sent = Some(Instant::now());
+        Ok(())
+    }
+
+    fn send_data_set(
+        &mut self,
+        template_id: u16,
+        records: &[u8],
+        count: u32,
+    ) -> Result<(), String> {
+        self.send_set(template_id, records)?;
+        // The sequence number counts data records, not messages
+        self.sequence = self.sequence.wrapping_add(count);
+        Ok(())
+    }
+
+    fn send_set(&self, set_id: u16, body: &[u8]) -> Result<(), String> {
+        let message = encode_message(self.observation_domain_id, self.sequence, set_id, body);
+        self.socket
+            .send_to(&message, self.collector)
+            .map_err(|e| format!("send to {} failed: {}", self.collector, e))?;
+        Ok(())
+    }
+}
+
+fn env_u32(name: &str, default: u32) -> Result<u32, String> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid {} '{}': {}", name, value, e)),
+        _ => Ok(default),
+    }
+}
+
+fn template_for(record: &FlowRecord) -> u16 {
+    if record.src_ip.is_ipv4() {
+        TEMPLATE_ID_V4
+    } else {
+        TEMPLATE_ID_V6
+    }
+}
+
+fn protocol_number(protocol: Option<&str>) -> u8 {
+    match protocol.unwrap_or("").to_ascii_uppercase().as_str() {
+        "ICMP" => 1,
+        "TCP" => 6,
+        "UDP" => 17,
+        "ICMPV6" => 58,
+        other => other.parse().unwrap_or(0),
+    }
+}
+
+fn encode_message(observation_domain_id: u32, sequence: u32, set_id: u16, body: &[u8]) -> Vec<u8> {
+    let export_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+    let set_len = SET_HEADER_LEN + body.len();
+    let total_len = IPFIX_HEADER_LEN + set_len;
+
+    let mut message = Vec::with_capacity(total_len);
+    message.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+    message.extend_from_slice(&(total_len as u16).to_be_bytes());
+    message.extend_from_slice(&export_time.to_be_bytes());
+    message.extend_from_slice(&sequence.to_be_bytes());
+    message.extend_from_slice(&observation_domain_id.to_be_bytes());
+    message.extend_from_slice(&set_id.to_be_bytes());
+    message.extend_from_slice(&(set_len as u16).to_be_bytes());
+    message.extend_from_slice(body);
+    message
+}
+
+fn encode_template(buf: &mut Vec<u8>, template_id: u16, ipv6: bool, enterprise_id: u32) {
+    let (src_ie, dst_ie, addr_len) = if ipv6 {
+        (IE_SOURCE_IPV6_ADDRESS, IE_DESTINATION_IPV6_ADDRESS, 16)
+    } else {
+        (IE_SOURCE_IPV4_ADDRESS, IE_DESTINATION_IPV4_ADDRESS, 4)
+    };
+    // (information element, length, enterprise-specific), in record order
+    let fields: [(u16, u16, bool); 19] = [
+        (IE_FLOW_START_MILLISECONDS, 8, false),
+        (IE_FLOW_END_MILLISECONDS, 8, false),
+        (src_ie, addr_len, false),
+        (dst_ie, addr_len, false),
+        (IE_SOURCE_TRANSPORT_PORT, 2, false),
+        (IE_DESTINATION_TRANSPORT_PORT, 2, false),
+        (IE_PROTOCOL_IDENTIFIER, 1, false),
+        (IE_OCTET_DELTA_COUNT, 8, false),
+        (IE_PACKET_DELTA_COUNT, 8, false),
+        (IE_INITIATOR_OCTETS, 8, false),
+        (IE_RESPONDER_OCTETS, 8, false),
+        (IE_INITIATOR_PACKETS, 8, false),
+        (IE_RESPONDER_PACKETS, 8, false),
+        (EIE_PROCESS_ID, 4, true),
+        (EIE_PROCESS_NAME, VARLEN, true),
+        (EIE_USER_NAME, VARLEN, true),
+        (EIE_WHITELIST_STATE, VARLEN, true),
+        (EIE_CRITICALITY, VARLEN, true),
+        (EIE_LABELS, VARLEN, true),
+    ];
+
+    buf.extend_from_slice(&template_id.to_be_bytes());
+    buf.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for (ie, len, enterprise) in fields {
+        if enterprise {
+            buf.extend_from_slice(&(0x8000 | ie).to_be_bytes());
+            buf.extend_from_slice(&len.to_be_bytes());
+            buf.extend_from_slice(&enterprise_id.to_be_bytes());
+        } else {
+            buf.extend_from_slice(&ie.to_be_bytes());
+            buf.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+}
+
+fn encode_record(record: &FlowRecord) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(128);
+    buf.extend_from_slice(&record.start_ms.to_be_bytes());
+    buf.extend_from_slice(&record.end_ms.to_be_bytes());
+    match (record.src_ip, record.dst_ip) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            buf.extend_from_slice(&src.octets());
+            buf.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            buf.extend_from_slice(&to_ipv6(src).octets());
+            buf.extend_from_slice(&to_ipv6(dst).octets());
+        }
+    }
+    buf.extend_from_slice(&record.src_port.to_be_bytes());
+    buf.extend_from_slice(&record.dst_port.to_be_bytes());
+    buf.push(record.protocol);
+    buf.extend_from_slice(&(record.initiator_octets + record.responder_octets).to_be_bytes());
+    buf.extend_from_slice(&(record.initiator_packets + record.responder_packets).to_be_bytes());
+    buf.extend_from_slice(&record.initiator_octets.to_be_bytes());
+    buf.extend_from_slice(&record.responder_octets.to_be_bytes());
+    buf.extend_from_slice(&record.initiator_packets.to_be_bytes());
+    buf.extend_from_slice(&record.responder_packets.to_be_bytes());
+    buf.extend_from_slice(&record.process_id.to_be_bytes());
+    encode_string(&mut buf, &record.process_name);
+    encode_string(&mut buf, &record.user_name);
+    encode_string(&mut buf, &record.whitelist_state);
+    encode_string(&mut buf, &record.criticality);
+    encode_string(&mut buf, &record.labels);
+    buf
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+// Variable-length encoding (RFC 7011, section 7)
+fn encode_string(buf: &mut Vec<u8>, value: &str) {
+    let mut end = value.len().min(MAX_STRING_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    let bytes = &value.as_bytes()[..end];
+    if bytes.len() < 255 {
+        buf.push(bytes.len() as u8);
+    } else {
+        buf.push(255);
+        buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    }
+    buf.extend_from_slice(bytes);
+}
+
+/// Parse an RFC 3339 timestamp (e.g. 2026-05-18T10:11:02.123Z) into Unix milliseconds.
+pub fn parse_rfc3339_millis(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.len() < 19 {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| -> Option<i64> { value.get(range)?.parse().ok() };
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+
+    let mut rest = &value[19..];
+    let mut millis = 0i64;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits: String = fraction
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        let padded = format!("{:0<3}", &digits[..digits.len().min(3)]);
+        millis = padded.parse().ok()?;
+        rest = &fraction[digits.len()..];
+    }
+    let offset_secs = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours: i64 = rest.get(1..3)?.parse().ok()?;
+            let minutes: i64 = rest.get(4..6)?.parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // Days from civil (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset_secs;
+    if secs < 0 {
+        return None;
+    }
+    Some(secs as u64 * 1000 + millis as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_session() -> Value {
+        json!({
+            "session": {
+                "protocol": "TCP",
+                "src_ip": "192.168.1.42",
+                "src_port": 54321,
+                "dst_ip": "140.82.121.4",
+                "dst_port": 443
+            },
+            "stats": {
+                "start_time": "2026-05-18T10:11:02Z",
+                "end_time": "2026-05-18T10:11:47.250Z",
+                "inbound_bytes": 88234,
+                "outbound_bytes": 12011,
+                "orig_pkts": 42,
+                "resp_pkts": 61
+            },
+            "l7": {
+                "pid": 31278,
+                "username": "runner",
+                "process_name": "curl"
+            },
+            "uid": "tcp:192.168.1.42:54321:140.82.121.4:443",
+            "is_whitelisted": "Conforming",
+            "criticality": "anomaly:normal"
+        })
+    }
+
+    #[test]
+    fn parses_rfc3339_timestamps() {
+        assert_eq!(parse_rfc3339_millis("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339_millis("2026-05-18T10:11:47.250Z"),
+            Some(1_779_099_107_250)
+        );
+        assert_eq!(
+            parse_rfc3339_millis("2026-05-18T12:11:47.250+02:00"),
+            Some(1_779_099_107_250)
+        );
+        assert_eq!(parse_rfc3339_millis("not a date"), None);
+    }
+
+    #[test]
+    fn flow_record_from_session_value() {
+        let record = FlowRecord::from_session_value(&sample_session()).expect("valid session");
+        assert_eq!(record.protocol, 6);
+        assert_eq!(record.dst_port, 443);
+        assert_eq!(record.initiator_octets, 12011);
+        assert_eq!(record.responder_octets, 88234);
+        assert_eq!(record.process_id, 31278);
+        assert_eq!(record.process_name, "curl");
+        assert_eq!(record.whitelist_state, "Conforming");
+        assert_eq!(record.end_ms - record.start_ms, 45_250);
+        assert!(record.finished);
+        assert_eq!(record.labels, "");
+    }
+
+    #[test]
+    fn exports_template_and_data_to_udp_listener() {
+        let listener = UdpSocket::bind("127.0.0.1:0").expect("bind listener");
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("set timeout");
+        let collector = listener.local_addr().expect("listener addr").to_string();
+
+        let mut exporter =
+            IpfixExporter::new(&collector, 7, DEFAULT_ENTERPRISE_ID).expect("create exporter");
+        let mut session = sample_session();
+        session["labels"] = json!(["corp-artifactory", "https"]);
+        let record = FlowRecord::from_session_value(&session).expect("valid session");
+        assert_eq!(exporter.send_records(&[record]).expect("send"), 1);
+
+        let mut buf = [0u8; 2048];
+        // Template set first, then the IPv4 data set
+        for expected_set_id in [TEMPLATE_SET_ID, TEMPLATE_ID_V4] {
+            let len = listener.recv(&mut buf).expect("receive message");
+            assert_eq!(u16::from_be_bytes([buf[0], buf[1]]), IPFIX_VERSION);
+            assert_eq!(u16::from_be_bytes([buf[2], buf[3]]) as usize, len);
+            assert_eq!(u32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]), 7);
+            assert_eq!(u16::from_be_bytes([buf[16], buf[17]]), expected_set_id);
+        }
+        // The data set carries the 5-tuple and the enterprise strings
+        let data = &buf[..];
+        assert_eq!(&data[36..40], &[192, 168, 1, 42]);
+        assert_eq!(&data[40..44], &[140, 82, 121, 4]);
+        assert!(data.windows(4).any(|w| w == b"curl"));
+        assert!(data.windows(10).any(|w| w == b"Conforming"));
+        let labels = b"corp-artifactory,https";
+        assert!(data.windows(labels.len()).any(|w| w == labels));
+    }
+}
@@ -0,0 +1,289 @@
+// User-defined session labels.
+//
+// A label file names traffic with the context only its owners have. Each
+// rule gives a label and the criteria a session must all meet to carry it:
+//
+//   labels:
+//     - label: corp-artifactory
+//       cidr: 10.20.0.0/16
+//     - label: monitoring
+//       process: /opt/agent/*
+//     - label: package-registry
+//       domain: "*.npmjs.org"
+//       port: 443
+//
+// `cidr` is matched against the destination IP, `domain` against the
+// destination domain, `process` against the process path (or its name when
+// the path is unknown) and `port` against the destination port. Domain and
+// process patterns support `*` and `?`, domains case-insensitively. A session
+// carries every label whose rule matches, in file order, in a `labels` field.
+
+use crate::sessions::{session_process, session_str, session_u64};
+use crate::whitelists::{cidr_contains, parse_cidr, wildcard_matches};
+use serde_json::Value;
+
+pub const LABELS_ENV: &str = "EDAMAME_LABELS_FILE";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelRule {
+    pub label: String,
+    pub cidr: Option<String>,
+    pub domain: Option<String>,
+    pub process: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl LabelRule {
+    pub fn matches(&self, session: &Value) -> bool {
+        self.cidr.as_ref().is_none_or(|cidr| {
+            session_str(session, "/session/dst_ip").is_some_and(|ip| cidr_contains(cidr, ip))
+        }) && self.domain.as_ref().is_none_or(|domain| {
+            session_str(session, "/dst_domain")
+                .is_some_and(|d| wildcard_matches(&domain.to_lowercase(), &d.to_lowercase()))
+        }) && self.process.as_ref().is_none_or(|process| {
+            session_str(session, "/l7/process_path")
+                .or_else(|| session_process(session))
+                .is_some_and(|p| wildcard_matches(process, p))
+        }) && self
+            .port
+            .is_none_or(|port| session_u64(session, "/session/dst_port") == port as u64)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LabelRules {
+    pub path: String,
+    pub rules: Vec<LabelRule>,
+}
+
+fn string_field(entry: &Value, field: &str) -> Result<Option<String>, String> {
+    match &entry[field] {
+        Value::Null => Ok(None),
+        Value::String(s) if !s.trim().is_empty() => Ok(Some(s.trim().to_string())),
+        _ => Err(format!("'{}' must be a non-empty string", field)),
+    }
+}
+
+fn parse_rule(entry: &Value) -> Result<LabelRule, String> {
+    let map = entry
+        .as_object()
+        .ok_or_else(|| "rule must be a mapping".to_string())?;
+    // A misspelled criterion would silently label more sessions
+    if let Some(unknown) = map
+        .keys()
+        .find(|key| !["label", "cidr", "domain", "process", "port"].contains(&key.as_str()))
+    {
+        return Err(format!("unknown field '{}'", unknown));
+    }
+    let label = string_field(entry, "label")?.ok_or_else(|| "missing 'label'".to_string())?;
+    if label.contains(',') || label.chars().any(char::is_whitespace) {
+        return Err(format!(
+            "label '{}' must not contain commas or whitespace",
+            label
+        ));
+    }
+    let cidr = string_field(entry, "cidr")?;
+    if let Some(cidr) = &cidr {
+        if parse_cidr(cidr).is_none() {
+            return Err(format!("invalid cidr '{}'", cidr));
+        }
+    }
+    let port = match &entry["port"] {
+        Value::Null => None,
+        value => Some(
+            value
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .filter(|port| *port > 0)
+                .ok_or_else(|| format!("invalid port {}", value))?,
+        ),
+    };
+    let rule = LabelRule {
+        label,
+        cidr,
+        domain: string_field(entry, "domain")?,
+        process: string_field(entry, "process")?,
+        port,
+    };
+    if rule.cidr.is_none() && rule.domain.is_none() && rule.process.is_none() && rule.port.is_none()
+    {
+        return Err("rules need a 'cidr', 'domain', 'process' or 'port'".to_string());
+    }
+    Ok(rule)
+}
+
+pub fn parse_label_rules(document: &Value) -> Result<Vec<LabelRule>, String> {
+    let rules = match &document["labels"] {
+        Value::Array(rules) => rules,
+        Value::Null if document.is_null() || document.is_object() => return Ok(Vec::new()),
+        _ => return Err("'labels' must be a list of rules".to_string()),
+    };
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| parse_rule(rule).map_err(|e| format!("labels[{}]: {}", i, e)))
+        .collect()
+}
+
+impl LabelRules {
+    pub fn load(path: &str) -> Result<LabelRules, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading label file '{}': {}", path, e))?;
+        let document: Value = serde_yaml_ng::from_str(&content)
+            .map_err(|e| format!("Error parsing label file '{}': {}", path, e))?;
+        let rules = parse_label_rules(&document)
+            .map_err(|e| format!("Invalid label file '{}': {}", path, e))?;
+        Ok(LabelRules {
+            path: path.to_string(),
+            rules,
+        })
+    }
+
+    /// Rules of the file named by EDAMAME_LABELS_FILE, if set.
+    pub fn from_env() -> Result<Option<LabelRules>, String> {
+        match std::env::var(LABELS_ENV) {
+            Ok(path) if !path.trim().is_empty() => LabelRules::load(path.trim()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Labels of a serialized session, in rule order and without duplicates.
+    pub fn labels(&self, session: &Value) -> Vec<String> {
+        let mut labels: Vec<String> = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.matches(session)) {
+            if !labels.contains(&rule.label) {
+                labels.push(rule.label.clone());
+            }
+        }
+        labels
+    }
+
+    /// Set the `labels` field of a serialized session.
+    pub fn apply(&self, session: &mut Value) {
+        let labels = self.labels(session);
+        if session.is_object() {
+            session["labels"] = Value::from(labels);
+        }
+    }
+}
+
+/// Labels of a session that went through `LabelRules::apply`.
+pub fn session_labels(session: &Value) -> Vec<&str> {
+    session["labels"]
+        .as_array()
+        .map(|labels| labels.iter().filter_map(|label| label.as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// True when a session carries a label matching one of the patterns.
+pub fn has_label(session: &Value, patterns: &[String]) -> bool {
+    session_labels(session).iter().any(|label| {
+        patterns
+            .iter()
+            .any(|pattern| wildcard_matches(pattern, label))
+    })
+}
+
+/// A log or Zeek output line with the session's labels appended: a
+/// `labels=a,b` suffix in log lines, a last tab-separated column in Zeek
+/// lines, `-` standing for no label as in Zeek logs.
+pub fn labeled_line(line: &str, labels: &[String], zeek_format: bool) -> String {
+    if zeek_format {
+        let column = if labels.is_empty() {
+            "-".to_string()
+        } else {
+            labels.join(",")
+        };
+        format!("{}\t{}", line, column)
+    } else if labels.is_empty() {
+        line.to_string()
+    } else {
+        format!("{} labels={}", line, labels.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(yaml: &str) -> Result<Vec<LabelRule>, String> {
+        parse_label_rules(&serde_yaml_ng::from_str::<Value>(yaml).unwrap())
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        let parsed = rules(
+            r#"
+labels:
+  - label: corp-artifactory
+    cidr: 10.20.0.0/16
+  - label: registry
+    domain: "*.npmjs.org"
+    port: 443
+"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].port, Some(443));
+
+        for (yaml, error) in [
+            ("labels:\n  - cidr: 10.0.0.0/8\n", "missing 'label'"),
+            ("labels:\n  - label: x\n", "rules need"),
+            (
+                "labels:\n  - label: x\n    cidr: 10.0.0.300/8\n",
+                "invalid cidr",
+            ),
+            ("labels:\n  - label: x\n    port: 70000\n", "invalid port"),
+            (
+                "labels:\n  - label: x\n    domian: a.com\n",
+                "unknown field 'domian'",
+            ),
+            ("labels:\n  - label: a b\n    port: 22\n", "whitespace"),
+        ] {
+            let e = rules(yaml).unwrap_err();
+            assert!(e.starts_with("labels[0]: ") && e.contains(error), "{}", e);
+        }
+    }
+
+    #[test]
+    fn sessions_get_every_matching_label() {
+        let rules = LabelRules {
+            path: String::new(),
+            rules: rules(
+                r#"
+labels:
+  - label: corp-artifactory
+    cidr: 10.20.0.0/16
+  - label: monitoring
+    process: /opt/agent/*
+  - label: https
+    port: 443
+  - label: monitoring
+    domain: "*.datadoghq.com"
+"#,
+            )
+            .unwrap(),
+        };
+        let mut session = json!({
+            "dst_domain": "intake.DatadogHQ.com",
+            "session": { "dst_ip": "10.20.3.4", "dst_port": 443 },
+            "l7": { "process_name": "agent", "process_path": "/opt/agent/bin/agent" },
+        });
+        rules.apply(&mut session);
+        assert_eq!(
+            session_labels(&session),
+            ["corp-artifactory", "monitoring", "https"]
+        );
+        assert!(has_label(&session, &["corp-*".to_string()]));
+        assert!(!has_label(&session, &["build".to_string()]));
+
+        let mut other = json!({ "session": { "dst_ip": "192.0.2.1", "dst_port": 80 } });
+        rules.apply(&mut other);
+        assert!(session_labels(&other).is_empty());
+
+        let labels = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(labeled_line("conn", &labels, false), "conn labels=a,b");
+        assert_eq!(labeled_line("conn", &[], true), "conn\t-");
+    }
+}
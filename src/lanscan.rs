@@ -0,0 +1,738 @@
+// LAN scan inventory: export, per-scan history and change detection.
+//
+// Devices are reduced to the fields that matter for an inventory (IP, MAC,
+// vendor, hostnames, open ports and criticality). The same JSON document is
+// used for `lanscan --format json`, for the scan history and by `lanscan-diff`:
+//
+//   { "scanned_at": "2026-10-18T09:12:00Z", "devices": [ { "ip": "192.168.1.20",
+//     "macs": ["a4:83:e7:12:34:56"], "vendor": "Apple", "hostnames": ["nas.local"],
+//     "open_ports": [22, 445], "criticality": "Low" } ] }
+
+use crate::whitelists::cidr_contains;
+use crate::ERROR_CODE_MISMATCH;
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use tracing::{info, warn};
+
+pub const HISTORY_ENV: &str = "EDAMAME_LANSCAN_DIR";
+pub const WEBHOOK_ENV: &str = "EDAMAME_LANSCAN_WEBHOOK";
+#[cfg(not(windows))]
+const DEFAULT_HISTORY_DIR: &str = "/var/lib/edamame_posture/lanscans";
+#[cfg(windows)]
+const DEFAULT_HISTORY_DIR: &str = r"C:\ProgramData\edamame_posture\lanscans";
+// Oldest scans are pruned beyond this count
+const MAX_HISTORY: usize = 100;
+const CSV_HEADER: &str = "ip,mac,vendor,hostnames,open_ports,criticality";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub ip: String,
+    pub macs: Vec<String>,
+    pub vendor: String,
+    pub hostnames: Vec<String>,
+    pub open_ports: Vec<u16>,
+    pub criticality: String,
+}
+
+/// Options of the `lanscan` command, the daemon's own scans use the defaults.
+#[derive(Debug, Default)]
+pub struct LanscanOptions {
+    // text (default), json or csv
+    pub format: String,
+    pub output: Option<String>,
+    pub persist: bool,
+    // Only devices within these ranges are reported, all devices when empty
+    pub cidrs: Vec<String>,
+    // Only these open ports are reported, all ports when empty
+    pub ports: Vec<u16>,
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => vec![s.trim().to_string()],
+        Value::Array(values) => values.iter().flat_map(strings).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn first_string(value: &Value, fields: &[&str]) -> String {
+    fields
+        .iter()
+        .flat_map(|field| strings(&value[*field]))
+        .next()
+        .unwrap_or_default()
+}
+
+fn sorted_unique(values: impl IntoIterator<Item = String>) -> Vec<String> {
+    values
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+impl Device {
+    /// Device from the serialized form of the core's device list.
+    pub fn from_core_value(device: &Value) -> Option<Device> {
+        let ip = first_string(
+            device,
+            &["ip_address", "ip_addresses_v4", "ip_addresses_v6"],
+        );
+        if ip.is_empty() {
+            return None;
+        }
+        let macs = ["mac_address", "mac_addresses"]
+            .iter()
+            .flat_map(|field| strings(&device[*field]))
+            .map(|mac| mac.to_lowercase());
+        let hostnames = ["hostname", "hostnames"]
+            .iter()
+            .flat_map(|field| strings(&device[*field]));
+        let mut open_ports: Vec<u16> = device["open_ports"]
+            .as_array()
+            .map(|ports| {
+                ports
+                    .iter()
+                    .filter_map(|port| port["port"].as_u64().or_else(|| port.as_u64()))
+                    .filter_map(|port| u16::try_from(port).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        open_ports.sort_unstable();
+        open_ports.dedup();
+        Some(Device {
+            ip,
+            macs: sorted_unique(macs),
+            vendor: first_string(device, &["device_vendor", "vendor"]),
+            hostnames: sorted_unique(hostnames),
+            open_ports,
+            criticality: first_string(device, &["criticality"]),
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ip": self.ip,
+            "macs": self.macs,
+            "vendor": self.vendor,
+            "hostnames": self.hostnames,
+            "open_ports": self.open_ports,
+            "criticality": self.criticality,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Device, String> {
+        let ip = value["ip"]
+            .as_str()
+            .filter(|ip| !ip.is_empty())
+            .ok_or_else(|| "missing 'ip'".to_string())?;
+        Ok(Device {
+            ip: ip.to_string(),
+            macs: strings(&value["macs"]),
+            vendor: value["vendor"].as_str().unwrap_or("").to_string(),
+            hostnames: strings(&value["hostnames"]),
+            open_ports: value["open_ports"]
+                .as_array()
+                .map(|ports| {
+                    ports
+                        .iter()
+                        .filter_map(|p| p.as_u64().and_then(|p| u16::try_from(p).ok()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            criticality: value["criticality"].as_str().unwrap_or("").to_string(),
+        })
+    }
+}
+
+pub fn devices_from_core(devices: &Value) -> Vec<Device> {
+    let mut devices: Vec<Device> = devices
+        .as_array()
+        .map(|devices| devices.iter().filter_map(Device::from_core_value).collect())
+        .unwrap_or_default();
+    devices.sort_by(|a, b| a.ip.cmp(&b.ip));
+    devices
+}
+
+/// Ranges to report for the requested CIDRs and interfaces. `local` holds the
+/// (interface, address/prefix) pairs of this host: the core only probes the
+/// networks of local interfaces, so a range outside of them is an error rather
+/// than a scan that silently finds nothing.
+pub fn resolve_scope(
+    cidrs: &[String],
+    interfaces: &[String],
+    local: &[(String, String)],
+) -> Result<Vec<String>, String> {
+    let mut scope: Vec<String> = Vec::new();
+    for interface in interfaces {
+        let networks: Vec<&String> = local
+            .iter()
+            .filter(|(name, _)| name == interface)
+            .map(|(_, network)| network)
+            .collect();
+        if networks.is_empty() {
+            let names: BTreeSet<&str> = local.iter().map(|(name, _)| name.as_str()).collect();
+            return Err(format!(
+                "Interface '{}' has no IP address on this host (available: {})",
+                interface,
+                names.into_iter().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        scope.extend(networks.into_iter().cloned());
+    }
+    for cidr in cidrs {
+        if !local
+            .iter()
+            .any(|(_, network)| cidr_contains(network, cidr) || cidr_contains(cidr, network))
+        {
+            return Err(format!(
+                "{} is not on a network of a local interface, LAN scans only cover directly attached networks",
+                cidr
+            ));
+        }
+        scope.push(cidr.clone());
+    }
+    Ok(scope)
+}
+
+/// Local IPv4 networks overlapping the resolved scope, as the (address,
+/// prefix, interface) triples the core scans. Ranges narrower than their
+/// network are scanned whole and narrowed down by `restrict_devices`.
+pub fn scan_interfaces(scope: &[String], local: &[(String, String)]) -> Vec<(String, u8, String)> {
+    let mut interfaces = Vec::new();
+    for (name, network) in local {
+        let Some((address, prefix)) = network.split_once('/') else {
+            continue;
+        };
+        let (Ok(address), Ok(prefix)) = (address.parse::<Ipv4Addr>(), prefix.parse::<u8>()) else {
+            continue;
+        };
+        let overlaps = scope
+            .iter()
+            .any(|cidr| cidr_contains(network, cidr) || cidr_contains(cidr, network));
+        let interface = (address.to_string(), prefix, name.clone());
+        if overlaps && !interfaces.contains(&interface) {
+            interfaces.push(interface);
+        }
+    }
+    interfaces
+}
+
+/// Keep the devices within `cidrs` and the open ports listed in `ports`.
+pub fn restrict_devices(devices: Vec<Device>, cidrs: &[String], ports: &[u16]) -> Vec<Device> {
+    devices
+        .into_iter()
+        .filter(|device| cidrs.is_empty() || cidrs.iter().any(|c| cidr_contains(c, &device.ip)))
+        .map(|mut device| {
+            if !ports.is_empty() {
+                device.open_ports.retain(|port| ports.contains(port));
+            }
+            device
+        })
+        .collect()
+}
+
+pub fn scan_document(scanned_at: &str, devices: &[Device]) -> Value {
+    json!({
+        "scanned_at": scanned_at,
+        "devices": devices.iter().map(Device::to_json).collect::<Vec<_>>(),
+    })
+}
+
+pub fn load_scan(path: &str) -> Result<Vec<Device>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading scan file '{}': {}", path, e))?;
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Error parsing scan file '{}': {}", path, e))?;
+    let devices = value["devices"]
+        .as_array()
+        .ok_or_else(|| format!("Scan file '{}' has no 'devices' array", path))?;
+    devices
+        .iter()
+        .enumerate()
+        .map(|(i, device)| {
+            Device::from_json(device).map_err(|e| format!("{}: devices[{}]: {}", path, i, e))
+        })
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// CSV export, one device per line. Multi-valued fields are joined with `;`.
+pub fn devices_csv(devices: &[Device]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for device in devices {
+        let ports: Vec<String> = device.open_ports.iter().map(|p| p.to_string()).collect();
+        let fields = [
+            device.ip.clone(),
+            device.macs.join(";"),
+            device.vendor.clone(),
+            device.hostnames.join(";"),
+            ports.join(";"),
+            device.criticality.clone(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Plain text listing, one device per line.
+pub fn devices_text(devices: &[Device]) -> String {
+    let lines: Vec<String> = devices
+        .iter()
+        .map(|device| {
+            let device = device.to_json();
+            let ports = ports_text(&device["open_ports"]);
+            format!(
+                "{} - open ports: {} - criticality: {}",
+                device_line(&device),
+                if ports.is_empty() { "none" } else { &ports },
+                device["criticality"]
+                    .as_str()
+                    .filter(|c| !c.is_empty())
+                    .unwrap_or("unknown")
+            )
+        })
+        .collect();
+    lines.join("\n")
+}
+
+pub fn history_dir() -> String {
+    std::env::var(HISTORY_ENV)
+        .ok()
+        .filter(|dir| !dir.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_HISTORY_DIR.to_string())
+}
+
+/// Scan files of the history directory, oldest first.
+pub fn history_files(dir: &str) -> Result<Vec<String>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Error reading scan history '{}': {}", dir, e)),
+    };
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("lanscan-") && name.ends_with(".json"))
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    // Names embed a zero-padded timestamp, so they sort chronologically
+    files.sort();
+    Ok(files)
+}
+
+/// Store a scan in the history directory and prune the oldest scans.
+pub fn save_to_history(dir: &str, document: &Value, now: u64) -> Result<String, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Error creating scan history '{}': {}", dir, e))?;
+    let path = Path::new(dir)
+        .join(format!("lanscan-{:012}.json", now))
+        .to_string_lossy()
+        .to_string();
+    let content = serde_json::to_string_pretty(document)
+        .map_err(|e| format!("Error serializing scan: {}", e))?;
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Error writing scan file '{}': {}", path, e))?;
+    let files = history_files(dir)?;
+    for old in files.iter().take(files.len().saturating_sub(MAX_HISTORY)) {
+        if let Err(e) = std::fs::remove_file(old) {
+            warn!("Cannot prune scan file '{}': {}", old, e);
+        }
+    }
+    Ok(path)
+}
+
+fn index(devices: &[Device]) -> BTreeMap<&str, &Device> {
+    devices.iter().map(|d| (d.ip.as_str(), d)).collect()
+}
+
+/// Compare two scans by IP address: new and disappeared devices, ports opened
+/// since the old scan, and IPs answering with a different MAC address.
+pub fn diff_scans(old: &[Device], new: &[Device]) -> Value {
+    let old_index = index(old);
+    let new_index = index(new);
+
+    let new_devices: Vec<Value> = new_index
+        .iter()
+        .filter(|(ip, _)| !old_index.contains_key(*ip))
+        .map(|(_, device)| device.to_json())
+        .collect();
+    let disappeared: Vec<Value> = old_index
+        .iter()
+        .filter(|(ip, _)| !new_index.contains_key(*ip))
+        .map(|(_, device)| device.to_json())
+        .collect();
+
+    let mut new_ports = Vec::new();
+    let mut mac_changes = Vec::new();
+    for (ip, device) in &new_index {
+        let Some(previous) = old_index.get(ip) else {
+            continue;
+        };
+        let opened: Vec<u16> = device
+            .open_ports
+            .iter()
+            .filter(|port| !previous.open_ports.contains(port))
+            .copied()
+            .collect();
+        if !opened.is_empty() {
+            new_ports.push(json!({ "ip": ip, "ports": opened }));
+        }
+        // Only a complete MAC replacement is reported, multi-homed devices add MACs
+        if !previous.macs.is_empty()
+            && !device.macs.is_empty()
+            && !device.macs.iter().any(|mac| previous.macs.contains(mac))
+        {
+            mac_changes.push(json!({
+                "ip": ip,
+                "old_macs": previous.macs,
+                "new_macs": device.macs,
+            }));
+        }
+    }
+
+    json!({
+        "new_devices": new_devices,
+        "disappeared_devices": disappeared,
+        "new_ports": new_ports,
+        "mac_changes": mac_changes,
+    })
+}
+
+fn list<'a>(report: &'a Value, key: &str) -> &'a [Value] {
+    report[key].as_array().map_or(&[], |v| v.as_slice())
+}
+
+pub fn has_changes(report: &Value) -> bool {
+    [
+        "new_devices",
+        "disappeared_devices",
+        "new_ports",
+        "mac_changes",
+    ]
+    .iter()
+    .any(|key| !list(report, key).is_empty())
+}
+
+fn device_line(device: &Value) -> String {
+    let mut details: Vec<String> = strings(&device["macs"]);
+    if let Some(vendor) = device["vendor"].as_str().filter(|v| !v.is_empty()) {
+        details.push(vendor.to_string());
+    }
+    details.extend(strings(&device["hostnames"]));
+    if details.is_empty() {
+        device["ip"].as_str().unwrap_or("").to_string()
+    } else {
+        format!(
+            "{} ({})",
+            device["ip"].as_str().unwrap_or(""),
+            details.join(", ")
+        )
+    }
+}
+
+fn ports_text(ports: &Value) -> String {
+    ports
+        .as_array()
+        .map(|ports| {
+            ports
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default()
+}
+
+/// One line per change, used for the daemon log and notifications.
+pub fn change_lines(report: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    for device in list(report, "new_devices") {
+        lines.push(format!("New device {}", device_line(device)));
+    }
+    for device in list(report, "disappeared_devices") {
+        lines.push(format!("Device disappeared {}", device_line(device)));
+    }
+    for change in list(report, "new_ports") {
+        lines.push(format!(
+            "New open ports on {}: {}",
+            change["ip"].as_str().unwrap_or(""),
+            ports_text(&change["ports"])
+        ));
+    }
+    for change in list(report, "mac_changes") {
+        lines.push(format!(
+            "MAC address of {} changed from {} to {} (possible spoofing)",
+            change["ip"].as_str().unwrap_or(""),
+            strings(&change["old_macs"]).join(", "),
+            strings(&change["new_macs"]).join(", ")
+        ));
+    }
+    lines
+}
+
+fn print_diff(report: &Value) {
+    let new_devices = list(report, "new_devices");
+    println!("New devices ({}):", new_devices.len());
+    for device in new_devices {
+        println!("  + {}", device_line(device));
+    }
+    let disappeared = list(report, "disappeared_devices");
+    println!("Disappeared devices ({}):", disappeared.len());
+    for device in disappeared {
+        println!("  - {}", device_line(device));
+    }
+    let new_ports = list(report, "new_ports");
+    println!("Newly opened ports ({}):", new_ports.len());
+    for change in new_ports {
+        println!(
+            "  {}: {}",
+            change["ip"].as_str().unwrap_or(""),
+            ports_text(&change["ports"])
+        );
+    }
+    let mac_changes = list(report, "mac_changes");
+    println!("MAC changes ({}):", mac_changes.len());
+    for change in mac_changes {
+        println!(
+            "  ! {}: {} -> {} (possible spoofing)",
+            change["ip"].as_str().unwrap_or(""),
+            strings(&change["old_macs"]).join(", "),
+            strings(&change["new_macs"]).join(", ")
+        );
+    }
+}
+
+/// Compare two scan files. Without files, the two latest scans of the history
+/// are compared, and with a single file it is compared to the latest scan.
+pub fn lanscan_diff(
+    old_path: Option<&str>,
+    new_path: Option<&str>,
+    json_output: bool,
+    fail_on_change: bool,
+) -> i32 {
+    let (old_path, new_path) = match (old_path, new_path) {
+        (Some(old), Some(new)) => (old.to_string(), new.to_string()),
+        (old, _) => {
+            let dir = history_dir();
+            let files = match history_files(&dir) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ERROR_CODE_PARAM;
+                }
+            };
+            match (old, files.as_slice()) {
+                (Some(old), [.., latest]) => (old.to_string(), latest.clone()),
+                (None, [.., previous, latest]) => (previous.clone(), latest.clone()),
+                _ => {
+                    eprintln!("Not enough scans in {} to compare, run lanscan first", dir);
+                    return ERROR_CODE_PARAM;
+                }
+            }
+        }
+    };
+    let (old, new) = match (load_scan(&old_path), load_scan(&new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+
+    let mut report = diff_scans(&old, &new);
+    report["old"] = Value::from(old_path);
+    report["new"] = Value::from(new_path);
+    if json_output {
+        match serde_json::to_string_pretty(&report) {
+            Ok(output) => println!("{}", output),
+            Err(e) => {
+                eprintln!("Error serializing diff: {}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+    } else {
+        print_diff(&report);
+    }
+
+    if fail_on_change && has_changes(&report) {
+        eprintln!("LAN inventory changed since the previous scan");
+        return ERROR_CODE_MISMATCH;
+    }
+    0
+}
+
+/// POST the changes to the webhook configured for the daemon. The payload has a
+/// `text` summary, so Slack and Teams incoming webhooks can take it as is.
+pub fn notify_changes(webhook: &str, report: &Value) -> Result<(), String> {
+    let lines = change_lines(report);
+    let payload = json!({
+        "text": format!("EDAMAME LAN scan changes:\n{}", lines.join("\n")),
+        "changes": report,
+    });
+    let status = std::process::Command::new("curl")
+        .args([
+            "-s",
+            "-f",
+            "-o",
+            if cfg!(windows) { "NUL" } else { "/dev/null" },
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            &payload.to_string(),
+            webhook,
+        ])
+        .status()
+        .map_err(|e| format!("Failed to execute 'curl' command: {}", e))?;
+    if status.success() {
+        info!("LAN scan changes sent to the webhook");
+        Ok(())
+    } else {
+        Err(format!(
+            "Webhook notification failed (exit code = {:?})",
+            status.code()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(ip: &str, mac: &str, ports: &[u16]) -> Device {
+        Device {
+            ip: ip.to_string(),
+            macs: vec![mac.to_string()],
+            vendor: "Acme".to_string(),
+            hostnames: vec![],
+            open_ports: ports.to_vec(),
+            criticality: "Low".to_string(),
+        }
+    }
+
+    #[test]
+    fn core_devices_are_normalized() {
+        let devices = devices_from_core(&json!([
+            {
+                "ip_address": "192.168.1.20",
+                "mac_addresses": ["A4:83:E7:12:34:56"],
+                "hostname": "nas.local",
+                "device_vendor": "Synology",
+                "open_ports": [ { "port": 445, "protocol": "tcp" }, { "port": 22 } ],
+                "criticality": "Medium"
+            },
+            { "ip_address": "", "hostname": "no-ip" },
+            { "ip_addresses_v4": ["192.168.1.1"], "mac_address": "00:11:22:33:44:55" }
+        ]));
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].ip, "192.168.1.1");
+        assert_eq!(devices[1].macs, vec!["a4:83:e7:12:34:56"]);
+        assert_eq!(devices[1].open_ports, vec![22, 445]);
+        assert_eq!(devices[1].hostnames, vec!["nas.local"]);
+
+        let document = scan_document("2026-10-18T09:12:00Z", &devices);
+        let round_trip: Vec<Device> = document["devices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| Device::from_json(d).unwrap())
+            .collect();
+        assert_eq!(round_trip, devices);
+
+        let csv = devices_csv(&devices);
+        assert_eq!(
+            csv.lines().nth(2).unwrap(),
+            "192.168.1.20,a4:83:e7:12:34:56,Synology,nas.local,22;445,Medium"
+        );
+    }
+
+    #[test]
+    fn diff_reports_devices_ports_and_mac_changes() {
+        let old = vec![
+            device("192.168.1.1", "00:11:22:33:44:55", &[53]),
+            device("192.168.1.20", "a4:83:e7:12:34:56", &[22]),
+            device("192.168.1.30", "aa:aa:aa:aa:aa:aa", &[]),
+        ];
+        let new = vec![
+            device("192.168.1.1", "de:ad:be:ef:00:01", &[53]),
+            device("192.168.1.20", "a4:83:e7:12:34:56", &[22, 3389]),
+            device("192.168.1.40", "bb:bb:bb:bb:bb:bb", &[80]),
+        ];
+        let report = diff_scans(&old, &new);
+        assert_eq!(report["new_devices"][0]["ip"], "192.168.1.40");
+        assert_eq!(report["disappeared_devices"][0]["ip"], "192.168.1.30");
+        assert_eq!(
+            report["new_ports"],
+            json!([{ "ip": "192.168.1.20", "ports": [3389] }])
+        );
+        assert_eq!(report["mac_changes"][0]["ip"], "192.168.1.1");
+        assert!(has_changes(&report));
+        assert_eq!(change_lines(&report).len(), 4);
+
+        assert!(!has_changes(&diff_scans(&new, &new)));
+    }
+
+    #[test]
+    fn scope_is_limited_to_local_networks() {
+        let local = vec![
+            ("eth0".to_string(), "192.168.1.10/24".to_string()),
+            ("eth0.20".to_string(), "10.20.0.5/16".to_string()),
+        ];
+        assert_eq!(
+            resolve_scope(&["10.20.3.0/24".to_string()], &["eth0".to_string()], &local).unwrap(),
+            vec!["192.168.1.10/24", "10.20.3.0/24"]
+        );
+        assert!(resolve_scope(&["172.16.0.0/24".to_string()], &[], &local).is_err());
+        assert!(resolve_scope(&[], &["wlan0".to_string()], &local)
+            .unwrap_err()
+            .contains("available: eth0, eth0.20"));
+        assert_eq!(
+            scan_interfaces(&["10.20.3.0/24".to_string()], &local),
+            vec![("10.20.0.5".to_string(), 16, "eth0.20".to_string())]
+        );
+        let local_v6 = vec![("eth0".to_string(), "fe80::1/64".to_string())];
+        assert!(scan_interfaces(&["fe80::/64".to_string()], &local_v6).is_empty());
+
+        let devices = vec![
+            device("192.168.1.20", "a4:83:e7:12:34:56", &[22, 80, 443]),
+            device("10.20.3.4", "bb:bb:bb:bb:bb:bb", &[22]),
+        ];
+        let restricted = restrict_devices(devices, &["192.168.1.10/24".to_string()], &[22, 443]);
+        assert_eq!(restricted.len(), 1);
+        assert_eq!(restricted[0].open_ports, vec![22, 443]);
+    }
+
+    #[test]
+    fn history_keeps_scans_in_order() {
+        let dir = std::env::temp_dir().join(format!("lanscans-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+        assert!(history_files(dir).unwrap().is_empty());
+
+        let devices = vec![device("192.168.1.1", "00:11:22:33:44:55", &[53])];
+        let first = save_to_history(dir, &scan_document("a", &devices), 900).unwrap();
+        let second = save_to_history(dir, &scan_document("b", &[]), 1_000).unwrap();
+        assert_eq!(history_files(dir).unwrap(), vec![first.clone(), second]);
+        assert_eq!(load_scan(&first).unwrap(), devices);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
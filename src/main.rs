@@ -1,7 +1,25 @@
 mod background;
 mod base;
+mod blacklists;
+mod bom;
+mod capture;
 mod cli;
 mod daemon;
+mod dismissals;
+mod feeds;
+mod geoip;
+mod history;
+mod ioc;
+mod ipfix;
+mod labels;
+mod lanscan;
+mod run;
+mod sessions;
+mod signing;
+mod steps;
+mod suppressions;
+mod wait;
+mod whitelists;
 use anyhow::Result;
 use background::*;
 use base::*;
@@ -29,6 +47,8 @@ const ERROR_CODE_MISMATCH: i32 = 1;
 const ERROR_CODE_SERVER_ERROR: i32 = 2;
 const ERROR_CODE_PARAM: i32 = 3;
 const ERROR_CODE_TIMEOUT: i32 = 4;
+// Default bound of the lanscan command, gateway detection included
+const LANSCAN_TIMEOUT: u64 = 300;
 
 lazy_static! {
     pub static ref EDAMAME_TARGET: String =
@@ -103,6 +123,117 @@ fn parse_signature(s: &str) -> Result<String, String> {
     Ok(s.to_string())
 }
 
+// Duration in seconds from "90", "90s", "30m", "2h" or "7d"
+fn parse_duration(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (value, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        Some((i, 'd')) => (&s[..i], 86400),
+        _ => (s, 1),
+    };
+    value
+        .parse::<u64>()
+        .ok()
+        .and_then(|v| v.checked_mul(multiplier))
+        .ok_or_else(|| String::from("Invalid duration, expected e.g. 90, 90s, 30m, 2h or 7d"))
+}
+
+// Absolute expiry (Unix seconds) from a duration ("7d"), a date ("2026-12-31",
+// midnight UTC) or an RFC 3339 timestamp
+fn parse_expiry(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let now = dismissals::now_secs();
+    if let Ok(secs) = parse_duration(s) {
+        return match secs {
+            0 => Err(String::from("Expiry must be in the future")),
+            secs => Ok(now + secs),
+        };
+    }
+    let timestamp = if s.len() == 10 {
+        format!("{}T00:00:00Z", s)
+    } else {
+        s.to_string()
+    };
+    match ipfix::parse_rfc3339_millis(&timestamp) {
+        Some(millis) if millis / 1000 > now => Ok(millis / 1000),
+        Some(_) => Err(String::from("Expiry must be in the future")),
+        None => Err(String::from(
+            "Invalid expiry, expected a duration (e.g. 7d), a date (e.g. 2026-12-31) or an RFC 3339 timestamp",
+        )),
+    }
+}
+
+// Point in time (Unix milliseconds) from a duration ago ("2h"), a date
+// ("2026-05-18", midnight UTC) or an RFC 3339 timestamp
+fn parse_time(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Ok(secs) = parse_duration(s) {
+        return Ok(steps::now_millis().saturating_sub(secs.saturating_mul(1000)));
+    }
+    let timestamp = if s.len() == 10 {
+        format!("{}T00:00:00Z", s)
+    } else {
+        s.to_string()
+    };
+    ipfix::parse_rfc3339_millis(&timestamp).ok_or_else(|| {
+        String::from(
+            "Invalid time, expected a duration ago (e.g. 2h), a date (e.g. 2026-05-18) or an RFC 3339 timestamp",
+        )
+    })
+}
+
+// Port set from "22,80,443" or ranges such as "8000-8100"
+fn parse_port_set(s: &str) -> Result<Vec<u16>, String> {
+    let invalid = || format!("Invalid port set '{}', expected e.g. 22,80,8000-8100", s);
+    let mut ports = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => (part, part),
+        };
+        let (first, last) = match (first.parse::<u16>(), last.parse::<u16>()) {
+            (Ok(first), Ok(last)) if first > 0 && first <= last => (first, last),
+            _ => return Err(invalid()),
+        };
+        ports.extend(first..=last);
+    }
+    if ports.is_empty() {
+        return Err(invalid());
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+// Size in bytes, with an optional K, M or G suffix (powers of 1024)
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (value, multiplier) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|v| *v > 0)
+        .and_then(|v| v.checked_mul(multiplier))
+        .ok_or_else(|| String::from("Invalid size, expected e.g. 1048576, 512K, 100M or 1G"))
+}
+
+fn parse_cidr_arg(s: &str) -> Result<String, String> {
+    match whitelists::parse_cidr(s) {
+        Some(_) if s.contains('/') => Ok(s.trim().to_string()),
+        _ => Err(format!(
+            "Invalid CIDR '{}', expected e.g. 192.168.10.0/24",
+            s
+        )),
+    }
+}
+
 pub fn initialize_core(
     device_id: String,
     computing: bool,
@@ -332,6 +463,12 @@ fn run_base() {
 
     let verbose = verbose_level > 0;
 
+    // Bound every blocking wait of the command
+    wait::configure(
+        matches.get_one::<u64>("timeout").copied(),
+        matches.get_flag("progress-json"),
+    );
+
     match matches.subcommand() {
         ////////////////
         // Base commands
@@ -340,65 +477,198 @@ fn run_base() {
             // Initialize the core with computing enabled
             initialize_core("".to_string(), true, false, false, false, false, verbose);
             ensure_admin();
-            base_get_score(true);
-        }
-        Some(("lanscan", _)) => {
-            // Initialize the core with all options disabled
-            initialize_core("".to_string(), false, false, false, false, false, verbose);
-            ensure_admin();
-            // Initialize network
-            set_network(NetworkAPI {
-                interfaces: vec![],
-                scanned_interfaces: vec![],
-                is_ethernet: true,
-                is_wifi: false,
-                is_vpn: false,
-                is_tethering: false,
-                is_mobile: false,
-                wifi_bssid: "".to_string(),
-                wifi_ip: "".to_string(),
-                wifi_submask: "".to_string(),
-                wifi_gateway: "".to_string(),
-                wifi_broadcast: "".to_string(),
-                wifi_name: "".to_string(),
-                wifi_ipv6: "".to_string(),
-                // Must be in RFC3339 format, set to EPOCH
-                last_seen: "1970-01-01T00:00:00Z".to_string(),
-                last_name: "".to_string(),
-            });
+            wait::handle_interrupts();
+            exit_code = base_get_score(true);
+        }
+        Some(("lanscan", sub_matches)) => {
+            let format = sub_matches
+                .get_one::<String>("format")
+                .map_or("text", |v| v.as_str());
+            let output = sub_matches.get_one::<String>("output").cloned();
+            let cidrs: Vec<String> = sub_matches
+                .get_many::<String>("cidr")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default();
+            let interfaces: Vec<String> = sub_matches
+                .get_many::<String>("interface")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default();
+            let ports = sub_matches
+                .get_one::<Vec<u16>>("ports")
+                .cloned()
+                .unwrap_or_default();
+            wait::default_timeout(LANSCAN_TIMEOUT);
+            if format == "json" {
+                wait::configure(None, true);
+            }
+            // Check the requested scope against the local networks
+            let local = base_local_networks();
+            match lanscan::resolve_scope(&cidrs, &interfaces, &local) {
+                Ok(cidrs) => {
+                    // Initialize the core with all options disabled
+                    initialize_core("".to_string(), false, false, false, false, false, verbose);
+                    ensure_admin();
+                    // Initialize network, limited to the interfaces of the scope if any
+                    let scanned = lanscan::scan_interfaces(&cidrs, &local);
+                    set_network(NetworkAPI {
+                        interfaces: scanned.clone(),
+                        scanned_interfaces: scanned,
+                        is_ethernet: true,
+                        is_wifi: false,
+                        is_vpn: false,
+                        is_tethering: false,
+                        is_mobile: false,
+                        wifi_bssid: "".to_string(),
+                        wifi_ip: "".to_string(),
+                        wifi_submask: "".to_string(),
+                        wifi_gateway: "".to_string(),
+                        wifi_broadcast: "".to_string(),
+                        wifi_name: "".to_string(),
+                        wifi_ipv6: "".to_string(),
+                        // Must be in RFC3339 format, set to EPOCH
+                        last_seen: "1970-01-01T00:00:00Z".to_string(),
+                        last_name: "".to_string(),
+                    });
 
-            // Grant consent
-            grant_consent();
+                    // Grant consent
+                    grant_consent();
+                    wait::handle_interrupts();
 
-            // Wait for the gateway detection to complete
-            let mut last_gateway_scan = get_last_gateway_scan();
-            while last_gateway_scan == "" {
-                println!("Waiting for gateway detection to complete...");
-                sleep(Duration::from_secs(20));
-                last_gateway_scan = get_last_gateway_scan();
-            }
-            println!("Gateway detection complete");
+                    // Wait for the gateway detection to complete
+                    eprintln!("Waiting for gateway detection to complete...");
+                    match base_wait_gateway(Duration::from_secs(GATEWAY_TIMEOUT)) {
+                        Ok(()) => {
+                            eprintln!("Gateway detection complete");
 
-            // Request a LAN scan
-            _ = get_lanscan(true, false, false);
+                            // Request a LAN scan
+                            _ = get_lanscan(true, false, false);
 
-            // Wait for the LAN scan to complete
-            base_lanscan();
+                            // Wait for the LAN scan to complete
+                            exit_code = base_lanscan(&lanscan::LanscanOptions {
+                                format: format.to_string(),
+                                output,
+                                // Scoped scans would show up as disappeared devices in diffs
+                                persist: cidrs.is_empty(),
+                                cidrs,
+                                ports,
+                            });
+                        }
+                        Err(interrupted) => {
+                            exit_code = wait::report("Gateway detection", interrupted);
+                            if interrupted == wait::Interrupted::TimedOut {
+                                eprintln!("No gateway detected: check that this host has a default route on the network to scan");
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit_code = ERROR_CODE_PARAM;
+                }
+            }
+        }
+        Some(("lanscan-diff", sub_matches)) => {
+            exit_code = lanscan::lanscan_diff(
+                sub_matches.get_one::<String>("OLD").map(|s| s.as_str()),
+                sub_matches.get_one::<String>("NEW").map(|s| s.as_str()),
+                sub_matches.get_flag("json"),
+                sub_matches.get_flag("fail-on-change"),
+            );
         }
         Some(("capture", sub_matches)) => {
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, true, verbose);
             ensure_admin();
 
-            let seconds = sub_matches.get_one::<u64>("SECONDS").unwrap_or(&900);
+            let stop_on_blacklisted = sub_matches.get_flag("stop-on-blacklisted");
+            let stop_on_nonconforming = sub_matches.get_flag("stop-on-nonconforming");
+            let max_sessions = sub_matches.get_one::<usize>("max-sessions").copied();
+            let until_pid = sub_matches.get_one::<u32>("until-pid").copied();
+            // Without a duration, a stop condition alone ends the capture
+            let stop_condition = stop_on_blacklisted
+                || stop_on_nonconforming
+                || max_sessions.is_some()
+                || until_pid.is_some();
+            let seconds = match sub_matches.get_one::<u64>("SECONDS") {
+                Some(seconds) => Some(*seconds),
+                None if stop_condition => None,
+                None => Some(900),
+            };
             let whitelist_name = sub_matches
                 .get_one::<String>("WHITELIST_NAME")
                 .map_or("", |v| v);
-            let zeek_format = sub_matches.get_one::<bool>("ZEEK_FORMAT").unwrap_or(&false);
+            let zeek_format = *sub_matches.get_one::<bool>("ZEEK_FORMAT").unwrap_or(&false)
+                || sub_matches
+                    .get_one::<String>("format")
+                    .is_some_and(|format| format == "zeek");
             let local_traffic = sub_matches
                 .get_one::<bool>("LOCAL_TRAFFIC")
                 .unwrap_or(&false);
-            base_capture(*seconds, whitelist_name, *zeek_format, *local_traffic);
+            wait::handle_interrupts();
+            exit_code = base_capture(&capture::CaptureOptions {
+                seconds,
+                whitelist_name: whitelist_name.to_string(),
+                zeek_format,
+                local_traffic: *local_traffic,
+                output: sub_matches.get_one::<String>("output").cloned(),
+                rotate_size: sub_matches.get_one::<u64>("rotate-size").copied(),
+                rotate_interval: sub_matches.get_one::<u64>("rotate-interval").copied(),
+                stop_on_blacklisted,
+                stop_on_nonconforming,
+                max_sessions,
+                until_pid,
+                generate_whitelist: sub_matches.get_one::<String>("generate-whitelist").cloned(),
+                with_process: sub_matches.get_flag("with-process"),
+                merge_into: sub_matches.get_one::<String>("merge-into").cloned(),
+            });
+        }
+        Some(("run", sub_matches)) => {
+            let options = run::RunOptions {
+                command: sub_matches
+                    .get_many::<String>("COMMAND")
+                    .map(|values| values.cloned().collect())
+                    .unwrap_or_default(),
+                whitelist_name: sub_matches
+                    .get_one::<String>("whitelist")
+                    .cloned()
+                    .unwrap_or_default(),
+                fail_on_whitelist: sub_matches.get_flag("fail-on-whitelist"),
+                fail_on_blacklist: sub_matches.get_flag("fail-on-blacklist"),
+                fail_on_anomalous: sub_matches.get_flag("fail-on-anomalous"),
+                fail_on_findings: sub_matches.get_flag("fail-on-findings"),
+                local_traffic: sub_matches.get_flag("include-local-traffic"),
+                daemon: sub_matches.get_flag("daemon"),
+                report: sub_matches.get_one::<String>("report").cloned(),
+            };
+            wait::handle_interrupts();
+            if options.daemon {
+                // Initialize the core with all options disabled
+                initialize_core("".to_string(), false, false, false, false, false, verbose);
+                is_background = true;
+                exit_code = background_run(&options);
+            } else {
+                // Initialize the core with capture enabled
+                initialize_core("".to_string(), false, false, false, false, true, verbose);
+                ensure_admin();
+                exit_code = base_run(&options);
+            }
+        }
+        Some(("background-mark-step", sub_matches)) => {
+            // The CLI owns the step registry, which lives with the daemon state
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            ensure_admin();
+            exit_code = steps::mark_step(
+                sub_matches.get_one::<String>("NAME").map(String::as_str),
+                sub_matches.get_flag("end"),
+            );
+        }
+        Some(("background-step-report", sub_matches)) => {
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            exit_code = background_step_report(
+                sub_matches.get_flag("include-local-traffic"),
+                sub_matches.get_flag("json"),
+            );
+            is_background = true;
         }
         Some(("get-core-info", _)) => {
             // Initialize the core with all options disabled
@@ -433,13 +703,15 @@ fn run_base() {
             // Initialize the core with computing enabled
             initialize_core("".to_string(), true, false, false, false, false, verbose);
             ensure_admin();
-            base_remediate(&remediations_to_skip)
+            wait::handle_interrupts();
+            exit_code = base_remediate(&remediations_to_skip);
         }
         Some(("remediate-all-threats-force", _)) => {
             // Initialize the core with computing enabled
             initialize_core("".to_string(), true, false, false, false, false, verbose);
             ensure_admin();
-            base_remediate("");
+            wait::handle_interrupts();
+            exit_code = base_remediate("");
         }
         Some(("remediate-threat", sub_matches)) => {
             let threat_id = sub_matches
@@ -458,7 +730,11 @@ fn run_base() {
                 .to_string();
             initialize_core("".to_string(), true, false, false, false, true, verbose);
             ensure_admin();
-            exit_code = base_dismiss_device(ip_address);
+            exit_code = base_dismiss_device(
+                ip_address,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
         }
         Some(("dismiss-device-port", sub_matches)) => {
             let ip_address = sub_matches
@@ -470,7 +746,12 @@ fn run_base() {
                 .expect("PORT not provided");
             initialize_core("".to_string(), true, false, false, false, true, verbose);
             ensure_admin();
-            exit_code = base_dismiss_device_port(ip_address, port);
+            exit_code = base_dismiss_device_port(
+                ip_address,
+                port,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
         }
         Some(("dismiss-session", sub_matches)) => {
             let session_uid = sub_matches
@@ -479,7 +760,11 @@ fn run_base() {
                 .to_string();
             initialize_core("".to_string(), true, false, false, false, true, verbose);
             ensure_admin();
-            exit_code = base_dismiss_session(session_uid);
+            exit_code = base_dismiss_session(
+                session_uid,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
         }
         Some(("dismiss-session-process", sub_matches)) => {
             let session_uid = sub_matches
@@ -488,7 +773,49 @@ fn run_base() {
                 .to_string();
             initialize_core("".to_string(), true, false, false, false, true, verbose);
             ensure_admin();
-            exit_code = base_dismiss_session_process(session_uid);
+            exit_code = base_dismiss_session_process(
+                session_uid,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
+        }
+        Some(("network-dismissals", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", list_matches)) => {
+                exit_code = dismissals::list_rules(list_matches.get_flag("json"));
+            }
+            Some(("remove", remove_matches)) => {
+                let rule_id = remove_matches
+                    .get_one::<String>("RULE_ID")
+                    .expect("RULE_ID not provided");
+                initialize_core("".to_string(), true, false, false, false, true, verbose);
+                ensure_admin();
+                exit_code = dismissals::remove_rule(rule_id, &base_undismiss);
+            }
+            Some(("export", export_matches)) => {
+                let file = export_matches
+                    .get_one::<String>("FILE")
+                    .expect("FILE not provided");
+                exit_code = dismissals::export_rules(file);
+            }
+            Some(("import", import_matches)) => {
+                let file = import_matches
+                    .get_one::<String>("FILE")
+                    .expect("FILE not provided");
+                initialize_core("".to_string(), true, false, false, false, true, verbose);
+                ensure_admin();
+                exit_code = base_import_dismiss_rules(file);
+            }
+            _ => unreachable!("network-dismissals requires a subcommand"),
+        },
+        Some(("expiring-suppressions", sub_matches)) => {
+            let within = *sub_matches.get_one::<u64>("within").unwrap_or(&604_800);
+            exit_code = dismissals::expiring_suppressions(within, sub_matches.get_flag("json"));
+        }
+        Some(("check-suppressions", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").cloned();
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            exit_code = background_check_suppressions(file, sub_matches.get_flag("json"));
+            is_background = true;
         }
         Some(("check-policy-for-domain", sub_matches)) => {
             let domain = sub_matches.get_one::<String>("DOMAIN").unwrap().to_string();
@@ -529,6 +856,7 @@ fn run_base() {
             // Initialize the core with computing enabled
             initialize_core("".to_string(), true, false, false, false, false, verbose);
             ensure_admin();
+            wait::handle_interrupts();
             exit_code = base_check_policy(minimum_score, threat_ids, tag_prefixes);
         }
         Some(("get-tag-prefixes", _)) => {
@@ -543,45 +871,61 @@ fn run_base() {
             exit_code = background_augment_custom_whitelists();
             is_background = true;
         }
-        Some(("merge-custom-whitelists", sub_matches)) => {
-            let wl1 = sub_matches
-                .get_one::<String>("WHITELIST_JSON_1")
-                .expect("WHITELIST_JSON_1 not provided")
-                .to_string();
-            let wl2 = sub_matches
-                .get_one::<String>("WHITELIST_JSON_2")
-                .expect("WHITELIST_JSON_2 not provided")
+        Some(("learn-whitelist", sub_matches)) => {
+            let out_file = sub_matches
+                .get_one::<String>("out")
+                .expect("out not provided")
                 .to_string();
+            let report_file = sub_matches
+                .get_one::<String>("report")
+                .cloned()
+                .unwrap_or_else(|| format!("{}.report.json", out_file));
+            let converge_below = *sub_matches.get_one::<f64>("converge-below").unwrap_or(&1.0);
+            let stable_rounds = *sub_matches.get_one::<u32>("stable-rounds").unwrap_or(&3);
+            let interval = *sub_matches.get_one::<u64>("interval").unwrap_or(&60);
+            let max_duration = *sub_matches.get_one::<u64>("max-duration").unwrap_or(&7200);
+            let apply = sub_matches.get_flag("apply");
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            ensure_admin();
+            wait::handle_interrupts();
+            exit_code = background_learn_whitelist(
+                &out_file,
+                &report_file,
+                converge_below,
+                stable_rounds,
+                interval,
+                max_duration,
+                apply,
+            );
+            is_background = true;
+        }
+        Some(("merge-custom-whitelists", sub_matches)) => {
+            let wl_jsons: Vec<String> = sub_matches
+                .get_many::<String>("WHITELIST_JSONS")
+                .expect("WHITELIST_JSONS not provided")
+                .cloned()
+                .collect();
             initialize_core("".to_string(), false, false, false, false, false, verbose);
             ensure_admin();
-            exit_code = base_merge_custom_whitelists(wl1, wl2);
+            exit_code = base_merge_custom_whitelists(
+                wl_jsons,
+                sub_matches.get_flag("annotate-sources"),
+                sub_matches.get_flag("fail-on-conflict"),
+            );
         }
         Some(("merge-custom-whitelists-from-files", sub_matches)) => {
-            let wl_file1 = sub_matches
-                .get_one::<String>("WHITELIST_FILE_1")
-                .expect("WHITELIST_FILE_1 not provided");
-            let wl_file2 = sub_matches
-                .get_one::<String>("WHITELIST_FILE_2")
-                .expect("WHITELIST_FILE_2 not provided");
-
-            match (
-                std::fs::read_to_string(wl_file1),
-                std::fs::read_to_string(wl_file2),
-            ) {
-                (Ok(wl1), Ok(wl2)) => {
-                    initialize_core("".to_string(), false, false, false, false, false, verbose);
-                    ensure_admin();
-                    exit_code = base_merge_custom_whitelists(wl1, wl2);
-                }
-                (Err(e), _) => {
-                    eprintln!("Error reading whitelist file '{}': {}", wl_file1, e);
-                    exit_code = ERROR_CODE_PARAM;
-                }
-                (_, Err(e)) => {
-                    eprintln!("Error reading whitelist file '{}': {}", wl_file2, e);
-                    exit_code = ERROR_CODE_PARAM;
-                }
-            }
+            let wl_files: Vec<String> = sub_matches
+                .get_many::<String>("WHITELIST_FILES")
+                .expect("WHITELIST_FILES not provided")
+                .cloned()
+                .collect();
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            ensure_admin();
+            exit_code = base_merge_custom_whitelists_files(
+                wl_files,
+                sub_matches.get_flag("annotate-sources"),
+                sub_matches.get_flag("fail-on-conflict"),
+            );
         }
         Some(("compare-custom-whitelists", sub_matches)) => {
             let wl1 = sub_matches
@@ -592,9 +936,22 @@ fn run_base() {
                 .get_one::<String>("WHITELIST_JSON_2")
                 .expect("WHITELIST_JSON_2 not provided")
                 .to_string();
-            initialize_core("".to_string(), false, false, false, false, false, verbose);
-            // No admin required for comparison
-            exit_code = base_compare_custom_whitelists(wl1, wl2);
+            let json_output = sub_matches.get_flag("json");
+            let fail_on_diff = sub_matches.get_flag("fail-on-diff");
+            if sub_matches.get_flag("diff") || json_output || fail_on_diff {
+                exit_code = whitelists::diff_custom_whitelists(
+                    &wl1,
+                    &wl2,
+                    "WHITELIST_JSON_1",
+                    "WHITELIST_JSON_2",
+                    json_output,
+                    fail_on_diff,
+                );
+            } else {
+                initialize_core("".to_string(), false, false, false, false, false, verbose);
+                // No admin required for comparison
+                exit_code = base_compare_custom_whitelists(wl1, wl2);
+            }
         }
         Some(("compare-custom-whitelists-from-files", sub_matches)) => {
             let wl_file1 = sub_matches
@@ -609,9 +966,22 @@ fn run_base() {
                 std::fs::read_to_string(wl_file2),
             ) {
                 (Ok(wl1), Ok(wl2)) => {
-                    initialize_core("".to_string(), false, false, false, false, false, verbose);
-                    // No admin required for comparison
-                    exit_code = base_compare_custom_whitelists(wl1, wl2);
+                    let json_output = sub_matches.get_flag("json");
+                    let fail_on_diff = sub_matches.get_flag("fail-on-diff");
+                    if sub_matches.get_flag("diff") || json_output || fail_on_diff {
+                        exit_code = whitelists::diff_custom_whitelists(
+                            &wl1,
+                            &wl2,
+                            wl_file1,
+                            wl_file2,
+                            json_output,
+                            fail_on_diff,
+                        );
+                    } else {
+                        initialize_core("".to_string(), false, false, false, false, false, verbose);
+                        // No admin required for comparison
+                        exit_code = base_compare_custom_whitelists(wl1, wl2);
+                    }
                 }
                 (Err(e), _) => {
                     eprintln!("Error reading whitelist file '{}': {}", wl_file1, e);
@@ -623,6 +993,49 @@ fn run_base() {
                 }
             }
         }
+        Some(("generate-list-key", sub_matches)) => {
+            let key_file = sub_matches
+                .get_one::<String>("PRIVATE_KEY_FILE")
+                .expect("PRIVATE_KEY_FILE not provided");
+            exit_code = signing::generate_list_key(key_file);
+        }
+        Some(("sign-list", sub_matches)) => {
+            let list_file = sub_matches
+                .get_one::<String>("LIST_FILE")
+                .expect("LIST_FILE not provided");
+            let key_file = sub_matches
+                .get_one::<String>("key")
+                .expect("key not provided");
+            exit_code = signing::sign_list_file(
+                list_file,
+                key_file,
+                sub_matches.get_one::<String>("output").map(String::as_str),
+            );
+        }
+        Some(("verify-list", sub_matches)) => {
+            let list_file = sub_matches
+                .get_one::<String>("LIST_FILE")
+                .expect("LIST_FILE not provided");
+            let trusted_keys: Vec<String> = sub_matches
+                .get_many::<String>("trusted-key")
+                .map(|keys| keys.cloned().collect())
+                .unwrap_or_default();
+            exit_code = signing::verify_list_file(
+                list_file,
+                sub_matches
+                    .get_one::<String>("signature")
+                    .map(String::as_str),
+                &trusted_keys,
+            );
+        }
+        Some(("lint-custom-whitelists", sub_matches)) => {
+            let wl_file = sub_matches
+                .get_one::<String>("WHITELIST_FILE")
+                .expect("WHITELIST_FILE not provided");
+            // Local file check, no core needed
+            exit_code =
+                whitelists::lint_custom_whitelists_file(wl_file, sub_matches.get_flag("strict"));
+        }
         Some(("rollback-threat", sub_matches)) => {
             let threat_id = sub_matches
                 .get_one::<String>("THREAT_ID")
@@ -657,10 +1070,13 @@ fn run_base() {
             // Initialize the core with computing enabled
             initialize_core("".to_string(), true, false, false, false, false, verbose);
             ensure_admin();
+            wait::handle_interrupts();
             // Display the score
-            base_get_score(true);
-            // Request the signature
-            exit_code = base_request_signature();
+            exit_code = base_get_score(true);
+            if exit_code == 0 {
+                // Request the signature
+                exit_code = base_request_signature();
+            }
         }
         Some(("request-report", sub_matches)) => {
             let email = sub_matches.get_one::<String>("EMAIL").unwrap().to_string();
@@ -705,6 +1121,7 @@ fn run_base() {
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
             is_background = true;
+            wait::handle_interrupts();
             exit_code = background_wait_for_connection(*timeout);
         }
         Some(("background-get-sessions", sub_matches)) => {
@@ -722,9 +1139,106 @@ fn run_base() {
                 fail_on_anomalous,
                 fail_on_blacklist,
                 fail_on_whitelist,
+                sub_matches.get_one::<String>("step").map(String::as_str),
+                &sessions::SessionFilter {
+                    countries: sub_matches
+                        .get_many::<String>("country")
+                        .map(|countries| countries.cloned().collect())
+                        .unwrap_or_default(),
+                    asns: sub_matches
+                        .get_many::<u32>("asn")
+                        .map(|asns| asns.copied().collect())
+                        .unwrap_or_default(),
+                    labels: sub_matches
+                        .get_many::<String>("label")
+                        .map(|labels| labels.cloned().collect())
+                        .unwrap_or_default(),
+                    summary: sub_matches
+                        .get_one::<String>("summary")
+                        .and_then(|group| geoip::GeoGroup::from_name(group)),
+                },
+            );
+            is_background = true;
+        }
+        Some(("background-sessions-snapshot", sub_matches)) => {
+            if let Some(("save", save_matches)) = sub_matches.subcommand() {
+                let file = save_matches
+                    .get_one::<String>("FILE")
+                    .expect("FILE not provided");
+                let local_traffic = save_matches.get_flag("include-local-traffic");
+
+                // Initialize the core with all options disabled
+                initialize_core("".to_string(), false, false, false, false, false, verbose);
+                exit_code = background_sessions_snapshot_save(file, local_traffic);
+                is_background = true;
+            }
+        }
+        Some(("history-query", sub_matches)) => {
+            let query = history::HistoryQuery {
+                kinds: sub_matches
+                    .get_many::<String>("kind")
+                    .map(|kinds| {
+                        kinds
+                            .filter_map(|kind| history::RecordKind::from_name(kind))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                since: sub_matches.get_one::<u64>("since").copied(),
+                until: sub_matches.get_one::<u64>("until").copied(),
+                fields: sub_matches
+                    .get_many::<(String, String)>("where")
+                    .map(|fields| fields.cloned().collect())
+                    .unwrap_or_default(),
+                limit: sub_matches.get_one::<usize>("limit").copied(),
+            };
+            let dir = sub_matches
+                .get_one::<String>("dir")
+                .cloned()
+                .unwrap_or_else(history::history_dir);
+            // Reads the store files only, no core needed
+            exit_code = history::history_query(&dir, &query, sub_matches.get_flag("json"));
+        }
+        Some(("background-ioc-sweep", sub_matches)) => {
+            let file = sub_matches
+                .get_one::<String>("FILE")
+                .expect("FILE not provided");
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            exit_code = background_ioc_sweep(
+                file,
+                sub_matches.get_one::<String>("dir").map(String::as_str),
+                sub_matches.get_flag("json"),
             );
             is_background = true;
         }
+        Some(("background-egress-bom", sub_matches)) => {
+            let format = sub_matches
+                .get_one::<String>("format")
+                .and_then(|format| bom::BomFormat::from_name(format))
+                .unwrap_or(bom::BomFormat::Json);
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            exit_code = background_egress_bom(
+                format,
+                sub_matches.get_one::<String>("output").map(String::as_str),
+                sub_matches.get_flag("include-local-traffic"),
+                sub_matches.get_one::<String>("step").map(String::as_str),
+            );
+            is_background = true;
+        }
+        Some(("sessions-diff", sub_matches)) => {
+            let old_file = sub_matches
+                .get_one::<String>("OLD_FILE")
+                .expect("OLD_FILE not provided");
+            let new_file = sub_matches
+                .get_one::<String>("NEW_FILE")
+                .expect("NEW_FILE not provided");
+            // Works on snapshot files only, no core needed
+            exit_code = sessions::sessions_diff_files(
+                old_file,
+                new_file,
+                sub_matches.get_flag("json"),
+                sub_matches.get_flag("fail-on-new-egress"),
+            );
+        }
         Some(("background-get-exceptions", sub_matches)) => {
             let zeek_format = sub_matches.get_one::<bool>("ZEEK_FORMAT").unwrap_or(&false);
             let local_traffic = sub_matches
@@ -736,6 +1250,18 @@ fn run_base() {
             exit_code = background_get_exceptions(*zeek_format, *local_traffic);
             is_background = true;
         }
+        Some(("explain-session", sub_matches)) => {
+            let session_uid = sub_matches
+                .get_one::<String>("SESSION_UID")
+                .expect("SESSION_UID not provided");
+            let limit = *sub_matches.get_one::<usize>("limit").unwrap_or(&5);
+
+            // Initialize the core with all options disabled
+            initialize_core("".to_string(), false, false, false, false, false, verbose);
+            exit_code =
+                background_explain_session(session_uid, limit, sub_matches.get_flag("json"));
+            is_background = true;
+        }
         Some(("background-get-anomalous-sessions", sub_matches)) => {
             let zeek_format = sub_matches.get_one::<bool>("ZEEK_FORMAT").unwrap_or(&false);
 
@@ -807,6 +1333,18 @@ fn run_base() {
             if sub_matches.get_flag("export_ai_failure_details") {
                 std::env::set_var("EDAMAME_EXPORT_AI_FAILURE_DETAILS", "force");
             }
+            if let Some(webhook) = sub_matches.get_one::<String>("lanscan_webhook") {
+                std::env::set_var(lanscan::WEBHOOK_ENV, webhook);
+            }
+            // Validated here, the daemon finds the file through the environment
+            let suppressions_code = suppressions::prepare_for_daemon(
+                sub_matches
+                    .get_one::<String>("suppressions")
+                    .map(|s| s.as_str()),
+            );
+            if suppressions_code != 0 {
+                std::process::exit(suppressions_code);
+            }
 
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
@@ -864,6 +1402,18 @@ fn run_base() {
             if sub_matches.get_flag("export_ai_failure_details") {
                 std::env::set_var("EDAMAME_EXPORT_AI_FAILURE_DETAILS", "force");
             }
+            if let Some(webhook) = sub_matches.get_one::<String>("lanscan_webhook") {
+                std::env::set_var(lanscan::WEBHOOK_ENV, webhook);
+            }
+            // Validated here, the daemon finds the file through the environment
+            let suppressions_code = suppressions::prepare_for_daemon(
+                sub_matches
+                    .get_one::<String>("suppressions")
+                    .map(|s| s.as_str()),
+            );
+            if suppressions_code != 0 {
+                std::process::exit(suppressions_code);
+            }
 
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
@@ -937,6 +1487,18 @@ fn run_base() {
             if sub_matches.get_flag("export_ai_failure_details") {
                 std::env::set_var("EDAMAME_EXPORT_AI_FAILURE_DETAILS", "force");
             }
+            if let Some(webhook) = sub_matches.get_one::<String>("lanscan_webhook") {
+                std::env::set_var(lanscan::WEBHOOK_ENV, webhook);
+            }
+            // Validated here, the daemon finds the file through the environment
+            let suppressions_code = suppressions::prepare_for_daemon(
+                sub_matches
+                    .get_one::<String>("suppressions")
+                    .map(|s| s.as_str()),
+            );
+            if suppressions_code != 0 {
+                std::process::exit(suppressions_code);
+            }
 
             // Directly call the background process
             run_background(
@@ -984,18 +1546,23 @@ fn run_base() {
                 .to_string();
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_set_custom_whitelists(whitelist_json);
+            exit_code = background_set_custom_whitelists(whitelist_json, None);
             is_background = true;
         }
         Some(("background-set-custom-whitelists-from-file", sub_matches)) => {
             let whitelist_file = sub_matches
                 .get_one::<String>("WHITELIST_FILE")
                 .expect("WHITELIST_FILE not provided");
+            let signature_file = sub_matches
+                .get_one::<String>("signature")
+                .cloned()
+                .unwrap_or_else(|| format!("{}.sig", whitelist_file));
             match std::fs::read_to_string(whitelist_file) {
                 Ok(whitelist_json) => {
                     // Initialize the core with all options disabled
                     initialize_core("".to_string(), false, false, false, false, false, verbose);
-                    exit_code = background_set_custom_whitelists(whitelist_json);
+                    exit_code =
+                        background_set_custom_whitelists(whitelist_json, Some(&signature_file));
                     is_background = true;
                 }
                 Err(e) => {
@@ -1011,18 +1578,23 @@ fn run_base() {
                 .to_string();
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_set_custom_blacklists(blacklist_json);
+            exit_code = background_set_custom_blacklists(blacklist_json, None);
             is_background = true;
         }
         Some(("background-set-custom-blacklists-from-file", sub_matches)) => {
             let blacklist_file = sub_matches
                 .get_one::<String>("BLACKLIST_FILE")
                 .expect("BLACKLIST_FILE not provided");
+            let signature_file = sub_matches
+                .get_one::<String>("signature")
+                .cloned()
+                .unwrap_or_else(|| format!("{}.sig", blacklist_file));
             match std::fs::read_to_string(blacklist_file) {
                 Ok(blacklist_json) => {
                     // Initialize the core with all options disabled
                     initialize_core("".to_string(), false, false, false, false, false, verbose);
-                    exit_code = background_set_custom_blacklists(blacklist_json);
+                    exit_code =
+                        background_set_custom_blacklists(blacklist_json, Some(&signature_file));
                     is_background = true;
                 }
                 Err(e) => {
@@ -1054,12 +1626,40 @@ fn run_base() {
             exit_code = background_get_score();
             is_background = true;
         }
+        Some(("import-blacklist", sub_matches)) => {
+            let string_arg = |id: &str| sub_matches.get_one::<String>(id).cloned();
+            let options = blacklists::BlacklistImport {
+                file: string_arg("FEED_FILE").expect("FEED_FILE not provided"),
+                name: string_arg("name"),
+                format: string_arg("format").and_then(|f| blacklists::IntelFormat::from_name(&f)),
+                csv_column: string_arg("column"),
+                description: string_arg("description"),
+                source_url: string_arg("source-url"),
+                resolve_domains: sub_matches.get_flag("resolve-domains"),
+            };
+            let merge = sub_matches.get_flag("merge");
+            if merge {
+                initialize_core("".to_string(), false, false, false, false, false, verbose);
+                is_background = true;
+            }
+            exit_code = background_import_blacklist(
+                &options,
+                sub_matches.get_one::<String>("output").map(String::as_str),
+                merge,
+            );
+        }
         Some(("background-get-blacklists", _)) => {
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
             exit_code = background_get_blacklists();
             is_background = true;
         }
+        Some(("blacklist-feeds", sub_matches)) => match sub_matches.subcommand() {
+            Some(("status", status_matches)) => {
+                exit_code = feeds::print_status(status_matches.get_flag("json"));
+            }
+            _ => unreachable!("blacklist-feeds requires a subcommand"),
+        },
         Some(("background-get-whitelists", _)) => {
             // Initialize the core with all options disabled
             initialize_core("".to_string(), false, false, false, false, false, verbose);
@@ -1192,7 +1792,11 @@ fn run_base() {
                 .expect("FINDING_KEY not provided")
                 .to_string();
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_divergence_dismiss(finding_key);
+            exit_code = background_divergence_dismiss(
+                finding_key,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
             is_background = true;
         }
         Some(("background-divergence-undismiss", sub_matches)) => {
@@ -1229,7 +1833,10 @@ fn run_base() {
         Some(("background-vulnerability-findings", sub_matches)) => {
             let active_only = sub_matches.get_flag("active-only");
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_vulnerability_findings(active_only);
+            exit_code = background_vulnerability_findings(
+                active_only,
+                sub_matches.get_one::<String>("step").map(String::as_str),
+            );
             is_background = true;
         }
         Some(("background-vulnerability-dismiss", sub_matches)) => {
@@ -1238,7 +1845,11 @@ fn run_base() {
                 .expect("FINDING_KEY not provided")
                 .to_string();
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_vulnerability_dismiss(finding_key);
+            exit_code = background_vulnerability_dismiss(
+                finding_key,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
             is_background = true;
         }
         Some(("background-vulnerability-undismiss", sub_matches)) => {
@@ -1272,7 +1883,11 @@ fn run_base() {
                 .expect("REQUEST_JSON not provided")
                 .to_string();
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_agentic_dismiss_with_scope(request_json);
+            exit_code = background_agentic_dismiss_with_scope(
+                request_json,
+                sub_matches.get_one::<u64>("expires").copied(),
+                sub_matches.get_one::<String>("reason").cloned(),
+            );
             is_background = true;
         }
         Some(("background-agentic-list-dismissal-rules", sub_matches)) => {
@@ -1594,7 +2209,10 @@ fn run_base() {
         Some(("background-get-file-events", sub_matches)) => {
             let fail_on_suspicious = sub_matches.get_flag("fail-on-suspicious");
             initialize_core("".to_string(), false, false, false, false, false, verbose);
-            exit_code = background_get_file_events(fail_on_suspicious);
+            exit_code = background_get_file_events(
+                fail_on_suspicious,
+                sub_matches.get_one::<String>("step").map(String::as_str),
+            );
             is_background = true;
         }
         Some(("background-clear-file-events", _)) => {
@@ -0,0 +1,664 @@
+// `run -- <command>`: run a command under capture and report its egress.
+//
+// The command's process tree is followed by polling the process table, and
+// sessions and file events are attributed to the tree through their PID.
+// Processes that start and exit between two polls are not seen, so their
+// sessions and file events stay unattributed. A PID of the tree taken over by
+// another process only attributes what happened before the takeover.
+
+use crate::labels::session_labels;
+use crate::sessions::{session_destination, session_process, session_str, session_u64};
+use crate::steps::{item_time_ms, FILE_EVENT_TIME, SESSION_TIME};
+use crate::wait;
+use crate::{ERROR_CODE_MISMATCH, ERROR_CODE_PARAM, ERROR_CODE_SERVER_ERROR};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+use sysinfo::{ProcessesToUpdate, System};
+
+/// Delay between two polls of the child and of the process table.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub command: Vec<String>,
+    pub whitelist_name: String,
+    pub fail_on_whitelist: bool,
+    pub fail_on_blacklist: bool,
+    pub fail_on_anomalous: bool,
+    pub fail_on_findings: bool,
+    pub local_traffic: bool,
+    // Use the running background process instead of an in-process capture
+    pub daemon: bool,
+    pub report: Option<String>,
+}
+
+/// Uids of the sessions that fail the enabled checks, as evaluated by the
+/// policy checks of the daemon, and the number of active vulnerability
+/// findings when they are checked.
+#[derive(Debug, Default)]
+pub struct Violations {
+    pub non_conforming: HashSet<String>,
+    pub blacklisted: HashSet<String>,
+    pub anomalous: HashSet<String>,
+    pub vulnerability_findings: u64,
+}
+
+/// A process of the process table.
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    /// Unix seconds, which tell a PID reuse apart
+    pub start_time: u64,
+}
+
+/// PIDs and names of a process and of every descendant seen so far.
+#[derive(Debug)]
+pub struct ProcessTree {
+    pub root: u32,
+    pub processes: BTreeMap<u32, String>,
+    started: BTreeMap<u32, u64>,
+    // PIDs of the tree taken over by another process, with its start time
+    reused: BTreeMap<u32, u64>,
+}
+
+impl ProcessTree {
+    /// `name` stands for the root until the process table lists it.
+    pub fn new(root: u32, name: &str) -> Self {
+        ProcessTree {
+            root,
+            processes: BTreeMap::from([(root, name.to_string())]),
+            started: BTreeMap::new(),
+            reused: BTreeMap::new(),
+        }
+    }
+
+    fn follows(&self, pid: u32) -> bool {
+        self.processes.contains_key(&pid) && !self.reused.contains_key(&pid)
+    }
+
+    /// Add the descendants found in a snapshot of the process table. Names of
+    /// the processes of the tree follow the snapshot, so that a process that
+    /// executes another program is reported under the new one.
+    pub fn update(&mut self, snapshot: &[ProcessInfo]) {
+        for process in snapshot {
+            let Some(name) = self.processes.get_mut(&process.pid) else {
+                continue;
+            };
+            match self.started.get(&process.pid) {
+                Some(started) if *started != process.start_time => {
+                    self.reused.entry(process.pid).or_insert(process.start_time);
+                }
+                _ => {
+                    name.clone_from(&process.name);
+                    self.started.insert(process.pid, process.start_time);
+                }
+            }
+        }
+        loop {
+            let mut added = false;
+            for process in snapshot {
+                let parent_in_tree = process.parent.is_some_and(|p| self.follows(p));
+                if parent_in_tree && !self.follows(process.pid) {
+                    // A reused PID can come back to the tree
+                    self.processes.insert(process.pid, process.name.clone());
+                    self.started.insert(process.pid, process.start_time);
+                    self.reused.remove(&process.pid);
+                    added = true;
+                }
+            }
+            if !added {
+                return;
+            }
+        }
+    }
+
+    /// Whether what `pid` did at `time_ms` belongs to the tree. Once the PID
+    /// was reused, only what is dated before the takeover does.
+    pub fn owns(&self, pid: u64, time_ms: Option<u64>) -> bool {
+        let Ok(pid) = u32::try_from(pid) else {
+            return false;
+        };
+        if !self.processes.contains_key(&pid) {
+            return false;
+        }
+        match self.reused.get(&pid) {
+            Some(reused_at) => time_ms.is_some_and(|ms| ms < reused_at * 1000),
+            None => true,
+        }
+    }
+}
+
+/// How the command ended.
+#[derive(Debug)]
+pub struct Tracked {
+    pub exit_code: i32,
+    pub duration: Duration,
+    pub tree: ProcessTree,
+}
+
+fn process_snapshot(system: &mut System) -> Vec<ProcessInfo> {
+    system.refresh_processes(ProcessesToUpdate::All, true);
+    system
+        .processes()
+        .iter()
+        .map(|(pid, process)| ProcessInfo {
+            pid: pid.as_u32(),
+            parent: process.parent().map(|p| p.as_u32()),
+            name: process.name().to_string_lossy().to_string(),
+            start_time: process.start_time(),
+        })
+        .collect()
+}
+
+fn status_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Spawn the command with inherited stdio and follow its process tree until
+/// it exits. The command is killed when the wait is interrupted. Errors are
+/// reported here and returned as exit codes.
+pub fn run_tracked(command: &[String]) -> Result<Tracked, i32> {
+    let Some((program, args)) = command.split_first() else {
+        eprintln!("No command to run");
+        return Err(ERROR_CODE_PARAM);
+    };
+    let started = Instant::now();
+    let mut child = match Command::new(program).args(args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Error running '{}': {}", program, e);
+            return Err(ERROR_CODE_PARAM);
+        }
+    };
+    let mut tree = ProcessTree::new(child.id(), program);
+    let mut system = System::new();
+    loop {
+        tree.update(&process_snapshot(&mut system));
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return Ok(Tracked {
+                    exit_code: status_code(status),
+                    duration: started.elapsed(),
+                    tree,
+                })
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error waiting for '{}': {}", program, e);
+                return Err(ERROR_CODE_SERVER_ERROR);
+            }
+        }
+        if let Err(interrupted) = wait::pause(POLL_INTERVAL) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(wait::report(program, interrupted));
+        }
+    }
+}
+
+/// Sessions whose L7 PID belongs to the process tree.
+pub fn attribute_sessions(sessions: Vec<Value>, tree: &ProcessTree) -> Vec<Value> {
+    sessions
+        .into_iter()
+        .filter(|session| {
+            session
+                .pointer("/l7/pid")
+                .and_then(|pid| pid.as_u64())
+                .is_some_and(|pid| tree.owns(pid, item_time_ms(session, SESSION_TIME)))
+        })
+        .collect()
+}
+
+/// Per-command egress report: destinations grouped by host, port, protocol
+/// and process, with the violations of the attributed sessions. Vulnerability
+/// findings are not attributed to processes and are reported as they are.
+pub fn egress_report(
+    options: &RunOptions,
+    tracked: &Tracked,
+    sessions: &[Value],
+    violations: &Violations,
+    file_events: Option<Vec<Value>>,
+) -> Value {
+    let mut destinations: BTreeMap<(String, u64, String, String), Value> = BTreeMap::new();
+    let (mut non_conforming, mut blacklisted, mut anomalous) = (0, 0, 0);
+    // Labels of the violating sessions, when they went through the label rules
+    let mut violating_labels: BTreeSet<&str> = BTreeSet::new();
+    for session in sessions {
+        let uid = session_str(session, "/uid").unwrap_or_default();
+        let whitelist_state = session_str(session, "/is_whitelisted").unwrap_or("Unknown");
+        let flags = [
+            violations.non_conforming.contains(uid),
+            violations.blacklisted.contains(uid),
+            violations.anomalous.contains(uid),
+        ];
+        non_conforming += usize::from(flags[0]);
+        blacklisted += usize::from(flags[1]);
+        anomalous += usize::from(flags[2]);
+        let labels = session_labels(session);
+        if flags.contains(&true) {
+            violating_labels.extend(&labels);
+        }
+        let key = (
+            session_destination(session),
+            session_u64(session, "/session/dst_port"),
+            session_str(session, "/session/protocol")
+                .unwrap_or("")
+                .to_string(),
+            session_process(session).unwrap_or("").to_string(),
+        );
+        let entry = destinations.entry(key.clone()).or_insert_with(|| {
+            json!({
+                "destination": key.0,
+                "port": key.1,
+                "protocol": key.2,
+                "process": key.3,
+                "sessions": 0,
+                "outbound_bytes": 0,
+                "inbound_bytes": 0,
+                "whitelist": whitelist_state,
+                "non_conforming": false,
+                "blacklisted": false,
+                "anomalous": false,
+                "labels": [],
+            })
+        });
+        entry["sessions"] = json!(entry["sessions"].as_u64().unwrap_or(0) + 1);
+        entry["outbound_bytes"] = json!(
+            entry["outbound_bytes"].as_u64().unwrap_or(0)
+                + session_u64(session, "/stats/outbound_bytes")
+        );
+        entry["inbound_bytes"] = json!(
+            entry["inbound_bytes"].as_u64().unwrap_or(0)
+                + session_u64(session, "/stats/inbound_bytes")
+        );
+        // The worst state of the grouped sessions is kept
+        if whitelist_state != "Conforming" {
+            entry["whitelist"] = json!(whitelist_state);
+        }
+        if let Some(entry_labels) = entry["labels"].as_array_mut() {
+            for label in labels {
+                if !entry_labels.iter().any(|known| known == label) {
+                    entry_labels.push(json!(label));
+                }
+            }
+        }
+        for (flag, name) in flags
+            .iter()
+            .zip(["non_conforming", "blacklisted", "anomalous"])
+        {
+            if *flag {
+                entry[name] = json!(true);
+            }
+        }
+    }
+
+    let processes: Vec<Value> = tracked
+        .tree
+        .processes
+        .iter()
+        .map(|(pid, name)| json!({ "pid": pid, "name": name }))
+        .collect();
+    let mut report = json!({
+        "command": options.command,
+        "exit_code": tracked.exit_code,
+        "duration_secs": tracked.duration.as_secs(),
+        "processes": processes,
+        "sessions": sessions.len(),
+        "destinations": destinations.into_values().collect::<Vec<Value>>(),
+        "violations": {
+            "non_conforming": non_conforming,
+            "blacklisted": blacklisted,
+            "anomalous": anomalous,
+            "vulnerability_findings": violations.vulnerability_findings,
+            "labels": violating_labels,
+        },
+    });
+    if let Some(events) = file_events {
+        report["file_events"] = json!(events);
+    }
+    report
+}
+
+/// File events whose PID belongs to the process tree. Events without a PID
+/// are not attributed, a process name can belong to any process.
+pub fn attribute_file_events(events: Vec<Value>, tree: &ProcessTree) -> Vec<Value> {
+    events
+        .into_iter()
+        .filter(|event| {
+            event
+                .pointer("/pid")
+                .and_then(|pid| pid.as_u64())
+                .is_some_and(|pid| tree.owns(pid, item_time_ms(event, FILE_EVENT_TIME)))
+        })
+        .collect()
+}
+
+pub fn print_report(report: &Value) {
+    println!();
+    println!(
+        "Egress of '{}' (exit code {}, {}s, {} processes):",
+        report["command"]
+            .as_array()
+            .map(|args| args
+                .iter()
+                .filter_map(|arg| arg.as_str())
+                .collect::<Vec<_>>()
+                .join(" "))
+            .unwrap_or_default(),
+        report["exit_code"],
+        report["duration_secs"],
+        report["processes"].as_array().map_or(0, |p| p.len())
+    );
+    let destinations = report["destinations"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    if destinations.is_empty() {
+        println!("  No network sessions attributed to the command");
+    }
+    for destination in destinations {
+        let flags: Vec<&str> = [
+            ("non_conforming", "non-conforming"),
+            ("blacklisted", "blacklisted"),
+            ("anomalous", "anomalous"),
+        ]
+        .into_iter()
+        .filter(|(field, _)| destination[field] == true)
+        .map(|(_, flag)| flag)
+        .collect();
+        let labels = session_labels(&destination).join(",");
+        println!(
+            "  {}:{}/{} {} - {} session(s), {} bytes out, {} bytes in{}{}",
+            destination["destination"].as_str().unwrap_or(""),
+            destination["port"],
+            destination["protocol"].as_str().unwrap_or(""),
+            destination["process"].as_str().unwrap_or(""),
+            destination["sessions"],
+            destination["outbound_bytes"],
+            destination["inbound_bytes"],
+            if flags.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", flags.join(", "))
+            },
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!(" labels={}", labels)
+            }
+        );
+    }
+    if let Some(events) = report["file_events"].as_array() {
+        println!("  File events: {}", events.len());
+    }
+}
+
+/// Write the report, print it and return the exit code of the run: the
+/// violation code when the enabled checks failed, the command's code otherwise.
+pub fn conclude(
+    options: &RunOptions,
+    tracked: &Tracked,
+    sessions: Vec<Value>,
+    violations: &Violations,
+    file_events: Option<Vec<Value>>,
+) -> i32 {
+    let sessions = attribute_sessions(sessions, &tracked.tree);
+    let file_events = file_events.map(|events| attribute_file_events(events, &tracked.tree));
+    let report = egress_report(options, tracked, &sessions, violations, file_events);
+    print_report(&report);
+    if let Some(path) = &options.report {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => match std::fs::write(path, json) {
+                Ok(()) => println!("Egress report saved to {}", path),
+                Err(e) => eprintln!("Error writing egress report '{}': {}", path, e),
+            },
+            Err(e) => eprintln!("Error serializing egress report: {}", e),
+        }
+    }
+
+    let counts = &report["violations"];
+    let total: u64 = [
+        "non_conforming",
+        "blacklisted",
+        "anomalous",
+        "vulnerability_findings",
+    ]
+    .iter()
+    .map(|name| counts[name].as_u64().unwrap_or(0))
+    .sum();
+    if total > 0 {
+        let labels = session_labels(counts).join(", ");
+        eprintln!(
+            "Policy violations by '{}': {} non-conforming, {} blacklisted, {} anomalous session(s), {} vulnerability finding(s){}",
+            options.command.first().map_or("", |c| c.as_str()),
+            counts["non_conforming"],
+            counts["blacklisted"],
+            counts["anomalous"],
+            counts["vulnerability_findings"],
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!(" (labels: {})", labels)
+            }
+        );
+        return ERROR_CODE_MISMATCH;
+    }
+    tracked.exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, parent: u32, name: &str, start_time: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            parent: Some(parent),
+            name: name.to_string(),
+            start_time,
+        }
+    }
+
+    #[test]
+    fn reused_pids_only_keep_what_happened_before_the_takeover() {
+        let mut tree = ProcessTree::new(100, "./build.sh");
+        tree.update(&[
+            process(100, 1, "make", 1_000),
+            process(101, 100, "cc", 1_001),
+            process(200, 1, "sshd", 10),
+        ]);
+        assert_eq!(tree.processes[&100], "make");
+        assert!(tree.owns(101, None));
+        assert!(!tree.owns(200, None));
+
+        // cc exited and an unrelated process, with a child, got its PID
+        tree.update(&[
+            process(100, 1, "make", 1_000),
+            process(101, 1, "cron", 2_000),
+            process(102, 101, "sh", 2_001),
+        ]);
+        assert_eq!(tree.processes[&101], "cc");
+        assert!(!tree.owns(102, None));
+        assert!(tree.owns(101, Some(1_500_000)));
+        assert!(!tree.owns(101, Some(2_500_000)));
+        assert!(!tree.owns(101, None));
+        let sessions = attribute_sessions(
+            vec![
+                json!({ "uid": "before", "l7": { "pid": 101 }, "stats": { "start_time": "1970-01-01T00:25:00Z" } }),
+                json!({ "uid": "after", "l7": { "pid": 101 }, "stats": { "start_time": "1970-01-01T00:41:40Z" } }),
+                json!({ "uid": "undated", "l7": { "pid": 101 } }),
+            ],
+            &tree,
+        );
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["uid"], "before");
+
+        // The PID comes back to the tree when make spawns a new process on it
+        tree.update(&[process(101, 100, "ld", 3_000)]);
+        assert!(tree.owns(101, None));
+        assert_eq!(tree.processes[&101], "ld");
+    }
+
+    #[test]
+    fn file_events_need_a_pid_of_the_tree() {
+        let mut tree = ProcessTree::new(100, "make");
+        tree.update(&[process(101, 100, "cc", 1_000)]);
+        let events = vec![
+            json!({ "path": "/tmp/a.o", "pid": 101, "process_name": "cc" }),
+            json!({ "path": "/etc/passwd", "pid": 300, "process_name": "cc" }),
+            json!({ "path": "/tmp/b.o", "process_name": "make" }),
+            json!({ "path": "/tmp/c.o", "pid": -1 }),
+            json!({ "path": "/tmp/d.o", "pid": u64::from(u32::MAX) + 101 }),
+        ];
+        let attributed = attribute_file_events(events, &tree);
+        assert_eq!(attributed.len(), 1);
+        assert_eq!(attributed[0]["path"], "/tmp/a.o");
+    }
+
+    #[test]
+    fn every_violation_kind_fails_the_run() {
+        let tracked = Tracked {
+            exit_code: 0,
+            duration: Duration::from_secs(3),
+            tree: ProcessTree::new(100, "npm"),
+        };
+        let options = RunOptions {
+            command: vec!["npm".to_string(), "ci".to_string()],
+            ..Default::default()
+        };
+        // The whitelist state is Unknown when the core could not evaluate it
+        let sessions = vec![
+            json!({ "uid": "a", "is_whitelisted": "Conforming", "l7": { "pid": 100 },
+                    "session": { "protocol": "TCP", "dst_ip": "104.16.0.1", "dst_port": 443 },
+                    "stats": { "outbound_bytes": 100, "inbound_bytes": 2000 } }),
+            json!({ "uid": "b", "is_whitelisted": "Unknown", "l7": { "pid": 100 },
+                    "session": { "protocol": "TCP", "dst_ip": "104.16.0.1", "dst_port": 443 },
+                    "stats": { "outbound_bytes": 50 } }),
+        ];
+
+        let clean = Violations::default();
+        let report = egress_report(&options, &tracked, &sessions, &clean, None);
+        let destinations = report["destinations"].as_array().unwrap();
+        assert_eq!(destinations.len(), 1);
+        assert_eq!(destinations[0]["sessions"], 2);
+        assert_eq!(destinations[0]["outbound_bytes"], 150);
+        assert_eq!(destinations[0]["whitelist"], "Unknown");
+        assert_eq!(
+            conclude(&options, &tracked, sessions.clone(), &clean, None),
+            0
+        );
+
+        let unknown = Violations {
+            non_conforming: HashSet::from(["b".to_string()]),
+            ..Default::default()
+        };
+        let report = egress_report(&options, &tracked, &sessions, &unknown, None);
+        assert_eq!(report["violations"]["non_conforming"], 1);
+        assert_eq!(report["destinations"][0]["non_conforming"], true);
+        // Violations of sessions outside the tree do not count
+        let elsewhere = Violations {
+            anomalous: HashSet::from(["z".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            conclude(&options, &tracked, sessions.clone(), &elsewhere, None),
+            0
+        );
+        let anomalous = Violations {
+            anomalous: HashSet::from(["a".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            conclude(&options, &tracked, sessions.clone(), &anomalous, None),
+            ERROR_CODE_MISMATCH
+        );
+        let findings = Violations {
+            vulnerability_findings: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            conclude(&options, &tracked, Vec::new(), &findings, None),
+            ERROR_CODE_MISMATCH
+        );
+    }
+
+    #[test]
+    fn violations_report_the_labels_of_their_sessions_only() {
+        let tracked = Tracked {
+            exit_code: 0,
+            duration: Duration::from_secs(1),
+            tree: ProcessTree::new(100, "npm"),
+        };
+        let options = RunOptions {
+            command: vec!["npm".to_string()],
+            ..Default::default()
+        };
+        // Two sessions to the same destination with different labels, and a
+        // conforming one elsewhere
+        let sessions = vec![
+            json!({ "uid": "a", "is_whitelisted": "NonConforming", "l7": { "pid": 100 },
+                    "session": { "protocol": "TCP", "dst_ip": "10.20.0.5", "dst_port": 443 },
+                    "labels": ["corp-artifactory"] }),
+            json!({ "uid": "b", "is_whitelisted": "Conforming", "l7": { "pid": 100 },
+                    "session": { "protocol": "TCP", "dst_ip": "10.20.0.5", "dst_port": 443 },
+                    "labels": ["corp-artifactory", "https"] }),
+            json!({ "uid": "c", "is_whitelisted": "Conforming", "l7": { "pid": 100 },
+                    "session": { "protocol": "TCP", "dst_ip": "104.16.0.1", "dst_port": 443 },
+                    "labels": ["package-registry"] }),
+        ];
+        let violations = Violations {
+            non_conforming: HashSet::from(["a".to_string()]),
+            ..Default::default()
+        };
+        let report = egress_report(&options, &tracked, &sessions, &violations, None);
+        assert_eq!(
+            report["destinations"][0]["labels"],
+            json!(["corp-artifactory", "https"])
+        );
+        assert_eq!(
+            report["destinations"][1]["labels"],
+            json!(["package-registry"])
+        );
+        assert_eq!(report["violations"]["labels"], json!(["corp-artifactory"]));
+
+        let unlabeled: Vec<Value> = sessions
+            .into_iter()
+            .map(|mut session| {
+                session.as_object_mut().unwrap().remove("labels");
+                session
+            })
+            .collect();
+        let report = egress_report(&options, &tracked, &unlabeled, &violations, None);
+        assert_eq!(report["destinations"][0]["labels"], json!([]));
+        assert_eq!(report["violations"]["labels"], json!([]));
+    }
+
+    #[test]
+    fn the_command_exit_code_survives_an_unwritable_report() {
+        let tracked = Tracked {
+            exit_code: 3,
+            duration: Duration::from_secs(1),
+            tree: ProcessTree::new(100, "false"),
+        };
+        let options = RunOptions {
+            command: vec!["false".to_string()],
+            report: Some("/nonexistent-dir/egress.json".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            conclude(&options, &tracked, Vec::new(), &Violations::default(), None),
+            3
+        );
+    }
+}
@@ -0,0 +1,373 @@
+// Session snapshots persisted as JSON and compared between runs.
+//
+// Sessions are handled in their serialized `SessionInfoAPI` form so that
+// snapshots written by one version of the CLI stay readable by the next.
+
+use crate::geoip::GeoGroup;
+use crate::labels::has_label;
+use crate::ERROR_CODE_MISMATCH;
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// String field at a JSON pointer, ignoring nulls and empty strings.
+pub fn session_str<'a>(session: &'a Value, pointer: &str) -> Option<&'a str> {
+    session
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+}
+
+pub fn session_u64(session: &Value, pointer: &str) -> u64 {
+    session
+        .pointer(pointer)
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+}
+
+/// Process name from the L7 attribution, if any.
+pub fn session_process(session: &Value) -> Option<&str> {
+    session_str(session, "/l7/process_name")
+}
+
+/// Destination domain, falling back to the destination IP when unresolved.
+pub fn session_destination(session: &Value) -> String {
+    session_str(session, "/dst_domain")
+        .or_else(|| session_str(session, "/session/dst_ip"))
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+pub fn session_total_bytes(session: &Value) -> u64 {
+    session_u64(session, "/stats/inbound_bytes") + session_u64(session, "/stats/outbound_bytes")
+}
+
+/// `--country`, `--asn`, `--label` and `--summary` options of session output.
+#[derive(Debug, Default, Clone)]
+pub struct SessionFilter {
+    // Matched against the geolocated `dst_country`, see crate::geoip
+    pub countries: Vec<String>,
+    pub asns: Vec<u32>,
+    // Label patterns, see crate::labels
+    pub labels: Vec<String>,
+    pub summary: Option<GeoGroup>,
+}
+
+impl SessionFilter {
+    pub fn is_empty(&self) -> bool {
+        self.countries.is_empty()
+            && self.asns.is_empty()
+            && self.labels.is_empty()
+            && self.summary.is_none()
+    }
+
+    pub fn matches(&self, session: &Value) -> bool {
+        let country_ok = self.countries.is_empty()
+            || session_str(session, "/dst_country").is_some_and(|country| {
+                self.countries
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(country))
+            });
+        let asn_ok = self.asns.is_empty()
+            || self
+                .asns
+                .iter()
+                .any(|asn| session_u64(session, "/dst_asn/as_number") == *asn as u64);
+        let label_ok = self.labels.is_empty() || has_label(session, &self.labels);
+        country_ok && asn_ok && label_ok
+    }
+}
+
+pub fn write_snapshot(path: &str, sessions: Vec<Value>) -> Result<usize, String> {
+    let count = sessions.len();
+    let captured_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let snapshot = json!({
+        "captured_at": captured_at,
+        "sessions": sessions,
+    });
+    let content = serde_json::to_string_pretty(&snapshot)
+        .map_err(|e| format!("Error serializing snapshot: {}", e))?;
+    std::fs::write(path, content)
+        .map_err(|e| format!("Error writing snapshot file '{}': {}", path, e))?;
+    Ok(count)
+}
+
+/// Load a snapshot file. Plain JSON arrays of sessions are accepted as well,
+/// so the output of `edamame_cli rpc get_current_sessions` can be compared too.
+pub fn load_snapshot(path: &str) -> Result<Vec<Value>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading snapshot file '{}': {}", path, e))?;
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Error parsing snapshot file '{}': {}", path, e))?;
+    match value {
+        Value::Array(sessions) => Ok(sessions),
+        Value::Object(mut object) => match object.remove("sessions") {
+            Some(Value::Array(sessions)) => Ok(sessions),
+            _ => Err(format!("Snapshot file '{}' has no 'sessions' array", path)),
+        },
+        _ => Err(format!(
+            "Snapshot file '{}' is not a session snapshot",
+            path
+        )),
+    }
+}
+
+// (destination, port, protocol)
+type DestinationKey = (String, u64, String);
+
+fn destination_key(session: &Value) -> DestinationKey {
+    (
+        session_destination(session),
+        session_u64(session, "/session/dst_port"),
+        session_str(session, "/session/protocol")
+            .unwrap_or("")
+            .to_uppercase(),
+    )
+}
+
+fn index_destinations(sessions: &[Value]) -> BTreeMap<DestinationKey, BTreeSet<String>> {
+    let mut destinations: BTreeMap<DestinationKey, BTreeSet<String>> = BTreeMap::new();
+    for session in sessions {
+        let processes = destinations.entry(destination_key(session)).or_default();
+        if let Some(process) = session_process(session) {
+            processes.insert(process.to_string());
+        }
+    }
+    destinations
+}
+
+fn index_bytes(sessions: &[Value]) -> BTreeMap<(String, String), u64> {
+    let mut bytes: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for session in sessions {
+        let process = session_process(session).unwrap_or("unknown").to_string();
+        *bytes
+            .entry((process, session_destination(session)))
+            .or_default() += session_total_bytes(session);
+    }
+    bytes
+}
+
+/// Compare two session sets. The returned report holds new and vanished
+/// destinations, processes new to the network and per (process, destination)
+/// byte volume changes.
+pub fn diff_sessions(old: &[Value], new: &[Value]) -> Value {
+    let old_destinations = index_destinations(old);
+    let new_destinations = index_destinations(new);
+
+    let destination_json = |key: &DestinationKey, processes: &BTreeSet<String>| {
+        json!({
+            "destination": key.0,
+            "port": key.1,
+            "protocol": key.2,
+            "processes": processes,
+        })
+    };
+    let added: Vec<Value> = new_destinations
+        .iter()
+        .filter(|(key, _)| !old_destinations.contains_key(*key))
+        .map(|(key, processes)| destination_json(key, processes))
+        .collect();
+    let vanished: Vec<Value> = old_destinations
+        .iter()
+        .filter(|(key, _)| !new_destinations.contains_key(*key))
+        .map(|(key, processes)| destination_json(key, processes))
+        .collect();
+
+    let old_processes: BTreeSet<&str> = old.iter().filter_map(session_process).collect();
+    let new_processes: BTreeSet<&str> = new
+        .iter()
+        .filter_map(session_process)
+        .filter(|process| !old_processes.contains(process))
+        .collect();
+
+    let old_bytes = index_bytes(old);
+    let new_bytes = index_bytes(new);
+    let pairs: BTreeSet<&(String, String)> = old_bytes.keys().chain(new_bytes.keys()).collect();
+    let mut byte_changes: Vec<(i128, Value)> = pairs
+        .into_iter()
+        .filter_map(|pair| {
+            let before = old_bytes.get(pair).copied().unwrap_or(0);
+            let after = new_bytes.get(pair).copied().unwrap_or(0);
+            if before == after {
+                return None;
+            }
+            let delta = after as i128 - before as i128;
+            Some((
+                delta,
+                json!({
+                    "process": pair.0,
+                    "destination": pair.1,
+                    "old_bytes": before,
+                    "new_bytes": after,
+                    "delta_bytes": delta as i64,
+                }),
+            ))
+        })
+        .collect();
+    // Largest changes first
+    byte_changes.sort_by_key(|change| std::cmp::Reverse(change.0.abs()));
+
+    json!({
+        "old_sessions": old.len(),
+        "new_sessions": new.len(),
+        "new_destinations": added,
+        "vanished_destinations": vanished,
+        "new_processes": new_processes,
+        "byte_changes": byte_changes.into_iter().map(|(_, change)| change).collect::<Vec<_>>(),
+    })
+}
+
+fn print_diff(report: &Value) {
+    let list = |key: &str| -> Vec<Value> {
+        report
+            .get(key)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let destination_line = |d: &Value| -> String {
+        let processes: Vec<&str> = d["processes"]
+            .as_array()
+            .map(|p| p.iter().filter_map(|p| p.as_str()).collect())
+            .unwrap_or_default();
+        let suffix = if processes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", processes.join(", "))
+        };
+        format!(
+            "{}:{}/{}{}",
+            d["destination"].as_str().unwrap_or(""),
+            d["port"],
+            d["protocol"].as_str().unwrap_or(""),
+            suffix
+        )
+    };
+
+    let new_destinations = list("new_destinations");
+    println!("New destinations ({}):", new_destinations.len());
+    for d in &new_destinations {
+        println!("  + {}", destination_line(d));
+    }
+    let vanished = list("vanished_destinations");
+    println!("Vanished destinations ({}):", vanished.len());
+    for d in &vanished {
+        println!("  - {}", destination_line(d));
+    }
+    let new_processes = list("new_processes");
+    println!("New processes ({}):", new_processes.len());
+    for p in &new_processes {
+        println!("  + {}", p.as_str().unwrap_or(""));
+    }
+    let byte_changes = list("byte_changes");
+    println!("Byte volume changes ({}):", byte_changes.len());
+    for c in &byte_changes {
+        println!(
+            "  {} -> {}: {} -> {} ({:+})",
+            c["process"].as_str().unwrap_or(""),
+            c["destination"].as_str().unwrap_or(""),
+            c["old_bytes"],
+            c["new_bytes"],
+            c["delta_bytes"].as_i64().unwrap_or(0)
+        );
+    }
+}
+
+pub fn sessions_diff_files(
+    old_path: &str,
+    new_path: &str,
+    json_output: bool,
+    fail_on_new_egress: bool,
+) -> i32 {
+    let (old, new) = match (load_snapshot(old_path), load_snapshot(new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+
+    let report = diff_sessions(&old, &new);
+    if json_output {
+        match serde_json::to_string_pretty(&report) {
+            Ok(output) => println!("{}", output),
+            Err(e) => {
+                eprintln!("Error serializing diff: {}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+    } else {
+        print_diff(&report);
+    }
+
+    let new_egress = report["new_destinations"].as_array().map_or(0, |d| d.len());
+    if fail_on_new_egress && new_egress > 0 {
+        eprintln!("New egress since baseline: {} destination(s)", new_egress);
+        return ERROR_CODE_MISMATCH;
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(process: &str, domain: &str, port: u64, bytes: u64) -> Value {
+        json!({
+            "session": { "protocol": "TCP", "dst_ip": "203.0.113.10", "dst_port": port },
+            "stats": { "inbound_bytes": bytes, "outbound_bytes": 0 },
+            "dst_domain": domain,
+            "l7": { "process_name": process }
+        })
+    }
+
+    #[test]
+    fn diff_reports_new_and_vanished_destinations() {
+        let old = vec![
+            session("curl", "github.com", 443, 100),
+            session("git", "old.example.com", 443, 50),
+        ];
+        let new = vec![
+            session("curl", "github.com", 443, 300),
+            session("wget", "new.example.com", 443, 10),
+        ];
+        let report = diff_sessions(&old, &new);
+
+        assert_eq!(report["new_destinations"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            report["new_destinations"][0]["destination"],
+            "new.example.com"
+        );
+        assert_eq!(
+            report["vanished_destinations"][0]["destination"],
+            "old.example.com"
+        );
+        assert_eq!(report["new_processes"], json!(["wget"]));
+        // github.com grew by 200 bytes and is the largest change
+        assert_eq!(report["byte_changes"][0]["process"], "curl");
+        assert_eq!(report["byte_changes"][0]["delta_bytes"], 200);
+    }
+
+    #[test]
+    fn identical_sets_have_empty_diff() {
+        let sessions = vec![session("curl", "github.com", 443, 100)];
+        let report = diff_sessions(&sessions, &sessions);
+        assert!(report["new_destinations"].as_array().unwrap().is_empty());
+        assert!(report["vanished_destinations"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+        assert!(report["byte_changes"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unresolved_domain_falls_back_to_ip() {
+        let mut unresolved = session("curl", "", 443, 0);
+        unresolved["dst_domain"] = Value::Null;
+        assert_eq!(session_destination(&unresolved), "203.0.113.10");
+    }
+}
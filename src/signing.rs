@@ -0,0 +1,751 @@
+// Ed25519 detached signatures for custom whitelist and blacklist files.
+//
+// Keys are base64 encoded 32-byte values. A signature file holds the signer's
+// public key and the signature over the canonical form of the list (see
+// `canonical_list`):
+//
+//   { "algorithm": "ed25519", "public_key": "<base64>", "signature": "<base64>" }
+//
+// When a signed list is applied, the signature travels in the `signature`
+// field of the whitelist and blacklist schema as
+// `ed25519:<public key>:<signature>`, so that the daemon can check the lists
+// it reads back from the core. Trusted keys are configured as
+// `name:<base64 public key>` entries, separated by commas, so that the signer
+// can be reported by name.
+
+use crate::ERROR_CODE_MISMATCH;
+use crate::ERROR_CODE_PARAM;
+use base64::prelude::*;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+// Can only require signatures on top of the service configuration
+pub const REQUIRE_SIGNED_ENV: &str = "EDAMAME_REQUIRE_SIGNED_LISTS";
+// Trusted keys when the service configuration has none, requires signatures
+pub const TRUSTED_KEYS_ENV: &str = "EDAMAME_TRUSTED_LIST_KEYS";
+// Admin-owned source of the policy, see linux/edamame_posture.conf
+#[cfg(not(any(target_os = "macos", windows)))]
+const SERVICE_CONFIG: &str = "/etc/edamame_posture.conf";
+#[cfg(target_os = "macos")]
+const SERVICE_CONFIG: &str =
+    "/Library/Application Support/EDAMAME/EDAMAME-Posture/edamame_posture.conf";
+#[cfg(windows)]
+const SERVICE_CONFIG: &str = r"C:\ProgramData\edamame_posture\edamame_posture.conf";
+const EMBEDDED_PREFIX: &str = "ed25519:";
+
+#[derive(Debug, Clone)]
+pub struct TrustedKey {
+    pub name: String,
+    pub key: VerifyingKey,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListPolicy {
+    pub require_signed: bool,
+    pub trusted: Vec<TrustedKey>,
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32], String> {
+    let bytes = BASE64_STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("invalid base64 key: {}", e))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected a 32-byte key, found {} bytes", bytes.len()))
+}
+
+fn parse_public_key(encoded: &str) -> Result<VerifyingKey, String> {
+    VerifyingKey::from_bytes(&decode_key(encoded)?)
+        .map_err(|e| format!("invalid Ed25519 public key: {}", e))
+}
+
+/// Parse `name:key` entries separated by commas. A bare key is named after
+/// its first characters.
+pub fn parse_trusted_keys(spec: &str) -> Result<Vec<TrustedKey>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, key) = match entry.split_once(':') {
+                Some((name, key)) => (name.trim().to_string(), key),
+                None => (format!("key {}", &entry[..entry.len().min(8)]), entry),
+            };
+            let key =
+                parse_public_key(key).map_err(|e| format!("trusted key '{}': {}", name, e))?;
+            Ok(TrustedKey { name, key })
+        })
+        .collect()
+}
+
+// Value of `key: "value"  # comment` in the service configuration file
+fn service_config_value(content: &str, key: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let rest = line
+            .trim()
+            .strip_prefix(key)?
+            .trim_start()
+            .strip_prefix(':')?;
+        let rest = rest.trim();
+        let value = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or(""),
+            None => rest.split('#').next().unwrap_or("").trim(),
+        };
+        Some(value.to_string())
+    })
+}
+
+fn is_true(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "true" | "1" | "yes")
+}
+
+/// Combine the service configuration with the environment. The environment
+/// can only make the policy stricter: it can require signatures, and it can
+/// supply the trusted keys when the configuration has none, in which case
+/// signatures are required.
+fn policy_from(
+    config: Option<&str>,
+    require_env: Option<String>,
+    keys_env: Option<String>,
+) -> Result<ListPolicy, String> {
+    let config = config.unwrap_or("");
+    let config_keys =
+        service_config_value(config, "trusted_list_keys").filter(|v| !v.trim().is_empty());
+    let keys_env = keys_env.filter(|v| !v.trim().is_empty());
+    let (trusted, keys_from_env) = match (config_keys, keys_env) {
+        (Some(spec), _) => (parse_trusted_keys(&spec)?, false),
+        (None, Some(spec)) => (
+            parse_trusted_keys(&spec).map_err(|e| format!("{}: {}", TRUSTED_KEYS_ENV, e))?,
+            true,
+        ),
+        (None, None) => (Vec::new(), false),
+    };
+    let require_signed = keys_from_env
+        || require_env.is_some_and(|v| is_true(&v))
+        || service_config_value(config, "require_signed_lists").is_some_and(|v| is_true(&v));
+    if require_signed && trusted.is_empty() {
+        return Err("signed lists are required but no trusted keys are configured".to_string());
+    }
+    Ok(ListPolicy {
+        require_signed,
+        trusted,
+    })
+}
+
+// A configuration that others can edit is not a policy, fail closed
+#[cfg(unix)]
+fn check_config_owner(path: &str) -> Result<(), String> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("cannot inspect {}: {}", path, e))?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "{} must be owned by root and writable only by root to hold the list signing policy",
+            path
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_config_owner(_path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+/// Signing policy of the service configuration file, which only an admin can
+/// edit, tightened by the environment (see `policy_from`).
+pub fn list_policy() -> Result<ListPolicy, String> {
+    let config = if std::path::Path::new(SERVICE_CONFIG).exists() {
+        check_config_owner(SERVICE_CONFIG)?;
+        // Fail closed: an unreadable configuration may require signatures
+        Some(std::fs::read_to_string(SERVICE_CONFIG).map_err(|e| {
+            format!(
+                "cannot read list signing policy from {}: {} (run as admin)",
+                SERVICE_CONFIG, e
+            )
+        })?)
+    } else {
+        None
+    };
+    policy_from(
+        config.as_deref(),
+        std::env::var(REQUIRE_SIGNED_ENV).ok(),
+        std::env::var(TRUSTED_KEYS_ENV).ok(),
+    )
+}
+
+fn canonical_value(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::String(s) if s.is_empty() => None,
+        Value::Array(items) => {
+            let items: Vec<Value> = items.iter().filter_map(canonical_value).collect();
+            (!items.is_empty()).then_some(Value::Array(items))
+        }
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let mut canonical = serde_json::Map::new();
+            for key in keys {
+                if let Some(value) = canonical_value(&map[key]) {
+                    canonical.insert(key.clone(), value);
+                }
+            }
+            (!canonical.is_empty()).then_some(Value::Object(canonical))
+        }
+        other => Some(other.clone()),
+    }
+}
+
+/// The form of a list that signatures cover. For JSON lists, compact JSON
+/// without the top-level `signature` field, with sorted keys and without null
+/// or empty values, so that the list file and the list read back from the
+/// core, whose formatting and defaults differ, have the same form. Other
+/// files, such as blacklist feeds, are signed as they are.
+pub fn canonical_list(content: &str) -> String {
+    match serde_json::from_str::<Value>(content) {
+        Ok(mut document) => {
+            if let Some(map) = document.as_object_mut() {
+                map.remove("signature");
+            }
+            canonical_value(&document)
+                .map(|value| value.to_string())
+                .unwrap_or_default()
+        }
+        Err(_) => content.to_string(),
+    }
+}
+
+/// Carry the signature of `signature_file` in the `signature` field of the
+/// list, for the daemon to check. Lists without a signature file are returned
+/// unchanged.
+pub fn embed_signature(content: &str, signature_file: Option<&str>) -> Result<String, String> {
+    let Some(path) = signature_file.filter(|path| std::path::Path::new(path).exists()) else {
+        return Ok(content.to_string());
+    };
+    let signature: Value = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading signature file '{}': {}", path, e))
+        .and_then(|document| {
+            serde_json::from_str(&document).map_err(|e| format!("invalid signature file: {}", e))
+        })?;
+    let mut document: Value =
+        serde_json::from_str(content).map_err(|e| format!("list is not valid JSON: {}", e))?;
+    document["signature"] = json!(format!(
+        "{}{}:{}",
+        EMBEDDED_PREFIX,
+        signature["public_key"].as_str().unwrap_or(""),
+        signature["signature"].as_str().unwrap_or("")
+    ));
+    serde_json::to_string_pretty(&document).map_err(|e| e.to_string())
+}
+
+// The signature carried by a list as a signature file document
+fn embedded_signature_document(content: &str) -> Option<String> {
+    let document: Value = serde_json::from_str(content).ok()?;
+    let (public_key, signature) = document["signature"]
+        .as_str()?
+        .strip_prefix(EMBEDDED_PREFIX)?
+        .split_once(':')?;
+    Some(
+        json!({
+            "algorithm": "ed25519",
+            "public_key": public_key,
+            "signature": signature,
+        })
+        .to_string(),
+    )
+}
+
+/// Check the signature carried by a list set on the core against the policy.
+/// Returns the signer name.
+pub fn verify_embedded_signature(content: &str, policy: &ListPolicy) -> Result<String, String> {
+    serde_json::from_str::<Value>(content).map_err(|e| format!("list is not valid JSON: {}", e))?;
+    let document = embedded_signature_document(content).ok_or("list is not signed")?;
+    let key = verify_signature_document(canonical_list(content).as_bytes(), &document)?;
+    trusted_name(&policy.trusted, &key)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            format!(
+                "list is signed by untrusted key {}",
+                BASE64_STANDARD.encode(key.to_bytes())
+            )
+        })
+}
+
+/// What the daemon does with a list read back from the core
+#[derive(Debug, PartialEq)]
+pub enum ListReview {
+    Unchanged,
+    /// Signed by the named trusted key, now the accepted list
+    Accepted(String),
+    /// The reason, the accepted list must be set again
+    Rejected(String),
+}
+
+/// The last list of one kind accepted by the daemon. The lists active when
+/// the daemon starts are accepted as they are.
+#[derive(Debug, Clone)]
+pub struct GuardedList {
+    pub accepted: String,
+}
+
+impl GuardedList {
+    pub fn new(accepted: String) -> Self {
+        GuardedList { accepted }
+    }
+
+    pub fn review(&mut self, current: String, policy: &ListPolicy) -> ListReview {
+        if current == self.accepted {
+            return ListReview::Unchanged;
+        }
+        match verify_embedded_signature(&current, policy) {
+            Ok(signer) => {
+                self.accepted = current;
+                ListReview::Accepted(signer)
+            }
+            Err(e) => ListReview::Rejected(e),
+        }
+    }
+}
+
+pub fn signature_document(signing_key: &SigningKey, content: &[u8]) -> Value {
+    json!({
+        "algorithm": "ed25519",
+        "public_key": BASE64_STANDARD.encode(signing_key.verifying_key().to_bytes()),
+        "signature": BASE64_STANDARD.encode(signing_key.sign(content).to_bytes()),
+    })
+}
+
+/// Verify a signature document over `content`. Returns the public key that
+/// signed it once the signature itself is valid.
+pub fn verify_signature_document(content: &[u8], document: &str) -> Result<VerifyingKey, String> {
+    let document: Value =
+        serde_json::from_str(document).map_err(|e| format!("invalid signature file: {}", e))?;
+    match document.get("algorithm").and_then(|a| a.as_str()) {
+        Some("ed25519") => {}
+        other => return Err(format!("unsupported signature algorithm {:?}", other)),
+    }
+    let field = |name: &str| {
+        document
+            .get(name)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("signature file has no '{}'", name))
+    };
+    let key = parse_public_key(field("public_key")?)?;
+    let signature = BASE64_STANDARD
+        .decode(field("signature")?)
+        .map_err(|e| format!("invalid base64 signature: {}", e))?;
+    let signature = Signature::from_slice(&signature)
+        .map_err(|e| format!("invalid Ed25519 signature: {}", e))?;
+    key.verify_strict(content, &signature)
+        .map_err(|_| "signature does not match the list content".to_string())?;
+    Ok(key)
+}
+
+fn trusted_name<'a>(trusted: &'a [TrustedKey], key: &VerifyingKey) -> Option<&'a str> {
+    trusted
+        .iter()
+        .find(|t| t.key == *key)
+        .map(|t| t.name.as_str())
+}
+
+/// Check a list against the signing policy before it is applied. Returns the
+/// signer identity when the list is signed. Without a signature file, the
+/// signature the list carries, if any, is checked. Invalid signatures are
+/// always refused; unsigned lists and untrusted signers only when the policy
+/// requires signed lists.
+pub fn check_list_signature(
+    content: &str,
+    signature_file: Option<&str>,
+    kind: &str,
+) -> Result<Option<String>, String> {
+    check_list_signature_with(content, signature_file, kind, &list_policy()?)
+}
+
+fn check_list_signature_with(
+    content: &str,
+    signature_file: Option<&str>,
+    kind: &str,
+    policy: &ListPolicy,
+) -> Result<Option<String>, String> {
+    let embedded = embedded_signature_document(content);
+    let document = match (signature_file, embedded) {
+        (Some(path), _) if std::path::Path::new(path).exists() => std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading signature file '{}': {}", path, e))?,
+        (_, Some(embedded)) => embedded,
+        (Some(path), None) if policy.require_signed => {
+            return Err(format!(
+                "{} is not signed (no signature file '{}') and signed lists are required",
+                kind, path
+            ))
+        }
+        (None, None) if policy.require_signed => {
+            return Err(format!(
+                "{} must be signed, signed lists are required",
+                kind
+            ))
+        }
+        _ => return Ok(None),
+    };
+
+    let key = verify_signature_document(canonical_list(content).as_bytes(), &document)
+        .map_err(|e| format!("{} signature is invalid: {}", kind, e))?;
+    match trusted_name(&policy.trusted, &key) {
+        Some(name) => Ok(Some(name.to_string())),
+        None if policy.require_signed => Err(format!(
+            "{} is signed by untrusted key {}",
+            kind,
+            BASE64_STANDARD.encode(key.to_bytes())
+        )),
+        None => {
+            warn!("{} is signed by a key that is not trusted", kind);
+            Ok(Some(format!(
+                "untrusted key {}",
+                BASE64_STANDARD.encode(key.to_bytes())
+            )))
+        }
+    }
+}
+
+/// Record who signed a list that was just applied.
+pub fn log_list_signer(kind: &str, signer: &Option<String>) {
+    match signer {
+        Some(signer) => {
+            info!("Applied custom {} signed by {}", kind, signer);
+            println!("Applied custom {} signed by {}", kind, signer);
+        }
+        None => info!("Applied unsigned custom {}", kind),
+    }
+}
+
+fn load_signing_key(path: &str) -> Result<SigningKey, String> {
+    let encoded = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading private key file '{}': {}", path, e))?;
+    let seed = decode_key(&encoded).map_err(|e| format!("Private key file '{}': {}", path, e))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+pub fn generate_list_key(private_key_file: &str) -> i32 {
+    if std::path::Path::new(private_key_file).exists() {
+        eprintln!(
+            "Refusing to overwrite existing key file '{}'",
+            private_key_file
+        );
+        return ERROR_CODE_PARAM;
+    }
+    let mut seed = [0u8; 32];
+    if let Err(e) = getrandom::getrandom(&mut seed) {
+        eprintln!("Error generating key: {}", e);
+        return ERROR_CODE_PARAM;
+    }
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(private_key_file).and_then(|mut file| {
+        use std::io::Write;
+        writeln!(file, "{}", BASE64_STANDARD.encode(seed))
+    });
+    if let Err(e) = written {
+        eprintln!("Error writing key file '{}': {}", private_key_file, e);
+        return ERROR_CODE_PARAM;
+    }
+    println!(
+        "{}",
+        BASE64_STANDARD.encode(signing_key.verifying_key().to_bytes())
+    );
+    eprintln!(
+        "Private key written to {}, the public key above goes into the trusted keys",
+        private_key_file
+    );
+    0
+}
+
+pub fn sign_list_file(list_file: &str, private_key_file: &str, output: Option<&str>) -> i32 {
+    let content = match std::fs::read_to_string(list_file) {
+        Ok(content) => canonical_list(&content),
+        Err(e) => {
+            eprintln!("Error reading list file '{}': {}", list_file, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let signing_key = match load_signing_key(private_key_file) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let signature_file = output
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}.sig", list_file));
+    let document = signature_document(&signing_key, content.as_bytes());
+    let written = serde_json::to_string_pretty(&document)
+        .map_err(|e| e.to_string())
+        .and_then(|document| {
+            std::fs::write(&signature_file, document + "\n").map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        eprintln!("Error writing signature file '{}': {}", signature_file, e);
+        return ERROR_CODE_PARAM;
+    }
+    println!("Signature written to {}", signature_file);
+    0
+}
+
+pub fn verify_list_file(
+    list_file: &str,
+    signature_file: Option<&str>,
+    trusted_keys: &[String],
+) -> i32 {
+    let signature_file = signature_file
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}.sig", list_file));
+    let (content, document) = match (
+        std::fs::read_to_string(list_file),
+        std::fs::read_to_string(&signature_file),
+    ) {
+        (Ok(content), Ok(document)) => (canonical_list(&content), document),
+        (Err(e), _) => {
+            eprintln!("Error reading list file '{}': {}", list_file, e);
+            return ERROR_CODE_PARAM;
+        }
+        (_, Err(e)) => {
+            eprintln!("Error reading signature file '{}': {}", signature_file, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let trusted = if trusted_keys.is_empty() {
+        list_policy().map(|policy| policy.trusted)
+    } else {
+        parse_trusted_keys(&trusted_keys.join(","))
+    };
+    let trusted = match trusted {
+        Ok(trusted) => trusted,
+        Err(e) => {
+            eprintln!("Error loading trusted keys: {}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+
+    let key = match verify_signature_document(content.as_bytes(), &document) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}: {}", list_file, e);
+            return ERROR_CODE_MISMATCH;
+        }
+    };
+    match trusted_name(&trusted, &key) {
+        Some(name) => {
+            println!("{}: valid signature by {}", list_file, name);
+            0
+        }
+        None => {
+            eprintln!(
+                "{}: valid signature by untrusted key {}",
+                list_file,
+                BASE64_STANDARD.encode(key.to_bytes())
+            );
+            ERROR_CODE_MISMATCH
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32])
+    }
+
+    fn public(key: &SigningKey) -> String {
+        BASE64_STANDARD.encode(key.verifying_key().to_bytes())
+    }
+
+    #[test]
+    fn signature_round_trip_and_tamper_detection() {
+        let signer = key(7);
+        let content = br#"{"date":"today","whitelists":[]}"#;
+        let document = signature_document(&signer, content).to_string();
+
+        let verified = verify_signature_document(content, &document).expect("valid");
+        assert_eq!(verified, signer.verifying_key());
+
+        let tampered = br#"{"date":"today","whitelists":[{"name":"x"}]}"#;
+        assert!(verify_signature_document(tampered, &document).is_err());
+    }
+
+    #[test]
+    fn embedded_signatures_survive_reformatting() {
+        let signer = key(5);
+        let list = r#"{"date":"today","signature":"","whitelists":[{"name":"ci","endpoints":[{"domain":"github.com","port":443}]}]}"#;
+        let dir = std::env::temp_dir().join(format!("edamame-signing-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let signature_file = dir.join("list.json.sig").to_string_lossy().to_string();
+        let document = signature_document(&signer, canonical_list(list).as_bytes());
+        std::fs::write(&signature_file, document.to_string()).unwrap();
+
+        let embedded = embed_signature(list, Some(&signature_file)).unwrap();
+        let policy = ListPolicy {
+            require_signed: true,
+            trusted: parse_trusted_keys(&format!("ci:{}", public(&signer))).unwrap(),
+        };
+        // As read back from the core: other key order, defaults as null
+        let mut stored: Value = serde_json::from_str(&embedded).unwrap();
+        stored["whitelists"][0]["extends"] = Value::Null;
+        stored["whitelists"][0]["endpoints"][0]["process"] = Value::Null;
+        assert_eq!(
+            verify_embedded_signature(&stored.to_string(), &policy).unwrap(),
+            "ci"
+        );
+
+        stored["whitelists"][0]["endpoints"][0]["port"] = json!(22);
+        assert!(verify_embedded_signature(&stored.to_string(), &policy).is_err());
+        assert!(verify_embedded_signature(list, &policy)
+            .unwrap_err()
+            .contains("not signed"));
+        let untrusted = ListPolicy {
+            trusted: parse_trusted_keys(&format!("other:{}", public(&key(6)))).unwrap(),
+            ..policy
+        };
+        assert!(verify_embedded_signature(&embedded, &untrusted)
+            .unwrap_err()
+            .contains("untrusted"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn trusted_keys_are_named() {
+        let alice = key(1);
+        let bob = key(2);
+        let spec = format!("alice:{}, {}", public(&alice), public(&bob));
+        let trusted = parse_trusted_keys(&spec).expect("keys");
+        assert_eq!(
+            trusted_name(&trusted, &alice.verifying_key()),
+            Some("alice")
+        );
+        assert!(trusted_name(&trusted, &bob.verifying_key())
+            .unwrap()
+            .starts_with("key "));
+        assert!(trusted_name(&trusted, &key(3).verifying_key()).is_none());
+        assert!(parse_trusted_keys("ci:not-a-key").is_err());
+    }
+
+    #[test]
+    fn environment_only_tightens_the_service_config() {
+        let ci = format!("ci:{}", public(&key(4)));
+        let config = format!(
+            "start_capture: \"true\"\nrequire_signed_lists: \"true\"   # refuse unsigned\ntrusted_list_keys: \"{}\"\n",
+            ci
+        );
+        let policy = policy_from(Some(&config), Some("false".to_string()), None).unwrap();
+        assert!(policy.require_signed);
+        assert_eq!(policy.trusted[0].name, "ci");
+
+        // Keys in the environment do not add to the configured ones
+        let env_keys = Some(format!("dev:{}", public(&key(9))));
+        let policy = policy_from(Some(&config), None, env_keys.clone()).unwrap();
+        assert_eq!(policy.trusted.len(), 1);
+        let optional = "trusted_list_keys: \"\"\nrequire_signed_lists: false\n";
+        assert!(
+            !policy_from(Some(optional), None, None)
+                .unwrap()
+                .require_signed
+        );
+        // Without configured keys, they apply and signatures become required
+        let policy = policy_from(Some(optional), None, env_keys).unwrap();
+        assert!(policy.require_signed);
+        assert_eq!(policy.trusted[0].name, "dev");
+        assert!(policy_from(None, None, Some("dev:not-a-key".to_string())).is_err());
+
+        // Requiring signatures without trusting anyone is a configuration error
+        assert!(policy_from(None, Some("true".to_string()), None).is_err());
+    }
+
+    fn signed(list: &str, signer: &SigningKey) -> String {
+        let mut document: Value = serde_json::from_str(list).unwrap();
+        let signature = signature_document(signer, canonical_list(list).as_bytes());
+        document["signature"] = json!(format!(
+            "{}{}:{}",
+            EMBEDDED_PREFIX,
+            signature["public_key"].as_str().unwrap(),
+            signature["signature"].as_str().unwrap()
+        ));
+        document.to_string()
+    }
+
+    #[test]
+    fn lists_are_checked_before_they_are_applied() {
+        let list = r#"{"date":"","signature":"","whitelists":[{"name":"ci","endpoints":[{"domain":"github.com","port":443}]}]}"#;
+        let required = ListPolicy {
+            require_signed: true,
+            trusted: parse_trusted_keys(&format!("ci:{}", public(&key(4)))).unwrap(),
+        };
+        let missing = std::env::temp_dir().join("edamame-no-such-list.json.sig");
+        let missing = missing.to_str().unwrap();
+
+        let err = check_list_signature_with(list, Some(missing), "Whitelist", &required);
+        assert!(err.unwrap_err().contains("no signature file"));
+        let err = check_list_signature_with(list, None, "Whitelist", &required);
+        assert!(err.unwrap_err().contains("must be signed"));
+        // A list passed as JSON can carry its signature
+        let carried = signed(list, &key(4));
+        assert_eq!(
+            check_list_signature_with(&carried, None, "Whitelist", &required).unwrap(),
+            Some("ci".to_string())
+        );
+        let err = check_list_signature_with(&signed(list, &key(8)), None, "Whitelist", &required);
+        assert!(err.unwrap_err().contains("untrusted"));
+
+        // Invalid signatures are refused even when signatures are optional
+        let optional = ListPolicy::default();
+        assert_eq!(
+            check_list_signature_with(list, Some(missing), "Whitelist", &optional).unwrap(),
+            None
+        );
+        let tampered = carried.replace("github.com", "evil.example");
+        let err = check_list_signature_with(&tampered, None, "Whitelist", &optional);
+        assert!(err.unwrap_err().contains("signature is invalid"));
+    }
+
+    #[test]
+    fn guard_rolls_back_unsigned_and_tampered_lists() {
+        let policy = ListPolicy {
+            require_signed: true,
+            trusted: parse_trusted_keys(&format!("ci:{}", public(&key(4)))).unwrap(),
+        };
+        // Whatever is active at startup is accepted
+        let mut guard = GuardedList::new(String::new());
+        assert_eq!(guard.review(String::new(), &policy), ListReview::Unchanged);
+
+        let list = r#"{"date":"","signature":"","whitelists":[{"name":"ci","endpoints":[{"domain":"github.com","port":443}]}]}"#;
+        assert!(matches!(
+            guard.review(list.to_string(), &policy),
+            ListReview::Rejected(e) if e.contains("not signed")
+        ));
+        assert_eq!(guard.accepted, "");
+
+        let signed = signed(list, &key(4));
+        assert_eq!(
+            guard.review(signed.clone(), &policy),
+            ListReview::Accepted("ci".to_string())
+        );
+        assert_eq!(guard.review(signed.clone(), &policy), ListReview::Unchanged);
+        let tampered = signed.replace("443", "22");
+        assert!(matches!(
+            guard.review(tampered, &policy),
+            ListReview::Rejected(_)
+        ));
+        assert_eq!(guard.accepted, signed);
+        assert!(matches!(
+            guard.review("not json".to_string(), &policy),
+            ListReview::Rejected(_)
+        ));
+    }
+}
@@ -0,0 +1,557 @@
+// Pipeline step markers.
+//
+// `background-mark-step` records when each named step of a CI job started and
+// ended, in a JSON registry kept next to the other state of the daemon. The
+// CLI owns the file, which requires admin privileges, and the daemon only
+// reads it to tag the records of its history store. The core has no RPC to
+// hand the steps to the daemon, so both always use the same fixed path rather
+// than one the environment of either side could change:
+//
+//   { "version": 1, "steps": [ { "name": "npm-install",
+//     "started_at_ms": 1760745600000, "ended_at_ms": 1760745663250 } ] }
+//
+// Starting a step ends the one in progress, so steps never overlap. Sessions,
+// file events and findings belong to the step in progress at their start time;
+// a name used twice (e.g. a retried step) covers both windows.
+
+use crate::dismissals::format_timestamp;
+use crate::sessions::{session_destination, session_str, session_u64};
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(windows))]
+const DEFAULT_REGISTRY: &str = "/var/lib/edamame_posture/steps.json";
+#[cfg(windows)]
+const DEFAULT_REGISTRY: &str = r"C:\ProgramData\edamame_posture\steps.json";
+const REGISTRY_VERSION: u64 = 1;
+
+/// Where the time of a session, a file event or a finding is found, in order
+/// of preference. Findings carry one of several fields depending on the detector.
+pub const SESSION_TIME: &[&str] = &[
+    "/stats/start_time",
+    "/stats/first_activity",
+    "/stats/last_activity",
+];
+pub const FILE_EVENT_TIME: &[&str] = &["/timestamp"];
+pub const FINDING_TIME: &[&str] = &["/first_seen", "/detected_at", "/created_at", "/timestamp"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub name: String,
+    pub started_at_ms: u64,
+    pub ended_at_ms: Option<u64>,
+}
+
+impl Step {
+    pub fn contains(&self, ms: u64) -> bool {
+        ms >= self.started_at_ms && self.ended_at_ms.is_none_or(|end| ms < end)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "started_at_ms": self.started_at_ms,
+            "ended_at_ms": self.ended_at_ms,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Step, String> {
+        let name = value
+            .get("name")
+            .and_then(|v| v.as_str())
+            .filter(|name| !name.trim().is_empty())
+            .ok_or("step without a name")?;
+        let started_at_ms = value
+            .get("started_at_ms")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("step '{}' without a start time", name))?;
+        Ok(Step {
+            name: name.to_string(),
+            started_at_ms,
+            ended_at_ms: value.get("ended_at_ms").and_then(|v| v.as_u64()),
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self.ended_at_ms {
+            Some(end) => format!(
+                "{} to {} ({}s)",
+                format_timestamp(self.started_at_ms / 1000),
+                format_timestamp(end / 1000),
+                end.saturating_sub(self.started_at_ms) / 1000
+            ),
+            None => format!("since {}", format_timestamp(self.started_at_ms / 1000)),
+        }
+    }
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub fn registry_path() -> String {
+    DEFAULT_REGISTRY.to_string()
+}
+
+/// Steps recorded in the registry file, empty when the file does not exist yet.
+pub fn load_steps(path: &str) -> Result<Vec<Step>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Error reading step registry '{}': {}", path, e)),
+    };
+    let document: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid step registry '{}': {}", path, e))?;
+    document
+        .get("steps")
+        .and_then(|steps| steps.as_array())
+        .ok_or_else(|| format!("Invalid step registry '{}': missing steps", path))?
+        .iter()
+        .map(|step| {
+            Step::from_json(step).map_err(|e| format!("Invalid step registry '{}': {}", path, e))
+        })
+        .collect()
+}
+
+pub fn save_steps(path: &str, steps: &[Step]) -> Result<(), String> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+    }
+    let document = json!({
+        "version": REGISTRY_VERSION,
+        "steps": steps.iter().map(Step::to_json).collect::<Vec<_>>(),
+    });
+    let content = serde_json::to_string_pretty(&document)
+        .map_err(|e| format!("Error serializing step registry: {}", e))?;
+    std::fs::write(path, content)
+        .map_err(|e| format!("Error writing step registry '{}': {}", path, e))
+}
+
+/// Start `name` at `now`, ending the step in progress. Returns the ended step.
+pub fn begin_step(steps: &mut Vec<Step>, name: &str, now: u64) -> Option<String> {
+    let ended = steps
+        .iter_mut()
+        .find(|step| step.ended_at_ms.is_none())
+        .map(|step| {
+            step.ended_at_ms = Some(now);
+            step.name.clone()
+        });
+    steps.push(Step {
+        name: name.to_string(),
+        started_at_ms: now,
+        ended_at_ms: None,
+    });
+    ended
+}
+
+/// End the step in progress, which must be `name` when one is given.
+pub fn end_step(steps: &mut [Step], name: Option<&str>, now: u64) -> Result<String, String> {
+    let step = steps
+        .iter_mut()
+        .find(|step| step.ended_at_ms.is_none())
+        .ok_or("No step in progress")?;
+    if let Some(name) = name {
+        if step.name != name {
+            return Err(format!(
+                "Step '{}' is not in progress, the current step is '{}'",
+                name, step.name
+            ));
+        }
+    }
+    step.ended_at_ms = Some(now);
+    Ok(step.name.clone())
+}
+
+/// Step in progress at `ms`.
+pub fn step_at(steps: &[Step], ms: u64) -> Option<&Step> {
+    steps.iter().find(|step| step.contains(ms))
+}
+
+/// Unix milliseconds of the first timestamp found at `pointers`, either an
+/// RFC 3339 string or a number of seconds or milliseconds.
+pub fn item_time_ms(item: &Value, pointers: &[&str]) -> Option<u64> {
+    pointers
+        .iter()
+        .find_map(|pointer| match item.pointer(pointer)? {
+            Value::String(s) => crate::ipfix::parse_rfc3339_millis(s),
+            Value::Number(n) => n
+                .as_u64()
+                .map(|t| if t < 100_000_000_000 { t * 1000 } else { t }),
+            _ => None,
+        })
+}
+
+/// Name of the step an item belongs to, if any.
+pub fn item_step<'a>(steps: &'a [Step], item: &Value, pointers: &[&str]) -> Option<&'a str> {
+    let ms = item_time_ms(item, pointers)?;
+    step_at(steps, ms).map(|step| step.name.as_str())
+}
+
+/// Steps of the registry, checking that `name` was recorded: the filter of
+/// the `--step` options.
+pub fn steps_for_filter(name: &str) -> Result<Vec<Step>, i32> {
+    let path = registry_path();
+    let steps = load_steps(&path).map_err(|e| {
+        eprintln!("{}", e);
+        ERROR_CODE_PARAM
+    })?;
+    if !steps.iter().any(|step| step.name == name) {
+        eprintln!("Unknown step '{}', no such step in '{}'", name, path);
+        return Err(ERROR_CODE_PARAM);
+    }
+    Ok(steps)
+}
+
+/// Start a step, or end the current one with `end`.
+pub fn mark_step(name: Option<&str>, end: bool) -> i32 {
+    let name = name.map(str::trim).filter(|name| !name.is_empty());
+    let path = registry_path();
+    let mut steps = match load_steps(&path) {
+        Ok(steps) => steps,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let now = now_millis();
+    if end {
+        match end_step(&mut steps, name, now) {
+            Ok(ended) => println!("Step '{}' ended at {}", ended, format_timestamp(now / 1000)),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+    } else {
+        let Some(name) = name else {
+            eprintln!("Step name cannot be empty");
+            return ERROR_CODE_PARAM;
+        };
+        if let Some(ended) = begin_step(&mut steps, name, now) {
+            println!("Step '{}' ended", ended);
+        }
+        println!(
+            "Step '{}' started at {}",
+            name,
+            format_timestamp(now / 1000)
+        );
+    }
+    match save_steps(&path, &steps) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            ERROR_CODE_PARAM
+        }
+    }
+}
+
+fn empty_breakdown() -> Value {
+    json!({
+        "sessions": 0,
+        "outbound_bytes": 0,
+        "inbound_bytes": 0,
+        "destinations": {},
+        "violations": {
+            "non_conforming": 0,
+            "blacklisted": 0,
+            "sensitive_file_events": 0,
+            "findings": 0,
+        },
+    })
+}
+
+fn increment(value: &mut Value, by: u64) {
+    *value = json!(value.as_u64().unwrap_or(0) + by);
+}
+
+/// Egress and violations of each step, in the order the steps were recorded.
+/// Items outside every step are counted under `outside_steps`.
+pub fn step_report(
+    steps: &[Step],
+    sessions: &[Value],
+    blacklisted: &HashSet<String>,
+    file_events: &[Value],
+    findings: &[Value],
+) -> Value {
+    // Steps run again under the same name share one breakdown
+    let mut breakdowns: BTreeMap<Option<String>, Value> = BTreeMap::new();
+    let mut bucket = |item: &Value, pointers: &[&str]| -> Option<String> {
+        let step = item_step(steps, item, pointers).map(str::to_string);
+        breakdowns
+            .entry(step.clone())
+            .or_insert_with(empty_breakdown);
+        step
+    };
+
+    let mut assignments = Vec::new();
+    for session in sessions {
+        assignments.push((bucket(session, SESSION_TIME), session, "session"));
+    }
+    for event in file_events {
+        assignments.push((bucket(event, FILE_EVENT_TIME), event, "file_event"));
+    }
+    for finding in findings {
+        assignments.push((bucket(finding, FINDING_TIME), finding, "finding"));
+    }
+
+    for (step, item, kind) in assignments {
+        let breakdown = breakdowns.get_mut(&step).expect("bucket created above");
+        match kind {
+            "session" => {
+                let outbound = session_u64(item, "/stats/outbound_bytes");
+                let inbound = session_u64(item, "/stats/inbound_bytes");
+                increment(&mut breakdown["sessions"], 1);
+                increment(&mut breakdown["outbound_bytes"], outbound);
+                increment(&mut breakdown["inbound_bytes"], inbound);
+                let key = format!(
+                    "{}:{}/{}",
+                    session_destination(item),
+                    session_u64(item, "/session/dst_port"),
+                    session_str(item, "/session/protocol").unwrap_or("")
+                );
+                let destination = breakdown["destinations"]
+                    .as_object_mut()
+                    .expect("destinations is an object")
+                    .entry(key)
+                    .or_insert_with(|| json!({ "sessions": 0, "outbound_bytes": 0 }));
+                increment(&mut destination["sessions"], 1);
+                increment(&mut destination["outbound_bytes"], outbound);
+                if session_str(item, "/is_whitelisted") == Some("NonConforming") {
+                    increment(&mut breakdown["violations"]["non_conforming"], 1);
+                }
+                if session_str(item, "/uid").is_some_and(|uid| blacklisted.contains(uid)) {
+                    increment(&mut breakdown["violations"]["blacklisted"], 1);
+                }
+            }
+            "file_event" => {
+                if item.get("is_sensitive").and_then(|v| v.as_bool()) == Some(true) {
+                    increment(&mut breakdown["violations"]["sensitive_file_events"], 1);
+                }
+            }
+            _ => {
+                if item.get("dismissed").and_then(|v| v.as_bool()) != Some(true) {
+                    increment(&mut breakdown["violations"]["findings"], 1);
+                }
+            }
+        }
+    }
+
+    let mut reported = HashSet::new();
+    let mut step_entries = Vec::new();
+    for step in steps {
+        if !reported.insert(step.name.as_str()) {
+            continue;
+        }
+        let mut breakdown = breakdowns
+            .remove(&Some(step.name.clone()))
+            .unwrap_or_else(empty_breakdown);
+        breakdown["name"] = json!(step.name);
+        breakdown["windows"] = json!(steps
+            .iter()
+            .filter(|s| s.name == step.name)
+            .map(|s| json!({ "started_at_ms": s.started_at_ms, "ended_at_ms": s.ended_at_ms }))
+            .collect::<Vec<_>>());
+        step_entries.push(breakdown);
+    }
+    json!({
+        "steps": step_entries,
+        "outside_steps": breakdowns.remove(&None).unwrap_or_else(empty_breakdown),
+    })
+}
+
+fn print_breakdown(title: &str, breakdown: &Value) {
+    let violations = &breakdown["violations"];
+    println!(
+        "{}: {} session(s), {} bytes out, {} bytes in",
+        title, breakdown["sessions"], breakdown["outbound_bytes"], breakdown["inbound_bytes"]
+    );
+    if let Some(destinations) = breakdown["destinations"].as_object() {
+        for (destination, stats) in destinations {
+            println!(
+                "  {} - {} session(s), {} bytes out",
+                destination, stats["sessions"], stats["outbound_bytes"]
+            );
+        }
+    }
+    println!(
+        "  Violations: {} non-conforming, {} blacklisted, {} sensitive file event(s), {} finding(s)",
+        violations["non_conforming"],
+        violations["blacklisted"],
+        violations["sensitive_file_events"],
+        violations["findings"]
+    );
+}
+
+pub fn print_step_report(steps: &[Step], report: &Value) {
+    if steps.is_empty() {
+        println!("No steps recorded in '{}'", registry_path());
+    }
+    for entry in report["steps"].as_array().into_iter().flatten() {
+        let name = entry["name"].as_str().unwrap_or("");
+        let windows: Vec<String> = steps
+            .iter()
+            .filter(|step| step.name == name)
+            .map(Step::describe)
+            .collect();
+        print_breakdown(&format!("Step '{}' ({})", name, windows.join(", ")), entry);
+    }
+    print_breakdown("Outside steps", &report["outside_steps"]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_follow_each_other() {
+        let mut steps = Vec::new();
+        assert_eq!(begin_step(&mut steps, "install", 1_000), None);
+        assert_eq!(
+            begin_step(&mut steps, "test", 5_000),
+            Some("install".to_string())
+        );
+        assert!(end_step(&mut steps, Some("install"), 9_000).is_err());
+        assert_eq!(end_step(&mut steps, None, 9_000), Ok("test".to_string()));
+        assert!(end_step(&mut steps, None, 9_500).is_err());
+        begin_step(&mut steps, "install", 10_000);
+
+        assert_eq!(step_at(&steps, 500), None);
+        assert_eq!(
+            step_at(&steps, 4_999).map(|s| s.name.as_str()),
+            Some("install")
+        );
+        assert_eq!(
+            step_at(&steps, 5_000).map(|s| s.name.as_str()),
+            Some("test")
+        );
+        assert_eq!(step_at(&steps, 9_200), None);
+        assert_eq!(
+            step_at(&steps, 60_000).map(|s| s.name.as_str()),
+            Some("install")
+        );
+
+        let parsed: Vec<Step> = steps
+            .iter()
+            .map(|step| Step::from_json(&step.to_json()).unwrap())
+            .collect();
+        assert_eq!(parsed, steps);
+    }
+
+    #[test]
+    fn damaged_registries_are_reported_rather_than_reset() {
+        let path = std::env::temp_dir()
+            .join(format!("steps-{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        assert!(load_steps(&path).unwrap().is_empty());
+
+        let mut steps = Vec::new();
+        begin_step(&mut steps, "build", 1_000);
+        save_steps(&path, &steps).unwrap();
+        assert_eq!(load_steps(&path).unwrap(), steps);
+
+        // mark-step would otherwise overwrite the steps of the job
+        for (content, error) in [
+            ("{\"version\": 1, \"steps\": [", "Invalid step registry"),
+            ("{\"version\": 1}", "missing steps"),
+            (
+                "{\"steps\": [{\"name\": \" \", \"started_at_ms\": 1}]}",
+                "step without a name",
+            ),
+            (
+                "{\"steps\": [{\"name\": \"build\"}]}",
+                "step 'build' without a start time",
+            ),
+        ] {
+            std::fs::write(&path, content).unwrap();
+            let e = load_steps(&path).unwrap_err();
+            assert!(e.contains(&path) && e.contains(error), "{}", e);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn item_times_accept_seconds_milliseconds_and_rfc3339() {
+        let steps = vec![Step {
+            name: "test".to_string(),
+            started_at_ms: 1_779_098_400_000,
+            ended_at_ms: Some(1_779_098_460_000),
+        }];
+        for time in [
+            json!(1_779_098_410),
+            json!(1_779_098_410_000u64),
+            json!("2026-05-18T10:00:10Z"),
+        ] {
+            let finding = json!({ "first_seen": time });
+            assert_eq!(item_step(&steps, &finding, FINDING_TIME), Some("test"));
+        }
+        // The first pointer present wins, even when a later one is in the step
+        let finding = json!({ "first_seen": 1_779_098_300, "created_at": 1_779_098_410 });
+        assert_eq!(item_step(&steps, &finding, FINDING_TIME), None);
+        // Items without a usable time belong to no step
+        assert_eq!(
+            item_step(&steps, &json!({ "first_seen": "soon" }), FINDING_TIME),
+            None
+        );
+        assert_eq!(item_step(&steps, &json!({}), SESSION_TIME), None);
+    }
+
+    #[test]
+    fn report_breaks_down_egress_per_step() {
+        let steps = vec![
+            Step {
+                name: "install".to_string(),
+                started_at_ms: 1_779_098_400_000,
+                ended_at_ms: Some(1_779_098_460_000),
+            },
+            Step {
+                name: "deploy".to_string(),
+                started_at_ms: 1_779_098_460_000,
+                ended_at_ms: None,
+            },
+        ];
+        let session = |uid: &str, start: &str, state: &str| {
+            json!({
+                "uid": uid,
+                "dst_domain": "registry.npmjs.org",
+                "session": { "dst_port": 443, "protocol": "TCP" },
+                "stats": { "start_time": start, "outbound_bytes": 100, "inbound_bytes": 900 },
+                "is_whitelisted": state,
+            })
+        };
+        let sessions = vec![
+            session("a", "2026-05-18T10:00:10Z", "Conforming"),
+            session("b", "2026-05-18T10:00:20Z", "Conforming"),
+            session("c", "2026-05-18T10:01:30Z", "NonConforming"),
+            session("d", "2026-05-18T09:59:00Z", "Conforming"),
+        ];
+        let blacklisted = HashSet::from(["c".to_string()]);
+        let events = vec![json!({ "timestamp": "2026-05-18T10:00:30Z", "is_sensitive": true })];
+        let findings = vec![json!({ "detected_at": 1_779_098_500, "dismissed": false })];
+
+        let report = step_report(&steps, &sessions, &blacklisted, &events, &findings);
+        let install = &report["steps"][0];
+        assert_eq!(install["name"], "install");
+        assert_eq!(install["sessions"], 2);
+        assert_eq!(install["outbound_bytes"], 200);
+        assert_eq!(
+            install["destinations"]["registry.npmjs.org:443/TCP"]["sessions"],
+            2
+        );
+        assert_eq!(install["violations"]["sensitive_file_events"], 1);
+        let deploy = &report["steps"][1];
+        assert_eq!(deploy["violations"]["non_conforming"], 1);
+        assert_eq!(deploy["violations"]["blacklisted"], 1);
+        assert_eq!(deploy["violations"]["findings"], 1);
+        assert_eq!(report["outside_steps"]["sessions"], 1);
+    }
+}
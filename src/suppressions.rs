@@ -0,0 +1,680 @@
+// Repository suppression file.
+//
+// Accepted risks can be kept next to the pipeline that causes them, in a
+// `.edamame-suppressions.yaml` file that goes through code review like the rest
+// of the repository. The start commands pick the file up from `--suppressions`
+// or from the working directory, and the daemon applies the entries through the
+// dismiss APIs as matching findings, evidence and sessions appear:
+//
+//   suppressions:
+//     - type: vulnerability
+//       check: credential_access
+//       process: "python*"
+//       justification: Test fixtures read the fake credentials on purpose
+//       owner: "@platform-team"
+//     - type: session
+//       process: cargo
+//       domain: "*.crates.io"
+//       justification: Dependency downloads
+//       owner: "@platform-team"
+//
+// Session entries can also match a `label` given by the label file (see
+// crate::labels) the daemon was started with.
+//
+// Patterns support `*` (any run of characters, including `.` and `/`) and `?`.
+// Process and domain patterns are case-insensitive. File entries have no
+// dismiss API behind them: the daemon publishes the ones it loaded to a state
+// file, and `get-file-events` honors those and only those, so that a file in the
+// working directory of the caller cannot excuse suspicious events.
+
+use crate::labels::has_label;
+use crate::sessions::{session_process, session_str};
+use crate::whitelists::wildcard_matches;
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+
+pub const SUPPRESSIONS_ENV: &str = "EDAMAME_SUPPRESSIONS_FILE";
+pub const DEFAULT_SUPPRESSIONS_FILE: &str = ".edamame-suppressions.yaml";
+// File entries of the daemon, written at startup; no environment override so
+// that the caller of get-file-events cannot point it elsewhere
+#[cfg(not(windows))]
+pub const DAEMON_FILE_ENTRIES: &str = "/var/lib/edamame_posture/file_suppressions.json";
+#[cfg(windows)]
+pub const DAEMON_FILE_ENTRIES: &str = r"C:\ProgramData\edamame_posture\file_suppressions.json";
+const DAEMON_FILE_ENTRIES_VERSION: u64 = 1;
+// Matched ids listed per entry in reports
+const REPORTED_IDS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    Vulnerability {
+        check: String,
+        process: Option<String>,
+    },
+    Divergence {
+        key: String,
+    },
+    Session {
+        process: Option<String>,
+        domain: Option<String>,
+        // Label of the session, see crate::labels
+        label: Option<String>,
+    },
+    File {
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suppression {
+    pub matcher: Matcher,
+    pub justification: String,
+    pub owner: String,
+}
+
+/// Finding, evidence, session or file event matched by a suppression entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    // Finding key, evidence key, session UID or file path
+    pub id: String,
+    pub dismissed: bool,
+}
+
+/// What the daemon currently reports, in the shape the entries are matched on.
+#[derive(Debug, Default)]
+pub struct Observations {
+    pub findings: Vec<Value>,
+    pub divergence: Vec<Value>,
+    pub sessions: Vec<Value>,
+    pub file_paths: Vec<String>,
+}
+
+impl Matcher {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Matcher::Vulnerability { .. } => "vulnerability",
+            Matcher::Divergence { .. } => "divergence",
+            Matcher::Session { .. } => "session",
+            Matcher::File { .. } => "file",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Matcher::Vulnerability { check, process } => match process {
+                Some(process) => format!("vulnerability check {} process {}", check, process),
+                None => format!("vulnerability check {}", check),
+            },
+            Matcher::Divergence { key } => format!("divergence evidence {}", key),
+            Matcher::Session {
+                process,
+                domain,
+                label,
+            } => {
+                let sessions = match (process, domain) {
+                    (Some(process), Some(domain)) => {
+                        format!("sessions of process {} to {}", process, domain)
+                    }
+                    (Some(process), None) => format!("sessions of process {}", process),
+                    (None, Some(domain)) => format!("sessions to {}", domain),
+                    (None, None) => "sessions".to_string(),
+                };
+                match label {
+                    Some(label) => format!("{} labeled {}", sessions, label),
+                    None => sessions,
+                }
+            }
+            Matcher::File { path } => format!("file events on {}", path),
+        }
+    }
+}
+
+fn matches_ignore_case(pattern: &str, value: &str) -> bool {
+    wildcard_matches(&pattern.to_lowercase(), &value.to_lowercase())
+}
+
+fn keyed_hit(item: &Value) -> Option<Hit> {
+    Some(Hit {
+        id: item["finding_key"].as_str()?.to_string(),
+        dismissed: item["dismissed"].as_bool().unwrap_or(false),
+    })
+}
+
+impl Suppression {
+    pub fn hits(&self, observations: &Observations) -> Vec<Hit> {
+        match &self.matcher {
+            Matcher::Vulnerability { check, process } => observations
+                .findings
+                .iter()
+                .filter(|finding| {
+                    finding["check"]
+                        .as_str()
+                        .is_some_and(|c| wildcard_matches(check, c))
+                })
+                .filter(|finding| {
+                    process.as_ref().is_none_or(|process| {
+                        ["process_name", "process_path"].iter().any(|field| {
+                            finding[*field]
+                                .as_str()
+                                .is_some_and(|p| matches_ignore_case(process, p))
+                        })
+                    })
+                })
+                .filter_map(keyed_hit)
+                .collect(),
+            Matcher::Divergence { key } => observations
+                .divergence
+                .iter()
+                .filter_map(keyed_hit)
+                .filter(|hit| wildcard_matches(key, &hit.id))
+                .collect(),
+            Matcher::Session {
+                process,
+                domain,
+                label,
+            } => observations
+                .sessions
+                .iter()
+                .filter(|session| {
+                    label
+                        .as_ref()
+                        .is_none_or(|label| has_label(session, std::slice::from_ref(label)))
+                })
+                .filter(|session| {
+                    process.as_ref().is_none_or(|process| {
+                        session_process(session).is_some_and(|p| matches_ignore_case(process, p))
+                    })
+                })
+                .filter(|session| {
+                    domain.as_ref().is_none_or(|domain| {
+                        session_str(session, "/dst_domain")
+                            .is_some_and(|d| matches_ignore_case(domain, d))
+                    })
+                })
+                .filter_map(|session| {
+                    Some(Hit {
+                        id: session["uid"].as_str()?.to_string(),
+                        dismissed: false,
+                    })
+                })
+                .collect(),
+            Matcher::File { path } => observations
+                .file_paths
+                .iter()
+                .filter(|p| wildcard_matches(path, p))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(|p| Hit {
+                    id: p.clone(),
+                    dismissed: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Objects carrying a `finding_key`, wherever they sit in a findings report
+/// or a divergence verdict.
+pub fn keyed_items(report: &Value) -> Vec<Value> {
+    let mut items = Vec::new();
+    let mut pending = vec![report];
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::Object(map) => {
+                if map.get("finding_key").is_some_and(Value::is_string) {
+                    items.push(value.clone());
+                }
+                pending.extend(map.values());
+            }
+            Value::Array(values) => pending.extend(values.iter()),
+            _ => {}
+        }
+    }
+    items
+}
+
+fn pattern_field(entry: &Value, field: &str) -> Result<Option<String>, String> {
+    match &entry[field] {
+        Value::Null => Ok(None),
+        Value::String(s) if !s.trim().is_empty() => Ok(Some(s.trim().to_string())),
+        _ => Err(format!("'{}' must be a non-empty string", field)),
+    }
+}
+
+fn required_field(entry: &Value, field: &str) -> Result<String, String> {
+    pattern_field(entry, field)?.ok_or_else(|| format!("missing '{}'", field))
+}
+
+fn parse_entry(entry: &Value) -> Result<Suppression, String> {
+    let map = entry
+        .as_object()
+        .ok_or_else(|| "entry must be a mapping".to_string())?;
+    let kind = required_field(entry, "type")?;
+    let fields: &[&str] = match kind.as_str() {
+        "vulnerability" => &["check", "process"],
+        "divergence" => &["key"],
+        "session" => &["process", "domain", "label"],
+        "file" => &["path"],
+        _ => {
+            return Err(format!(
+                "unknown type '{}', expected vulnerability, divergence, session or file",
+                kind
+            ))
+        }
+    };
+    // A misspelled field would silently widen the suppression
+    if let Some(unknown) = map.keys().find(|key| {
+        !fields.contains(&key.as_str())
+            && !["type", "justification", "owner"].contains(&key.as_str())
+    }) {
+        return Err(format!("unknown field '{}' for type {}", unknown, kind));
+    }
+    let matcher = match kind.as_str() {
+        "vulnerability" => Matcher::Vulnerability {
+            check: required_field(entry, "check")?,
+            process: pattern_field(entry, "process")?,
+        },
+        "divergence" => Matcher::Divergence {
+            key: required_field(entry, "key")?,
+        },
+        "session" => {
+            let process = pattern_field(entry, "process")?;
+            let domain = pattern_field(entry, "domain")?;
+            let label = pattern_field(entry, "label")?;
+            if process.is_none() && domain.is_none() && label.is_none() {
+                return Err("session entries need a 'process', a 'domain' or a 'label'".to_string());
+            }
+            Matcher::Session {
+                process,
+                domain,
+                label,
+            }
+        }
+        _ => Matcher::File {
+            path: required_field(entry, "path")?,
+        },
+    };
+    Ok(Suppression {
+        matcher,
+        justification: required_field(entry, "justification")?,
+        owner: required_field(entry, "owner")?,
+    })
+}
+
+pub fn parse_suppressions(document: &Value) -> Result<Vec<Suppression>, String> {
+    let entries = match &document["suppressions"] {
+        Value::Array(entries) => entries,
+        Value::Null if document.is_null() || document.is_object() => return Ok(Vec::new()),
+        _ => return Err("'suppressions' must be a list of entries".to_string()),
+    };
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| parse_entry(entry).map_err(|e| format!("suppressions[{}]: {}", i, e)))
+        .collect()
+}
+
+pub fn load_suppressions(path: &str) -> Result<Vec<Suppression>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Error reading suppression file '{}': {}", path, e))?;
+    let document: Value = serde_yaml_ng::from_str(&content)
+        .map_err(|e| format!("Error parsing suppression file '{}': {}", path, e))?;
+    parse_suppressions(&document).map_err(|e| format!("Invalid suppression file '{}': {}", path, e))
+}
+
+/// The suppression file given explicitly, or the one in the working directory.
+pub fn resolve_path(explicit: Option<&str>) -> Option<String> {
+    match explicit.filter(|p| !p.trim().is_empty()) {
+        Some(path) => Some(path.to_string()),
+        None => Path::new(DEFAULT_SUPPRESSIONS_FILE)
+            .is_file()
+            .then(|| DEFAULT_SUPPRESSIONS_FILE.to_string()),
+    }
+}
+
+/// Validate the suppression file of a start command and hand its absolute path
+/// to the daemon, which runs from another directory once forked.
+pub fn prepare_for_daemon(explicit: Option<&str>) -> i32 {
+    let Some(path) = resolve_path(explicit) else {
+        std::env::remove_var(SUPPRESSIONS_ENV);
+        return 0;
+    };
+    let entries = match load_suppressions(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let absolute = match std::fs::canonicalize(&path) {
+        Ok(absolute) => absolute.to_string_lossy().to_string(),
+        Err(e) => {
+            eprintln!("Error resolving suppression file '{}': {}", path, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    println!("Loaded {} suppressions from {}", entries.len(), absolute);
+    std::env::set_var(SUPPRESSIONS_ENV, &absolute);
+    0
+}
+
+/// Path patterns of the file entries.
+pub fn file_patterns(entries: &[Suppression]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| match &entry.matcher {
+            Matcher::File { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Record the file entries of the daemon at `path`, through a temporary file so
+/// that readers never see a partial one. A daemon without suppressions records
+/// none, which clears the entries of a previous daemon.
+pub fn publish_file_entries(path: &str, active: Option<&ActiveSuppressions>) -> Result<(), String> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating '{}': {}", parent.display(), e))?;
+        }
+    }
+    let document = json!({
+        "version": DAEMON_FILE_ENTRIES_VERSION,
+        "file": active.map(|active| active.path.clone()),
+        "paths": active.map(|active| file_patterns(&active.entries)).unwrap_or_default(),
+    });
+    let content = serde_json::to_string_pretty(&document)
+        .map_err(|e| format!("Error serializing file suppressions: {}", e))?;
+    let temp = format!("{}.tmp", path);
+    std::fs::write(&temp, content)
+        .and_then(|_| std::fs::rename(&temp, path))
+        .map_err(|e| format!("Error writing file suppressions '{}': {}", path, e))
+}
+
+/// Path patterns published by the daemon at `path`; none when it never
+/// published any.
+pub fn published_file_entries(path: &str) -> Result<Vec<String>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Error reading file suppressions '{}': {}", path, e)),
+    };
+    let document: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Error parsing file suppressions '{}': {}", path, e))?;
+    if document["version"].as_u64() != Some(DAEMON_FILE_ENTRIES_VERSION) {
+        return Err(format!(
+            "Unsupported file suppressions version in '{}'",
+            path
+        ));
+    }
+    document["paths"]
+        .as_array()
+        .and_then(|paths| {
+            paths
+                .iter()
+                .map(|p| p.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| {
+            format!(
+                "Invalid file suppressions '{}': 'paths' must be a list of strings",
+                path
+            )
+        })
+}
+
+/// Suppressions applied by the daemon and the state needed to report changes
+/// once per transition rather than on every pass.
+pub struct ActiveSuppressions {
+    pub path: String,
+    pub entries: Vec<Suppression>,
+    // "<kind>:<id>" of everything dismissed so far
+    pub applied: HashSet<String>,
+    matched: Vec<bool>,
+}
+
+impl ActiveSuppressions {
+    pub fn new(path: &str, entries: Vec<Suppression>) -> ActiveSuppressions {
+        ActiveSuppressions {
+            path: path.to_string(),
+            matched: vec![false; entries.len()],
+            entries,
+            applied: HashSet::new(),
+        }
+    }
+
+    /// Record which entries matched in this pass and return the indices of the
+    /// entries that matched before and no longer match anything.
+    pub fn update_matches(&mut self, hits: &[Vec<Hit>]) -> Vec<usize> {
+        let mut stale = Vec::new();
+        for (i, entry_hits) in hits.iter().enumerate() {
+            let matched = !entry_hits.is_empty();
+            if self.matched[i] && !matched {
+                stale.push(i);
+            }
+            self.matched[i] = matched;
+        }
+        stale
+    }
+}
+
+/// Per-entry match report; entries matching nothing are listed as stale.
+pub fn suppression_report(path: &str, entries: &[Suppression], hits: &[Vec<Hit>]) -> Value {
+    let report: Vec<Value> = entries
+        .iter()
+        .zip(hits)
+        .enumerate()
+        .map(|(i, (entry, entry_hits))| {
+            json!({
+                "index": i,
+                "type": entry.matcher.kind(),
+                "description": entry.matcher.describe(),
+                "owner": entry.owner,
+                "justification": entry.justification,
+                "matches": entry_hits.len(),
+                "matched_ids": entry_hits
+                    .iter()
+                    .take(REPORTED_IDS)
+                    .map(|hit| hit.id.clone())
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let stale: Vec<usize> = hits
+        .iter()
+        .enumerate()
+        .filter(|(_, entry_hits)| entry_hits.is_empty())
+        .map(|(i, _)| i)
+        .collect();
+    json!({
+        "file": path,
+        "entries": report,
+        "stale": stale,
+    })
+}
+
+pub fn print_suppression_report(report: &Value) {
+    println!(
+        "Suppressions from {}",
+        report["file"].as_str().unwrap_or("")
+    );
+    let empty = Vec::new();
+    for entry in report["entries"].as_array().unwrap_or(&empty) {
+        let matches = entry["matches"].as_u64().unwrap_or(0);
+        let status = if matches == 0 {
+            "STALE, matches nothing".to_string()
+        } else {
+            format!("{} matches", matches)
+        };
+        println!(
+            "  [{}] {}: {} (owner {})",
+            entry["index"],
+            entry["description"].as_str().unwrap_or(""),
+            status,
+            entry["owner"].as_str().unwrap_or("")
+        );
+        println!(
+            "      justification: {}",
+            entry["justification"].as_str().unwrap_or("")
+        );
+    }
+    let stale = report["stale"].as_array().map_or(0, |s| s.len());
+    if stale > 0 {
+        println!(
+            "{} suppressions match nothing and can be removed from the file",
+            stale
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(yaml: &str) -> Result<Vec<Suppression>, String> {
+        parse_suppressions(&serde_yaml_ng::from_str::<Value>(yaml).unwrap())
+    }
+
+    #[test]
+    fn entries_require_justification_and_owner() {
+        let parsed = entries(
+            r#"
+suppressions:
+  - type: vulnerability
+    check: credential_access
+    process: "python*"
+    justification: Fixtures read fake credentials
+    owner: "@platform"
+  - type: file
+    path: /home/runner/.ssh/*
+    justification: Deploy key
+    owner: ops
+"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].matcher,
+            Matcher::Vulnerability {
+                check: "credential_access".to_string(),
+                process: Some("python*".to_string()),
+            }
+        );
+        assert_eq!(parsed[1].owner, "ops");
+
+        let missing_owner =
+            "suppressions:\n  - type: divergence\n    key: k\n    justification: j\n";
+        assert_eq!(
+            entries(missing_owner).unwrap_err(),
+            "suppressions[0]: missing 'owner'"
+        );
+        let typo = "suppressions:\n  - type: session\n    proces: curl\n    domain: x\n    justification: j\n    owner: o\n";
+        assert_eq!(
+            entries(typo).unwrap_err(),
+            "suppressions[0]: unknown field 'proces' for type session"
+        );
+        let no_pattern = "suppressions:\n  - type: session\n    justification: j\n    owner: o\n";
+        assert!(entries(no_pattern).is_err());
+        assert!(entries("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn entries_match_observations() {
+        let observations = Observations {
+            findings: keyed_items(&json!({
+                "findings": [
+                    { "finding_key": "f1", "check": "credential_access", "process_name": "Python3", "dismissed": false },
+                    { "finding_key": "f2", "check": "credential_access", "process_name": "node", "dismissed": true },
+                ]
+            })),
+            divergence: keyed_items(&json!({
+                "verdict": { "evidence": [ { "finding_key": "egress:api.example.com" } ] }
+            })),
+            sessions: vec![
+                json!({ "uid": "s1", "dst_domain": "index.crates.io", "l7": { "process_name": "cargo" }, "labels": ["corp-mirror"] }),
+                json!({ "uid": "s2", "dst_domain": "evil.example", "l7": { "process_name": "cargo" } }),
+            ],
+            file_paths: vec![
+                "/home/runner/.ssh/id_ed25519".to_string(),
+                "/home/runner/.ssh/id_ed25519".to_string(),
+            ],
+        };
+        let parsed = entries(
+            r#"
+suppressions:
+  - { type: vulnerability, check: "credential_*", process: "python*", justification: j, owner: o }
+  - { type: divergence, key: "egress:*", justification: j, owner: o }
+  - { type: session, process: cargo, domain: "*.crates.io", justification: j, owner: o }
+  - { type: file, path: "/home/runner/.ssh/*", justification: j, owner: o }
+  - { type: vulnerability, check: "memory_*", justification: j, owner: o }
+  - { type: session, label: "corp-*", justification: j, owner: o }
+"#,
+        )
+        .unwrap();
+        let hits: Vec<Vec<Hit>> = parsed.iter().map(|e| e.hits(&observations)).collect();
+        assert_eq!(
+            hits[0],
+            vec![Hit {
+                id: "f1".to_string(),
+                dismissed: false
+            }]
+        );
+        assert_eq!(hits[1][0].id, "egress:api.example.com");
+        assert_eq!(hits[2].len(), 1);
+        assert_eq!(hits[2][0].id, "s1");
+        assert_eq!(hits[3].len(), 1);
+        assert!(hits[4].is_empty());
+        assert_eq!(hits[5].len(), 1);
+        assert_eq!(hits[5][0].id, "s1");
+
+        let report = suppression_report("s.yaml", &parsed, &hits);
+        assert_eq!(report["stale"], json!([4]));
+
+        let mut active = ActiveSuppressions::new("s.yaml", parsed);
+        assert!(active.update_matches(&hits).is_empty());
+        let mut later = hits.clone();
+        later[2].clear();
+        assert_eq!(active.update_matches(&later), vec![2]);
+        assert!(active.update_matches(&later).is_empty());
+    }
+
+    #[test]
+    fn file_events_only_honor_what_the_daemon_published() {
+        let path = std::env::temp_dir()
+            .join(format!("file-suppressions-{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        // No daemon ever published entries: nothing is suppressed
+        assert!(published_file_entries(&path).unwrap().is_empty());
+
+        let parsed = entries(
+            r#"
+suppressions:
+  - { type: file, path: "/home/runner/.ssh/*", justification: j, owner: o }
+  - { type: divergence, key: "egress:*", justification: j, owner: o }
+"#,
+        )
+        .unwrap();
+        let active = ActiveSuppressions::new("/repo/.edamame-suppressions.yaml", parsed);
+        publish_file_entries(&path, Some(&active)).unwrap();
+        assert_eq!(
+            published_file_entries(&path).unwrap(),
+            vec!["/home/runner/.ssh/*".to_string()]
+        );
+
+        // A daemon restarted without suppressions clears the previous entries
+        publish_file_entries(&path, None).unwrap();
+        assert!(published_file_entries(&path).unwrap().is_empty());
+
+        // A damaged record fails rather than suppressing nothing or everything
+        std::fs::write(&path, r#"{"version": 1, "paths": ["/a", 3]}"#).unwrap();
+        assert!(published_file_entries(&path).is_err());
+        std::fs::write(&path, "not json").unwrap();
+        assert!(published_file_entries(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+// Bounded waits of foreground commands.
+//
+// Every blocking wait of a command goes through `pause` or `wait_for`, which
+// check in slices of 100ms whether the global `--timeout` (EDAMAME_TIMEOUT) has
+// passed or Ctrl-C was pressed, and return `Interrupted` instead of hanging.
+// `report` explains the interruption and gives the exit code, 4. With
+// `--progress-json`, progress is reported on stderr as JSON lines:
+//
+//   {"event":"progress","task":"LAN scan","percent":40,"elapsed_secs":12}
+//   {"event":"timeout","task":"LAN scan","timeout_secs":300}
+//
+// A second Ctrl-C runs the cleanups registered with `on_interrupt`, e.g.
+// stopping a capture, and exits at once.
+
+use crate::ERROR_CODE_TIMEOUT;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Longest sleep between two checks of Ctrl-C and of the deadline.
+const SLICE: Duration = Duration::from_millis(100);
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static JSON_PROGRESS: AtomicBool = AtomicBool::new(false);
+static DEADLINE: Mutex<Option<(Instant, u64)>> = Mutex::new(None);
+static CLEANUPS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+static HANDLER: Once = Once::new();
+
+/// Why a wait returned before the awaited condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupted {
+    TimedOut,
+    Cancelled,
+}
+
+/// State of the awaited operation, as seen by one poll.
+pub enum Poll {
+    Ready,
+    Pending(Option<u64>),
+}
+
+/// Apply the global `--timeout` (seconds from now) and `--progress-json`.
+pub fn configure(timeout: Option<u64>, json_progress: bool) {
+    if let Some(timeout) = timeout {
+        set_deadline(timeout);
+    }
+    if json_progress {
+        JSON_PROGRESS.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Use `timeout` as the deadline of the command unless `--timeout` was given.
+pub fn default_timeout(timeout: u64) {
+    let unset = DEADLINE.lock().map(|d| d.is_none()).unwrap_or(false);
+    if unset {
+        set_deadline(timeout);
+    }
+}
+
+fn set_deadline(timeout: u64) {
+    if let Ok(mut deadline) = DEADLINE.lock() {
+        *deadline = Some((Instant::now() + Duration::from_secs(timeout), timeout));
+    }
+}
+
+pub fn json_progress() -> bool {
+    JSON_PROGRESS.load(Ordering::SeqCst)
+}
+
+/// Catch Ctrl-C in foreground commands: the running wait stops at its next
+/// check and returns `Interrupted::Cancelled`. A second Ctrl-C runs the
+/// registered cleanups and exits at once.
+pub fn handle_interrupts() {
+    HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if CANCELLED.swap(true, Ordering::SeqCst) {
+                run_cleanups();
+                std::process::exit(ERROR_CODE_TIMEOUT);
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: Ctrl-C will not be handled: {}", e);
+        }
+    });
+}
+
+/// Register an action undoing a side effect of the command, such as a running
+/// capture, for when it is cut short.
+pub fn on_interrupt(cleanup: fn()) {
+    if let Ok(mut cleanups) = CLEANUPS.lock() {
+        cleanups.push(cleanup);
+    }
+}
+
+fn run_cleanups() {
+    let cleanups = match CLEANUPS.lock() {
+        Ok(mut cleanups) => std::mem::take(&mut *cleanups),
+        Err(_) => return,
+    };
+    for cleanup in cleanups {
+        cleanup();
+    }
+}
+
+fn check(limit: Option<Instant>) -> Result<(), Interrupted> {
+    if CANCELLED.load(Ordering::SeqCst) {
+        return Err(Interrupted::Cancelled);
+    }
+    let now = Instant::now();
+    let global = DEADLINE.lock().ok().and_then(|d| d.map(|(at, _)| at));
+    if [limit, global].into_iter().flatten().any(|at| now >= at) {
+        return Err(Interrupted::TimedOut);
+    }
+    Ok(())
+}
+
+/// Sleep for `duration`, returning early on Ctrl-C or past the deadline.
+pub fn pause(duration: Duration) -> Result<(), Interrupted> {
+    pause_until(Instant::now() + duration, None)
+}
+
+fn pause_until(until: Instant, limit: Option<Instant>) -> Result<(), Interrupted> {
+    loop {
+        check(limit)?;
+        let now = Instant::now();
+        if now >= until {
+            return Ok(());
+        }
+        sleep(SLICE.min(until - now));
+    }
+}
+
+/// Poll every `interval` until `poll` reports `Poll::Ready`, for at most
+/// `limit` and within the global deadline. Progress percentages are shown
+/// on a progress bar when `bar` is set, or as JSON events on stderr.
+pub fn wait_for(
+    task: &str,
+    limit: Option<Duration>,
+    interval: Duration,
+    bar: bool,
+    mut poll: impl FnMut() -> Poll,
+) -> Result<(), Interrupted> {
+    let started = Instant::now();
+    let limit = limit.map(|limit| started + limit);
+    let pb = (bar && !json_progress()).then(|| {
+        let pb = ProgressBar::new(100);
+        pb.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} ({eta})")
+            .expect("failed to set progress style")
+            .progress_chars("#>-"));
+        pb
+    });
+    let mut last_percent = None;
+    loop {
+        let percent = match poll() {
+            Poll::Ready => {
+                if let Some(pb) = &pb {
+                    pb.finish_and_clear();
+                }
+                emit(json!({
+                    "event": "done",
+                    "task": task,
+                    "elapsed_secs": started.elapsed().as_secs(),
+                }));
+                return Ok(());
+            }
+            Poll::Pending(percent) => percent,
+        };
+        if let (Some(pb), Some(percent)) = (&pb, percent) {
+            pb.set_position(percent);
+        }
+        if percent != last_percent {
+            last_percent = percent;
+            emit(json!({
+                "event": "progress",
+                "task": task,
+                "percent": percent,
+                "elapsed_secs": started.elapsed().as_secs(),
+            }));
+        }
+        if let Err(interrupted) = pause_until(Instant::now() + interval, limit) {
+            if let Some(pb) = &pb {
+                pb.abandon();
+            }
+            return Err(interrupted);
+        }
+    }
+}
+
+/// Explain why `task` stopped and return `ERROR_CODE_TIMEOUT`.
+pub fn report(task: &str, interrupted: Interrupted) -> i32 {
+    match interrupted {
+        Interrupted::Cancelled => {
+            emit(json!({ "event": "cancelled", "task": task }));
+            eprintln!("{} cancelled", task);
+        }
+        Interrupted::TimedOut => {
+            // The command timeout, rather than the limit of this wait, ran out
+            let timeout = DEADLINE
+                .lock()
+                .ok()
+                .and_then(|d| d.filter(|(at, _)| Instant::now() >= *at))
+                .map(|(_, secs)| secs);
+            emit(json!({ "event": "timeout", "task": task, "timeout_secs": timeout }));
+            match timeout {
+                Some(secs) => eprintln!("{} did not complete within {} seconds", task, secs),
+                None => eprintln!("{} timed out", task),
+            }
+        }
+    }
+    ERROR_CODE_TIMEOUT
+}
+
+fn emit(event: Value) {
+    if json_progress() {
+        eprintln!("{}", event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_are_bounded() {
+        let mut polls = 0;
+        let outcome = wait_for("test", Some(Duration::from_secs(5)), SLICE, false, || {
+            polls += 1;
+            if polls == 3 {
+                Poll::Ready
+            } else {
+                Poll::Pending(Some(polls * 30))
+            }
+        });
+        assert_eq!(outcome, Ok(()));
+        assert_eq!(polls, 3);
+
+        let started = Instant::now();
+        let outcome = wait_for(
+            "test",
+            Some(Duration::from_millis(250)),
+            Duration::from_secs(60),
+            false,
+            || Poll::Pending(None),
+        );
+        assert_eq!(outcome, Err(Interrupted::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
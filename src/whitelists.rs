@@ -0,0 +1,1855 @@
+// Custom whitelist tooling that runs locally on whitelist JSON files: linting,
+// structured diffs, N-way merge support and per-session match explanations.
+//
+// The linter mirrors the flodbadd whitelist schema (see "Whitelist Structure"
+// in README.md) and reports each problem with the JSON path of the offending
+// value, e.g. `$.whitelists[1].endpoints[4].port`.
+
+use crate::sessions::{session_process, session_str};
+use crate::ERROR_CODE_MISMATCH;
+use crate::ERROR_CODE_PARAM;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+
+const ENDPOINT_FIELDS: [&str; 9] = [
+    "domain",
+    "ip",
+    "port",
+    "protocol",
+    "as_number",
+    "as_country",
+    "as_owner",
+    "process",
+    "description",
+];
+const KNOWN_PROTOCOLS: [&str; 4] = ["TCP", "UDP", "ICMP", "ICMPV6"];
+// CIDR ranges shorter than these prefixes are reported as overly broad
+const MIN_IPV4_PREFIX: u8 = 16;
+const MIN_IPV6_PREFIX: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct LintIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+/// Endpoint fields relevant to matching, as parsed from the JSON.
+#[derive(Debug, Clone, Default)]
+pub struct WhitelistEndpoint {
+    pub domain: Option<String>,
+    pub ip: Option<String>,
+    pub port: Option<u64>,
+    pub protocol: Option<String>,
+    pub as_number: Option<u64>,
+    pub as_country: Option<String>,
+    pub as_owner: Option<String>,
+    pub process: Option<String>,
+    pub path: String,
+}
+
+impl WhitelistEndpoint {
+    fn has_as_criteria(&self) -> bool {
+        self.as_number.is_some() || self.as_country.is_some() || self.as_owner.is_some()
+    }
+
+    // Matching criteria only, normalized for comparison (description is ignored)
+    fn key(&self) -> String {
+        let lower = |v: &Option<String>| v.as_deref().unwrap_or("").to_lowercase();
+        format!(
+            "{}|{}|{:?}|{}|{:?}|{}|{}|{}",
+            lower(&self.domain),
+            lower(&self.ip),
+            self.port,
+            lower(&self.protocol),
+            self.as_number,
+            lower(&self.as_country),
+            lower(&self.as_owner),
+            lower(&self.process)
+        )
+    }
+
+    // Destination the endpoint allows, used to pair endpoints across versions
+    fn destination(&self) -> String {
+        if let Some(domain) = &self.domain {
+            return format!("domain:{}", domain.to_lowercase());
+        }
+        if let Some(ip) = &self.ip {
+            return format!("ip:{}", ip.to_lowercase());
+        }
+        format!(
+            "as:{:?}|{}|{}",
+            self.as_number,
+            self.as_country.as_deref().unwrap_or("").to_lowercase(),
+            self.as_owner.as_deref().unwrap_or("").to_lowercase()
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = serde_json::Map::new();
+        let strings = [
+            ("domain", &self.domain),
+            ("ip", &self.ip),
+            ("protocol", &self.protocol),
+            ("as_country", &self.as_country),
+            ("as_owner", &self.as_owner),
+            ("process", &self.process),
+        ];
+        for (field, value) in strings {
+            if let Some(value) = value {
+                object.insert(field.to_string(), Value::from(value.as_str()));
+            }
+        }
+        if let Some(port) = self.port {
+            object.insert("port".to_string(), Value::from(port));
+        }
+        if let Some(as_number) = self.as_number {
+            object.insert("as_number".to_string(), Value::from(as_number));
+        }
+        Value::Object(object)
+    }
+
+    // One-line form used by the unified diff view
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(domain) = &self.domain {
+            parts.push(format!("domain={}", domain));
+        }
+        if let Some(ip) = &self.ip {
+            parts.push(format!("ip={}", ip));
+        }
+        if let Some(port) = self.port {
+            parts.push(format!("port={}", port));
+        }
+        if let Some(protocol) = &self.protocol {
+            parts.push(format!("protocol={}", protocol));
+        }
+        if let Some(process) = &self.process {
+            parts.push(format!("process={}", process));
+        }
+        if let Some(as_number) = self.as_number {
+            parts.push(format!("as_number={}", as_number));
+        }
+        if let Some(as_country) = &self.as_country {
+            parts.push(format!("as_country={}", as_country));
+        }
+        if let Some(as_owner) = &self.as_owner {
+            parts.push(format!("as_owner={:?}", as_owner));
+        }
+        if parts.is_empty() {
+            "<any destination>".to_string()
+        } else {
+            parts.join(" ")
+        }
+    }
+}
+
+/// A whitelist as parsed from the JSON, with its own endpoints only.
+#[derive(Debug, Clone, Default)]
+pub struct WhitelistInfo {
+    pub name: String,
+    pub extends: Vec<String>,
+    pub endpoints: Vec<WhitelistEndpoint>,
+    pub path: String,
+}
+
+struct Linter {
+    issues: Vec<LintIssue>,
+}
+
+impl Linter {
+    fn error(&mut self, path: &str, message: String) {
+        self.issues.push(LintIssue {
+            severity: Severity::Error,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.issues.push(LintIssue {
+            severity: Severity::Warning,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn optional_string(&mut self, object: &Value, field: &str, path: &str) -> Option<String> {
+        match object.get(field) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => {
+                self.error(
+                    &format!("{}.{}", path, field),
+                    format!("expected a string, found {}", type_name(other)),
+                );
+                None
+            }
+        }
+    }
+
+    fn optional_u64(&mut self, object: &Value, field: &str, path: &str) -> Option<u64> {
+        match object.get(field) {
+            None | Some(Value::Null) => None,
+            Some(value) => match value.as_u64() {
+                Some(n) => Some(n),
+                None => {
+                    self.error(
+                        &format!("{}.{}", path, field),
+                        format!("expected an unsigned integer, found {}", value),
+                    );
+                    None
+                }
+            },
+        }
+    }
+
+    fn port(&mut self, object: &Value, path: &str) -> Option<u64> {
+        match object.get("port") {
+            None | Some(Value::Null) => None,
+            Some(Value::Number(n)) => match n.as_u64().filter(|p| (1..=65535).contains(p)) {
+                Some(port) => Some(port),
+                None => {
+                    self.error(
+                        &format!("{}.port", path),
+                        format!("port {} is outside the valid range 1-65535", n),
+                    );
+                    None
+                }
+            },
+            Some(other) => {
+                self.error(
+                    &format!("{}.port", path),
+                    format!("expected a port number, found {}", type_name(other)),
+                );
+                None
+            }
+        }
+    }
+
+    fn parse_endpoint(&mut self, value: &Value, path: &str) -> Option<WhitelistEndpoint> {
+        let object = match value.as_object() {
+            Some(object) => object,
+            None => {
+                self.error(
+                    path,
+                    format!("expected an endpoint object, found {}", type_name(value)),
+                );
+                return None;
+            }
+        };
+        for key in object.keys() {
+            if !ENDPOINT_FIELDS.contains(&key.as_str()) {
+                self.warning(
+                    &format!("{}.{}", path, key),
+                    format!("unknown endpoint field '{}' is ignored", key),
+                );
+            }
+        }
+
+        let endpoint = WhitelistEndpoint {
+            domain: self.optional_string(value, "domain", path),
+            ip: self.optional_string(value, "ip", path),
+            port: self.port(value, path),
+            protocol: self.optional_string(value, "protocol", path),
+            as_number: self.optional_u64(value, "as_number", path),
+            as_country: self.optional_string(value, "as_country", path),
+            as_owner: self.optional_string(value, "as_owner", path),
+            process: self.optional_string(value, "process", path),
+            path: path.to_string(),
+        };
+        self.optional_string(value, "description", path);
+
+        if let Some(as_number) = endpoint.as_number {
+            if as_number > u32::MAX as u64 {
+                self.error(
+                    &format!("{}.as_number", path),
+                    format!("AS number {} does not fit in 32 bits", as_number),
+                );
+            }
+        }
+        if let Some(protocol) = &endpoint.protocol {
+            if !KNOWN_PROTOCOLS.contains(&protocol.to_uppercase().as_str()) {
+                self.warning(
+                    &format!("{}.protocol", path),
+                    format!(
+                        "unusual protocol '{}' will only match sessions reporting it",
+                        protocol
+                    ),
+                );
+            }
+        }
+        if let Some(ip) = &endpoint.ip {
+            match parse_cidr(ip) {
+                Some((addr, prefix)) => {
+                    let (min_prefix, family) = if addr.is_ipv4() {
+                        (MIN_IPV4_PREFIX, "IPv4")
+                    } else {
+                        (MIN_IPV6_PREFIX, "IPv6")
+                    };
+                    if prefix < min_prefix {
+                        self.warning(
+                            &format!("{}.ip", path),
+                            format!(
+                                "{} range '{}' is overly broad (prefix shorter than /{})",
+                                family, ip, min_prefix
+                            ),
+                        );
+                    }
+                }
+                None => self.error(
+                    &format!("{}.ip", path),
+                    format!("'{}' is not a valid IP address or CIDR range", ip),
+                ),
+            }
+        }
+        if let Some(domain) = &endpoint.domain {
+            if let Some(reason) = broad_domain_reason(domain) {
+                self.warning(
+                    &format!("{}.domain", path),
+                    format!("domain '{}' {}", domain, reason),
+                );
+            }
+        }
+        if endpoint.domain.is_none() && endpoint.ip.is_none() && !endpoint.has_as_criteria() {
+            self.warning(
+                path,
+                "endpoint has no domain, ip or AS criteria and matches any destination".to_string(),
+            );
+        }
+
+        Some(endpoint)
+    }
+
+    fn parse_whitelist(&mut self, value: &Value, path: &str) -> Option<WhitelistInfo> {
+        if !value.is_object() {
+            self.error(
+                path,
+                format!("expected a whitelist object, found {}", type_name(value)),
+            );
+            return None;
+        }
+        let name = match value.get("name") {
+            Some(Value::String(name)) if !name.trim().is_empty() => name.clone(),
+            Some(Value::String(_)) => {
+                self.error(&format!("{}.name", path), "name is empty".to_string());
+                String::new()
+            }
+            Some(other) => {
+                self.error(
+                    &format!("{}.name", path),
+                    format!("expected a string, found {}", type_name(other)),
+                );
+                String::new()
+            }
+            None => {
+                self.error(path, "missing required field 'name'".to_string());
+                String::new()
+            }
+        };
+
+        let mut extends = Vec::new();
+        match value.get("extends") {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(parents)) => {
+                for (i, parent) in parents.iter().enumerate() {
+                    match parent.as_str() {
+                        Some(parent) => extends.push(parent.to_string()),
+                        None => self.error(
+                            &format!("{}.extends[{}]", path, i),
+                            format!("expected a whitelist name, found {}", type_name(parent)),
+                        ),
+                    }
+                }
+            }
+            Some(other) => self.error(
+                &format!("{}.extends", path),
+                format!("expected an array of names, found {}", type_name(other)),
+            ),
+        }
+
+        let mut endpoints = Vec::new();
+        match value.get("endpoints") {
+            Some(Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    let endpoint_path = format!("{}.endpoints[{}]", path, i);
+                    if let Some(endpoint) = self.parse_endpoint(item, &endpoint_path) {
+                        endpoints.push(endpoint);
+                    }
+                }
+            }
+            Some(other) => self.error(
+                &format!("{}.endpoints", path),
+                format!("expected an array, found {}", type_name(other)),
+            ),
+            None => self.error(path, "missing required field 'endpoints'".to_string()),
+        }
+
+        Some(WhitelistInfo {
+            name,
+            extends,
+            endpoints,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Parse whitelist JSON into its whitelists, collecting schema problems on the way.
+pub fn parse_whitelists(json: &str) -> (Vec<WhitelistInfo>, Vec<LintIssue>) {
+    let mut linter = Linter { issues: Vec::new() };
+    let root: Value = match serde_json::from_str(json) {
+        Ok(root) => root,
+        Err(e) => {
+            linter.error("$", format!("invalid JSON: {}", e));
+            return (Vec::new(), linter.issues);
+        }
+    };
+    if !root.is_object() {
+        linter.error(
+            "$",
+            format!("expected a whitelist object, found {}", type_name(&root)),
+        );
+        return (Vec::new(), linter.issues);
+    }
+
+    match root.get("date") {
+        Some(Value::String(_)) => {}
+        Some(other) => linter.error(
+            "$.date",
+            format!("expected a string, found {}", type_name(other)),
+        ),
+        None => linter.error("$", "missing required field 'date'".to_string()),
+    }
+    linter.optional_string(&root, "signature", "$");
+
+    let mut whitelists = Vec::new();
+    match root.get("whitelists") {
+        Some(Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                if let Some(whitelist) =
+                    linter.parse_whitelist(item, &format!("$.whitelists[{}]", i))
+                {
+                    whitelists.push(whitelist);
+                }
+            }
+        }
+        Some(other) => linter.error(
+            "$.whitelists",
+            format!("expected an array, found {}", type_name(other)),
+        ),
+        None => linter.error("$", "missing required field 'whitelists'".to_string()),
+    }
+    (whitelists, linter.issues)
+}
+
+/// Lint whitelist JSON: schema, duplicate and shadowed endpoints, overly broad
+/// wildcards or CIDRs, inconsistent process usage, unreachable `extends` and
+/// out-of-range ports.
+pub fn lint_whitelists_json(json: &str) -> Vec<LintIssue> {
+    let (whitelists, issues) = parse_whitelists(json);
+    let mut linter = Linter { issues };
+
+    let mut by_name: HashMap<&str, usize> = HashMap::new();
+    for (i, whitelist) in whitelists.iter().enumerate() {
+        if whitelist.name.is_empty() {
+            continue;
+        }
+        if let Some(first) = by_name.get(whitelist.name.as_str()) {
+            linter.error(
+                &format!("{}.name", whitelist.path),
+                format!(
+                    "duplicate whitelist name '{}' (first defined at {})",
+                    whitelist.name, whitelists[*first].path
+                ),
+            );
+        } else {
+            by_name.insert(&whitelist.name, i);
+        }
+    }
+
+    for whitelist in &whitelists {
+        for (i, parent) in whitelist.extends.iter().enumerate() {
+            let path = format!("{}.extends[{}]", whitelist.path, i);
+            if parent == &whitelist.name {
+                linter.warning(&path, format!("whitelist '{}' extends itself", parent));
+            } else if !by_name.contains_key(parent.as_str()) {
+                linter.error(
+                    &path,
+                    format!(
+                        "extends unknown whitelist '{}', its endpoints will never be inherited",
+                        parent
+                    ),
+                );
+            }
+        }
+        if extends_cycle(&whitelists, &by_name, &whitelist.name) {
+            linter.warning(
+                &format!("{}.extends", whitelist.path),
+                format!(
+                    "whitelist '{}' is part of an inheritance cycle",
+                    whitelist.name
+                ),
+            );
+        }
+
+        // Entries without a process are much broader than their siblings that have one
+        let with_process = whitelist
+            .endpoints
+            .iter()
+            .filter(|e| e.process.is_some())
+            .count();
+        if with_process > 0 && with_process < whitelist.endpoints.len() {
+            for endpoint in whitelist.endpoints.iter().filter(|e| e.process.is_none()) {
+                linter.warning(
+                    &endpoint.path,
+                    format!(
+                        "endpoint has no process while {} other endpoint(s) of '{}' do, it matches any process",
+                        with_process, whitelist.name
+                    ),
+                );
+            }
+        }
+
+        // Compare each endpoint with the earlier ones and with the inherited ones
+        let inherited = inherited_endpoints(&whitelists, &by_name, &whitelist.name);
+        for (i, endpoint) in whitelist.endpoints.iter().enumerate() {
+            let candidates = whitelist.endpoints[..i]
+                .iter()
+                .chain(inherited.iter().copied());
+            for other in candidates {
+                if other.key() == endpoint.key() {
+                    linter.warning(
+                        &endpoint.path,
+                        format!("duplicate of endpoint {}", other.path),
+                    );
+                    break;
+                }
+                if endpoint_covers(other, endpoint) {
+                    linter.warning(
+                        &endpoint.path,
+                        format!("shadowed by broader endpoint {}", other.path),
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    linter.issues
+}
+
+fn inherited_endpoints<'a>(
+    whitelists: &'a [WhitelistInfo],
+    by_name: &HashMap<&str, usize>,
+    name: &str,
+) -> Vec<&'a WhitelistEndpoint> {
+    let mut visited: HashSet<&str> = HashSet::new();
+    visited.insert(name);
+    let mut stack: Vec<&str> = match by_name.get(name) {
+        Some(i) => whitelists[*i].extends.iter().map(|s| s.as_str()).collect(),
+        None => Vec::new(),
+    };
+    let mut endpoints = Vec::new();
+    while let Some(parent) = stack.pop() {
+        if !visited.insert(parent) {
+            continue;
+        }
+        if let Some(i) = by_name.get(parent) {
+            endpoints.extend(whitelists[*i].endpoints.iter());
+            stack.extend(whitelists[*i].extends.iter().map(|s| s.as_str()));
+        }
+    }
+    endpoints
+}
+
+fn extends_cycle(whitelists: &[WhitelistInfo], by_name: &HashMap<&str, usize>, name: &str) -> bool {
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut stack: Vec<&str> = match by_name.get(name) {
+        Some(i) => whitelists[*i].extends.iter().map(|s| s.as_str()).collect(),
+        None => return false,
+    };
+    while let Some(parent) = stack.pop() {
+        if parent == name {
+            // Self-references are reported separately
+            return !whitelists[by_name[name]].extends.iter().any(|p| p == name);
+        }
+        if !visited.insert(parent) {
+            continue;
+        }
+        if let Some(i) = by_name.get(parent) {
+            stack.extend(whitelists[*i].extends.iter().map(|s| s.as_str()));
+        }
+    }
+    false
+}
+
+/// True when every session matched by `narrow` is also matched by `broad`,
+/// following the whitelist matching order (fundamentals, then domain, IP, AS).
+pub fn endpoint_covers(broad: &WhitelistEndpoint, narrow: &WhitelistEndpoint) -> bool {
+    let same_or_unset = |b: &Option<String>, n: &Option<String>| match (b, n) {
+        (None, _) => true,
+        (Some(b), Some(n)) => b.eq_ignore_ascii_case(n),
+        (Some(_), None) => false,
+    };
+    if !same_or_unset(&broad.protocol, &narrow.protocol)
+        || !same_or_unset(&broad.process, &narrow.process)
+        || (broad.port.is_some() && broad.port != narrow.port)
+    {
+        return false;
+    }
+
+    if let Some(domain) = &broad.domain {
+        return narrow.ip.is_none()
+            && narrow
+                .domain
+                .as_deref()
+                .is_some_and(|d| domain_matches(domain, d));
+    }
+    if let Some(ip) = &broad.ip {
+        return narrow.domain.is_none()
+            && narrow.ip.as_deref().is_some_and(|n| cidr_contains(ip, n));
+    }
+    if broad.has_as_criteria() {
+        return narrow.domain.is_none()
+            && narrow.ip.is_none()
+            && (broad.as_number.is_none() || broad.as_number == narrow.as_number)
+            && same_or_unset(&broad.as_country, &narrow.as_country)
+            && same_or_unset(&broad.as_owner, &narrow.as_owner);
+    }
+    true
+}
+
+fn broad_domain_reason(domain: &str) -> Option<&'static str> {
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.iter().all(|l| *l == "*") {
+        return Some("matches every domain");
+    }
+    if labels.first() == Some(&"*") && labels.len() == 2 {
+        return Some("matches every domain under a top-level domain");
+    }
+    if labels.iter().any(|l| l.contains('*') && *l != "*") {
+        return Some("uses a partial-label wildcard, only whole labels can be wildcarded");
+    }
+    None
+}
+
+/// Match a domain against a whitelist pattern: `*.example.com` (one or more
+/// leading labels), `example.*` (one or more trailing labels) and
+/// `api.*.example.com` (exactly one middle label). Case-insensitive.
+pub fn domain_matches(pattern: &str, domain: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    if !pattern.contains('*') {
+        return pattern == domain;
+    }
+    let p: Vec<&str> = pattern.split('.').collect();
+    let d: Vec<&str> = domain.split('.').collect();
+
+    if p.len() > 1 && p[0] == "*" && !p[1..].contains(&"*") {
+        let suffix = &p[1..];
+        return d.len() > suffix.len() && d[d.len() - suffix.len()..] == *suffix;
+    }
+    if p.len() > 1 && p[p.len() - 1] == "*" && !p[..p.len() - 1].contains(&"*") {
+        let prefix = &p[..p.len() - 1];
+        return d.len() > prefix.len() && d[..prefix.len()] == *prefix;
+    }
+    // Middle wildcards match exactly one label each
+    p.len() == d.len() && p.iter().zip(d.iter()).all(|(p, d)| *p == "*" || p == d)
+}
+
+/// Parse `ip` or `ip/prefix` into an address and prefix length.
+pub fn parse_cidr(value: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match value.trim().split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (value.trim(), None),
+    };
+    let addr: IpAddr = addr.parse().ok()?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    Some((addr, prefix))
+}
+
+fn addr_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// True when the address or range `inner` lies within the range `outer`.
+pub fn cidr_contains(outer: &str, inner: &str) -> bool {
+    let (Some((outer_addr, outer_prefix)), Some((inner_addr, inner_prefix))) =
+        (parse_cidr(outer), parse_cidr(inner))
+    else {
+        return false;
+    };
+    if outer_addr.is_ipv4() != inner_addr.is_ipv4() || outer_prefix > inner_prefix {
+        return false;
+    }
+    let width = if outer_addr.is_ipv4() { 32 } else { 128 };
+    let shift = width - outer_prefix as u32;
+    if shift >= 128 {
+        return true;
+    }
+    addr_bits(outer_addr) >> shift == addr_bits(inner_addr) >> shift
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Print issues to stderr and return (errors, warnings).
+pub fn print_lint_issues(issues: &[LintIssue]) -> (usize, usize) {
+    let mut errors = 0;
+    let mut warnings = 0;
+    for issue in issues {
+        let label = match issue.severity {
+            Severity::Error => {
+                errors += 1;
+                "error"
+            }
+            Severity::Warning => {
+                warnings += 1;
+                "warning"
+            }
+        };
+        eprintln!("{}: {}: {}", label, issue.path, issue.message);
+    }
+    (errors, warnings)
+}
+
+/// Lint whitelist JSON before it is used by another command. Warnings are
+/// printed, errors are returned so the caller can refuse the input.
+pub fn check_custom_whitelists(json: &str, context: &str) -> Result<(), String> {
+    // Empty JSON is the "reset to default" signal
+    if json.trim().is_empty() {
+        return Ok(());
+    }
+    let (errors, _) = print_lint_issues(&lint_whitelists_json(json));
+    if errors > 0 {
+        return Err(format!(
+            "{} has {} error(s), run lint-custom-whitelists for details",
+            context, errors
+        ));
+    }
+    Ok(())
+}
+
+pub fn lint_custom_whitelists_file(file: &str, strict: bool) -> i32 {
+    let json = match std::fs::read_to_string(file) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Error reading whitelist file '{}': {}", file, e);
+            return ERROR_CODE_PARAM;
+        }
+    };
+    let (errors, warnings) = print_lint_issues(&lint_whitelists_json(&json));
+    println!("{}: {} error(s), {} warning(s)", file, errors, warnings);
+    if errors > 0 || (strict && warnings > 0) {
+        return ERROR_CODE_PARAM;
+    }
+    0
+}
+
+fn changed_fields(old: &WhitelistEndpoint, new: &WhitelistEndpoint) -> Vec<&'static str> {
+    let differs = |a: &Option<String>, b: &Option<String>| {
+        a.as_deref().map(str::to_lowercase) != b.as_deref().map(str::to_lowercase)
+    };
+    let mut fields = Vec::new();
+    if differs(&old.domain, &new.domain) {
+        fields.push("domain");
+    }
+    if differs(&old.ip, &new.ip) {
+        fields.push("ip");
+    }
+    if old.port != new.port {
+        fields.push("port");
+    }
+    if differs(&old.protocol, &new.protocol) {
+        fields.push("protocol");
+    }
+    if differs(&old.process, &new.process) {
+        fields.push("process");
+    }
+    if old.as_number != new.as_number {
+        fields.push("as_number");
+    }
+    if differs(&old.as_country, &new.as_country) {
+        fields.push("as_country");
+    }
+    if differs(&old.as_owner, &new.as_owner) {
+        fields.push("as_owner");
+    }
+    fields
+}
+
+/// Endpoint-level differences of one whitelist between two versions.
+#[derive(Debug, Default)]
+pub struct WhitelistDiff {
+    pub name: String,
+    pub old_extends: Option<Vec<String>>,
+    pub new_extends: Option<Vec<String>>,
+    pub added: Vec<WhitelistEndpoint>,
+    pub removed: Vec<WhitelistEndpoint>,
+    pub modified: Vec<(WhitelistEndpoint, WhitelistEndpoint)>,
+}
+
+impl WhitelistDiff {
+    fn is_empty(&self) -> bool {
+        self.old_extends == self.new_extends
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.old_extends, &self.new_extends) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            _ => "modified",
+        }
+    }
+}
+
+fn diff_endpoints(old: &[WhitelistEndpoint], new: &[WhitelistEndpoint], diff: &mut WhitelistDiff) {
+    // Identical endpoints (ignoring descriptions) are unchanged
+    let mut unmatched_new: Vec<&WhitelistEndpoint> = new.iter().collect();
+    let mut unmatched_old: Vec<&WhitelistEndpoint> = Vec::new();
+    for endpoint in old {
+        match unmatched_new.iter().position(|n| n.key() == endpoint.key()) {
+            Some(i) => {
+                unmatched_new.remove(i);
+            }
+            None => unmatched_old.push(endpoint),
+        }
+    }
+
+    // Remaining endpoints for the same destination are modifications
+    for endpoint in unmatched_old {
+        match unmatched_new
+            .iter()
+            .position(|n| n.destination() == endpoint.destination())
+        {
+            Some(i) => {
+                let new_endpoint = unmatched_new.remove(i);
+                diff.modified.push((endpoint.clone(), new_endpoint.clone()));
+            }
+            None => diff.removed.push(endpoint.clone()),
+        }
+    }
+    diff.added.extend(unmatched_new.into_iter().cloned());
+}
+
+/// Compare two whitelist sets endpoint by endpoint, grouped by whitelist name.
+/// Only whitelists with differences are returned, sorted by name.
+pub fn diff_whitelists(old: &[WhitelistInfo], new: &[WhitelistInfo]) -> Vec<WhitelistDiff> {
+    let mut names: Vec<&str> = old
+        .iter()
+        .chain(new.iter())
+        .map(|w| w.name.as_str())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let mut diffs = Vec::new();
+    for name in names {
+        let old_whitelist = old.iter().find(|w| w.name == name);
+        let new_whitelist = new.iter().find(|w| w.name == name);
+        let mut diff = WhitelistDiff {
+            name: name.to_string(),
+            old_extends: old_whitelist.map(|w| w.extends.clone()),
+            new_extends: new_whitelist.map(|w| w.extends.clone()),
+            ..Default::default()
+        };
+        diff_endpoints(
+            old_whitelist.map_or(&[], |w| w.endpoints.as_slice()),
+            new_whitelist.map_or(&[], |w| w.endpoints.as_slice()),
+            &mut diff,
+        );
+        if !diff.is_empty() {
+            diffs.push(diff);
+        }
+    }
+    diffs
+}
+
+pub fn whitelist_diff_json(diffs: &[WhitelistDiff]) -> Value {
+    let endpoints = |list: &[WhitelistEndpoint]| -> Vec<Value> {
+        list.iter().map(WhitelistEndpoint::to_json).collect()
+    };
+    let whitelists: Vec<Value> = diffs
+        .iter()
+        .map(|diff| {
+            let mut entry = json!({
+                "name": diff.name,
+                "status": diff.status(),
+                "added": endpoints(&diff.added),
+                "removed": endpoints(&diff.removed),
+                "modified": diff.modified.iter().map(|(old, new)| json!({
+                    "old": old.to_json(),
+                    "new": new.to_json(),
+                    "changed_fields": changed_fields(old, new),
+                })).collect::<Vec<_>>(),
+            });
+            if diff.old_extends != diff.new_extends {
+                entry["extends"] = json!({
+                    "old": diff.old_extends,
+                    "new": diff.new_extends,
+                });
+            }
+            entry
+        })
+        .collect();
+    json!({
+        "summary": {
+            "whitelists_changed": diffs.len(),
+            "endpoints_added": diffs.iter().map(|d| d.added.len()).sum::<usize>(),
+            "endpoints_removed": diffs.iter().map(|d| d.removed.len()).sum::<usize>(),
+            "endpoints_modified": diffs.iter().map(|d| d.modified.len()).sum::<usize>(),
+        },
+        "whitelists": whitelists,
+    })
+}
+
+pub fn whitelist_diff_unified(diffs: &[WhitelistDiff], old_label: &str, new_label: &str) -> String {
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for diff in diffs {
+        out.push_str(&format!(
+            "@@ whitelist {} ({}) @@\n",
+            diff.name,
+            diff.status()
+        ));
+        if diff.old_extends != diff.new_extends {
+            if let Some(extends) = &diff.old_extends {
+                out.push_str(&format!("-extends: [{}]\n", extends.join(", ")));
+            }
+            if let Some(extends) = &diff.new_extends {
+                out.push_str(&format!("+extends: [{}]\n", extends.join(", ")));
+            }
+        }
+        for endpoint in &diff.removed {
+            out.push_str(&format!("-{}\n", endpoint.describe()));
+        }
+        for (old, new) in &diff.modified {
+            out.push_str(&format!("-{}\n", old.describe()));
+            out.push_str(&format!(
+                "+{}    # changed: {}\n",
+                new.describe(),
+                changed_fields(old, new).join(", ")
+            ));
+        }
+        for endpoint in &diff.added {
+            out.push_str(&format!("+{}\n", endpoint.describe()));
+        }
+    }
+    out
+}
+
+/// Print the structured diff of two whitelist JSON documents. With
+/// `fail_on_diff`, a non-empty diff exits with ERROR_CODE_MISMATCH.
+pub fn diff_custom_whitelists(
+    old_json: &str,
+    new_json: &str,
+    old_label: &str,
+    new_label: &str,
+    json_output: bool,
+    fail_on_diff: bool,
+) -> i32 {
+    let mut parsed = Vec::new();
+    for (json, label) in [(old_json, old_label), (new_json, new_label)] {
+        let (whitelists, issues) = parse_whitelists(json);
+        let errors: Vec<&LintIssue> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        if !errors.is_empty() {
+            for issue in errors {
+                eprintln!("error: {}: {}", issue.path, issue.message);
+            }
+            eprintln!("Cannot diff '{}', it is not a valid whitelist", label);
+            return ERROR_CODE_PARAM;
+        }
+        parsed.push(whitelists);
+    }
+
+    let diffs = diff_whitelists(&parsed[0], &parsed[1]);
+    if json_output {
+        match serde_json::to_string_pretty(&whitelist_diff_json(&diffs)) {
+            Ok(output) => println!("{}", output),
+            Err(e) => {
+                eprintln!("Error serializing whitelist diff: {}", e);
+                return ERROR_CODE_PARAM;
+            }
+        }
+    } else if !diffs.is_empty() {
+        print!("{}", whitelist_diff_unified(&diffs, old_label, new_label));
+    }
+
+    if fail_on_diff && !diffs.is_empty() {
+        eprintln!("Whitelists differ in {} whitelist(s)", diffs.len());
+        return ERROR_CODE_MISMATCH;
+    }
+    0
+}
+
+/// Expand file arguments that contain `*` or `?` in their file name into the
+/// matching files, sorted by name. Other arguments are kept as given.
+pub fn expand_whitelist_paths(patterns: &[String]) -> Result<Vec<String>, String> {
+    let mut paths: Vec<String> = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?']) {
+            if !paths.contains(pattern) {
+                paths.push(pattern.clone());
+            }
+            continue;
+        }
+        let path = std::path::Path::new(pattern);
+        let file_pattern = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| format!("Invalid file pattern '{}'", pattern))?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        if dir.to_string_lossy().contains(['*', '?']) {
+            return Err(format!(
+                "Wildcards are only supported in the file name: '{}'",
+                pattern
+            ));
+        }
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Error reading directory '{}': {}", dir.display(), e))?;
+        let mut matches: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| wildcard_matches(file_pattern, name))
+            })
+            .map(|entry| {
+                if path.parent().is_some_and(|p| !p.as_os_str().is_empty()) {
+                    entry.path().to_string_lossy().to_string()
+                } else {
+                    entry.file_name().to_string_lossy().to_string()
+                }
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("No whitelist file matches '{}'", pattern));
+        }
+        matches.sort();
+        for path in matches {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+// Shell-style matching of `*` (any run of characters) and `?` (one character)
+pub fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ni = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Same-name whitelists whose `extends` differ between merge inputs. The
+/// merged whitelist inherits from the union of the parents, which may not be
+/// what every input intended.
+pub fn extends_conflicts(inputs: &[(String, Vec<WhitelistInfo>)]) -> Vec<Value> {
+    let mut by_name: BTreeMap<&str, Vec<(&str, Vec<String>)>> = BTreeMap::new();
+    for (label, whitelists) in inputs {
+        for whitelist in whitelists {
+            let mut extends = whitelist.extends.clone();
+            extends.sort();
+            extends.dedup();
+            by_name
+                .entry(whitelist.name.as_str())
+                .or_default()
+                .push((label.as_str(), extends));
+        }
+    }
+    by_name
+        .into_iter()
+        .filter(|(_, variants)| variants.iter().any(|(_, e)| *e != variants[0].1))
+        .map(|(name, variants)| {
+            json!({
+                "whitelist": name,
+                "extends": variants.iter().map(|(label, extends)| json!({
+                    "source": label,
+                    "extends": extends,
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect()
+}
+
+const SOURCES_PREFIX: &str = "sources: ";
+
+// Drop a provenance annotation left by a previous merge
+fn strip_sources(description: &str) -> &str {
+    match description.find(SOURCES_PREFIX) {
+        Some(0) => "",
+        Some(i) if description[..i].ends_with(" [") && description.ends_with(']') => {
+            &description[..i - 2]
+        }
+        _ => description,
+    }
+}
+
+/// Sort a merged whitelist document so it diffs cleanly: whitelists by name,
+/// `extends` alphabetically and endpoints by their matching criteria. With
+/// `annotate_sources`, each endpoint's description records the inputs that
+/// contributed it.
+pub fn normalize_merged_whitelists(
+    merged: &mut Value,
+    inputs: &[(String, Vec<WhitelistInfo>)],
+    annotate_sources: bool,
+) {
+    let Some(whitelists) = merged.get_mut("whitelists").and_then(|w| w.as_array_mut()) else {
+        return;
+    };
+    for whitelist in whitelists.iter_mut() {
+        let name = whitelist
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or("")
+            .to_string();
+        if let Some(extends) = whitelist.get_mut("extends").and_then(|e| e.as_array_mut()) {
+            extends.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+            extends.dedup();
+        }
+        let Some(endpoints) = whitelist
+            .get_mut("endpoints")
+            .and_then(|e| e.as_array_mut())
+        else {
+            continue;
+        };
+        let mut keyed: Vec<(String, Value)> = endpoints
+            .drain(..)
+            .map(|endpoint| {
+                let mut linter = Linter { issues: Vec::new() };
+                let key = linter
+                    .parse_endpoint(&endpoint, "")
+                    .map(|e| e.key())
+                    .unwrap_or_default();
+                (key, endpoint)
+            })
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (key, mut endpoint) in keyed {
+            if annotate_sources {
+                let sources: Vec<&str> = inputs
+                    .iter()
+                    .filter(|(_, whitelists)| {
+                        whitelists
+                            .iter()
+                            .filter(|w| w.name == name)
+                            .any(|w| w.endpoints.iter().any(|e| e.key() == key))
+                    })
+                    .map(|(label, _)| label.as_str())
+                    .collect();
+                if !sources.is_empty() {
+                    let description = endpoint
+                        .get("description")
+                        .and_then(|d| d.as_str())
+                        .map(strip_sources)
+                        .unwrap_or("");
+                    let annotation = format!("{}{}", SOURCES_PREFIX, sources.join(", "));
+                    endpoint["description"] = Value::from(if description.is_empty() {
+                        annotation
+                    } else {
+                        format!("{} [{}]", description, annotation)
+                    });
+                }
+            }
+            endpoints.push(endpoint);
+        }
+    }
+    whitelists.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+}
+
+/// Matching attributes of a serialized session, in whitelist endpoint form.
+pub fn session_endpoint(session: &Value) -> WhitelistEndpoint {
+    let string = |pointer: &str| session_str(session, pointer).map(|s| s.to_string());
+    WhitelistEndpoint {
+        domain: string("/dst_domain"),
+        ip: string("/session/dst_ip"),
+        port: session
+            .pointer("/session/dst_port")
+            .and_then(|v| v.as_u64()),
+        protocol: string("/session/protocol"),
+        as_number: session
+            .pointer("/dst_asn/as_number")
+            .and_then(|v| v.as_u64()),
+        as_country: string("/dst_asn/country"),
+        as_owner: string("/dst_asn/owner"),
+        process: session_process(session).map(|s| s.to_string()),
+        path: String::new(),
+    }
+}
+
+/// A whitelist endpoint evaluated against one session.
+#[derive(Debug, Clone)]
+pub struct RuleEvaluation {
+    pub whitelist: String,
+    pub endpoint: WhitelistEndpoint,
+    // Criteria specified by the endpoint that the session satisfies
+    pub matched: usize,
+    // First criterion that failed, in matching order
+    pub mismatch: Option<String>,
+}
+
+/// Evaluate an endpoint with the matching order used by the daemon: protocol,
+/// port and process first, then domain, IP or AS information, whichever the
+/// endpoint specifies first.
+fn evaluate_endpoint(
+    endpoint: &WhitelistEndpoint,
+    session: &WhitelistEndpoint,
+) -> (usize, Option<String>) {
+    let mut matched = 0;
+    let mut mismatch: Option<String> = None;
+    let mut check = |ok: bool, reason: String| {
+        if ok {
+            matched += 1;
+        } else if mismatch.is_none() {
+            mismatch = Some(reason);
+        }
+    };
+    let same = |expected: &str, actual: &Option<String>| {
+        actual
+            .as_deref()
+            .is_some_and(|a| a.eq_ignore_ascii_case(expected))
+    };
+    let actual = |value: &Option<String>, missing: &str| match value {
+        Some(value) => format!("session has {}", value),
+        None => format!("session has {}", missing),
+    };
+
+    if let Some(protocol) = &endpoint.protocol {
+        check(
+            same(protocol, &session.protocol),
+            format!(
+                "protocol: expects {}, {}",
+                protocol,
+                actual(&session.protocol, "no protocol")
+            ),
+        );
+    }
+    if let Some(port) = endpoint.port {
+        let found = match session.port {
+            Some(p) => format!("session uses port {}", p),
+            None => "session has no port".to_string(),
+        };
+        check(
+            session.port == Some(port),
+            format!("port: expects {}, {}", port, found),
+        );
+    }
+    if let Some(process) = &endpoint.process {
+        check(
+            same(process, &session.process),
+            format!(
+                "process: expects {}, {}",
+                process,
+                actual(&session.process, "no process attribution")
+            ),
+        );
+    }
+
+    if let Some(domain) = &endpoint.domain {
+        let reason = match &session.domain {
+            Some(d) => format!("domain: expects {}, session domain is {}", domain, d),
+            None => format!(
+                "domain: expects {}, session domain not resolved (destination {})",
+                domain,
+                session.ip.as_deref().unwrap_or("unknown")
+            ),
+        };
+        check(
+            session
+                .domain
+                .as_deref()
+                .is_some_and(|d| domain_matches(domain, d)),
+            reason,
+        );
+    } else if let Some(ip) = &endpoint.ip {
+        check(
+            session.ip.as_deref().is_some_and(|i| cidr_contains(ip, i)),
+            format!(
+                "ip: expects {}, {}",
+                ip,
+                actual(&session.ip, "no destination IP")
+            ),
+        );
+    } else {
+        if let Some(as_number) = endpoint.as_number {
+            let found = match session.as_number {
+                Some(n) => format!("session ASN is {}", n),
+                None => "session ASN unknown".to_string(),
+            };
+            check(
+                session.as_number == Some(as_number),
+                format!("as_number: expects {}, {}", as_number, found),
+            );
+        }
+        if let Some(country) = &endpoint.as_country {
+            check(
+                same(country, &session.as_country),
+                format!(
+                    "as_country: expects {}, {}",
+                    country,
+                    actual(&session.as_country, "no AS country")
+                ),
+            );
+        }
+        if let Some(owner) = &endpoint.as_owner {
+            check(
+                same(owner, &session.as_owner),
+                format!(
+                    "as_owner: expects {:?}, {}",
+                    owner,
+                    actual(&session.as_owner, "no AS owner")
+                ),
+            );
+        }
+    }
+    (matched, mismatch)
+}
+
+/// Whitelists in effect for `name`: the whitelist itself followed by its
+/// ancestors, each listed once.
+fn whitelist_hierarchy<'a>(whitelists: &'a [WhitelistInfo], name: &str) -> Vec<&'a WhitelistInfo> {
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut queue: Vec<&str> = vec![name];
+    let mut hierarchy = Vec::new();
+    while !queue.is_empty() {
+        let current = queue.remove(0);
+        if !visited.insert(current) {
+            continue;
+        }
+        if let Some(whitelist) = whitelists.iter().find(|w| w.name == current) {
+            hierarchy.push(whitelist);
+            queue.extend(whitelist.extends.iter().map(|s| s.as_str()));
+        }
+    }
+    hierarchy
+}
+
+/// Evaluate every endpoint of the `name` hierarchy against a session, closest
+/// candidates first: matching rules, then the ones satisfying the most criteria.
+pub fn evaluate_session(
+    whitelists: &[WhitelistInfo],
+    name: &str,
+    session: &Value,
+) -> Vec<RuleEvaluation> {
+    let facts = session_endpoint(session);
+    let mut evaluations: Vec<RuleEvaluation> = whitelist_hierarchy(whitelists, name)
+        .into_iter()
+        .flat_map(|whitelist| {
+            whitelist.endpoints.iter().map(|endpoint| {
+                let (matched, mismatch) = evaluate_endpoint(endpoint, &facts);
+                RuleEvaluation {
+                    whitelist: whitelist.name.clone(),
+                    endpoint: endpoint.clone(),
+                    matched,
+                    mismatch,
+                }
+            })
+        })
+        .collect();
+    // Stable sort keeps the hierarchy order among equally close candidates
+    evaluations.sort_by(|a, b| {
+        a.mismatch
+            .is_some()
+            .cmp(&b.mismatch.is_some())
+            .then(b.matched.cmp(&a.matched))
+    });
+    evaluations
+}
+
+/// Narrowest endpoint that lets the session conform: its domain (or its IP when
+/// unresolved), port, protocol and process.
+pub fn suggested_endpoint(session: &Value) -> Value {
+    let facts = session_endpoint(session);
+    let suggestion = WhitelistEndpoint {
+        ip: if facts.domain.is_none() {
+            facts.ip.clone()
+        } else {
+            None
+        },
+        domain: facts.domain,
+        port: facts.port,
+        protocol: facts.protocol,
+        process: facts.process,
+        ..Default::default()
+    };
+    let mut endpoint = suggestion.to_json();
+    if let Some(uid) = session_str(session, "/uid") {
+        endpoint["description"] = Value::from(format!("Added for session {}", uid));
+    }
+    endpoint
+}
+
+/// Report why a session does or does not conform to the `name` whitelist
+/// hierarchy, with at most `limit` candidate rules.
+pub fn explain_session(session: &Value, whitelists_json: &str, name: &str, limit: usize) -> Value {
+    let (whitelists, _) = parse_whitelists(whitelists_json);
+    let hierarchy: Vec<&str> = whitelist_hierarchy(&whitelists, name)
+        .iter()
+        .map(|w| w.name.as_str())
+        .collect();
+    let evaluations = evaluate_session(&whitelists, name, session);
+    let matching = evaluations.iter().find(|e| e.mismatch.is_none());
+    let candidates: Vec<Value> = evaluations
+        .iter()
+        .take(limit)
+        .map(|e| {
+            json!({
+                "whitelist": e.whitelist,
+                "path": e.endpoint.path,
+                "endpoint": e.endpoint.to_json(),
+                "matched_criteria": e.matched,
+                "first_mismatch": e.mismatch,
+            })
+        })
+        .collect();
+    let facts = session_endpoint(session);
+
+    json!({
+        "uid": session_str(session, "/uid"),
+        "session": facts.to_json(),
+        "daemon_state": session_str(session, "/is_whitelisted"),
+        "daemon_reason": session_str(session, "/whitelist_reason"),
+        "whitelist": name,
+        "hierarchy": hierarchy,
+        "endpoints_evaluated": evaluations.len(),
+        "conforms": matching.is_some(),
+        "matching_rule": matching.map(|e| json!({
+            "whitelist": e.whitelist,
+            "path": e.endpoint.path,
+            "endpoint": e.endpoint.to_json(),
+        })),
+        "candidates": candidates,
+        "suggestion": if matching.is_some() { Value::Null } else { suggested_endpoint(session) },
+    })
+}
+
+/// Human-readable form of an `explain_session` report.
+pub fn print_session_explanation(report: &Value) {
+    let text = |pointer: &str| {
+        report
+            .pointer(pointer)
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+    };
+    println!("Session {}", text("/uid"));
+    println!("  {}", endpoint_summary(&report["session"]));
+    if let Some(state) = report["daemon_state"].as_str() {
+        match report["daemon_reason"].as_str() {
+            Some(reason) => println!("  daemon state: {} ({})", state, reason),
+            None => println!("  daemon state: {}", state),
+        }
+    }
+    if let Some(geoip) = report.get("geoip") {
+        println!(
+            "  GeoIP: country {}, AS {} {}",
+            geoip["dst_country"].as_str().unwrap_or("unknown"),
+            geoip["dst_asn"]["as_number"].as_u64().unwrap_or(0),
+            geoip["dst_asn"]["owner"].as_str().unwrap_or("")
+        );
+    }
+    let hierarchy: Vec<&str> = report["hierarchy"]
+        .as_array()
+        .map(|names| names.iter().filter_map(|n| n.as_str()).collect())
+        .unwrap_or_default();
+    println!(
+        "Whitelist: {} ({} endpoints in {})",
+        text("/whitelist"),
+        report["endpoints_evaluated"].as_u64().unwrap_or(0),
+        if hierarchy.is_empty() {
+            "no whitelist".to_string()
+        } else {
+            hierarchy.join(" -> ")
+        }
+    );
+
+    if let Some(rule) = report["matching_rule"].as_object() {
+        println!(
+            "Conforms: matched by {} {} ({})",
+            rule["whitelist"].as_str().unwrap_or(""),
+            rule["path"].as_str().unwrap_or(""),
+            endpoint_summary(&rule["endpoint"])
+        );
+        if report["daemon_state"]
+            .as_str()
+            .is_some_and(|s| s != "Conforming")
+        {
+            println!(
+                "Note: the daemon reports {}, the whitelists may have changed since it evaluated the session",
+                text("/daemon_state")
+            );
+        }
+        return;
+    }
+
+    println!("Does not conform. Closest candidate rules:");
+    let candidates = report["candidates"].as_array().cloned().unwrap_or_default();
+    if candidates.is_empty() {
+        println!("  (none, the whitelist has no endpoints)");
+    }
+    for candidate in candidates {
+        println!(
+            "  {} {}: {}",
+            candidate["whitelist"].as_str().unwrap_or(""),
+            candidate["path"].as_str().unwrap_or(""),
+            endpoint_summary(&candidate["endpoint"])
+        );
+        println!(
+            "    first mismatch: {}",
+            candidate["first_mismatch"].as_str().unwrap_or("none")
+        );
+    }
+    println!("Suggested endpoint:");
+    println!(
+        "  {}",
+        serde_json::to_string(&report["suggestion"]).unwrap_or_default()
+    );
+}
+
+fn endpoint_summary(endpoint: &Value) -> String {
+    let string = |field: &str| {
+        endpoint
+            .get(field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+    WhitelistEndpoint {
+        domain: string("domain"),
+        ip: string("ip"),
+        port: endpoint.get("port").and_then(|v| v.as_u64()),
+        protocol: string("protocol"),
+        as_number: endpoint.get("as_number").and_then(|v| v.as_u64()),
+        as_country: string("as_country"),
+        as_owner: string("as_owner"),
+        process: string("process"),
+        path: String::new(),
+    }
+    .describe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(issues: &[LintIssue], severity: Severity) -> Vec<String> {
+        issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .map(|i| i.path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn valid_whitelist_has_no_issues() {
+        let json = r#"{
+            "date": "October 24th 2023",
+            "whitelists": [
+                { "name": "base", "endpoints": [
+                    { "domain": "api.example.com", "port": 443, "protocol": "TCP" }
+                ]},
+                { "name": "extended", "extends": ["base"], "endpoints": [
+                    { "domain": "cdn.example.com", "port": 443, "protocol": "TCP" }
+                ]}
+            ]
+        }"#;
+        let issues = lint_whitelists_json(json);
+        assert!(issues.is_empty(), "unexpected issues: {:?}", issues);
+    }
+
+    #[test]
+    fn reports_schema_errors_with_paths() {
+        let json = r#"{
+            "whitelists": [
+                { "name": "base", "extends": ["missing"], "endpoints": [
+                    { "domain": "api.example.com", "port": 70000 },
+                    { "ip": "10.0.0.300" },
+                    { "domain": 42 }
+                ]}
+            ]
+        }"#;
+        let issues = lint_whitelists_json(json);
+        let errors = paths(&issues, Severity::Error);
+        assert!(
+            errors.contains(&"$".to_string()),
+            "missing date: {:?}",
+            errors
+        );
+        assert!(errors.contains(&"$.whitelists[0].extends[0]".to_string()));
+        assert!(errors.contains(&"$.whitelists[0].endpoints[0].port".to_string()));
+        assert!(errors.contains(&"$.whitelists[0].endpoints[1].ip".to_string()));
+        assert!(errors.contains(&"$.whitelists[0].endpoints[2].domain".to_string()));
+    }
+
+    #[test]
+    fn reports_duplicate_shadowed_and_broad_endpoints() {
+        let json = r#"{
+            "date": "today",
+            "whitelists": [
+                { "name": "base", "endpoints": [
+                    { "domain": "*.example.com", "port": 443 },
+                    { "ip": "10.0.0.0/8", "process": "curl" }
+                ]},
+                { "name": "child", "extends": ["base"], "endpoints": [
+                    { "domain": "api.example.com", "port": 443, "protocol": "TCP" },
+                    { "domain": "*.com" },
+                    { "domain": "*.com", "description": "same criteria" }
+                ]}
+            ]
+        }"#;
+        let issues = lint_whitelists_json(json);
+        let warnings = paths(&issues, Severity::Warning);
+        // Shadowed by the inherited wildcard
+        assert!(warnings.contains(&"$.whitelists[1].endpoints[0]".to_string()));
+        // Overly broad wildcard and CIDR
+        assert!(warnings.contains(&"$.whitelists[1].endpoints[1].domain".to_string()));
+        assert!(warnings.contains(&"$.whitelists[0].endpoints[1].ip".to_string()));
+        // Duplicate (description is ignored)
+        assert!(warnings.contains(&"$.whitelists[1].endpoints[2]".to_string()));
+        // Missing process while a sibling has one
+        assert!(warnings.contains(&"$.whitelists[0].endpoints[0]".to_string()));
+        assert!(paths(&issues, Severity::Error).is_empty());
+    }
+
+    #[test]
+    fn domain_wildcards_follow_readme_semantics() {
+        assert!(domain_matches("*.example.com", "a.b.example.com"));
+        assert!(!domain_matches("*.example.com", "example.com"));
+        assert!(domain_matches("example.*", "example.co.uk"));
+        assert!(!domain_matches("example.*", "www.example.com"));
+        assert!(domain_matches("api.*.example.com", "api.v1.example.com"));
+        assert!(!domain_matches("api.*.example.com", "api.example.com"));
+        assert!(domain_matches("API.Example.com", "api.example.com"));
+    }
+
+    #[test]
+    fn cidr_containment() {
+        assert!(cidr_contains("10.0.0.0/8", "10.1.2.3"));
+        assert!(cidr_contains("10.0.0.0/8", "10.1.0.0/16"));
+        assert!(!cidr_contains("10.1.0.0/16", "10.0.0.0/8"));
+        assert!(cidr_contains("0.0.0.0/0", "192.0.2.1"));
+        assert!(cidr_contains("2001:db8::/32", "2001:db8:1::1"));
+        assert!(!cidr_contains("10.0.0.0/8", "2001:db8::1"));
+    }
+
+    #[test]
+    fn diff_groups_added_removed_and_modified_endpoints() {
+        let old = r#"{
+            "date": "v1",
+            "whitelists": [
+                { "name": "base", "endpoints": [
+                    { "domain": "api.example.com", "port": 443, "protocol": "TCP" },
+                    { "domain": "old.example.com", "port": 443, "protocol": "TCP" },
+                    { "ip": "192.0.2.10", "port": 53, "protocol": "UDP", "description": "dns" }
+                ]},
+                { "name": "gone", "endpoints": [ { "domain": "x.example.net" } ] }
+            ]
+        }"#;
+        let new = r#"{
+            "date": "v2",
+            "whitelists": [
+                { "name": "base", "extends": ["shared"], "endpoints": [
+                    { "domain": "api.example.com", "port": 8443, "protocol": "TCP" },
+                    { "ip": "192.0.2.10", "port": 53, "protocol": "UDP", "description": "resolver" },
+                    { "domain": "new.example.com", "port": 443, "protocol": "TCP", "process": "curl" }
+                ]},
+                { "name": "shared", "endpoints": [] }
+            ]
+        }"#;
+        let (old, _) = parse_whitelists(old);
+        let (new, _) = parse_whitelists(new);
+        let diffs = diff_whitelists(&old, &new);
+        let report = whitelist_diff_json(&diffs);
+
+        assert_eq!(report["summary"]["whitelists_changed"], 3);
+        let base = &report["whitelists"][0];
+        assert_eq!(base["name"], "base");
+        assert_eq!(base["status"], "modified");
+        assert_eq!(base["extends"]["new"], json!(["shared"]));
+        assert_eq!(base["added"][0]["domain"], "new.example.com");
+        assert_eq!(base["removed"][0]["domain"], "old.example.com");
+        assert_eq!(base["modified"][0]["changed_fields"], json!(["port"]));
+        // A description-only change is not a difference
+        assert_eq!(base["modified"].as_array().unwrap().len(), 1);
+        assert_eq!(report["whitelists"][1]["status"], "removed");
+        assert_eq!(report["whitelists"][2]["status"], "added");
+
+        let unified = whitelist_diff_unified(&diffs, "old.json", "new.json");
+        assert!(unified.starts_with("--- old.json\n+++ new.json\n@@ whitelist base (modified) @@"));
+        assert!(unified.contains("\n-domain=old.example.com port=443 protocol=TCP\n"));
+        assert!(unified
+            .contains("\n+domain=api.example.com port=8443 protocol=TCP    # changed: port\n"));
+    }
+
+    #[test]
+    fn identical_whitelists_have_empty_diff() {
+        let json = r#"{ "date": "v1", "whitelists": [
+            { "name": "base", "endpoints": [ { "domain": "api.example.com", "port": 443 } ] }
+        ]}"#;
+        let (whitelists, _) = parse_whitelists(json);
+        assert!(diff_whitelists(&whitelists, &whitelists).is_empty());
+    }
+
+    #[test]
+    fn merged_whitelists_are_sorted_and_annotated() {
+        let linux = r#"{ "date": "d", "whitelists": [
+            { "name": "ci", "extends": ["base"], "endpoints": [
+                { "domain": "z.example.com", "port": 443 },
+                { "domain": "a.example.com", "port": 443 }
+            ]}
+        ]}"#;
+        let macos = r#"{ "date": "d", "whitelists": [
+            { "name": "ci", "extends": ["base", "macos"], "endpoints": [
+                { "domain": "a.example.com", "port": 443, "description": "API [sources: old.json]" }
+            ]}
+        ]}"#;
+        let inputs: Vec<(String, Vec<WhitelistInfo>)> =
+            [("linux.json", linux), ("macos.json", macos)]
+                .iter()
+                .map(|(label, json)| (label.to_string(), parse_whitelists(json).0))
+                .collect();
+
+        let conflicts = extends_conflicts(&inputs);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0]["whitelist"], "ci");
+
+        let mut merged = json!({ "date": "d", "whitelists": [
+            { "name": "zeta", "endpoints": [] },
+            { "name": "ci", "extends": ["macos", "base"], "endpoints": [
+                { "domain": "z.example.com", "port": 443 },
+                { "domain": "a.example.com", "port": 443, "description": "API [sources: old.json]" }
+            ]}
+        ]});
+        normalize_merged_whitelists(&mut merged, &inputs, true);
+        let ci = &merged["whitelists"][0];
+        assert_eq!(ci["name"], "ci");
+        assert_eq!(ci["extends"], json!(["base", "macos"]));
+        assert_eq!(ci["endpoints"][0]["domain"], "a.example.com");
+        assert_eq!(
+            ci["endpoints"][0]["description"],
+            "API [sources: linux.json, macos.json]"
+        );
+        assert_eq!(ci["endpoints"][1]["description"], "sources: linux.json");
+        assert_eq!(merged["whitelists"][1]["name"], "zeta");
+    }
+
+    #[test]
+    fn wildcard_file_patterns() {
+        assert!(wildcard_matches("*.json", "linux.json"));
+        assert!(wildcard_matches("wl-?.json", "wl-1.json"));
+        assert!(!wildcard_matches("*.json", "linux.json.bak"));
+        assert!(wildcard_matches("*-*.json", "a-b-c.json"));
+    }
+
+    #[test]
+    fn explains_closest_rule_and_suggests_endpoint() {
+        let json = r#"{
+            "date": "October 24th 2023",
+            "whitelists": [
+                { "name": "base", "endpoints": [
+                    { "domain": "*.github.com", "port": 443, "protocol": "TCP" },
+                    { "ip": "10.0.0.0/8", "protocol": "UDP" }
+                ]},
+                { "name": "ci", "extends": ["base"], "endpoints": [
+                    { "domain": "api.github.com", "port": 443, "process": "git" }
+                ]}
+            ]
+        }"#;
+        let session = json!({
+            "uid": "tcp:10.1.2.3:50000:140.82.112.5:8443",
+            "session": { "protocol": "TCP", "dst_ip": "140.82.112.5", "dst_port": 8443 },
+            "dst_domain": "api.github.com",
+            "l7": { "process_name": "curl" },
+            "is_whitelisted": "NonConforming"
+        });
+
+        let report = explain_session(&session, json, "ci", 2);
+        assert_eq!(report["conforms"], false);
+        assert_eq!(report["hierarchy"], json!(["ci", "base"]));
+        assert_eq!(report["endpoints_evaluated"], 3);
+        let candidates = report["candidates"].as_array().unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0]["whitelist"], "base");
+        assert_eq!(candidates[0]["matched_criteria"], 2);
+        assert_eq!(
+            candidates[0]["first_mismatch"],
+            "port: expects 443, session uses port 8443"
+        );
+        assert_eq!(
+            report["suggestion"],
+            json!({
+                "domain": "api.github.com",
+                "port": 8443,
+                "protocol": "TCP",
+                "process": "curl",
+                "description": "Added for session tcp:10.1.2.3:50000:140.82.112.5:8443"
+            })
+        );
+
+        let mut unresolved = session.clone();
+        unresolved["dst_domain"] = Value::Null;
+        unresolved["session"]["dst_port"] = json!(443);
+        let report = explain_session(&unresolved, json, "ci", 5);
+        assert_eq!(
+            report["candidates"][0]["first_mismatch"],
+            "domain: expects *.github.com, session domain not resolved (destination 140.82.112.5)"
+        );
+        assert_eq!(report["suggestion"]["ip"], "140.82.112.5");
+
+        let mut conforming = session.clone();
+        conforming["session"]["dst_port"] = json!(443);
+        let report = explain_session(&conforming, json, "ci", 5);
+        assert_eq!(report["conforms"], true);
+        assert_eq!(report["matching_rule"]["whitelist"], "base");
+        assert!(report["suggestion"].is_null());
+    }
+}